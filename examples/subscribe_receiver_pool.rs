@@ -1,8 +1,5 @@
-use bitcoincore_zmq::subscribe_receiver;
-use std::{
-    sync::{Arc, Mutex},
-    thread,
-};
+use bitcoincore_zmq::{Distribution, SubscribeBuilder};
+use std::thread;
 
 /// Use 4 threads to handle messages
 const POOL_THREADS: usize = 4;
@@ -10,15 +7,14 @@ const POOL_THREADS: usize = 4;
 fn main() {
     let mut threads = Vec::new();
 
-    let rx = Arc::new(Mutex::new(
-        subscribe_receiver(&["tcp://127.0.0.1:28332", "tcp://127.0.0.1:28333"]).unwrap(),
-    ));
-
-    for id in 0..POOL_THREADS {
-        let rx = rx.clone();
+    let consumers = SubscribeBuilder::new(&["tcp://127.0.0.1:28332", "tcp://127.0.0.1:28333"])
+        .consumer_group(POOL_THREADS, Distribution::LeastLoaded)
+        .unwrap();
 
+    for consumer in consumers {
         threads.push(thread::spawn(move || {
-            while let Ok(msg) = { rx.lock().unwrap().recv() } {
+            let id = consumer.id();
+            for msg in consumer.iter() {
                 match msg {
                     Ok(msg) => println!("Thread {id}: Received message: {msg}"),
                     Err(err) => println!("Thread {id}: Error receiving message: {err}"),
@@ -0,0 +1,46 @@
+/*
+ * C API of the bitcoincore-zmq crate, exported by its shared library when built with the `ffi`
+ * feature:
+ *
+ *     cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * See the documentation of the `ffi` module for details.
+ */
+
+#ifndef BITCOINCORE_ZMQ_H
+#define BITCOINCORE_ZMQ_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BczmqSubscription bczmq_subscription;
+typedef struct BczmqMessage bczmq_message;
+
+/* Return 0 to continue receiving, any other value to stop. */
+typedef int (*bczmq_callback)(const bczmq_message *msg, void *user_data);
+
+const char *bczmq_last_error(void);
+
+bczmq_subscription *bczmq_subscribe(const char *const *endpoints, size_t len);
+void bczmq_subscription_free(bczmq_subscription *sub);
+
+/* Returns 1 if a message was stored in *out, 0 on timeout, -1 on failure. */
+int bczmq_recv(bczmq_subscription *sub, int64_t timeout_ms, bczmq_message **out);
+/* Returns 0 when stopped by the callback, -1 on failure or if callback is NULL. */
+int bczmq_run(bczmq_subscription *sub, bczmq_callback callback, void *user_data);
+
+void bczmq_message_free(bczmq_message *msg);
+const char *bczmq_message_topic(const bczmq_message *msg);
+uint32_t bczmq_message_sequence(const bczmq_message *msg);
+const uint8_t *bczmq_message_data(const bczmq_message *msg, size_t *len);
+void bczmq_message_hash(const bczmq_message *msg, uint8_t out[32]);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BITCOINCORE_ZMQ_H */
@@ -2,4 +2,4 @@ pub const HASHBLOCK: &str = "tcp://127.0.0.1:28370";
 pub const HASHTX: &str = "tcp://127.0.0.1:28371";
 pub const RAWBLOCK: &str = "tcp://127.0.0.1:28372";
 pub const RAWTX: &str = "tcp://127.0.0.1:28373";
-// pub const SEQUENCE: &str = "tcp://127.0.0.1:28374";
+pub const SEQUENCE: &str = "tcp://127.0.0.1:28374";
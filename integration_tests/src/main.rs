@@ -1,14 +1,16 @@
 mod endpoints;
+mod scenarios;
 mod util;
 
 use bitcoincore_rpc::Client;
 use bitcoincore_zmq::{
     subscribe_async, subscribe_async_monitor, subscribe_async_wait_handshake,
     subscribe_async_wait_handshake_timeout, subscribe_blocking, subscribe_receiver, Message,
-    MonitorMessage, SocketEvent, SocketMessage,
+    MonitorMessage, SequenceMessage, SocketEvent, SocketMessage, Topic,
 };
-use core::{assert_eq, ops::ControlFlow, time::Duration};
+use core::{assert_eq, fmt::Debug, ops::ControlFlow, time::Duration};
 use futures::{executor::block_on, StreamExt};
+use scenarios::Reorg;
 use std::{net::SocketAddr, sync::mpsc, thread};
 use tokio::{
     io::AsyncWriteExt,
@@ -16,7 +18,10 @@ use tokio::{
     runtime,
     sync::mpsc::unbounded_channel,
 };
-use util::{generate, recv_timeout_2, setup_rpc, sleep, static_ref_heap, RECV_TIMEOUT};
+use util::{
+    generate, recv_timeout, recv_timeout_2, setup_rpc, static_ref_heap, wait_until_ready,
+    RECV_TIMEOUT,
+};
 
 macro_rules! test {
     ($($function:ident,)*) => {
@@ -39,6 +44,8 @@ fn main() {
         test_subscribe_timeout_tokio,
         test_subscribe_timeout_inefficient,
         test_disconnect,
+        test_reorg,
+        test_mempool_removal,
     }
 }
 
@@ -46,6 +53,9 @@ fn test_hashblock(rpc: &Client) {
     let receiver = subscribe_receiver(&[endpoints::HASHBLOCK, endpoints::RAWBLOCK])
         .expect("failed to subscribe to Bitcoin Core's ZMQ publisher");
 
+    wait_until_ready(&receiver, Topic::HashBlock, rpc, RECV_TIMEOUT);
+    wait_until_ready(&receiver, Topic::RawBlock, rpc, RECV_TIMEOUT);
+
     let rpc_hash = generate(rpc, 1).expect("rpc call failed").0[0];
 
     match recv_timeout_2(&receiver) {
@@ -64,6 +74,9 @@ fn test_hashtx(rpc: &Client) {
     let receiver = subscribe_receiver(&[endpoints::HASHTX, endpoints::RAWTX])
         .expect("failed to subscribe to Bitcoin Core's ZMQ publisher");
 
+    wait_until_ready(&receiver, Topic::HashTx, rpc, RECV_TIMEOUT);
+    wait_until_ready(&receiver, Topic::RawTx, rpc, RECV_TIMEOUT);
+
     generate(rpc, 1).expect("rpc call failed");
 
     match recv_timeout_2(&receiver) {
@@ -78,38 +91,36 @@ fn test_hashtx(rpc: &Client) {
 }
 
 fn test_sub_blocking(rpc: &Client) {
-    sleep(1000);
-
     let (tx, rx) = mpsc::channel();
 
     let h = thread::spawn(move || {
-        subscribe_blocking(&[endpoints::HASHBLOCK], |msg| {
-            let msg = msg.expect("zmq message error");
-
-            match msg {
-                Message::HashBlock(hash, _) => {
-                    tx.send(hash).unwrap();
-                }
-                msg => {
-                    panic!("invalid message received: {msg}");
-                }
+        let res = subscribe_blocking(&[endpoints::HASHBLOCK], |msg| {
+            // Stop when the receiver is dropped
+            match tx.send(msg) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
             }
-
-            // Stop after 1 message
-            ControlFlow::Break(())
         })
         .expect("failed to subscribe to Bitcoin Core's ZMQ publisher");
+
+        assert!(res.is_break());
     });
 
-    sleep(1000);
+    wait_until_ready(&rx, Topic::HashBlock, rpc, RECV_TIMEOUT);
 
     let rpc_hash = generate(rpc, 1).expect("rpc call failed").0[0];
 
-    let zmq_hash = rx.recv_timeout(RECV_TIMEOUT).unwrap();
+    match recv_timeout(&rx) {
+        Message::HashBlock(zmq_hash, _) => assert_eq!(rpc_hash, zmq_hash),
+        msg => panic!("invalid message received: {msg}"),
+    }
 
-    h.join().unwrap();
+    drop(rx);
 
-    assert_eq!(rpc_hash, zmq_hash);
+    // wake the subscriber up so it notices the receiver is gone
+    generate(rpc, 1).expect("rpc call failed");
+
+    h.join().unwrap();
 }
 
 fn test_hashblock_async(rpc: &Client) {
@@ -261,20 +272,11 @@ fn test_disconnect(rpc: &'static Client) {
                 // send the signal to close the proxy
                 tx.send(()).unwrap();
 
-                loop {
-                    match stream.next().await {
-                        Some(Ok(SocketMessage::Event(MonitorMessage {
-                            event: SocketEvent::Disconnected { .. },
-                            source_url,
-                        }))) if source_url == "tcp://127.0.0.1:29999" => {
-                            break;
-                        }
-                        Some(Ok(SocketMessage::Event(_))) => {
-                            // ignore other events
-                        }
-                        other => panic!("unexpected response: {other:?}"),
-                    }
-                }
+                let event = stream
+                    .wait_for_disconnect(Some("tcp://127.0.0.1:29999"))
+                    .await
+                    .unwrap();
+                assert!(matches!(event.event, SocketEvent::Disconnected { .. }));
             });
 
             // proxy endpoints::HASHBLOCK to 127.0.0.1:29999 to simulate a disconnect
@@ -310,3 +312,106 @@ fn test_disconnect(rpc: &'static Client) {
             h.await.unwrap();
         });
 }
+
+fn test_reorg(rpc: &Client) {
+    let receiver = subscribe_receiver(&[endpoints::SEQUENCE, endpoints::RAWBLOCK])
+        .expect("failed to subscribe to Bitcoin Core's ZMQ publisher");
+
+    wait_until_ready(&receiver, Topic::Sequence, rpc, RECV_TIMEOUT);
+    wait_until_ready(&receiver, Topic::RawBlock, rpc, RECV_TIMEOUT);
+
+    let reorg = scenarios::reorg(rpc, 2).expect("rpc call failed");
+    assert_reorg_messages(&receiver, &reorg);
+
+    // the blocks that are connected again were received before
+    let reorg = scenarios::reorg_back(rpc, &reorg).expect("rpc call failed");
+    assert_reorg_messages(&receiver, &reorg);
+}
+
+/// Receives the `sequence` and `rawblock` messages caused by `reorg` and checks they match it.
+fn assert_reorg_messages(rx: &mpsc::Receiver<Result<Message, impl Debug>>, reorg: &Reorg) {
+    let expected = reorg
+        .disconnected
+        .iter()
+        .map(|&blockhash| SequenceMessage::BlockDisconnect { blockhash })
+        .chain(
+            reorg
+                .connected
+                .iter()
+                .map(|&blockhash| SequenceMessage::BlockConnect { blockhash }),
+        )
+        .collect::<Vec<_>>();
+
+    let mut block_events = Vec::new();
+    let mut blocks = Vec::new();
+    while block_events.len() < expected.len() || blocks.len() < reorg.connected.len() {
+        match recv_timeout(rx) {
+            Message::Sequence(
+                sm @ (SequenceMessage::BlockConnect { .. }
+                | SequenceMessage::BlockDisconnect { .. }),
+                _,
+            ) => block_events.push(sm),
+            // transactions of disconnected blocks are added to the mempool again
+            Message::Sequence(SequenceMessage::MempoolAcceptance { .. }, _) => {}
+            Message::Block(block, _) => {
+                assert!(block.check_merkle_root());
+                blocks.push(block.block_hash());
+            }
+            msg => panic!("invalid message received: {msg}"),
+        }
+    }
+
+    assert_eq!(block_events, expected);
+    // rawblock is only published for connected blocks
+    assert_eq!(blocks, reorg.connected);
+}
+
+fn test_mempool_removal(rpc: &Client) {
+    let receiver = subscribe_receiver(&[endpoints::SEQUENCE])
+        .expect("failed to subscribe to Bitcoin Core's ZMQ publisher");
+
+    wait_until_ready(&receiver, Topic::Sequence, rpc, RECV_TIMEOUT);
+
+    let replacement = scenarios::replace_tx(rpc).expect("rpc call failed");
+
+    let (mut accepted, mut removed, mut replaced_by) = (None, None, None);
+    while accepted.is_none() || removed.is_none() || replaced_by.is_none() {
+        match recv_timeout(&receiver) {
+            Message::Sequence(
+                SequenceMessage::MempoolAcceptance {
+                    txid,
+                    mempool_sequence,
+                },
+                _,
+            ) if txid == replacement.original => accepted = Some(mempool_sequence),
+            Message::Sequence(
+                SequenceMessage::MempoolRemoval {
+                    txid,
+                    mempool_sequence,
+                },
+                _,
+            ) if txid == replacement.original => removed = Some(mempool_sequence),
+            Message::Sequence(
+                SequenceMessage::MempoolAcceptance {
+                    txid,
+                    mempool_sequence,
+                },
+                _,
+            ) if txid == replacement.replacement => replaced_by = Some(mempool_sequence),
+            msg => panic!("invalid message received: {msg}"),
+        }
+    }
+    // the original is removed before its replacement is added
+    assert!(accepted < removed);
+    assert!(removed < replaced_by);
+
+    // transactions that are removed because they are included in a block do not produce a
+    // MempoolRemoval message, so the next message is the BlockConnect
+    let blockhash = generate(rpc, 1).expect("rpc call failed").0[0];
+    match recv_timeout(&receiver) {
+        Message::Sequence(SequenceMessage::BlockConnect { blockhash: hash }, _) => {
+            assert_eq!(hash, blockhash);
+        }
+        msg => panic!("invalid message received: {msg}"),
+    }
+}
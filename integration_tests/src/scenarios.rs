@@ -0,0 +1,83 @@
+//! Scenarios that drive Bitcoin Core through the message flows that are hard to get right, for
+//! use by multiple tests. They only make RPC calls, the messages they cause are checked by the
+//! caller.
+
+use crate::util::generate;
+use bitcoin::{Amount, BlockHash, Txid};
+use bitcoincore_rpc::{jsonrpc::serde_json, Client, Error, RpcApi};
+
+/// The blocks a reorg disconnected and connected.
+#[derive(Debug, Clone)]
+pub struct Reorg {
+    /// The disconnected blocks, in the order they were disconnected (tip first).
+    pub disconnected: Vec<BlockHash>,
+    /// The connected blocks, in the order they were connected.
+    pub connected: Vec<BlockHash>,
+}
+
+/// Replaces the last `depth` blocks of the active chain with `depth + 1` new blocks.
+///
+/// The old blocks are disconnected with `invalidateblock`, then the new blocks are generated and
+/// the old blocks are marked valid again with `reconsiderblock`. They have less work, so they stay
+/// stale until [`reorg_back`].
+pub fn reorg(rpc: &Client, depth: u64) -> Result<Reorg, Error> {
+    let tip_height = rpc.get_block_count()?;
+    let disconnected = (0..depth)
+        .map(|i| rpc.get_block_hash(tip_height - i))
+        .collect::<Result<Vec<_>, _>>()?;
+    let fork = disconnected.last().expect("depth must be at least 1");
+
+    rpc.invalidate_block(fork)?;
+    let (connected, _) = generate(rpc, depth + 1)?;
+    rpc.reconsider_block(fork)?;
+
+    Ok(Reorg {
+        disconnected,
+        connected,
+    })
+}
+
+/// Undoes a [`reorg`] by invalidating the blocks it connected, so the node switches back to the
+/// blocks it disconnected. The invalidated blocks are not reconsidered, as they have more work and
+/// would be connected again.
+pub fn reorg_back(rpc: &Client, reorg: &Reorg) -> Result<Reorg, Error> {
+    rpc.invalidate_block(&reorg.connected[0])?;
+
+    Ok(Reorg {
+        disconnected: reorg.connected.iter().rev().copied().collect(),
+        connected: reorg.disconnected.iter().rev().copied().collect(),
+    })
+}
+
+/// A transaction that was evicted from the mempool by a replacement.
+#[derive(Debug, Clone, Copy)]
+pub struct Replacement {
+    /// The evicted transaction.
+    pub original: Txid,
+    /// The transaction that replaced it.
+    pub replacement: Txid,
+}
+
+/// Sends a replaceable transaction from the wallet and evicts it from the mempool by replacing it
+/// with `bumpfee`. Both transactions stay unconfirmed.
+pub fn replace_tx(rpc: &Client) -> Result<Replacement, Error> {
+    let addr = rpc.get_new_address(None, None)?.assume_checked();
+    let original = rpc.send_to_address(
+        &addr,
+        Amount::ONE_BTC,
+        None,
+        None,
+        None,
+        Some(true),
+        None,
+        None,
+    )?;
+
+    let res: serde_json::Value = rpc.call("bumpfee", &[serde_json::to_value(original)?])?;
+    let replacement = serde_json::from_value(res["txid"].clone())?;
+
+    Ok(Replacement {
+        original,
+        replacement,
+    })
+}
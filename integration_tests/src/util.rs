@@ -1,10 +1,18 @@
 use bitcoin::{Address, BlockHash};
 use bitcoincore_rpc::{Auth, Client, RpcApi};
+use bitcoincore_zmq::{Message, Topic};
 use core::{fmt::Debug, time::Duration};
-use std::{env, sync::mpsc::Receiver, thread};
+use std::{
+    env,
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::Instant,
+};
 
 pub const RECV_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Time to wait for a message after triggering an event before triggering a new one.
+const READY_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
 pub fn setup_rpc() -> Client {
     Client::new(
         "http://localhost:18443",
@@ -54,6 +62,40 @@ pub fn recv_timeout_2<T>(rx: &Receiver<Result<T, impl Debug>>) -> (T, T) {
     (recv_timeout(rx), recv_timeout(rx))
 }
 
-pub fn sleep(ms: u64) {
-    thread::sleep(Duration::from_millis(ms));
+/// Generates blocks until a message with `topic` arrives on `rx`, proving the full path from
+/// Bitcoin Core to the subscriber works. Generating a block produces a message on every topic.
+/// Messages caused by these throwaway blocks are discarded, so `rx` is empty when this returns.
+pub fn wait_until_ready<E: Debug>(
+    rx: &Receiver<Result<Message, E>>,
+    topic: Topic,
+    rpc: &Client,
+    timeout: Duration,
+) {
+    let deadline = Instant::now() + timeout;
+
+    'trigger: loop {
+        generate(rpc, 1).expect("rpc call failed");
+
+        let retry = Instant::now() + READY_RETRY_INTERVAL;
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                panic!("no {topic} message received within {timeout:?}");
+            }
+
+            match rx.recv_timeout(retry.min(deadline) - now) {
+                Ok(msg) => {
+                    if msg.expect("zmq message error").topic_type() == topic {
+                        break 'trigger;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => continue 'trigger,
+                Err(RecvTimeoutError::Disconnected) => panic!("receiver disconnected"),
+            }
+        }
+    }
+
+    // discard the other messages caused by the generated blocks
+    while rx.recv_timeout(READY_RETRY_INTERVAL).is_ok() {}
 }
@@ -0,0 +1,232 @@
+//! [`proptest`] strategies for the types of this crate.
+//!
+//! [`Topic`], [`SequenceMessage`], [`Message`] and [`RawMessage`] implement [`Arbitrary`], so
+//! `any::<Message>()` can be used in property tests. Strategies for the [`bitcoin`] types contained in messages are
+//! provided as functions, because [`Arbitrary`] can not be implemented for foreign types.
+
+use crate::{
+    message::{Message, TOPIC_MAX_LEN},
+    raw_message::{IntoRawMessage, RawMessage},
+    sequence_message::SequenceMessage,
+    topic::Topic,
+};
+use bitcoin::{
+    absolute::LockTime,
+    block::{Header, Version},
+    hashes::Hash,
+    transaction, Amount, Block, BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence,
+    Transaction, TxIn, TxMerkleNode, TxOut, Txid, Witness,
+};
+use proptest::{
+    arbitrary::{any, Arbitrary},
+    collection::vec,
+    prop_oneof,
+    sample::select,
+    strategy::{BoxedStrategy, Strategy},
+};
+
+/// Returns a strategy that generates [`BlockHash`]es.
+pub fn blockhash() -> impl Strategy<Value = BlockHash> {
+    any::<[u8; 32]>().prop_map(BlockHash::from_byte_array)
+}
+
+/// Returns a strategy that generates [`Txid`]s.
+pub fn txid() -> impl Strategy<Value = Txid> {
+    any::<[u8; 32]>().prop_map(Txid::from_byte_array)
+}
+
+fn script() -> impl Strategy<Value = ScriptBuf> {
+    vec(any::<u8>(), 0..64).prop_map(ScriptBuf::from_bytes)
+}
+
+fn tx_in() -> impl Strategy<Value = TxIn> {
+    (
+        txid(),
+        any::<u32>(),
+        script(),
+        any::<u32>(),
+        vec(vec(any::<u8>(), 0..80), 0..4),
+    )
+        .prop_map(|(txid, vout, script_sig, sequence, witness)| TxIn {
+            previous_output: OutPoint { txid, vout },
+            script_sig,
+            sequence: Sequence(sequence),
+            witness: Witness::from_slice(&witness),
+        })
+}
+
+fn tx_out() -> impl Strategy<Value = TxOut> {
+    (0..=Amount::MAX_MONEY.to_sat(), script()).prop_map(|(value, script_pubkey)| TxOut {
+        value: Amount::from_sat(value),
+        script_pubkey,
+    })
+}
+
+/// Returns a strategy that generates [`Transaction`]s that survive a consensus serialization
+/// roundtrip. Transactions always have at least one input, as transactions without inputs can
+/// not be distinguished from segwit transactions when deserializing.
+pub fn transaction() -> impl Strategy<Value = Transaction> {
+    (
+        select(&[transaction::Version::ONE, transaction::Version::TWO][..]),
+        any::<u32>(),
+        vec(tx_in(), 1..4),
+        vec(tx_out(), 0..4),
+    )
+        .prop_map(|(version, lock_time, input, output)| Transaction {
+            version,
+            lock_time: LockTime::from_consensus(lock_time),
+            input,
+            output,
+        })
+}
+
+/// Returns a strategy that generates [`Block`]s. The header fields are random, so the blocks
+/// are not valid (wrong merkle root, insufficient proof of work).
+pub fn block() -> impl Strategy<Value = Block> {
+    (
+        any::<i32>(),
+        blockhash(),
+        any::<[u8; 32]>(),
+        any::<u32>(),
+        any::<u32>(),
+        any::<u32>(),
+        vec(transaction(), 1..4),
+    )
+        .prop_map(
+            |(version, prev_blockhash, merkle_root, time, bits, nonce, txdata)| Block {
+                header: Header {
+                    version: Version::from_consensus(version),
+                    prev_blockhash,
+                    merkle_root: TxMerkleNode::from_byte_array(merkle_root),
+                    time,
+                    bits: CompactTarget::from_consensus(bits),
+                    nonce,
+                },
+                txdata,
+            },
+        )
+}
+
+impl Arbitrary for Topic {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        select(&Self::ALL[..]).boxed()
+    }
+}
+
+impl Arbitrary for SequenceMessage {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            blockhash().prop_map(|blockhash| Self::BlockConnect { blockhash }),
+            blockhash().prop_map(|blockhash| Self::BlockDisconnect { blockhash }),
+            (txid(), any::<u64>()).prop_map(|(txid, mempool_sequence)| {
+                Self::MempoolAcceptance {
+                    txid,
+                    mempool_sequence,
+                }
+            }),
+            (txid(), any::<u64>()).prop_map(|(txid, mempool_sequence)| {
+                Self::MempoolRemoval {
+                    txid,
+                    mempool_sequence,
+                }
+            }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Message {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        (any::<Topic>(), any::<u32>())
+            .prop_flat_map(|(topic, seq)| match topic {
+                Topic::HashBlock => blockhash()
+                    .prop_map(move |blockhash| Self::HashBlock(blockhash, seq))
+                    .boxed(),
+                Topic::HashTx => txid().prop_map(move |txid| Self::HashTx(txid, seq)).boxed(),
+                Topic::RawBlock => block()
+                    .prop_map(move |block| Self::Block(block, seq))
+                    .boxed(),
+                Topic::RawTx => transaction().prop_map(move |tx| Self::Tx(tx, seq)).boxed(),
+                Topic::Sequence => any::<SequenceMessage>()
+                    .prop_map(move |sm| Self::Sequence(sm, seq))
+                    .boxed(),
+            })
+            .boxed()
+    }
+}
+
+/// Generates the parts of valid messages (see [`Message`]) as well as random topics and data
+/// parts, which may not be parsable.
+impl Arbitrary for RawMessage {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            any::<Message>().prop_map(|msg| {
+                let [topic, data, sequence] = msg.into_raw_message();
+                Self {
+                    topic,
+                    data,
+                    sequence: u32::from_le_bytes(sequence.try_into().unwrap()),
+                }
+            }),
+            (
+                vec(any::<u8>(), 0..=TOPIC_MAX_LEN),
+                vec(any::<u8>(), 0..256),
+                any::<u32>()
+            )
+                .prop_map(|(topic, data, sequence)| Self {
+                    topic,
+                    data,
+                    sequence,
+                }),
+        ]
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FromRawMessage, IntoRawMessage, Message, RawMessage, SequenceMessage};
+    use bitcoin::consensus::{deserialize, serialize};
+    use proptest::{arbitrary::any, proptest};
+
+    proptest! {
+        #[test]
+        fn message_roundtrip(msg in any::<Message>()) {
+            assert_eq!(Message::from_multipart(&msg.serialize_to_vecs()).unwrap(), msg);
+        }
+
+        #[test]
+        fn sequence_message_roundtrip(sm in any::<SequenceMessage>()) {
+            assert_eq!(SequenceMessage::from_byte_slice(sm.serialize_to_vec()).unwrap(), sm);
+        }
+
+        #[test]
+        fn raw_message_roundtrip(raw in any::<RawMessage>()) {
+            let [topic, data, sequence] = raw.clone().into_raw_message();
+            let parsed =
+                RawMessage::from_raw_message(&topic, &data, sequence.try_into().unwrap()).unwrap();
+            assert_eq!(parsed, raw);
+            // the parts of a message that parses serialize to the same parts
+            if let Ok(msg) = Message::try_from(raw.clone()) {
+                assert_eq!(msg.into_raw_message(), raw.into_raw_message());
+            }
+        }
+
+        #[test]
+        fn transaction_roundtrip(tx in super::transaction()) {
+            assert_eq!(deserialize::<bitcoin::Transaction>(&serialize(&tx)).unwrap(), tx);
+        }
+    }
+}
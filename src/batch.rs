@@ -0,0 +1,222 @@
+use crate::{error::Result, message::Message, sequence_message::SequenceMessage};
+use bitcoin::Txid;
+use core::time::Duration;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::Instant,
+};
+
+/// Output of a [`TxidBatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Batched {
+    /// Txids from `hashtx` and `sequence` mempool acceptance messages, in order of arrival and
+    /// without duplicates.
+    Txids(Vec<Txid>),
+    /// Any other message, passed through unchanged.
+    Message(Message),
+}
+
+/// Coalesces txids into batches, to handle many of them at once instead of one at a time during
+/// mempool floods and after blocks.
+///
+/// Txids are taken from `hashtx` messages and [`SequenceMessage::MempoolAcceptance`]s. Bitcoin
+/// Core publishes `hashtx` for transactions entering the mempool, but also for every transaction
+/// of a connected or disconnected block, so batches are not only mempool arrivals. To batch
+/// mempool arrivals only, subscribe to the `sequence` topic without `hashtx`. A batch
+/// is ready when it holds the maximum number of txids or when the oldest txid in it has waited
+/// for the maximum delay. All other messages are ready immediately, the pending batch is flushed
+/// before them so the order of events is preserved.
+#[derive(Debug, Clone)]
+pub struct TxidBatcher {
+    max_len: usize,
+    max_delay: Duration,
+    pending: Vec<Txid>,
+    pending_set: HashSet<Txid>,
+    first_pending_at: Option<Instant>,
+    ready: VecDeque<Batched>,
+}
+
+impl TxidBatcher {
+    /// Creates a new [`TxidBatcher`] that delivers batches of at most `max_len` txids, at most
+    /// `max_delay` after the first txid of the batch arrived.
+    #[inline]
+    pub fn new(max_len: usize, max_delay: Duration) -> Self {
+        Self {
+            max_len: max_len.max(1),
+            max_delay,
+            pending: Vec::new(),
+            pending_set: HashSet::new(),
+            first_pending_at: None,
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Adds a message that arrived at `now`.
+    pub fn push(&mut self, msg: Message, now: Instant) {
+        let txid = match msg {
+            Message::HashTx(txid, _)
+            | Message::Sequence(SequenceMessage::MempoolAcceptance { txid, .. }, _) => txid,
+            msg => {
+                self.flush();
+                self.ready.push_back(Batched::Message(msg));
+                return;
+            }
+        };
+
+        if self.pending_set.insert(txid) {
+            self.pending.push(txid);
+            self.first_pending_at.get_or_insert(now);
+        }
+        if self.pending.len() >= self.max_len {
+            self.flush();
+        }
+    }
+
+    /// Returns the time at which the pending batch becomes ready, if there is one.
+    #[inline]
+    pub fn deadline(&self) -> Option<Instant> {
+        self.first_pending_at.map(|at| at + self.max_delay)
+    }
+
+    /// Returns the next output that is ready at `now`.
+    pub fn pop_ready(&mut self, now: Instant) -> Option<Batched> {
+        if self.ready.is_empty() && self.deadline().is_some_and(|deadline| deadline <= now) {
+            self.flush();
+        }
+
+        self.ready.pop_front()
+    }
+
+    /// Makes the pending batch ready, regardless of its size and age.
+    pub fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            self.pending_set.clear();
+            self.first_pending_at = None;
+            self.ready
+                .push_back(Batched::Txids(core::mem::take(&mut self.pending)));
+        }
+    }
+
+    /// Wraps the [`Receiver`] returned by [`subscribe_receiver`] into an iterator of
+    /// [`Batched`] outputs. Errors are passed through immediately. The iterator ends, after
+    /// delivering the pending batch, when the sending side of the channel disconnects.
+    ///
+    /// [`subscribe_receiver`]: crate::subscribe_receiver
+    #[inline]
+    pub fn batches(self, receiver: Receiver<Result<Message>>) -> TxidBatches {
+        TxidBatches {
+            batcher: self,
+            receiver,
+            disconnected: false,
+        }
+    }
+}
+
+/// Iterator returned by [`TxidBatcher::batches`].
+#[derive(Debug)]
+pub struct TxidBatches {
+    batcher: TxidBatcher,
+    receiver: Receiver<Result<Message>>,
+    disconnected: bool,
+}
+
+impl Iterator for TxidBatches {
+    type Item = Result<Batched>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(batched) = self.batcher.pop_ready(Instant::now()) {
+                return Some(Ok(batched));
+            }
+            if self.disconnected {
+                self.batcher.flush();
+                return self.batcher.pop_ready(Instant::now()).map(Ok);
+            }
+
+            let res = match self.batcher.deadline() {
+                Some(deadline) => self
+                    .receiver
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => self
+                    .receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match res {
+                Ok(Ok(msg)) => self.batcher.push(msg, Instant::now()),
+                Ok(Err(err)) => return Some(Err(err)),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => self.disconnected = true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Batched, Message, SequenceMessage, TxidBatcher};
+    use bitcoin::{hashes::Hash, BlockHash, Txid};
+    use core::time::Duration;
+    use std::{sync::mpsc::channel, time::Instant};
+
+    #[test]
+    fn batching() {
+        let txid = |i: u8| Txid::from_byte_array([i; 32]);
+        let blockhash = BlockHash::all_zeros();
+        let start = Instant::now();
+
+        let mut batcher = TxidBatcher::new(3, Duration::from_millis(200));
+        batcher.push(Message::HashTx(txid(1), 0), start);
+        batcher.push(
+            Message::Sequence(
+                SequenceMessage::MempoolAcceptance {
+                    txid: txid(1),
+                    mempool_sequence: 1,
+                },
+                0,
+            ),
+            start,
+        );
+        batcher.push(Message::HashTx(txid(2), 1), start);
+        assert_eq!(batcher.pop_ready(start), None);
+        assert_eq!(batcher.deadline(), Some(start + Duration::from_millis(200)));
+        assert_eq!(
+            batcher.pop_ready(start + Duration::from_millis(200)),
+            Some(Batched::Txids(vec![txid(1), txid(2)]))
+        );
+        assert_eq!(batcher.deadline(), None);
+
+        // full batch
+        for i in 0..4 {
+            batcher.push(Message::HashTx(txid(i), i.into()), start);
+        }
+        assert_eq!(
+            batcher.pop_ready(start),
+            Some(Batched::Txids(vec![txid(0), txid(1), txid(2)]))
+        );
+        assert_eq!(batcher.pop_ready(start), None);
+
+        // block events flush the pending batch and pass through
+        batcher.push(Message::HashBlock(blockhash, 0), start);
+        assert_eq!(
+            batcher.pop_ready(start),
+            Some(Batched::Txids(vec![txid(3)]))
+        );
+        assert_eq!(
+            batcher.pop_ready(start),
+            Some(Batched::Message(Message::HashBlock(blockhash, 0)))
+        );
+
+        let (tx, rx) = channel();
+        tx.send(Ok(Message::HashTx(txid(5), 0))).unwrap();
+        tx.send(Ok(Message::HashTx(txid(6), 1))).unwrap();
+        drop(tx);
+        let batches: Vec<_> = TxidBatcher::new(10, Duration::from_secs(60))
+            .batches(rx)
+            .map(|res| res.unwrap())
+            .collect();
+        assert_eq!(batches, [Batched::Txids(vec![txid(5), txid(6)])]);
+    }
+}
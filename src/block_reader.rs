@@ -0,0 +1,155 @@
+use crate::error::Result;
+use bitcoin::{
+    block::Header,
+    consensus::{encode::VarInt, Decodable},
+    io::FromStd,
+    Transaction,
+};
+use core::fmt;
+use std::io::Read;
+
+/// Decodes a block incrementally from a [`Read`]er: the header and transaction count are read
+/// up front, the transactions one by one as the reader is iterated.
+///
+/// Useful for the data part of `rawblock` messages that are read from disk or a socket, for
+/// example with [`codec::FrameReader`](crate::codec::FrameReader), so a block never has to be
+/// buffered or decoded as a whole. Transactions that are not iterated are not decoded.
+///
+/// The reader is not buffered, wrap it in a [`BufReader`](std::io::BufReader) when it makes a
+/// syscall per read.
+pub struct BlockReader<R> {
+    reader: FromStd<R>,
+    header: Header,
+    tx_count: u64,
+    remaining: u64,
+}
+
+impl<R: Read> BlockReader<R> {
+    /// Reads the header and transaction count of a block from `reader`.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut reader = FromStd::new(reader);
+        let header = Header::consensus_decode(&mut reader)?;
+        let VarInt(tx_count) = VarInt::consensus_decode(&mut reader)?;
+
+        Ok(Self {
+            reader,
+            header,
+            tx_count,
+            remaining: tx_count,
+        })
+    }
+
+    /// Decodes the next transaction, returns [`None`] when all transactions have been read.
+    ///
+    /// After an error, the position of the reader is unknown and [`None`] is returned from then
+    /// on.
+    pub fn next_tx(&mut self) -> Option<Result<Transaction>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        match Transaction::consensus_decode(&mut self.reader) {
+            Ok(tx) => {
+                self.remaining -= 1;
+                Some(Ok(tx))
+            }
+            Err(err) => {
+                self.remaining = 0;
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+impl<R> BlockReader<R> {
+    /// Returns the header of the block.
+    #[inline]
+    pub const fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the number of transactions in the block, as encoded in its data.
+    #[inline]
+    pub const fn tx_count(&self) -> u64 {
+        self.tx_count
+    }
+
+    /// Returns the number of transactions that have not been read yet.
+    #[inline]
+    pub const fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Returns a reference to the underlying reader.
+    #[inline]
+    pub fn get_ref(&self) -> &R {
+        self.reader.inner()
+    }
+
+    /// Returns a mutable reference to the underlying reader. Reading from it before all
+    /// transactions are read corrupts the transactions that follow.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut R {
+        self.reader.inner_mut()
+    }
+
+    /// Returns the underlying reader, positioned after the last transaction that was read.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+}
+
+impl<R> fmt::Debug for BlockReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockReader")
+            .field("header", &self.header)
+            .field("tx_count", &self.tx_count)
+            .field("remaining", &self.remaining)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read> Iterator for BlockReader<R> {
+    type Item = Result<Transaction>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_tx()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, usize::try_from(self.remaining).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlockReader;
+    use bitcoin::{consensus::serialize, constants::genesis_block, Network};
+
+    #[test]
+    fn read_block_incrementally() {
+        let mut block = genesis_block(Network::Bitcoin);
+        block.txdata.push(block.txdata[0].clone());
+        let mut data = serialize(&block);
+        data.extend_from_slice(b"rest");
+
+        let mut reader = BlockReader::new(&data[..]).unwrap();
+        assert_eq!(*reader.header(), block.header);
+        assert_eq!(reader.tx_count(), 2);
+
+        assert_eq!(reader.next().unwrap().unwrap(), block.txdata[0]);
+        assert_eq!(reader.remaining(), 1);
+        assert_eq!(reader.next().unwrap().unwrap(), block.txdata[1]);
+        assert!(reader.next().is_none());
+        assert_eq!(reader.into_inner(), b"rest");
+
+        // truncated in the second transaction
+        let reader = BlockReader::new(&data[..data.len() - 10]).unwrap();
+        let txs: Vec<_> = reader.collect();
+        assert_eq!(txs.len(), 2);
+        assert!(txs[1].is_err());
+    }
+}
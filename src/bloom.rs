@@ -0,0 +1,451 @@
+use crate::{error::Result, message::Message};
+use bitcoin::{
+    consensus::{encode, Decodable, Encodable},
+    io::{Read, Write},
+    script::Instruction,
+    OutPoint, Script, Transaction,
+};
+use core::f64::consts::LN_2;
+use std::collections::VecDeque;
+
+/// Maximum size of a BIP37 filter in bytes.
+const MAX_FILTER_SIZE: usize = 36_000;
+
+/// Maximum number of hash functions of a BIP37 filter.
+const MAX_HASH_FUNCS: u32 = 50;
+
+/// How a [`BloomFilter`] adds outpoints of matched outputs, so transactions spending them match
+/// too (BIP37 `nFlags`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BloomFlags {
+    /// Never add outpoints.
+    None,
+    /// Add the outpoints of all matched outputs.
+    All,
+    /// Only add the outpoints of matched pay-to-pubkey and bare multisig outputs.
+    PubkeyOnly,
+}
+
+impl BloomFlags {
+    const fn to_u8(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::All => 1,
+            Self::PubkeyOnly => 2,
+        }
+    }
+}
+
+/// A BIP37 bloom filter matching transactions by txid, output script data, spent outpoints and
+/// input script data, for cheap filtering of `rawtx` and `rawblock` messages by a large watch set.
+///
+/// Unlike an exact set, a bloom filter has false positives, at roughly the rate it was created
+/// with. Matching behaves exactly like a Bitcoin Core node serving a BIP37 filter, so filters
+/// built elsewhere (and serialized with [`Encodable`]) can be used as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    data: Vec<u8>,
+    hash_funcs: u32,
+    tweak: u32,
+    flags: BloomFlags,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for `elements` elements with a false positive rate of
+    /// `fp_rate` (between 0 and 1), with BIP37's limits on the size and number of hash functions.
+    /// `tweak` randomizes the hash functions.
+    pub fn new(elements: usize, fp_rate: f64, tweak: u32, flags: BloomFlags) -> Self {
+        let elements = elements.max(1) as f64;
+        let bits = (-1.0 / (LN_2 * LN_2) * elements * fp_rate.ln())
+            .min((MAX_FILTER_SIZE * 8) as f64) as usize;
+        let len = (bits / 8).max(1);
+        let hash_funcs = ((len * 8) as f64 / elements * LN_2).clamp(1.0, MAX_HASH_FUNCS as f64);
+
+        Self {
+            data: vec![0; len],
+            hash_funcs: hash_funcs as u32,
+            tweak,
+            flags,
+        }
+    }
+
+    /// Creates a filter from its parts, as sent in a BIP37 `filterload` message. Returns [`None`]
+    /// if `data` is empty or the parts exceed BIP37's limits on the size and number of hash
+    /// functions.
+    pub fn from_parts(
+        data: Vec<u8>,
+        hash_funcs: u32,
+        tweak: u32,
+        flags: BloomFlags,
+    ) -> Option<Self> {
+        if data.is_empty() || data.len() > MAX_FILTER_SIZE || hash_funcs > MAX_HASH_FUNCS {
+            return None;
+        }
+
+        Some(Self {
+            data,
+            hash_funcs,
+            tweak,
+            flags,
+        })
+    }
+
+    /// Creates a filter containing the data pushes of `scripts` (for example the public key
+    /// hashes of P2PKH and P2WPKH outputs) and `outpoints`, with a false positive rate of
+    /// `fp_rate`. Uses [`BloomFlags::All`], so spends of matched outputs match too.
+    pub fn from_watch_set<'a>(
+        scripts: impl IntoIterator<Item = &'a Script>,
+        outpoints: impl IntoIterator<Item = OutPoint>,
+        fp_rate: f64,
+        tweak: u32,
+    ) -> Self {
+        let mut elements: Vec<Vec<u8>> = Vec::new();
+        for script in scripts {
+            elements.extend(pushes(script).map(<[u8]>::to_vec));
+        }
+        elements.extend(
+            outpoints
+                .into_iter()
+                .map(|outpoint| encode::serialize(&outpoint)),
+        );
+
+        let mut filter = Self::new(elements.len(), fp_rate, tweak, BloomFlags::All);
+        for element in &elements {
+            filter.insert(element);
+        }
+
+        filter
+    }
+
+    /// Adds `data` to the filter.
+    pub fn insert(&mut self, data: &[u8]) {
+        for i in 0..self.hash_funcs {
+            let bit = self.bit(i, data);
+            self.data[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Adds `outpoint` to the filter.
+    #[inline]
+    pub fn insert_outpoint(&mut self, outpoint: OutPoint) {
+        self.insert(&encode::serialize(&outpoint));
+    }
+
+    /// Returns `true` if `data` may have been added to the filter.
+    pub fn contains(&self, data: &[u8]) -> bool {
+        (0..self.hash_funcs).all(|i| {
+            let bit = self.bit(i, data);
+            self.data[bit / 8] & (1 << (bit % 8)) != 0
+        })
+    }
+
+    /// Returns `true` if `outpoint` may have been added to the filter.
+    #[inline]
+    pub fn contains_outpoint(&self, outpoint: OutPoint) -> bool {
+        self.contains(&encode::serialize(&outpoint))
+    }
+
+    /// Returns `true` if `tx` matches the filter, following BIP37. Depending on the flags, the
+    /// outpoints of matched outputs are added to the filter.
+    pub fn matches(&mut self, tx: &Transaction) -> bool {
+        let txid = tx.compute_txid();
+        let mut matched = self.contains(txid.as_ref());
+
+        for (vout, output) in tx.output.iter().enumerate() {
+            if pushes(&output.script_pubkey).any(|data| self.contains(data)) {
+                matched = true;
+                let update = match self.flags {
+                    BloomFlags::None => false,
+                    BloomFlags::All => true,
+                    BloomFlags::PubkeyOnly => {
+                        output.script_pubkey.is_p2pk() || output.script_pubkey.is_multisig()
+                    }
+                };
+                if update {
+                    self.insert_outpoint(OutPoint::new(txid, vout as u32));
+                }
+            }
+        }
+        if matched {
+            return true;
+        }
+
+        tx.input.iter().any(|input| {
+            self.contains_outpoint(input.previous_output)
+                || pushes(&input.script_sig).any(|data| self.contains(data))
+        })
+    }
+
+    /// Returns the matching transactions in a `rawtx` or `rawblock` message. Other messages never
+    /// match.
+    pub fn process(&mut self, msg: &Message) -> Vec<Transaction> {
+        match msg {
+            Message::Tx(tx, _) => {
+                if self.matches(tx) {
+                    vec![tx.clone()]
+                } else {
+                    Vec::new()
+                }
+            }
+            Message::Block(block, _) => block
+                .txdata
+                .iter()
+                .filter(|tx| self.matches(tx))
+                .cloned()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Wraps an iterator of messages (for example the [`Receiver`] returned by
+    /// [`subscribe_receiver`]) to only yield the matching transactions, see
+    /// [`process`](BloomFilter::process).
+    ///
+    /// [`Receiver`]: std::sync::mpsc::Receiver
+    /// [`subscribe_receiver`]: crate::subscribe_receiver
+    #[inline]
+    pub fn filter_messages<I>(self, messages: I) -> BloomMatches<I::IntoIter>
+    where
+        I: IntoIterator<Item = Result<Message>>,
+    {
+        BloomMatches {
+            filter: self,
+            messages: messages.into_iter(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn bit(&self, i: u32, data: &[u8]) -> usize {
+        let seed = i.wrapping_mul(0xfba4_c795).wrapping_add(self.tweak);
+        murmur3(seed, data) as usize % (self.data.len() * 8)
+    }
+}
+
+impl Encodable for BloomFilter {
+    fn consensus_encode<W: Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> core::result::Result<usize, bitcoin::io::Error> {
+        Ok(self.data.consensus_encode(w)?
+            + self.hash_funcs.consensus_encode(w)?
+            + self.tweak.consensus_encode(w)?
+            + self.flags.to_u8().consensus_encode(w)?)
+    }
+}
+
+impl Decodable for BloomFilter {
+    fn consensus_decode<R: Read + ?Sized>(r: &mut R) -> core::result::Result<Self, encode::Error> {
+        let data = Vec::<u8>::consensus_decode(r)?;
+        if data.is_empty() || data.len() > MAX_FILTER_SIZE {
+            return Err(encode::Error::ParseFailed("invalid bloom filter size"));
+        }
+        let hash_funcs = u32::consensus_decode(r)?;
+        if hash_funcs > MAX_HASH_FUNCS {
+            return Err(encode::Error::ParseFailed(
+                "too many bloom filter hash functions",
+            ));
+        }
+        let tweak = u32::consensus_decode(r)?;
+        let flags = match u8::consensus_decode(r)? {
+            0 => BloomFlags::None,
+            1 => BloomFlags::All,
+            2 => BloomFlags::PubkeyOnly,
+            _ => return Err(encode::Error::ParseFailed("invalid bloom filter flags")),
+        };
+
+        Ok(Self {
+            data,
+            hash_funcs,
+            tweak,
+            flags,
+        })
+    }
+}
+
+/// Iterator returned by [`BloomFilter::filter_messages`].
+#[derive(Debug)]
+pub struct BloomMatches<I> {
+    filter: BloomFilter,
+    messages: I,
+    pending: VecDeque<Transaction>,
+}
+
+impl<I> Iterator for BloomMatches<I>
+where
+    I: Iterator<Item = Result<Message>>,
+{
+    type Item = Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(tx) = self.pending.pop_front() {
+                return Some(Ok(tx));
+            }
+
+            match self.messages.next()? {
+                Ok(msg) => self.pending.extend(self.filter.process(&msg)),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Returns the non-empty data pushes of `script`, parsing stops at the first invalid opcode.
+fn pushes(script: &Script) -> impl Iterator<Item = &[u8]> {
+    script
+        .instructions()
+        .map_while(|instruction| instruction.ok())
+        .filter_map(|instruction| match instruction {
+            Instruction::PushBytes(bytes) if !bytes.is_empty() => Some(bytes.as_bytes()),
+            _ => None,
+        })
+}
+
+/// MurmurHash3 (x86, 32-bit), as used by BIP37.
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut h = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h ^= k;
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (i, byte) in tail.iter().enumerate() {
+            k |= u32::from(*byte) << (8 * i);
+        }
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{murmur3, BloomFilter, BloomFlags, MAX_HASH_FUNCS};
+    use crate::Message;
+    use bitcoin::{
+        absolute::LockTime,
+        consensus::encode::{deserialize, serialize},
+        hashes::Hash,
+        hex::{DisplayHex, FromHex},
+        transaction::Version,
+        Amount, OutPoint, PubkeyHash, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+    };
+
+    #[test]
+    fn murmur3_vectors() {
+        // test vectors from Bitcoin Core's hash_tests.cpp
+        assert_eq!(murmur3(0, &[]), 0);
+        assert_eq!(murmur3(0xfba4_c795, &[]), 0x6a39_6f08);
+        assert_eq!(murmur3(0, &[0x00]), 0x514e_28b7);
+        assert_eq!(murmur3(0xfba4_c795, &[0x00]), 0xea3f_0b17);
+        assert_eq!(murmur3(0, &[0x00, 0x11, 0x22]), 0x8eb5_1c3d);
+        assert_eq!(murmur3(0, &[0x00, 0x11, 0x22, 0x33]), 0xb447_1bf8);
+        assert_eq!(murmur3(0, &[0x00, 0x11, 0x22, 0x33, 0x44]), 0xe230_1fa8);
+    }
+
+    #[test]
+    fn filter_serialization() {
+        // test vectors from Bitcoin Core's bloom_tests.cpp
+        let elements = [
+            "99108ad8ed9bb6274d3980bab5a85c048f0950c8",
+            "b5a2c786d9ef4658287ced5914b37a1b4aa32eee",
+            "b9300670b4c5366e95b2699e8b18bc75e5f729c5",
+        ]
+        .map(|hex| Vec::<u8>::from_hex(hex).unwrap());
+
+        for (tweak, expected) in [
+            (0, "03614e9b050000000000000001"),
+            (2_147_483_649, "03ce4299050000000100008001"),
+        ] {
+            let mut filter = BloomFilter::new(3, 0.01, tweak, BloomFlags::All);
+            assert!(!filter.contains(&elements[0]));
+            for element in &elements {
+                filter.insert(element);
+            }
+            assert!(elements.iter().all(|element| filter.contains(element)));
+            assert!(!filter.contains(
+                &Vec::<u8>::from_hex("19108ad8ed9bb6274d3980bab5a85c048f0950c8").unwrap()
+            ));
+
+            let bytes = serialize(&filter);
+            assert_eq!(bytes.to_lower_hex_string(), expected);
+            assert_eq!(deserialize::<BloomFilter>(&bytes).unwrap(), filter);
+        }
+
+        // an empty filter can not hash anything to a bit
+        assert_eq!(
+            BloomFilter::from_parts(Vec::new(), 1, 0, BloomFlags::None),
+            None
+        );
+        assert!(deserialize::<BloomFilter>(&[0, 1, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert_eq!(
+            BloomFilter::from_parts(vec![0; 1], MAX_HASH_FUNCS + 1, 0, BloomFlags::None),
+            None
+        );
+        let filter = BloomFilter::from_parts(vec![0; 1], 1, 0, BloomFlags::None).unwrap();
+        assert!(!filter.contains(b"data"));
+    }
+
+    #[test]
+    fn matches() {
+        let pkh = PubkeyHash::from_byte_array([7; 20]);
+        let watched = ScriptBuf::new_p2pkh(&pkh);
+        let tx = |input: OutPoint, script_pubkey: ScriptBuf| Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: input,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey,
+            }],
+        };
+
+        let payment = tx(
+            OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+            watched.clone(),
+        );
+        let spend = tx(
+            OutPoint::new(payment.compute_txid(), 0),
+            ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array([8; 20])),
+        );
+        let unrelated = tx(
+            OutPoint::new(Txid::from_byte_array([2; 32]), 0),
+            ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array([9; 20])),
+        );
+
+        let filter = BloomFilter::from_watch_set([watched.as_script()], [], 0.0001, 5);
+        let messages = [
+            Ok(Message::Tx(unrelated.clone(), 0)),
+            Ok(Message::Tx(payment.clone(), 1)),
+            // the spend matches because the payment's output was added to the filter
+            Ok(Message::Tx(spend.clone(), 2)),
+            Ok(Message::Tx(unrelated, 3)),
+        ];
+        let matched: Vec<_> = filter
+            .filter_messages(messages)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(matched, [payment, spend]);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::error::{Error, Result};
+use core::fmt;
+
+/// An optional feature of libzmq, see [`Capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// The CURVE security mechanism, requires libzmq to be built with libsodium.
+    Curve,
+    /// The `ipc://` transport.
+    Ipc,
+    /// The `ws://` and `wss://` transports.
+    Ws,
+    /// The draft API.
+    Draft,
+}
+
+impl Capability {
+    /// Returns the name of this capability as passed to `zmq_has`.
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Curve => "curve",
+            Self::Ipc => "ipc",
+            Self::Ws => "ws",
+            Self::Draft => "draft",
+        }
+    }
+
+    /// Returns the capability needed to connect to `endpoint`, based on its transport.
+    #[inline]
+    pub fn for_endpoint(endpoint: &str) -> Option<Self> {
+        let (transport, _) = endpoint.split_once("://")?;
+        match transport {
+            "ipc" => Some(Self::Ipc),
+            "ws" | "wss" => Some(Self::Ws),
+            _ => None,
+        }
+    }
+
+    /// Returns what libzmq needs to be built with to support this capability.
+    const fn requirement(self) -> &'static str {
+        match self {
+            Self::Curve => "libsodium",
+            Self::Ipc => "IPC support",
+            Self::Ws => "WebSocket support",
+            Self::Draft => "the draft API",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Curve => "CURVE",
+            Self::Ipc => "ipc transport",
+            Self::Ws => "ws transport",
+            Self::Draft => "draft API",
+        })
+    }
+}
+
+/// Error returned when a feature is used that the loaded libzmq does not support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingCapability(pub Capability);
+
+impl fmt::Display for MissingCapability {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requested but libzmq built without {}",
+            self.0,
+            self.0.requirement()
+        )
+    }
+}
+
+impl std::error::Error for MissingCapability {}
+
+/// The optional features and version of the libzmq this crate is linked to, see
+/// [`capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether the CURVE security mechanism is available.
+    pub curve: bool,
+    /// Whether the `ipc://` transport is available.
+    pub ipc: bool,
+    /// Whether the `ws://` transport is available.
+    pub ws: bool,
+    /// Whether the draft API is available.
+    pub draft: bool,
+    /// The version of libzmq as (major, minor, patch).
+    pub version: (i32, i32, i32),
+}
+
+impl Capabilities {
+    /// Returns whether `capability` is available.
+    #[inline]
+    pub const fn has(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Curve => self.curve,
+            Capability::Ipc => self.ipc,
+            Capability::Ws => self.ws,
+            Capability::Draft => self.draft,
+        }
+    }
+
+    /// Returns [`Error::MissingCapability`] if `capability` is not available.
+    #[inline]
+    pub const fn require(&self, capability: Capability) -> Result<()> {
+        if self.has(capability) {
+            Ok(())
+        } else {
+            Err(Error::MissingCapability(MissingCapability(capability)))
+        }
+    }
+
+    /// Returns [`Error::MissingCapability`] if the transport of `endpoint` is not available.
+    /// Endpoints with other transports, like `tcp://`, are not checked.
+    #[inline]
+    pub fn check_endpoint(&self, endpoint: &str) -> Result<()> {
+        match Capability::for_endpoint(endpoint) {
+            Some(capability) => self.require(capability),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Queries the optional features and version of the libzmq this crate is linked to, using
+/// [`zmq_has`](https://libzmq.readthedocs.io/en/latest/zmq_has.html) and
+/// [`zmq_version`](https://libzmq.readthedocs.io/en/latest/zmq_version.html).
+///
+/// Subscriptions check the transports of their endpoints with this before connecting, so an
+/// unsupported transport fails early with [`Error::MissingCapability`].
+#[inline]
+pub fn capabilities() -> Capabilities {
+    let has = |capability: Capability| zmq::has(capability.as_str()).unwrap_or(false);
+
+    Capabilities {
+        curve: has(Capability::Curve),
+        ipc: has(Capability::Ipc),
+        ws: has(Capability::Ws),
+        draft: has(Capability::Draft),
+        version: zmq::version(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{capabilities, Capabilities, Capability, MissingCapability};
+    use crate::Error;
+
+    #[test]
+    fn capabilities_check() {
+        let caps = capabilities();
+        assert!(caps.version >= (4, 0, 0));
+
+        let none = Capabilities {
+            curve: false,
+            ipc: false,
+            ws: false,
+            draft: false,
+            version: caps.version,
+        };
+        assert!(none.check_endpoint("tcp://127.0.0.1:28332").is_ok());
+        assert!(matches!(
+            none.check_endpoint("ipc:///tmp/zmq.sock"),
+            Err(Error::MissingCapability(MissingCapability(Capability::Ipc)))
+        ));
+        assert!(matches!(
+            none.check_endpoint("wss://example.com"),
+            Err(Error::MissingCapability(MissingCapability(Capability::Ws)))
+        ));
+        assert_eq!(
+            MissingCapability(Capability::Curve).to_string(),
+            "CURVE requested but libzmq built without libsodium"
+        );
+
+        let all = Capabilities {
+            curve: true,
+            ipc: true,
+            ws: true,
+            draft: true,
+            ..none
+        };
+        assert!(all.require(Capability::Curve).is_ok());
+        assert!(all.check_endpoint("ipc:///tmp/zmq.sock").is_ok());
+    }
+}
@@ -0,0 +1,471 @@
+//! A [`tokio_util::codec`] for sending messages over any byte stream, enabled with the `codec`
+//! feature.
+//!
+//! Wrap an `AsyncRead`/`AsyncWrite` transport (a TCP connection, a Unix socket, ...) in
+//! [`Framed`](tokio_util::codec::Framed) with a [`NotificationCodec`] to send and receive
+//! messages with the same types as the subscribers of this crate.
+//!
+//! Every message is encoded as its three ZMQ parts, with length prefixes:
+//!
+//! | field       | size               | contents                              |
+//! |-------------|--------------------|---------------------------------------|
+//! | topic len   | 1                  | length of the topic                   |
+//! | topic       | topic len          | the topic, like `rawblock`            |
+//! | data len    | 4                  | length of the data, little endian     |
+//! | data        | data len           | the data part, as published by Core   |
+//! | sequence    | 4                  | the sequence number, little endian    |
+//!
+//! Recorded sessions can also be read from a blocking reader with [`FrameReader`], which decodes
+//! blocks incrementally.
+
+use crate::{
+    block_reader::BlockReader,
+    error::{Error, Result},
+    message::{Message, DATA_MAX_LEN, SEQUENCE_LEN, TOPIC_MAX_LEN},
+    raw_message::{FromRawMessage, IntoRawMessage},
+};
+use bitcoin::{block::Header, Transaction};
+use bytes::{BufMut, BytesMut};
+use core::{cmp::min, fmt, marker::PhantomData, mem};
+use std::io::{self, Read, Take};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Encodes and decodes messages in the format described in the [module documentation](self).
+///
+/// Decodes `M`s, [`Message`]s by default, and encodes anything that implements
+/// [`IntoRawMessage`].
+pub struct NotificationCodec<M = Message> {
+    max_data_len: usize,
+    message_type: PhantomData<fn() -> M>,
+}
+
+impl NotificationCodec {
+    /// Creates a new [`NotificationCodec`] that decodes [`Message`]s.
+    #[inline]
+    pub const fn new() -> Self {
+        Self::with_message_type()
+    }
+}
+
+impl<M> NotificationCodec<M> {
+    /// Creates a new [`NotificationCodec`] that decodes `M`s.
+    #[inline]
+    pub const fn with_message_type() -> Self {
+        Self {
+            max_data_len: DATA_MAX_LEN,
+            message_type: PhantomData,
+        }
+    }
+
+    /// Sets the maximum length of the data part of messages. Longer messages are not decoded or
+    /// encoded, but return [`Error::MessageTooLarge`]. Defaults to [`DATA_MAX_LEN`].
+    #[inline]
+    pub const fn max_data_len(mut self, max_data_len: usize) -> Self {
+        self.max_data_len = max_data_len;
+        self
+    }
+}
+
+impl Default for NotificationCodec {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M> Clone for NotificationCodec<M> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self::with_message_type().max_data_len(self.max_data_len)
+    }
+}
+
+impl<M> fmt::Debug for NotificationCodec<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotificationCodec")
+            .field("max_data_len", &self.max_data_len)
+            .finish()
+    }
+}
+
+/// Returns [`Error::InvalidTopic`] for a topic of `len` bytes that is too long, of which
+/// `topic` is available.
+fn invalid_topic(len: usize, topic: &[u8]) -> Error {
+    let mut buf = [0; TOPIC_MAX_LEN];
+    let available = min(topic.len(), TOPIC_MAX_LEN);
+    buf[..available].copy_from_slice(&topic[..available]);
+    Error::InvalidTopic(len, buf)
+}
+
+impl<M: FromRawMessage> Decoder for NotificationCodec<M> {
+    type Item = M;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<M>> {
+        let Some(&topic_len) = src.first() else {
+            return Ok(None);
+        };
+        let topic_len = usize::from(topic_len);
+        if topic_len > TOPIC_MAX_LEN {
+            return Err(invalid_topic(
+                topic_len,
+                &src[1..min(src.len(), 1 + topic_len)],
+            ));
+        }
+
+        let data_len_at = 1 + topic_len;
+        let Some(data_len) = src.get(data_len_at..data_len_at + 4) else {
+            src.reserve(data_len_at + 4 - src.len());
+            return Ok(None);
+        };
+        let data_len = u32::from_le_bytes(data_len.try_into().unwrap()) as usize;
+        if data_len > self.max_data_len {
+            return Err(Error::MessageTooLarge(data_len, self.max_data_len));
+        }
+
+        let len = data_len_at + 4 + data_len + SEQUENCE_LEN;
+        if src.len() < len {
+            src.reserve(len - src.len());
+            return Ok(None);
+        }
+
+        let frame = src.split_to(len);
+        let topic = &frame[1..data_len_at];
+        let data = &frame[data_len_at + 4..len - SEQUENCE_LEN];
+        let sequence = frame[len - SEQUENCE_LEN..].try_into().unwrap();
+
+        M::from_raw_message(topic, data, sequence).map(Some)
+    }
+}
+
+impl<M, T: IntoRawMessage> Encoder<T> for NotificationCodec<M> {
+    type Error = Error;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<()> {
+        let [topic, data, sequence] = item.into_raw_message();
+
+        if topic.len() > TOPIC_MAX_LEN {
+            return Err(invalid_topic(topic.len(), &topic));
+        }
+        if data.len() > self.max_data_len {
+            return Err(Error::MessageTooLarge(data.len(), self.max_data_len));
+        }
+        if sequence.len() != SEQUENCE_LEN {
+            return Err(Error::InvalidSequenceLength(sequence.len()));
+        }
+
+        dst.reserve(1 + topic.len() + 4 + data.len() + SEQUENCE_LEN);
+        dst.put_u8(topic.len() as u8);
+        dst.put_slice(&topic);
+        dst.put_u32_le(data.len() as u32);
+        dst.put_slice(&data);
+        dst.put_slice(&sequence);
+
+        Ok(())
+    }
+}
+
+/// Reads messages in the format described in the [module documentation](self) from a blocking
+/// [`Read`]er, like a recorded session on disk, without buffering whole blocks.
+///
+/// `rawblock` frames are returned as a [`BlockFrame`] that decodes its transactions as it is
+/// iterated, all other frames as a [`Message`]. The reader is not buffered, wrap it in a
+/// [`BufReader`](std::io::BufReader) when it makes a syscall per read.
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    reader: R,
+    max_data_len: usize,
+    /// The number of bytes of the previous frame that were not read.
+    skip: u64,
+}
+
+/// A frame read by [`FrameReader::next_frame`].
+#[derive(Debug)]
+pub enum Frame<'a, R> {
+    /// A `rawblock` frame, decoded incrementally.
+    Block(BlockFrame<'a, R>),
+    /// Any other frame.
+    Message(Message),
+}
+
+/// A `rawblock` frame that is decoded incrementally, iterate it to decode its transactions.
+///
+/// The sequence number follows the block data, [`finish`](BlockFrame::finish) skips the
+/// transactions that were not read and returns it. A frame that is dropped without finishing it
+/// is skipped by the next call to [`FrameReader::next_frame`].
+pub struct BlockFrame<'a, R> {
+    block: BlockReader<Take<&'a mut R>>,
+    skip: &'a mut u64,
+    finished: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Creates a new [`FrameReader`] that reads frames from `reader`.
+    #[inline]
+    pub const fn new(reader: R) -> Self {
+        Self {
+            reader,
+            max_data_len: DATA_MAX_LEN,
+            skip: 0,
+        }
+    }
+
+    /// Sets the maximum length of the data part of messages. Longer messages are skipped and
+    /// return [`Error::MessageTooLarge`]. Defaults to [`DATA_MAX_LEN`].
+    #[inline]
+    pub const fn max_data_len(mut self, max_data_len: usize) -> Self {
+        self.max_data_len = max_data_len;
+        self
+    }
+
+    /// Reads the next frame, returns [`None`] at the end of the reader.
+    ///
+    /// Frames with a topic that is too long or data that is too large are skipped, the next call
+    /// reads the frame after them. After other errors, the position of the reader is unknown.
+    pub fn next_frame(&mut self) -> Result<Option<Frame<'_, R>>> {
+        if self.skip > 0 {
+            let skip = mem::take(&mut self.skip);
+            if io::copy(&mut (&mut self.reader).take(skip), &mut io::sink())? < skip {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+
+        let mut topic_len = [0];
+        if self.reader.read(&mut topic_len)? == 0 {
+            return Ok(None);
+        }
+        let topic_len = usize::from(topic_len[0]);
+        let mut topic = [0; u8::MAX as usize];
+        let topic = &mut topic[..topic_len];
+        self.reader.read_exact(topic)?;
+
+        let mut data_len = [0; 4];
+        self.reader.read_exact(&mut data_len)?;
+        let data_len = u32::from_le_bytes(data_len);
+
+        if topic_len > TOPIC_MAX_LEN || data_len as usize > self.max_data_len {
+            self.skip = u64::from(data_len) + SEQUENCE_LEN as u64;
+            return Err(if topic_len > TOPIC_MAX_LEN {
+                invalid_topic(topic_len, topic)
+            } else {
+                Error::MessageTooLarge(data_len as usize, self.max_data_len)
+            });
+        }
+
+        if topic == b"rawblock" {
+            let block = BlockReader::new((&mut self.reader).take(u64::from(data_len)))?;
+            return Ok(Some(Frame::Block(BlockFrame {
+                block,
+                skip: &mut self.skip,
+                finished: false,
+            })));
+        }
+
+        let mut data = vec![0; data_len as usize];
+        self.reader.read_exact(&mut data)?;
+        let mut sequence = [0; SEQUENCE_LEN];
+        self.reader.read_exact(&mut sequence)?;
+
+        Message::from_parts(topic, &data, sequence).map(|msg| Some(Frame::Message(msg)))
+    }
+
+    /// Returns the underlying reader.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> BlockFrame<'_, R> {
+    /// Returns the header of the block.
+    #[inline]
+    pub const fn header(&self) -> &Header {
+        self.block.header()
+    }
+
+    /// Returns the number of transactions in the block.
+    #[inline]
+    pub const fn tx_count(&self) -> u64 {
+        self.block.tx_count()
+    }
+
+    /// Returns the number of transactions that have not been read yet.
+    #[inline]
+    pub const fn remaining(&self) -> u64 {
+        self.block.remaining()
+    }
+
+    /// Skips the rest of the block data and returns the sequence number of the message.
+    pub fn finish(mut self) -> Result<u32> {
+        let data = self.block.get_mut();
+        io::copy(data, &mut io::sink())?;
+        if data.limit() > 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let mut sequence = [0; SEQUENCE_LEN];
+        data.get_mut().read_exact(&mut sequence)?;
+        self.finished = true;
+
+        Ok(u32::from_le_bytes(sequence))
+    }
+}
+
+impl<R: Read> Iterator for BlockFrame<'_, R> {
+    type Item = Result<Transaction>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.block.next_tx()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.block.size_hint()
+    }
+}
+
+impl<R> Drop for BlockFrame<'_, R> {
+    fn drop(&mut self) {
+        if !self.finished {
+            *self.skip = self.block.get_ref().limit() + SEQUENCE_LEN as u64;
+        }
+    }
+}
+
+impl<R> fmt::Debug for BlockFrame<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockFrame")
+            .field("block", &self.block)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Frame, FrameReader, NotificationCodec};
+    use crate::{Error, Message, RawMessage};
+    use bitcoin::{constants::genesis_block, Network};
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    #[test]
+    fn roundtrip() {
+        let block = genesis_block(Network::Bitcoin);
+        let msgs = [
+            Message::HashBlock(block.block_hash(), 0),
+            Message::Block(block.clone(), 1),
+            Message::Tx(block.txdata[0].clone(), 2),
+        ];
+
+        let mut codec = NotificationCodec::new();
+        let mut encoded = BytesMut::new();
+        for msg in &msgs {
+            codec.encode(msg, &mut encoded).unwrap();
+        }
+
+        // feed the bytes one at a time, like a slow transport
+        let mut src = BytesMut::new();
+        let mut decoded = Vec::new();
+        for byte in encoded.iter() {
+            src.extend_from_slice(&[*byte]);
+            if let Some(msg) = codec.decode(&mut src).unwrap() {
+                decoded.push(msg);
+            }
+        }
+        assert_eq!(decoded, msgs);
+        assert!(src.is_empty());
+
+        // other message types
+        let mut raw_codec = NotificationCodec::<RawMessage>::with_message_type();
+        let mut src = BytesMut::new();
+        raw_codec.encode(&msgs[0], &mut src).unwrap();
+        let raw = raw_codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(raw.topic, b"hashblock");
+    }
+
+    #[test]
+    fn limits() {
+        let block = genesis_block(Network::Bitcoin);
+        let mut codec = NotificationCodec::new().max_data_len(100);
+
+        let mut dst = BytesMut::new();
+        assert!(matches!(
+            codec.encode(Message::Block(block.clone(), 0), &mut dst),
+            Err(Error::MessageTooLarge(285, 100))
+        ));
+
+        NotificationCodec::new()
+            .encode(Message::Block(block, 0), &mut dst)
+            .unwrap();
+        assert!(matches!(
+            codec.decode(&mut dst),
+            Err(Error::MessageTooLarge(285, 100))
+        ));
+
+        let mut src = BytesMut::from(&[10u8, b'r', b'a', b'w'][..]);
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(Error::InvalidTopic(10, _))
+        ));
+    }
+
+    #[test]
+    fn frame_reader() {
+        let mut block = genesis_block(Network::Bitcoin);
+        block.txdata.push(block.txdata[0].clone());
+        let msgs = [
+            Message::HashBlock(block.block_hash(), 0),
+            Message::Block(block.clone(), 1),
+            Message::Tx(block.txdata[0].clone(), 2),
+            Message::Block(block.clone(), 3),
+            Message::Block(block.clone(), 4),
+        ];
+        let mut encoded = BytesMut::new();
+        for msg in &msgs {
+            NotificationCodec::new().encode(msg, &mut encoded).unwrap();
+        }
+
+        let mut reader = FrameReader::new(&encoded[..]);
+        let Some(Frame::Message(msg)) = reader.next_frame().unwrap() else {
+            panic!("expected a message");
+        };
+        assert_eq!(msg, msgs[0]);
+
+        // dropped after reading one transaction, the rest is skipped
+        let Some(Frame::Block(mut frame)) = reader.next_frame().unwrap() else {
+            panic!("expected a block");
+        };
+        assert_eq!(*frame.header(), block.header);
+        assert_eq!(frame.tx_count(), 2);
+        assert_eq!(frame.next().unwrap().unwrap(), block.txdata[0]);
+        drop(frame);
+
+        let Some(Frame::Message(msg)) = reader.next_frame().unwrap() else {
+            panic!("expected a message");
+        };
+        assert_eq!(msg, msgs[2]);
+
+        let Some(Frame::Block(frame)) = reader.next_frame().unwrap() else {
+            panic!("expected a block");
+        };
+        assert_eq!(frame.finish().unwrap(), 3);
+
+        let Some(Frame::Block(frame)) = reader.next_frame().unwrap() else {
+            panic!("expected a block");
+        };
+        let txs: Vec<_> = frame.map(Result::unwrap).collect();
+        assert_eq!(txs, block.txdata);
+
+        assert!(reader.next_frame().unwrap().is_none());
+
+        // too large frames are skipped
+        let mut reader = FrameReader::new(&encoded[..]).max_data_len(250);
+        assert!(matches!(reader.next_frame(), Ok(Some(Frame::Message(_)))));
+        assert!(matches!(
+            reader.next_frame(),
+            Err(Error::MessageTooLarge(_, 250))
+        ));
+        assert!(matches!(reader.next_frame(), Ok(Some(Frame::Message(_)))));
+    }
+}
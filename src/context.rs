@@ -0,0 +1,64 @@
+use crate::error::Result;
+use std::sync::{Mutex, MutexGuard};
+use zmq::Context;
+
+static GLOBAL_CONTEXT: Mutex<Option<Context>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Context>> {
+    // the mutex only guards an Option, it can not be left in an inconsistent state
+    GLOBAL_CONTEXT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Returns the process-wide ZMQ context, creating it if it does not exist yet.
+///
+/// Subscriptions created with [`SubscribeBuilder::global_context`] use this context, so they
+/// share I/O threads instead of each creating their own context.
+///
+/// [`SubscribeBuilder::global_context`]: crate::SubscribeBuilder::global_context
+#[inline]
+pub fn global_context() -> Context {
+    lock().get_or_insert_with(Context::new).clone()
+}
+
+/// Terminates the process-wide ZMQ context if it exists. A new one is created the next time it
+/// is used.
+///
+/// This blocks until all sockets that use the global context (for example the sockets of
+/// subscriptions created with [`SubscribeBuilder::global_context`]) are dropped, see
+/// [`zmq_ctx_term`](https://libzmq.readthedocs.io/en/latest/zmq_ctx_term.html).
+///
+/// [`SubscribeBuilder::global_context`]: crate::SubscribeBuilder::global_context
+#[inline]
+pub fn terminate_global_context() -> Result<()> {
+    // take the context out before terminating, so the lock is not held while blocking
+    let context = lock().take();
+    if let Some(mut context) = context {
+        context.destroy()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{global_context, terminate_global_context};
+
+    #[test]
+    fn global_context_lifecycle() {
+        let socket = global_context().socket(zmq::PUB).unwrap();
+        socket.bind("inproc://global_context_lifecycle").unwrap();
+
+        // the same context is returned, so the inproc endpoint is reachable
+        let sub = global_context().socket(zmq::SUB).unwrap();
+        sub.connect("inproc://global_context_lifecycle").unwrap();
+
+        drop((socket, sub));
+        terminate_global_context().unwrap();
+        terminate_global_context().unwrap();
+
+        // a new context is created after termination
+        global_context().socket(zmq::SUB).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{message::Message, topic::Topic};
+use std::collections::{HashSet, VecDeque};
+
+/// Identifies the logical event a [`Message`] is about, returned by [`Message::dedup_key`].
+///
+/// Two messages have the same key if they have the same topic and are about the same block or
+/// transaction. For `sequence` messages the kind of event (block connected, mempool removal, ...)
+/// is part of the key too. The sequence numbers of the ZMQ message and the mempool sequence
+/// number are not, as they differ per node, so the same event received from multiple endpoints
+/// or nodes, or received again after reconnecting, has the same key.
+///
+/// Note that a block that is connected again after a reorg has the same key as the first time it
+/// was connected, and so has a transaction that is accepted to the mempool again after it was
+/// removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DedupKey {
+    pub(crate) topic: Topic,
+    pub(crate) label: Option<u8>,
+    pub(crate) hash: [u8; 32],
+}
+
+impl DedupKey {
+    /// Returns the topic of the message this key was created from.
+    #[inline]
+    pub const fn topic(&self) -> Topic {
+        self.topic
+    }
+}
+
+/// Remembers the keys of the last messages to filter out duplicates, for example when
+/// subscribing to multiple endpoints of the same node or of nodes that relay the same blocks and
+/// transactions.
+///
+/// At most [`capacity`] keys are remembered, the oldest are forgotten first. A duplicate that
+/// arrives after that many other messages is not detected.
+///
+/// [`capacity`]: DedupWindow::capacity
+#[derive(Debug, Clone)]
+pub struct DedupWindow {
+    keys: HashSet<DedupKey>,
+    order: VecDeque<DedupKey>,
+    capacity: usize,
+}
+
+impl DedupWindow {
+    /// Default maximum number of remembered keys.
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+
+    /// Creates a new [`DedupWindow`] that remembers at most `capacity` keys.
+    #[inline]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            keys: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Returns the maximum number of remembered keys.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of remembered keys.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Returns `true` if no keys are remembered.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Returns `true` if `key` is remembered.
+    #[inline]
+    pub fn contains(&self, key: &DedupKey) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// Remembers `key` and returns `true` if it was not remembered yet, `false` if it is a
+    /// duplicate.
+    pub fn insert_key(&mut self, key: DedupKey) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        if !self.keys.insert(key) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        self.order.push_back(key);
+        true
+    }
+
+    /// Remembers the key of `msg` and returns `true` if `msg` is the first message with that key
+    /// in the window, `false` if it is a duplicate.
+    #[inline]
+    pub fn insert(&mut self, msg: &Message) -> bool {
+        self.insert_key(msg.dedup_key())
+    }
+
+    /// Forgets all keys.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.order.clear();
+    }
+}
+
+impl Default for DedupWindow {
+    #[inline]
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DedupWindow;
+    use crate::{Message, SequenceMessage, Topic};
+    use bitcoin::{constants::genesis_block, Network};
+
+    #[test]
+    fn dedup_key() {
+        let block = genesis_block(Network::Regtest);
+        let blockhash = block.block_hash();
+        let tx = block.txdata[0].clone();
+        let txid = tx.compute_txid();
+
+        // the ZMQ sequence number is ignored
+        assert_eq!(
+            Message::HashBlock(blockhash, 1).dedup_key(),
+            Message::HashBlock(blockhash, 2).dedup_key()
+        );
+        assert_eq!(
+            Message::Block(block.clone(), 1).dedup_key(),
+            Message::Block(block, 7).dedup_key()
+        );
+        assert_eq!(
+            Message::Tx(tx.clone(), 1).dedup_key(),
+            Message::Tx(tx, 9).dedup_key()
+        );
+
+        // the topic is not ignored
+        assert_ne!(
+            Message::HashBlock(blockhash, 1).dedup_key(),
+            Message::HashTx(txid, 1).dedup_key()
+        );
+        assert_eq!(Message::HashTx(txid, 1).dedup_key().topic(), Topic::HashTx);
+
+        // neither is the kind of sequence event, but the mempool sequence number is ignored
+        let seq = |sm, sequence| Message::Sequence(sm, sequence).dedup_key();
+        let connect = SequenceMessage::BlockConnect { blockhash };
+        let disconnect = SequenceMessage::BlockDisconnect { blockhash };
+        assert_eq!(seq(connect, 1), seq(connect, 2));
+        assert_ne!(seq(connect, 1), seq(disconnect, 1));
+
+        let acceptance = |mempool_sequence| SequenceMessage::MempoolAcceptance {
+            txid,
+            mempool_sequence,
+        };
+        let removal = SequenceMessage::MempoolRemoval {
+            txid,
+            mempool_sequence: 5,
+        };
+        assert_eq!(seq(acceptance(5), 1), seq(acceptance(5), 2));
+        assert_eq!(seq(acceptance(5), 1), seq(acceptance(6), 2));
+        assert_ne!(seq(acceptance(5), 1), seq(removal, 1));
+    }
+
+    #[test]
+    fn window() {
+        let block = genesis_block(Network::Regtest);
+        let msg = |i: u8| {
+            let mut block = block.clone();
+            block.header.nonce = i.into();
+            Message::HashBlock(block.block_hash(), i.into())
+        };
+
+        let mut window = DedupWindow::new(2);
+        assert!(window.is_empty());
+        assert!(window.insert(&msg(0)));
+        assert!(!window.insert(&msg(0)));
+        assert!(window.insert(&msg(1)));
+        assert!(window.contains(&msg(0).dedup_key()));
+
+        // the oldest key is forgotten
+        assert!(window.insert(&msg(2)));
+        assert_eq!(window.len(), 2);
+        assert!(!window.contains(&msg(0).dedup_key()));
+        assert!(!window.insert(&msg(1)));
+        assert!(window.insert(&msg(0)));
+
+        window.clear();
+        assert!(window.is_empty());
+        assert!(window.insert(&msg(1)));
+
+        // nothing is remembered
+        let mut window = DedupWindow::new(0);
+        assert!(window.insert(&msg(0)));
+        assert!(window.insert(&msg(0)));
+        assert!(window.is_empty());
+
+        assert_eq!(
+            DedupWindow::default().capacity(),
+            DedupWindow::DEFAULT_CAPACITY
+        );
+    }
+}
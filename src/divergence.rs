@@ -0,0 +1,268 @@
+use crate::{message::Message, sequence_message::SequenceMessage};
+use bitcoin::{BlockHash, Txid};
+use core::{hash::Hash, time::Duration};
+use std::{collections::HashMap, time::Instant};
+
+/// A divergence between nodes found by a [`DivergenceChecker`]. Nodes are identified by their
+/// index, as passed to [`DivergenceChecker::observe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// A block announced by some nodes was not announced by `missing` within the window.
+    MissingBlock {
+        blockhash: BlockHash,
+        missing: Vec<usize>,
+    },
+    /// A transaction accepted to the mempool of some nodes was not accepted by `missing` within
+    /// the window.
+    MissingTx { txid: Txid, missing: Vec<usize> },
+    /// The tips of the nodes (the last connected block, [`None`] if unknown) differed for longer
+    /// than the window. Reported once per mismatch.
+    TipMismatch { tips: Vec<Option<BlockHash>> },
+}
+
+/// Reported and complete sightings are kept this many windows longer, so a late announcement (or
+/// an announcement of the same block or transaction on another topic) does not look like a new
+/// divergence.
+const RETENTION_WINDOWS: u32 = 10;
+
+#[derive(Debug, Clone)]
+struct Sighting {
+    first_seen: Instant,
+    seen: Vec<bool>,
+    reported: bool,
+    /// Every node announced it.
+    complete: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Sightings<K> {
+    pending: HashMap<K, Sighting>,
+}
+
+impl<K: Copy + Eq + Hash> Sightings<K> {
+    fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+        }
+    }
+
+    fn observe(&mut self, key: K, node: usize, nodes: usize, now: Instant) {
+        let sighting = self.pending.entry(key).or_insert_with(|| Sighting {
+            first_seen: now,
+            seen: vec![false; nodes],
+            reported: false,
+            complete: false,
+        });
+        sighting.seen[node] = true;
+        sighting.complete = sighting.seen.iter().all(|seen| *seen);
+    }
+
+    fn expire(&mut self, window: Duration, now: Instant) -> Vec<(K, Vec<usize>)> {
+        let mut expired = Vec::new();
+        self.pending.retain(|key, sighting| {
+            let age = now.duration_since(sighting.first_seen);
+            if sighting.reported || sighting.complete {
+                return age < window * RETENTION_WINDOWS;
+            }
+            if age >= window {
+                let missing = (0..sighting.seen.len())
+                    .filter(|node| !sighting.seen[*node])
+                    .collect();
+                expired.push((*key, missing));
+                sighting.reported = true;
+            }
+            true
+        });
+
+        expired
+    }
+}
+
+/// Compares the messages of two or more nodes and reports [`Divergence`]s, to alert on nodes
+/// that are forked, stuck or otherwise unhealthy.
+///
+/// Blocks are taken from the `hashblock`, `rawblock` and `sequence` topics, mempool acceptances
+/// from the `hashtx` and `sequence` topics. Subscribe to the same topics on every node.
+#[derive(Debug, Clone)]
+pub struct DivergenceChecker {
+    nodes: usize,
+    window: Duration,
+    blocks: Sightings<BlockHash>,
+    txs: Sightings<Txid>,
+    tips: Vec<Option<BlockHash>>,
+    tip_mismatch_since: Option<Instant>,
+    tip_mismatch_reported: bool,
+}
+
+impl DivergenceChecker {
+    /// Creates a new [`DivergenceChecker`] for `nodes` nodes, that reports differences that
+    /// persist for longer than `window`.
+    #[inline]
+    pub fn new(nodes: usize, window: Duration) -> Self {
+        Self {
+            nodes,
+            window,
+            blocks: Sightings::new(),
+            txs: Sightings::new(),
+            tips: vec![None; nodes],
+            tip_mismatch_since: None,
+            tip_mismatch_reported: false,
+        }
+    }
+
+    /// Returns the number of nodes.
+    #[inline]
+    pub const fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    /// Processes a message received from `node` at `now`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is not less than the number of nodes.
+    pub fn observe(&mut self, node: usize, msg: &Message, now: Instant) {
+        assert!(node < self.nodes, "node index out of bounds");
+
+        match msg {
+            Message::HashBlock(blockhash, _)
+            | Message::Sequence(SequenceMessage::BlockConnect { blockhash }, _) => {
+                self.block_connected(node, *blockhash, now);
+            }
+            Message::Block(block, _) => self.block_connected(node, block.block_hash(), now),
+            Message::Sequence(SequenceMessage::BlockDisconnect { .. }, _) => {
+                self.tips[node] = None;
+            }
+            Message::HashTx(txid, _)
+            | Message::Sequence(SequenceMessage::MempoolAcceptance { txid, .. }, _) => {
+                self.txs.observe(*txid, node, self.nodes, now);
+            }
+            Message::Tx(..) | Message::Sequence(SequenceMessage::MempoolRemoval { .. }, _) => {}
+        }
+    }
+
+    fn block_connected(&mut self, node: usize, blockhash: BlockHash, now: Instant) {
+        self.tips[node] = Some(blockhash);
+        self.blocks.observe(blockhash, node, self.nodes, now);
+    }
+
+    /// Returns the divergences that exceeded the window at `now`. Call this periodically, and
+    /// after [`observe`](Self::observe).
+    pub fn poll(&mut self, now: Instant) -> Vec<Divergence> {
+        let mut divergences: Vec<Divergence> = self
+            .blocks
+            .expire(self.window, now)
+            .into_iter()
+            .map(|(blockhash, missing)| Divergence::MissingBlock { blockhash, missing })
+            .collect();
+        divergences.extend(
+            self.txs
+                .expire(self.window, now)
+                .into_iter()
+                .map(|(txid, missing)| Divergence::MissingTx { txid, missing }),
+        );
+
+        if self.tips.windows(2).all(|tips| tips[0] == tips[1]) {
+            self.tip_mismatch_since = None;
+            self.tip_mismatch_reported = false;
+        } else {
+            let since = *self.tip_mismatch_since.get_or_insert(now);
+            if !self.tip_mismatch_reported && now.duration_since(since) >= self.window {
+                self.tip_mismatch_reported = true;
+                divergences.push(Divergence::TipMismatch {
+                    tips: self.tips.clone(),
+                });
+            }
+        }
+
+        divergences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RETENTION_WINDOWS;
+    use crate::{Divergence, DivergenceChecker, Message, SequenceMessage};
+    use bitcoin::{constants::genesis_block, hashes::Hash, BlockHash, Network, Txid};
+    use core::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn divergence() {
+        let window = Duration::from_secs(10);
+        let start = Instant::now();
+        let blockhash = |i: u8| BlockHash::from_byte_array([i; 32]);
+        let txid = Txid::from_byte_array([9; 32]);
+
+        let mut checker = DivergenceChecker::new(3, window);
+        for node in 0..3 {
+            checker.observe(node, &Message::HashBlock(blockhash(1), 0), start);
+        }
+        checker.observe(0, &Message::HashBlock(blockhash(2), 1), start);
+        checker.observe(1, &Message::HashBlock(blockhash(2), 1), start);
+        checker.observe(2, &Message::HashTx(txid, 1), start);
+        assert_eq!(checker.poll(start), []);
+
+        let later = start + window;
+        assert_eq!(
+            checker.poll(later),
+            [
+                Divergence::MissingBlock {
+                    blockhash: blockhash(2),
+                    missing: vec![2]
+                },
+                Divergence::MissingTx {
+                    txid,
+                    missing: vec![0, 1]
+                },
+                Divergence::TipMismatch {
+                    tips: vec![Some(blockhash(2)), Some(blockhash(2)), Some(blockhash(1))]
+                },
+            ]
+        );
+        // reported once
+        assert_eq!(checker.poll(later + window * 2), []);
+
+        checker.observe(2, &Message::HashBlock(blockhash(2), 2), later + window * 2);
+        assert_eq!(checker.poll(later + window * 3), []);
+    }
+
+    #[test]
+    fn multiple_topics() {
+        let window = Duration::from_secs(10);
+        let start = Instant::now();
+        let block = genesis_block(Network::Regtest);
+        let blockhash = block.block_hash();
+        let txid = block.txdata[0].compute_txid();
+
+        let mut checker = DivergenceChecker::new(2, window);
+        checker.observe(0, &Message::HashBlock(blockhash, 0), start);
+        checker.observe(0, &Message::Block(block.clone(), 0), start);
+        checker.observe(1, &Message::HashBlock(blockhash, 0), start);
+        // the block was announced by every node, announcing it again on another topic does not
+        // start a new sighting
+        checker.observe(1, &Message::Block(block, 0), start);
+        checker.observe(
+            1,
+            &Message::Sequence(SequenceMessage::BlockConnect { blockhash }, 0),
+            start,
+        );
+
+        checker.observe(0, &Message::HashTx(txid, 1), start);
+        checker.observe(1, &Message::HashTx(txid, 1), start);
+        checker.observe(
+            0,
+            &Message::Sequence(
+                SequenceMessage::MempoolAcceptance {
+                    txid,
+                    mempool_sequence: 1,
+                },
+                1,
+            ),
+            start,
+        );
+
+        assert_eq!(checker.poll(start + window), []);
+        assert_eq!(checker.poll(start + window * RETENTION_WINDOWS), []);
+    }
+}
@@ -0,0 +1,304 @@
+use core::{fmt, str::FromStr};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+/// The host of a `tcp://` [`Endpoint`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Host {
+    /// An IPv4 or IPv6 address.
+    Ip(IpAddr),
+    /// A hostname, stored in lowercase.
+    Name(String),
+}
+
+impl fmt::Display for Host {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(IpAddr::V6(ip)) => write!(f, "[{ip}]"),
+            Self::Ip(ip) => write!(f, "{ip}"),
+            Self::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+/// A ZMQ endpoint, like `tcp://127.0.0.1:28332` or `ipc:///run/bitcoind/zmq.sock`.
+///
+/// `tcp://` endpoints are parsed into their host and port, so endpoints that are written
+/// differently but refer to the same address compare equal: IP addresses are compared by value
+/// (`[::1]` equals `[0:0:0:0:0:0:0:1]`) and hostnames case insensitively. Hostnames are not
+/// resolved. Endpoints with other transports, and `tcp://` endpoints with a source address or a
+/// scoped IPv6 address (like `tcp://[fe80::1%eth0]:28332`), are kept as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// A `tcp://host:port` endpoint.
+    Tcp { host: Host, port: u16 },
+    /// An `ipc://` endpoint, with its path.
+    Ipc(String),
+    /// An `inproc://` endpoint, with its name.
+    Inproc(String),
+    /// An endpoint with another transport, or one that could not be parsed (see
+    /// [`parse_lossy`](Endpoint::parse_lossy)).
+    Other(String),
+}
+
+/// Error returned when parsing an [`Endpoint`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointError {
+    /// The endpoint does not start with a transport like `tcp://`.
+    MissingTransport,
+    /// The host of a `tcp://` endpoint is empty.
+    MissingHost,
+    /// A `tcp://` endpoint has no port.
+    MissingPort,
+    /// The port of a `tcp://` endpoint is not a number between 0 and 65535.
+    InvalidPort(String),
+    /// An IPv6 address is not enclosed in brackets correctly, like `tcp://[::1:28332`.
+    InvalidBrackets,
+    /// The hostname of a `tcp://` endpoint could not be resolved to an address.
+    UnresolvableHost(String),
+}
+
+impl fmt::Display for EndpointError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingTransport => write!(f, "missing transport (like tcp://)"),
+            Self::MissingHost => write!(f, "missing host"),
+            Self::MissingPort => write!(f, "missing port"),
+            Self::InvalidPort(port) => write!(f, "invalid port '{port}'"),
+            Self::InvalidBrackets => write!(f, "invalid brackets around IPv6 address"),
+            Self::UnresolvableHost(host) => write!(f, "unable to resolve host '{host}'"),
+        }
+    }
+}
+
+impl std::error::Error for EndpointError {}
+
+impl Endpoint {
+    /// Parses an endpoint reported by libzmq, for example in a [`MonitorMessage`]. Never fails,
+    /// endpoints that are not valid UTF-8 or can not be parsed become [`Endpoint::Other`].
+    ///
+    /// [`MonitorMessage`]: crate::MonitorMessage
+    #[inline]
+    pub fn parse_lossy(endpoint: &[u8]) -> Self {
+        let endpoint = String::from_utf8_lossy(endpoint);
+        endpoint
+            .parse()
+            .unwrap_or_else(|_| Self::Other(endpoint.into_owned()))
+    }
+
+    /// Resolves the host of a `tcp://` endpoint to the addresses it refers to, failing with
+    /// [`EndpointError::UnresolvableHost`] if a hostname has no addresses. Blocks while looking
+    /// up hostnames. Returns no addresses for endpoints with other transports.
+    ///
+    /// libzmq only resolves hostnames when it connects, in its I/O thread, so a hostname that
+    /// can not be resolved otherwise only shows up as connection retries on monitored streams.
+    pub fn resolve(&self) -> Result<Vec<SocketAddr>, EndpointError> {
+        match self {
+            Self::Tcp {
+                host: Host::Ip(ip),
+                port,
+            } => Ok(vec![SocketAddr::new(*ip, *port)]),
+            Self::Tcp {
+                host: Host::Name(name),
+                port,
+            } => match (name.as_str(), *port).to_socket_addrs() {
+                Ok(addrs) if addrs.len() != 0 => Ok(addrs.collect()),
+                _ => Err(EndpointError::UnresolvableHost(name.clone())),
+            },
+            Self::Ipc(_) | Self::Inproc(_) | Self::Other(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns `true` if `endpoint` parses to an endpoint equal to this one. Endpoints that can
+    /// not be parsed are compared as strings.
+    #[inline]
+    pub fn matches(&self, endpoint: &str) -> bool {
+        match endpoint.parse::<Self>() {
+            Ok(endpoint) => *self == endpoint,
+            Err(_) => matches!(self, Self::Other(other) if other == endpoint),
+        }
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = EndpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (transport, address) = s.split_once("://").ok_or(EndpointError::MissingTransport)?;
+
+        Ok(match transport {
+            // source addresses (`tcp://source;host:port`) and scoped IPv6 addresses
+            // (`tcp://[fe80::1%eth0]:port`) are uncommon, keep them as they are
+            "tcp" if !address.contains([';', '%']) => {
+                let (host, port) = parse_host_port(address)?;
+                Self::Tcp { host, port }
+            }
+            "ipc" => Self::Ipc(address.to_owned()),
+            "inproc" => Self::Inproc(address.to_owned()),
+            _ => Self::Other(s.to_owned()),
+        })
+    }
+}
+
+fn parse_host_port(address: &str) -> Result<(Host, u16), EndpointError> {
+    let (host, port) = if let Some(rest) = address.strip_prefix('[') {
+        let (ip, rest) = rest.split_once(']').ok_or(EndpointError::InvalidBrackets)?;
+        let ip: Ipv6Addr = ip.parse().map_err(|_| EndpointError::InvalidBrackets)?;
+        let port = match rest.strip_prefix(':') {
+            Some(port) => port,
+            None if rest.is_empty() => return Err(EndpointError::MissingPort),
+            None => return Err(EndpointError::InvalidBrackets),
+        };
+        (Host::Ip(IpAddr::V6(ip)), port)
+    } else {
+        let (host, port) = address.rsplit_once(':').ok_or(EndpointError::MissingPort)?;
+        if host.contains(':') || host.contains(['[', ']']) {
+            // an IPv6 address without brackets, the port can not be told apart
+            return Err(EndpointError::InvalidBrackets);
+        }
+        let host = match host.parse() {
+            Ok(ip) => Host::Ip(ip),
+            Err(_) if host.is_empty() => return Err(EndpointError::MissingHost),
+            Err(_) => Host::Name(host.to_ascii_lowercase()),
+        };
+        (host, port)
+    };
+
+    if port.is_empty() {
+        return Err(EndpointError::MissingPort);
+    }
+    let port = port
+        .parse()
+        .map_err(|_| EndpointError::InvalidPort(port.to_owned()))?;
+
+    Ok((host, port))
+}
+
+impl fmt::Display for Endpoint {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp { host, port } => write!(f, "tcp://{host}:{port}"),
+            Self::Ipc(path) => write!(f, "ipc://{path}"),
+            Self::Inproc(name) => write!(f, "inproc://{name}"),
+            Self::Other(endpoint) => f.write_str(endpoint),
+        }
+    }
+}
+
+impl PartialEq<str> for Endpoint {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.matches(other)
+    }
+}
+
+impl PartialEq<&str> for Endpoint {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self.matches(other)
+    }
+}
+
+impl PartialEq<String> for Endpoint {
+    #[inline]
+    fn eq(&self, other: &String) -> bool {
+        self.matches(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Endpoint, EndpointError, Host};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn parse_endpoint() {
+        assert_eq!(
+            "tcp://127.0.0.1:28332".parse(),
+            Ok(Endpoint::Tcp {
+                host: Host::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                port: 28332,
+            })
+        );
+        assert_eq!(
+            "tcp://Node.Example:28332".parse(),
+            Ok(Endpoint::Tcp {
+                host: Host::Name("node.example".to_owned()),
+                port: 28332,
+            })
+        );
+        assert_eq!(
+            "ipc:///tmp/zmq.sock".parse(),
+            Ok(Endpoint::Ipc("/tmp/zmq.sock".to_owned()))
+        );
+        assert_eq!(
+            "ws://example.com/zmq".parse(),
+            Ok(Endpoint::Other("ws://example.com/zmq".to_owned()))
+        );
+        assert_eq!(
+            "tcp://[fe80::1%eth0]:28332".parse(),
+            Ok(Endpoint::Other("tcp://[fe80::1%eth0]:28332".to_owned()))
+        );
+
+        for (endpoint, err) in [
+            ("127.0.0.1:28332", EndpointError::MissingTransport),
+            ("tcp://127.0.0.1", EndpointError::MissingPort),
+            ("tcp://127.0.0.1:", EndpointError::MissingPort),
+            ("tcp://:28332", EndpointError::MissingHost),
+            (
+                "tcp://127.0.0.1:port",
+                EndpointError::InvalidPort("port".to_owned()),
+            ),
+            ("tcp://[::1:28332", EndpointError::InvalidBrackets),
+            ("tcp://::1:28332", EndpointError::InvalidBrackets),
+            ("tcp://[::1]", EndpointError::MissingPort),
+        ] {
+            assert_eq!(endpoint.parse::<Endpoint>(), Err(err), "{endpoint}");
+        }
+    }
+
+    #[test]
+    fn resolve_endpoint() {
+        let endpoint: Endpoint = "tcp://[2001:db8::1]:28332".parse().unwrap();
+        assert_eq!(
+            endpoint.resolve().unwrap(),
+            ["[2001:db8::1]:28332".parse().unwrap()]
+        );
+
+        let endpoint: Endpoint = "tcp://localhost:28332".parse().unwrap();
+        assert!(endpoint
+            .resolve()
+            .unwrap()
+            .iter()
+            .all(|addr| addr.ip().is_loopback() && addr.port() == 28332));
+
+        // the .invalid TLD never resolves (RFC 2606)
+        let endpoint: Endpoint = "tcp://node.invalid:28332".parse().unwrap();
+        assert_eq!(
+            endpoint.resolve(),
+            Err(EndpointError::UnresolvableHost("node.invalid".to_owned()))
+        );
+
+        let endpoint: Endpoint = "ipc:///tmp/zmq.sock".parse().unwrap();
+        assert_eq!(endpoint.resolve(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn normalized_equality() {
+        let endpoint: Endpoint = "tcp://[::1]:28332".parse().unwrap();
+        assert_eq!(endpoint, "tcp://[0:0:0:0:0:0:0:1]:28332");
+        assert_ne!(endpoint, "tcp://[::1]:28333");
+        assert_eq!(endpoint, "tcp://[::1]:28332".to_owned());
+        assert_eq!(endpoint.to_string(), "tcp://[::1]:28332");
+        assert_eq!(
+            Endpoint::parse_lossy(b"tcp://LOCALHOST:1"),
+            "tcp://localhost:1"
+        );
+        assert_eq!(
+            Endpoint::parse_lossy(b"tcp://\xff"),
+            Endpoint::Other("tcp://\u{fffd}".to_owned())
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use crate::{
-    message::{DATA_MAX_LEN, SEQUENCE_LEN, TOPIC_MAX_LEN},
+    capabilities::MissingCapability,
+    endpoint::{Endpoint, EndpointError},
+    message::{SEQUENCE_LEN, TOPIC_MAX_LEN},
     monitor::MonitorMessageError,
+    topic::Topic,
 };
 use bitcoin::consensus;
 use core::{cmp::min, fmt};
@@ -8,17 +11,37 @@ use core::{cmp::min, fmt};
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     InvalidMutlipartLength(usize),
     InvalidTopic(usize, [u8; TOPIC_MAX_LEN]),
+    #[deprecated(
+        since = "2.0.0",
+        note = "Never returned. Data parts over the configured limit are reported as Error::MessageTooLarge."
+    )]
     InvalidDataLength(usize),
+    /// The data part of a message (length, limit) exceeds the limit set with
+    /// [`SubscribeBuilder::max_data_len`] or by a codec. Parts larger than
+    /// [`SubscribeBuilder::max_msg_size`] never reach the subscriber, libzmq drops the connection
+    /// instead, so they are not reported as this error.
+    ///
+    /// [`SubscribeBuilder::max_data_len`]: crate::SubscribeBuilder::max_data_len
+    /// [`SubscribeBuilder::max_msg_size`]: crate::SubscribeBuilder::max_msg_size
+    MessageTooLarge(usize, usize),
     InvalidSequenceLength(usize),
     InvalidSequenceMessageLength(usize),
     InvalidSequenceMessageLabel(u8),
     Invalid256BitHashLength(usize),
     BitcoinDeserialization(consensus::encode::Error),
+    Deserialization(DeserializationError),
     Zmq(zmq::Error),
     MonitorMessage(MonitorMessageError),
+    Io(std::io::Error),
+    MissingCapability(MissingCapability),
+    InvalidEndpoint(String, EndpointError),
+    UnsupportedOption(&'static str),
+    #[cfg(feature = "rpc")]
+    Rpc(bitcoincore_rpc::Error),
 }
 
 impl Error {
@@ -35,6 +58,55 @@ impl Error {
     }
 }
 
+/// Error returned when the data of a `rawblock` or `rawtx` message could not be deserialized,
+/// with the context needed to diagnose the failure from logs.
+#[derive(Debug)]
+pub struct DeserializationError {
+    /// The topic of the message.
+    pub topic: Topic,
+    /// The length of the data part of the message in bytes.
+    pub payload_len: usize,
+    /// The sequence number of the message.
+    pub sequence: u32,
+    /// The number of bytes the consensus decoder consumed before it failed.
+    pub offset: usize,
+    /// The error returned by the consensus decoder.
+    pub source: consensus::encode::Error,
+    /// The endpoint the message was received from. libzmq does not tell which endpoint sent a
+    /// message, so this is only known when subscribed to a single endpoint, and [`None`] for
+    /// messages that were not received by a subscriber.
+    pub endpoint: Option<Endpoint>,
+}
+
+impl fmt::Display for DeserializationError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to deserialize {} message (sequence={}, {} bytes",
+            self.topic, self.sequence, self.payload_len
+        )?;
+        if let Some(endpoint) = &self.endpoint {
+            write!(f, " from {endpoint}")?;
+        }
+        write!(f, ") at offset {}: {}", self.offset, self.source)
+    }
+}
+
+impl std::error::Error for DeserializationError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<DeserializationError> for Error {
+    #[inline]
+    fn from(value: DeserializationError) -> Self {
+        Self::Deserialization(value)
+    }
+}
+
 impl From<zmq::Error> for Error {
     #[inline]
     fn from(value: zmq::Error) -> Self {
@@ -73,6 +145,28 @@ impl From<consensus::encode::Error> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    #[inline]
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<MissingCapability> for Error {
+    #[inline]
+    fn from(value: MissingCapability) -> Self {
+        Self::MissingCapability(value)
+    }
+}
+
+#[cfg(feature = "rpc")]
+impl From<bitcoincore_rpc::Error> for Error {
+    #[inline]
+    fn from(value: bitcoincore_rpc::Error) -> Self {
+        Self::Rpc(value)
+    }
+}
+
 impl From<MonitorMessageError> for Error {
     #[inline]
     fn from(value: MonitorMessageError) -> Self {
@@ -99,8 +193,10 @@ impl fmt::Display for Error {
                     }
                 )
             }
-            Self::InvalidDataLength(len) => {
-                write!(f, "data too long ({len} > {DATA_MAX_LEN})")
+            #[allow(deprecated)]
+            Self::InvalidDataLength(len) => write!(f, "data too long ({len} bytes)"),
+            Self::MessageTooLarge(len, max) => {
+                write!(f, "message too large: {len} bytes (limit is {max} bytes)")
             }
             Self::InvalidSequenceLength(len) => {
                 write!(
@@ -125,26 +221,44 @@ impl fmt::Display for Error {
             Self::BitcoinDeserialization(e) => {
                 write!(f, "bitcoin consensus deserialization error: {e}")
             }
+            Self::Deserialization(e) => write!(f, "{e}"),
             Self::Zmq(e) => write!(f, "ZMQ Error: {e}"),
             Self::MonitorMessage(err) => write!(f, "unable to parse monitor message: {err}"),
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::MissingCapability(e) => write!(f, "{e}"),
+            Self::InvalidEndpoint(endpoint, e) => write!(f, "invalid endpoint '{endpoint}': {e}"),
+            Self::UnsupportedOption(option) => {
+                write!(f, "option {option} has no effect on this subscriber")
+            }
+            #[cfg(feature = "rpc")]
+            Self::Rpc(e) => write!(f, "RPC error: {e}"),
         }
     }
 }
 
 impl std::error::Error for Error {
     #[inline]
+    #[allow(deprecated)]
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         Some(match self {
             Self::BitcoinDeserialization(e) => e,
+            Self::Deserialization(e) => e,
             Self::Zmq(e) => e,
             Self::MonitorMessage(e) => e,
+            Self::Io(e) => e,
+            Self::MissingCapability(e) => e,
+            Self::InvalidEndpoint(_, e) => e,
+            #[cfg(feature = "rpc")]
+            Self::Rpc(e) => e,
             Self::InvalidMutlipartLength(_)
             | Self::InvalidTopic(_, _)
             | Self::InvalidDataLength(_)
+            | Self::MessageTooLarge(_, _)
             | Self::InvalidSequenceLength(_)
             | Self::InvalidSequenceMessageLength(_)
             | Self::InvalidSequenceMessageLabel(_)
-            | Self::Invalid256BitHashLength(_) => return None,
+            | Self::Invalid256BitHashLength(_)
+            | Self::UnsupportedOption(_) => return None,
         })
     }
 }
@@ -0,0 +1,206 @@
+use crate::{message::Message, sequence_message::SequenceMessage};
+use bitcoin::{Amount, FeeRate, Transaction, Txid, Weight};
+use std::collections::HashMap;
+
+/// Default lower bounds of the buckets of a [`FeeHistogram`] in sat/vB.
+pub const DEFAULT_FEE_BUCKETS: [u64; 37] = [
+    1, 2, 3, 4, 5, 6, 8, 10, 12, 15, 20, 30, 40, 50, 60, 70, 80, 90, 100, 125, 150, 175, 200, 250,
+    300, 350, 400, 500, 600, 700, 800, 900, 1000, 1200, 1400, 1700, 2000,
+];
+
+/// Totals of the transactions in a bucket of a [`FeeHistogram`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeBucket {
+    /// Lowest feerate of transactions in this bucket. Transactions paying less than the lowest
+    /// bucket are counted in it as well.
+    pub min_feerate: FeeRate,
+    pub tx_count: usize,
+    pub weight: Weight,
+    pub fees: Amount,
+}
+
+/// Rolling histogram of the feerates of mempool transactions, updated incrementally from
+/// `rawtx`, `rawblock` and `sequence` messages.
+///
+/// `rawtx` messages do not contain fees, so a function that looks up the fee of a transaction
+/// (for example from a UTXO set or the `getmempoolentry` RPC) has to be supplied. Transactions
+/// without a known fee are not counted. Transactions are removed by [`MempoolRemoval`] messages
+/// and when they are included in a block (`rawblock` messages).
+///
+/// [`MempoolRemoval`]: SequenceMessage::MempoolRemoval
+#[derive(Debug, Clone)]
+pub struct FeeHistogram<F = fn(&Transaction) -> Option<Amount>> {
+    buckets: Vec<FeeBucket>,
+    txs: HashMap<Txid, (usize, Weight, Amount)>,
+    fee_fn: F,
+}
+
+impl<F> FeeHistogram<F>
+where
+    F: FnMut(&Transaction) -> Option<Amount>,
+{
+    /// Creates a new [`FeeHistogram`] with [`DEFAULT_FEE_BUCKETS`] that looks up fees with
+    /// `fee_fn`.
+    #[inline]
+    pub fn new(fee_fn: F) -> Self {
+        Self::with_buckets(&DEFAULT_FEE_BUCKETS, fee_fn)
+    }
+
+    /// Creates a new [`FeeHistogram`] with buckets starting at the given feerates in sat/vB. The
+    /// bounds are sorted and deduplicated, an empty list results in a single bucket.
+    pub fn with_buckets(sat_per_vb: &[u64], fee_fn: F) -> Self {
+        let mut bounds = sat_per_vb.to_vec();
+        bounds.sort_unstable();
+        bounds.dedup();
+        if bounds.is_empty() {
+            bounds.push(0);
+        }
+
+        Self {
+            buckets: bounds
+                .into_iter()
+                .map(|bound| FeeBucket {
+                    // 1 sat/vB is 250 sat/kwu
+                    min_feerate: FeeRate::from_sat_per_kwu(bound.saturating_mul(250)),
+                    tx_count: 0,
+                    weight: Weight::ZERO,
+                    fees: Amount::ZERO,
+                })
+                .collect(),
+            txs: HashMap::new(),
+            fee_fn,
+        }
+    }
+
+    /// Updates the histogram with a message. Returns `true` if the histogram changed.
+    pub fn process(&mut self, msg: &Message) -> bool {
+        match msg {
+            Message::Tx(tx, _) => match (self.fee_fn)(tx) {
+                Some(fee) => self.insert(tx.compute_txid(), tx.weight(), fee),
+                None => false,
+            },
+            Message::Sequence(SequenceMessage::MempoolRemoval { txid, .. }, _) => self.remove(txid),
+            Message::Block(block, _) => {
+                let mut changed = false;
+                for tx in &block.txdata {
+                    changed |= self.remove(&tx.compute_txid());
+                }
+                changed
+            }
+            Message::HashBlock(..) | Message::HashTx(..) | Message::Sequence(..) => false,
+        }
+    }
+
+    /// Adds a transaction. Returns `false` if it was already counted.
+    pub fn insert(&mut self, txid: Txid, weight: Weight, fee: Amount) -> bool {
+        if self.txs.contains_key(&txid) || weight == Weight::ZERO {
+            return false;
+        }
+
+        let index = self.bucket_index(fee / weight);
+        let bucket = &mut self.buckets[index];
+        bucket.tx_count += 1;
+        bucket.weight += weight;
+        bucket.fees += fee;
+        self.txs.insert(txid, (index, weight, fee));
+
+        true
+    }
+
+    /// Removes a transaction. Returns `false` if it was not counted.
+    pub fn remove(&mut self, txid: &Txid) -> bool {
+        let Some((index, weight, fee)) = self.txs.remove(txid) else {
+            return false;
+        };
+
+        let bucket = &mut self.buckets[index];
+        bucket.tx_count -= 1;
+        bucket.weight -= weight;
+        bucket.fees -= fee;
+
+        true
+    }
+
+    /// Returns the buckets, ordered from lowest to highest feerate.
+    #[inline]
+    pub fn buckets(&self) -> &[FeeBucket] {
+        &self.buckets
+    }
+
+    /// Returns the total weight of counted transactions.
+    #[inline]
+    pub fn total_weight(&self) -> Weight {
+        self.buckets.iter().map(|bucket| bucket.weight).sum()
+    }
+
+    /// Returns the weight of counted transactions that would be mined before a transaction paying
+    /// `feerate`, at the granularity of the buckets (transactions in the same bucket are not
+    /// counted).
+    pub fn weight_ahead(&self, feerate: FeeRate) -> Weight {
+        self.buckets[self.bucket_index(feerate) + 1..]
+            .iter()
+            .map(|bucket| bucket.weight)
+            .sum()
+    }
+
+    /// Returns the projected block (0 is the next block) a transaction paying `feerate` would be
+    /// included in if no new transactions arrive, assuming full blocks of the maximum weight.
+    #[inline]
+    pub fn projected_block(&self, feerate: FeeRate) -> u64 {
+        self.weight_ahead(feerate).to_wu() / Weight::MAX_BLOCK.to_wu()
+    }
+
+    fn bucket_index(&self, feerate: FeeRate) -> usize {
+        self.buckets
+            .partition_point(|bucket| bucket.min_feerate <= feerate)
+            .saturating_sub(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FeeHistogram, Message, SequenceMessage};
+    use bitcoin::{constants::genesis_block, hashes::Hash, Amount, FeeRate, Network, Txid, Weight};
+
+    #[test]
+    fn fee_histogram() {
+        let txid = |i: u8| Txid::from_byte_array([i; 32]);
+        let vb = |n: u64| Weight::from_vb_unchecked(n);
+        let sat_per_vb = |n: u64| FeeRate::from_sat_per_kwu(n * 250);
+
+        let mut histogram = FeeHistogram::with_buckets(&[10, 1, 5], |_| None);
+        assert!(histogram.insert(txid(1), vb(100), Amount::from_sat(100)));
+        assert!(histogram.insert(txid(2), vb(100), Amount::from_sat(600)));
+        assert!(histogram.insert(txid(3), vb(200), Amount::from_sat(4000)));
+        assert!(!histogram.insert(txid(3), vb(200), Amount::from_sat(4000)));
+        // below the lowest bucket
+        assert!(histogram.insert(txid(4), vb(100), Amount::ZERO));
+
+        let counts: Vec<_> = histogram.buckets().iter().map(|b| b.tx_count).collect();
+        assert_eq!(counts, [2, 1, 1]);
+        assert_eq!(histogram.buckets()[2].fees, Amount::from_sat(4000));
+        assert_eq!(histogram.total_weight(), vb(500));
+
+        assert_eq!(histogram.weight_ahead(sat_per_vb(2)), vb(300));
+        assert_eq!(histogram.weight_ahead(sat_per_vb(50)), Weight::ZERO);
+        assert_eq!(histogram.projected_block(sat_per_vb(1)), 0);
+
+        assert!(histogram.process(&Message::Sequence(
+            SequenceMessage::MempoolRemoval {
+                txid: txid(3),
+                mempool_sequence: 1
+            },
+            0
+        )));
+        assert!(!histogram.remove(&txid(3)));
+        assert_eq!(histogram.buckets()[2].weight, Weight::ZERO);
+
+        let block = genesis_block(Network::Bitcoin);
+        let tx = block.txdata[0].clone();
+        let mut histogram = FeeHistogram::new(|_| Some(Amount::from_sat(1000)));
+        assert!(histogram.process(&Message::Tx(tx.clone(), 0)));
+        assert_eq!(histogram.total_weight(), tx.weight());
+        assert!(histogram.process(&Message::Block(block, 0)));
+        assert_eq!(histogram.total_weight(), Weight::ZERO);
+    }
+}
@@ -0,0 +1,367 @@
+//! A C API for the subscriber, for services written in C, C++, Python (`ctypes`/`cffi`) or other
+//! languages that can call C functions. The declarations are in `include/bitcoincore_zmq.h`.
+//!
+//! The crate is only built as a Rust library by default, so dependents do not build a shared
+//! library they do not need. Build the shared library (cdylib) exporting these functions with
+//!
+//! ```sh
+//! cargo rustc --release --lib --features ffi --crate-type cdylib
+//! ```
+//!
+//! ```c
+//! const char *endpoints[] = {"tcp://127.0.0.1:28332"};
+//! bczmq_subscription *sub = bczmq_subscribe(endpoints, 1);
+//! if (!sub) {
+//!     fprintf(stderr, "%s\n", bczmq_last_error());
+//!     return 1;
+//! }
+//! bczmq_message *msg;
+//! while (bczmq_recv(sub, -1, &msg) == 1) {
+//!     printf("%s %u\n", bczmq_message_topic(msg), bczmq_message_sequence(msg));
+//!     bczmq_message_free(msg);
+//! }
+//! bczmq_subscription_free(sub);
+//! ```
+//!
+//! All functions returning an error code (`-1`) or a null pointer on failure set an error message
+//! that can be read with [`bczmq_last_error`]. Objects are owned by the caller and must be freed
+//! with the matching `_free` function. A subscription must not be used by multiple threads at
+//! once.
+
+use crate::{error::Error, message::Message, subscribe::subscription::Subscription};
+use core::{
+    cell::RefCell,
+    ffi::{c_char, c_int, c_void, CStr},
+    ptr, slice,
+    time::Duration,
+};
+use std::ffi::CString;
+
+/// A subscription, see [`bczmq_subscribe`].
+#[derive(Debug)]
+pub struct BczmqSubscription(Subscription);
+
+/// A received message, see [`bczmq_recv`].
+#[derive(Debug)]
+pub struct BczmqMessage {
+    msg: Message,
+    data: Vec<u8>,
+}
+
+/// Called by [`bczmq_run`] for every message. The message is only valid during the call. Return
+/// 0 to continue receiving, any other value to stop. Null is represented by [`None`].
+pub type BczmqCallback =
+    Option<extern "C" fn(msg: *const BczmqMessage, user_data: *mut c_void) -> c_int>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(err: impl ToString) {
+    // interior NUL bytes can not be represented, they never occur in practice
+    let err = CString::new(err.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(err));
+}
+
+/// Returns the message of the last error on the calling thread, or null if there was none. The
+/// string is valid until the next call of a `bczmq_` function on the same thread.
+#[no_mangle]
+pub extern "C" fn bczmq_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |err| err.as_ptr())
+    })
+}
+
+/// Subscribes to `len` ZMQ endpoints. Returns null on failure.
+///
+/// # Safety
+///
+/// `endpoints` must point to `len` pointers to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn bczmq_subscribe(
+    endpoints: *const *const c_char,
+    len: usize,
+) -> *mut BczmqSubscription {
+    let endpoints: &[*const c_char] = if len == 0 {
+        &[]
+    } else {
+        // SAFETY: guaranteed by the caller
+        unsafe { slice::from_raw_parts(endpoints, len) }
+    };
+    let endpoints = match endpoints
+        .iter()
+        // SAFETY: guaranteed by the caller
+        .map(|endpoint| unsafe { CStr::from_ptr(*endpoint) }.to_str())
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(endpoints) => endpoints,
+        Err(err) => {
+            set_last_error(format_args!("endpoint is not valid UTF-8: {err}"));
+            return ptr::null_mut();
+        }
+    };
+
+    match Subscription::new(&endpoints) {
+        Ok(subscription) => Box::into_raw(Box::new(BczmqSubscription(subscription))),
+        Err(err) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Closes and frees a subscription. Does nothing if `sub` is null.
+///
+/// # Safety
+///
+/// `sub` must be null or returned by [`bczmq_subscribe`], and not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn bczmq_subscription_free(sub: *mut BczmqSubscription) {
+    if !sub.is_null() {
+        // SAFETY: guaranteed by the caller
+        drop(unsafe { Box::from_raw(sub) });
+    }
+}
+
+/// Receives the next message, waiting at most `timeout_ms` milliseconds, or until a message is
+/// received if `timeout_ms` is negative. Returns 1 and stores the message in `*out` if one was
+/// received, 0 on timeout and -1 on failure. Messages that fail to parse are failures, the
+/// subscription can be used to receive the next message after one.
+///
+/// # Safety
+///
+/// `sub` must be returned by [`bczmq_subscribe`] and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bczmq_recv(
+    sub: *mut BczmqSubscription,
+    timeout_ms: i64,
+    out: *mut *mut BczmqMessage,
+) -> c_int {
+    // SAFETY: guaranteed by the caller
+    let sub = unsafe { &mut (*sub).0 };
+    let res = match u64::try_from(timeout_ms) {
+        Ok(timeout) => sub.recv_timeout(Duration::from_millis(timeout)),
+        Err(_) => sub.recv().map(Some),
+    };
+
+    match res {
+        Ok(Some(msg)) => {
+            let msg = Box::new(BczmqMessage::new(msg));
+            // SAFETY: guaranteed by the caller
+            unsafe { out.write(Box::into_raw(msg)) };
+            1
+        }
+        Ok(None) => 0,
+        Err(err) => fail(err),
+    }
+}
+
+/// Receives messages and calls `callback` with each of them and `user_data`, until `callback`
+/// returns a nonzero value. Returns 0 when stopped by the callback and -1 on failure, or if
+/// `callback` is null.
+///
+/// # Safety
+///
+/// `sub` must be returned by [`bczmq_subscribe`].
+#[no_mangle]
+pub unsafe extern "C" fn bczmq_run(
+    sub: *mut BczmqSubscription,
+    callback: BczmqCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let Some(callback) = callback else {
+        set_last_error("callback is null");
+        return -1;
+    };
+    // SAFETY: guaranteed by the caller
+    let sub = unsafe { &mut (*sub).0 };
+    loop {
+        match sub.recv() {
+            Ok(msg) => {
+                let msg = BczmqMessage::new(msg);
+                if callback(&msg, user_data) != 0 {
+                    return 0;
+                }
+            }
+            Err(err) => return fail(err),
+        }
+    }
+}
+
+fn fail(err: Error) -> c_int {
+    set_last_error(err);
+    -1
+}
+
+impl BczmqMessage {
+    fn new(msg: Message) -> Self {
+        let data = msg.serialize_data_to_vec();
+        Self { msg, data }
+    }
+
+    /// Returns the message this was created from.
+    #[inline]
+    pub const fn message(&self) -> &Message {
+        &self.msg
+    }
+}
+
+/// Frees a message. Does nothing if `msg` is null.
+///
+/// # Safety
+///
+/// `msg` must be null or returned by [`bczmq_recv`], and not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn bczmq_message_free(msg: *mut BczmqMessage) {
+    if !msg.is_null() {
+        // SAFETY: guaranteed by the caller
+        drop(unsafe { Box::from_raw(msg) });
+    }
+}
+
+/// Returns the topic of a message, like `"hashblock"`, as a static NUL-terminated string.
+///
+/// # Safety
+///
+/// `msg` must be a valid message.
+#[no_mangle]
+pub unsafe extern "C" fn bczmq_message_topic(msg: *const BczmqMessage) -> *const c_char {
+    // SAFETY: guaranteed by the caller
+    let msg = unsafe { &(*msg).msg };
+    let topic = match msg {
+        Message::HashBlock(..) => c"hashblock",
+        Message::HashTx(..) => c"hashtx",
+        Message::Block(..) => c"rawblock",
+        Message::Tx(..) => c"rawtx",
+        Message::Sequence(..) => c"sequence",
+    };
+    topic.as_ptr()
+}
+
+/// Returns the sequence number of a message (the last part of the ZMQ message, not the mempool
+/// sequence number of `sequence` messages).
+///
+/// # Safety
+///
+/// `msg` must be a valid message.
+#[no_mangle]
+pub unsafe extern "C" fn bczmq_message_sequence(msg: *const BczmqMessage) -> u32 {
+    // SAFETY: guaranteed by the caller
+    unsafe { (*msg).msg.sequence() }
+}
+
+/// Returns the data of a message as published by Bitcoin Core (a serialized block or
+/// transaction, or a hash in the byte order it is usually displayed in) and stores its length in
+/// `*len`. The data is valid until the message is freed.
+///
+/// # Safety
+///
+/// `msg` must be a valid message and `len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bczmq_message_data(
+    msg: *const BczmqMessage,
+    len: *mut usize,
+) -> *const u8 {
+    // SAFETY: guaranteed by the caller
+    let data = unsafe { &(*msg).data };
+    // SAFETY: guaranteed by the caller
+    unsafe { len.write(data.len()) };
+    data.as_ptr()
+}
+
+/// Writes the block hash or txid a message is about to `out`, in the byte order it is usually
+/// displayed in. For `rawblock` and `rawtx` messages it is computed from the data.
+///
+/// # Safety
+///
+/// `msg` must be a valid message and `out` must be valid for writes of 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bczmq_message_hash(msg: *const BczmqMessage, out: *mut [u8; 32]) {
+    use bitcoin::hashes::Hash;
+
+    // SAFETY: guaranteed by the caller
+    let msg = unsafe { &(*msg).msg };
+    let mut hash = match msg {
+        Message::HashBlock(blockhash, _) => blockhash.to_byte_array(),
+        Message::HashTx(txid, _) => txid.to_byte_array(),
+        Message::Block(block, _) => block.block_hash().to_byte_array(),
+        Message::Tx(tx, _) => tx.compute_txid().to_byte_array(),
+        Message::Sequence(sm, _) => sm.inner_hash_as_bytes(),
+    };
+    hash.reverse();
+    // SAFETY: guaranteed by the caller
+    unsafe { out.write(hash) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::publish_until;
+    use bitcoin::{constants::genesis_block, Network};
+
+    #[test]
+    fn subscribe_and_recv() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = CString::new(publisher.get_last_endpoint().unwrap().unwrap()).unwrap();
+
+        let sub = unsafe { bczmq_subscribe(&endpoint.as_ptr(), 1) };
+        assert!(!sub.is_null());
+
+        let mut msg = ptr::null_mut();
+        assert_eq!(unsafe { bczmq_recv(sub, 0, &mut msg) }, 0);
+
+        let tx = genesis_block(Network::Bitcoin).txdata[0].clone();
+        let ((), sequence) = publish_until(
+            &publisher,
+            |sequence| Message::Tx(tx.clone(), sequence),
+            || (unsafe { bczmq_recv(sub, 0, &mut msg) } != 0).then_some(()),
+        );
+
+        unsafe {
+            assert_eq!(CStr::from_ptr(bczmq_message_topic(msg)), c"rawtx");
+            assert!(bczmq_message_sequence(msg) < sequence);
+
+            let mut len = 0;
+            let data = bczmq_message_data(msg, &mut len);
+            assert_eq!(
+                slice::from_raw_parts(data, len),
+                bitcoin::consensus::serialize(&tx)
+            );
+
+            let mut hash = [0; 32];
+            bczmq_message_hash(msg, &mut hash);
+            assert_eq!(
+                bitcoin::hex::DisplayHex::to_lower_hex_string(&hash[..]),
+                tx.compute_txid().to_string()
+            );
+
+            bczmq_message_free(msg);
+            bczmq_subscription_free(sub);
+        }
+    }
+
+    #[test]
+    fn last_error() {
+        let endpoint = c"not an endpoint";
+        let sub = unsafe { bczmq_subscribe(&endpoint.as_ptr(), 1) };
+        assert!(sub.is_null());
+        assert!(!bczmq_last_error().is_null());
+    }
+
+    #[test]
+    fn run_without_callback() {
+        let endpoint = c"tcp://127.0.0.1:1";
+        let sub = unsafe { bczmq_subscribe(&endpoint.as_ptr(), 1) };
+        assert!(!sub.is_null());
+
+        unsafe {
+            assert_eq!(bczmq_run(sub, None, ptr::null_mut()), -1);
+            assert_eq!(CStr::from_ptr(bczmq_last_error()), c"callback is null");
+            bczmq_subscription_free(sub);
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use crate::message::Message;
+use bitcoin::{
+    block::Header,
+    consensus::{Decodable, Encodable},
+    constants::genesis_block,
+    params::Params,
+    pow::Work,
+    BlockHash, CompactTarget, Network,
+};
+use core::fmt;
+use std::{collections::HashMap, io};
+
+/// Side branch headers this many blocks below the tip are forgotten, along with the headers built
+/// on them.
+const MAX_SIDE_DEPTH: u32 = 144;
+
+/// A header with its position in the chain.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    header: Header,
+    height: u32,
+    chainwork: Work,
+}
+
+/// The result of adding a header to a [`HeaderChain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderUpdate {
+    /// The header extends the active chain.
+    Extended,
+    /// The header made a side branch the active chain. `disconnected` are the hashes of the
+    /// blocks that left the active chain, from the old tip down, `connected` the hashes of the
+    /// blocks that joined it, from the fork point up.
+    Reorg {
+        disconnected: Vec<BlockHash>,
+        connected: Vec<BlockHash>,
+    },
+    /// The header is valid, but on a side branch with less work than the active chain.
+    SideBranch,
+    /// The header was added before.
+    AlreadyKnown,
+}
+
+/// Error returned when a header can not be added to a [`HeaderChain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderChainError {
+    /// The previous block is unknown, headers must be added in order.
+    UnknownParent(BlockHash),
+    /// The header's hash does not meet its target, or its target is above the network's limit.
+    InvalidPow(BlockHash),
+    /// The header's target (bits) is not the one required by the difficulty rules.
+    UnexpectedBits {
+        expected: CompactTarget,
+        actual: CompactTarget,
+    },
+}
+
+impl fmt::Display for HeaderChainError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownParent(prev) => write!(f, "unknown previous block {prev}"),
+            Self::InvalidPow(hash) => write!(f, "invalid proof of work of block {hash}"),
+            Self::UnexpectedBits { expected, actual } => write!(
+                f,
+                "unexpected bits {:08x} (expected {:08x})",
+                actual.to_consensus(),
+                expected.to_consensus()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeaderChainError {}
+
+/// A validated chain of block headers, maintained from `rawblock` messages, for a minimal SPV
+/// backend.
+///
+/// Headers are checked for their proof of work, linkage to a known header and the difficulty
+/// rules of the network (retargeting, and the minimum difficulty rule of test networks). The
+/// difficulty is only checked when the headers it depends on are known, so a chain started from
+/// a checkpoint (see [`from_checkpoint`]) skips some checks until the next retarget.
+///
+/// The active chain is the one with the most work. Side branches are kept so they can become
+/// the active chain in a reorg.
+///
+/// [`from_checkpoint`]: HeaderChain::from_checkpoint
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    params: Params,
+    /// The active chain, the first entry is at `base_height`.
+    active: Vec<Entry>,
+    base_height: u32,
+    index: HashMap<BlockHash, u32>,
+    side: HashMap<BlockHash, Entry>,
+}
+
+impl HeaderChain {
+    /// Creates a chain for `network` that starts at its genesis block.
+    pub fn new(network: Network) -> Self {
+        let genesis = genesis_block(network).header;
+        Self::from_checkpoint(network, 0, genesis, genesis.work())
+    }
+
+    /// Creates a chain for `network` that starts at the trusted `header` at `height`, with
+    /// `chainwork` the total work of the chain up to and including it (`chainwork` in the
+    /// `getblockheader` RPC).
+    pub fn from_checkpoint(network: Network, height: u32, header: Header, chainwork: Work) -> Self {
+        let mut index = HashMap::new();
+        index.insert(header.block_hash(), height);
+
+        Self {
+            params: Params::new(network),
+            active: vec![Entry {
+                header,
+                height,
+                chainwork,
+            }],
+            base_height: height,
+            index,
+            side: HashMap::new(),
+        }
+    }
+
+    /// Returns the height of the tip of the active chain.
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.tip_entry().height
+    }
+
+    /// Returns the hash of the tip of the active chain.
+    #[inline]
+    pub fn tip(&self) -> BlockHash {
+        self.tip_entry().header.block_hash()
+    }
+
+    /// Returns the header of the tip of the active chain.
+    #[inline]
+    pub fn tip_header(&self) -> &Header {
+        &self.tip_entry().header
+    }
+
+    /// Returns the total work of the active chain.
+    #[inline]
+    pub fn chainwork(&self) -> Work {
+        self.tip_entry().chainwork
+    }
+
+    /// Returns the header at `height` in the active chain.
+    #[inline]
+    pub fn header_at(&self, height: u32) -> Option<&Header> {
+        let i = height.checked_sub(self.base_height)?;
+        Some(&self.active.get(i as usize)?.header)
+    }
+
+    /// Returns the height of the block with hash `blockhash` if it is in the active chain.
+    #[inline]
+    pub fn height_of(&self, blockhash: &BlockHash) -> Option<u32> {
+        self.index.get(blockhash).copied()
+    }
+
+    /// Adds the header of the block in a `rawblock` message. Returns [`None`] for other messages.
+    #[inline]
+    pub fn process(&mut self, msg: &Message) -> Option<Result<HeaderUpdate, HeaderChainError>> {
+        match msg {
+            Message::Block(block, _) => Some(self.insert(block.header)),
+            _ => None,
+        }
+    }
+
+    /// Validates and adds `header`.
+    pub fn insert(&mut self, header: Header) -> Result<HeaderUpdate, HeaderChainError> {
+        let hash = header.block_hash();
+        if self.index.contains_key(&hash) || self.side.contains_key(&hash) {
+            return Ok(HeaderUpdate::AlreadyKnown);
+        }
+
+        let parent = *self
+            .entry(&header.prev_blockhash)
+            .ok_or(HeaderChainError::UnknownParent(header.prev_blockhash))?;
+
+        if let Some(expected) = self.expected_bits(&parent, &header) {
+            if header.bits != expected {
+                return Err(HeaderChainError::UnexpectedBits {
+                    expected,
+                    actual: header.bits,
+                });
+            }
+        }
+        let target = header.target();
+        if target > self.params.max_attainable_target || header.validate_pow(target).is_err() {
+            return Err(HeaderChainError::InvalidPow(hash));
+        }
+
+        let entry = Entry {
+            header,
+            height: parent.height + 1,
+            chainwork: parent.chainwork + header.work(),
+        };
+
+        if header.prev_blockhash == self.tip() {
+            self.push_active(hash, entry);
+            return Ok(HeaderUpdate::Extended);
+        }
+
+        if entry.chainwork <= self.chainwork() {
+            self.side.insert(hash, entry);
+            return Ok(HeaderUpdate::SideBranch);
+        }
+
+        // the branch ending at the new header has more work, find where it forks off
+        let mut connected = vec![(hash, entry)];
+        let mut prev = header.prev_blockhash;
+        while !self.index.contains_key(&prev) {
+            // the parent is known and not in the active chain, so it is in a side branch
+            let side = self.side.remove(&prev).expect("branch is connected");
+            connected.push((prev, side));
+            prev = side.header.prev_blockhash;
+        }
+
+        let fork_height = self.index[&prev];
+        let mut disconnected = Vec::new();
+        while self.height() > fork_height {
+            let old = self
+                .active
+                .pop()
+                .expect("fork point is in the active chain");
+            let old_hash = old.header.block_hash();
+            self.index.remove(&old_hash);
+            self.side.insert(old_hash, old);
+            disconnected.push(old_hash);
+        }
+
+        let connected: Vec<_> = connected
+            .into_iter()
+            .rev()
+            .map(|(hash, entry)| {
+                self.push_active(hash, entry);
+                hash
+            })
+            .collect();
+
+        Ok(HeaderUpdate::Reorg {
+            disconnected,
+            connected,
+        })
+    }
+
+    /// Writes the active chain to `w`, to restore it later with [`read_from`].
+    ///
+    /// [`read_from`]: HeaderChain::read_from
+    pub fn write_to<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(&self.base_height.to_le_bytes())?;
+        w.write_all(&self.active[0].chainwork.to_le_bytes())?;
+        w.write_all(&(self.active.len() as u32).to_le_bytes())?;
+        for entry in &self.active {
+            let mut buf = Vec::with_capacity(80);
+            entry.header.consensus_encode(&mut buf)?;
+            w.write_all(&buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a chain written by [`write_to`]. The first header is trusted, the others are
+    /// validated again.
+    ///
+    /// [`write_to`]: HeaderChain::write_to
+    pub fn read_from<R: io::Read>(network: Network, mut r: R) -> io::Result<Self> {
+        let mut u32_buf = [0; 4];
+        r.read_exact(&mut u32_buf)?;
+        let base_height = u32::from_le_bytes(u32_buf);
+        let mut work_buf = [0; 32];
+        r.read_exact(&mut work_buf)?;
+        let chainwork = Work::from_le_bytes(work_buf);
+        r.read_exact(&mut u32_buf)?;
+        let len = u32::from_le_bytes(u32_buf);
+
+        let mut read_header = || {
+            let mut buf = [0; 80];
+            r.read_exact(&mut buf)?;
+            Header::consensus_decode(&mut &buf[..])
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "empty header chain",
+            ));
+        }
+        let mut chain = Self::from_checkpoint(network, base_height, read_header()?, chainwork);
+        for _ in 1..len {
+            chain
+                .insert(read_header()?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+
+        Ok(chain)
+    }
+
+    fn tip_entry(&self) -> &Entry {
+        // there is always at least the checkpoint
+        self.active.last().expect("chain is not empty")
+    }
+
+    fn entry(&self, hash: &BlockHash) -> Option<&Entry> {
+        match self.index.get(hash) {
+            Some(height) => self.active.get((height - self.base_height) as usize),
+            None => self.side.get(hash),
+        }
+    }
+
+    fn push_active(&mut self, hash: BlockHash, entry: Entry) {
+        self.index.insert(hash, entry.height);
+        self.active.push(entry);
+
+        let height = entry.height;
+        if !self
+            .side
+            .values()
+            .any(|side| side.height + MAX_SIDE_DEPTH < height)
+        {
+            return;
+        }
+
+        // forget whole branches, a header whose parent was forgotten can never be connected.
+        // parents are lower than their children, so they are kept or forgotten first
+        let mut side: Vec<_> = self.side.drain().collect();
+        side.sort_unstable_by_key(|(_, side)| side.height);
+        for (hash, side) in side {
+            let prev = side.header.prev_blockhash;
+            if side.height + MAX_SIDE_DEPTH >= height
+                && (self.index.contains_key(&prev) || self.side.contains_key(&prev))
+            {
+                self.side.insert(hash, side);
+            }
+        }
+    }
+
+    /// Returns the bits required for `header`, a child of `parent`, or [`None`] if they depend on
+    /// headers before the checkpoint.
+    fn expected_bits(&self, parent: &Entry, header: &Header) -> Option<CompactTarget> {
+        let params = &self.params;
+        let interval = params.difficulty_adjustment_interval() as u32;
+        let height = parent.height + 1;
+
+        if height % interval != 0 {
+            if params.allow_min_difficulty_blocks {
+                // blocks more than 20 minutes after their parent may have the minimum difficulty
+                let min_difficulty_after = 2 * params.pow_target_spacing as u32;
+                if header.time > parent.header.time + min_difficulty_after {
+                    return Some(params.max_attainable_target.to_compact_lossy());
+                }
+
+                // otherwise the bits of the last block that did not use the minimum difficulty
+                let pow_limit = params.max_attainable_target.to_compact_lossy();
+                let mut entry = parent;
+                while entry.height % interval != 0 && entry.header.bits == pow_limit {
+                    entry = self.entry(&entry.header.prev_blockhash)?;
+                }
+                return Some(entry.header.bits);
+            }
+
+            return Some(parent.header.bits);
+        }
+
+        if params.no_pow_retargeting {
+            return Some(parent.header.bits);
+        }
+
+        // the first block of the period that ends with the parent
+        let mut first = parent;
+        for _ in 0..interval - 1 {
+            first = self.entry(&first.header.prev_blockhash)?;
+        }
+
+        Some(CompactTarget::from_header_difficulty_adjustment(
+            first.header,
+            parent.header,
+            params,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeaderChain, HeaderChainError, HeaderUpdate};
+    use crate::Message;
+    use bitcoin::{
+        block::Header, constants::genesis_block, hashes::Hash, Block, BlockHash, Network,
+        TxMerkleNode,
+    };
+
+    fn meets(header: &Header) -> bool {
+        header.validate_pow(header.target()).is_ok()
+    }
+
+    /// Mines a regtest header on top of `prev`.
+    fn mine(prev: &Header, tag: u8) -> Header {
+        let mut header = Header {
+            prev_blockhash: prev.block_hash(),
+            merkle_root: TxMerkleNode::from_byte_array([tag; 32]),
+            time: prev.time + 600,
+            nonce: 0,
+            ..*prev
+        };
+        while !meets(&header) {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[test]
+    fn header_chain() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut chain = HeaderChain::new(Network::Regtest);
+        assert_eq!(chain.height(), 0);
+        assert_eq!(chain.tip(), genesis.block_hash());
+
+        let a1 = mine(&genesis, 1);
+        let a2 = mine(&a1, 1);
+        assert_eq!(
+            chain.process(&Message::Block(
+                Block {
+                    header: a1,
+                    txdata: Vec::new()
+                },
+                0
+            )),
+            Some(Ok(HeaderUpdate::Extended))
+        );
+        assert_eq!(chain.insert(a2), Ok(HeaderUpdate::Extended));
+        assert_eq!(chain.insert(a2), Ok(HeaderUpdate::AlreadyKnown));
+        assert_eq!(chain.height(), 2);
+        assert_eq!(chain.chainwork(), genesis.work() + a1.work() + a2.work());
+
+        // a competing branch from a1 takes over once it has more work
+        let b2 = mine(&a1, 2);
+        let b3 = mine(&b2, 2);
+        assert_eq!(chain.insert(b2), Ok(HeaderUpdate::SideBranch));
+        assert_eq!(
+            chain.insert(b3),
+            Ok(HeaderUpdate::Reorg {
+                disconnected: vec![a2.block_hash()],
+                connected: vec![b2.block_hash(), b3.block_hash()],
+            })
+        );
+        assert_eq!(chain.height(), 3);
+        assert_eq!(chain.height_of(&a2.block_hash()), None);
+        assert_eq!(chain.header_at(2), Some(&b2));
+
+        // invalid headers
+        let orphan = Header {
+            prev_blockhash: BlockHash::all_zeros(),
+            ..a1
+        };
+        assert_eq!(
+            chain.insert(orphan),
+            Err(HeaderChainError::UnknownParent(BlockHash::all_zeros()))
+        );
+        let mut bad_pow = mine(&b3, 3);
+        while meets(&bad_pow) {
+            bad_pow.nonce += 1;
+        }
+        assert_eq!(
+            chain.insert(bad_pow),
+            Err(HeaderChainError::InvalidPow(bad_pow.block_hash()))
+        );
+
+        // persistence
+        let mut buf = Vec::new();
+        chain.write_to(&mut buf).unwrap();
+        let restored = HeaderChain::read_from(Network::Regtest, &buf[..]).unwrap();
+        assert_eq!(restored.tip(), chain.tip());
+        assert_eq!(restored.chainwork(), chain.chainwork());
+    }
+
+    #[test]
+    fn side_branches_are_forgotten_whole() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut chain = HeaderChain::new(Network::Regtest);
+
+        let a1 = mine(&genesis, 1);
+        let b2 = mine(&a1, 2);
+        let b3 = mine(&b2, 2);
+        let mut tip = a1;
+        chain.insert(a1).unwrap();
+        for _ in 0..2 {
+            tip = mine(&tip, 1);
+            chain.insert(tip).unwrap();
+        }
+        assert_eq!(chain.insert(b2), Ok(HeaderUpdate::SideBranch));
+        assert_eq!(chain.insert(b3), Ok(HeaderUpdate::SideBranch));
+
+        // b2 falls out of the window first, b3 goes with it even though it is higher
+        while chain.height() < 2 + super::MAX_SIDE_DEPTH + 1 {
+            tip = mine(&tip, 1);
+            chain.insert(tip).unwrap();
+        }
+        assert_eq!(
+            chain.insert(mine(&b3, 2)),
+            Err(HeaderChainError::UnknownParent(b3.block_hash()))
+        );
+    }
+
+    #[test]
+    fn unexpected_bits() {
+        let genesis = genesis_block(Network::Bitcoin).header;
+        let mut chain = HeaderChain::new(Network::Bitcoin);
+        let header = Header {
+            prev_blockhash: genesis.block_hash(),
+            bits: bitcoin::CompactTarget::from_consensus(0x1c00ffff),
+            ..genesis
+        };
+        assert_eq!(
+            chain.insert(header),
+            Err(HeaderChainError::UnexpectedBits {
+                expected: genesis.bits,
+                actual: header.bits,
+            })
+        );
+    }
+}
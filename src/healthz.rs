@@ -0,0 +1,414 @@
+//! A tiny HTTP server for liveness and readiness probes, for example of Kubernetes, reporting
+//! whether the subscriber is connected and messages are flowing.
+//!
+//! Feed monitor events (see [`subscribe_async_monitor`]) and messages to a [`Health`] and serve
+//! it with a [`HealthServer`]:
+//!
+//! - `GET /healthz` responds with `200 OK` while something was received within `max_silence`,
+//!   `503 Service Unavailable` otherwise. Use it as liveness probe, so the pod is restarted when
+//!   the ZMQ link is dead.
+//! - `GET /readyz` responds with `200 OK` while at least one endpoint is connected and
+//!   `/healthz` is ok, `503 Service Unavailable` otherwise.
+//!
+//! [`subscribe_async_monitor`]: crate::subscribe_async_monitor
+
+use crate::{
+    endpoint::Endpoint,
+    message::Message,
+    monitor::{event::SocketEvent, MonitorMessage},
+};
+use core::time::Duration;
+use std::{
+    collections::HashSet,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+/// The connection state and last sign of life of a subscriber. Clones share the same state, so
+/// one can be kept to record events while another is served by a [`HealthServer`].
+#[derive(Debug, Clone)]
+pub struct Health(Arc<Mutex<HealthState>>);
+
+#[derive(Debug)]
+struct HealthState {
+    max_silence: Duration,
+    connected: HashSet<Endpoint>,
+    last_activity: Instant,
+}
+
+impl Health {
+    /// Creates a new [`Health`] that is alive for `max_silence` after the last sign of life.
+    /// Creating it counts as one, so probes do not fail while the subscriber starts.
+    #[inline]
+    pub fn new(max_silence: Duration) -> Self {
+        Self(Arc::new(Mutex::new(HealthState {
+            max_silence,
+            connected: HashSet::new(),
+            last_activity: Instant::now(),
+        })))
+    }
+
+    fn state(&self) -> MutexGuard<'_, HealthState> {
+        // the state stays consistent if a thread panics while holding the lock
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records that a message was received.
+    #[inline]
+    pub fn process(&self, _msg: &Message) {
+        self.record_activity(Instant::now());
+    }
+
+    /// Records a connection or disconnection of an endpoint. Other events are ignored.
+    pub fn process_event(&self, event: &MonitorMessage) {
+        match event.event {
+            SocketEvent::Connected { .. } => self.record_connected(event.source_url.clone()),
+            SocketEvent::Disconnected { .. } => self.record_disconnected(&event.source_url),
+            _ => {}
+        }
+    }
+
+    /// Records that `endpoint` connected. Connecting counts as a sign of life.
+    pub fn record_connected(&self, endpoint: Endpoint) {
+        let mut state = self.state();
+        state.connected.insert(endpoint);
+        state.last_activity = state.last_activity.max(Instant::now());
+    }
+
+    /// Records that `endpoint` disconnected.
+    #[inline]
+    pub fn record_disconnected(&self, endpoint: &Endpoint) {
+        self.state().connected.remove(endpoint);
+    }
+
+    /// Records a sign of life at `now`.
+    #[inline]
+    pub fn record_activity(&self, now: Instant) {
+        let mut state = self.state();
+        state.last_activity = state.last_activity.max(now);
+    }
+
+    /// Returns `true` if at least one endpoint is connected.
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        !self.state().connected.is_empty()
+    }
+
+    /// Returns `true` if something was received within `max_silence` before `now`.
+    #[inline]
+    pub fn is_alive(&self, now: Instant) -> bool {
+        let state = self.state();
+        now.saturating_duration_since(state.last_activity) <= state.max_silence
+    }
+
+    /// Returns `true` if at least one endpoint is connected and something was received within
+    /// `max_silence` before `now`.
+    #[inline]
+    pub fn is_ready(&self, now: Instant) -> bool {
+        self.is_connected() && self.is_alive(now)
+    }
+}
+
+/// An HTTP server serving the probes described in the [module documentation](self) on a
+/// background thread. Connections are handled by a small fixed number of worker threads,
+/// connections arriving while all of them are busy are closed. The server stops when this is
+/// dropped.
+#[derive(Debug)]
+pub struct HealthServer {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HealthServer {
+    /// Listens on `addr` and serves `health`. Use port 0 to let the OS pick a port, see
+    /// [`local_addr`](HealthServer::local_addr).
+    pub fn bind(addr: impl ToSocketAddrs, health: Health) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = thread::Builder::new()
+            .name("zmq-healthz".to_owned())
+            .spawn({
+                let stop = stop.clone();
+                move || {
+                    let (sender, receiver) = mpsc::sync_channel(WORKERS);
+                    let receiver = Arc::new(Mutex::new(receiver));
+                    let workers: Vec<_> = (0..WORKERS)
+                        .filter_map(|_| {
+                            let receiver = receiver.clone();
+                            let health = health.clone();
+                            thread::Builder::new()
+                                .name("zmq-healthz".to_owned())
+                                .spawn(move || serve(&receiver, &health))
+                                .ok()
+                        })
+                        .collect();
+
+                    for stream in listener.incoming() {
+                        if stop.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        // a slow or misbehaving client only occupies one worker until its
+                        // deadline, connections that do not fit in the queue are dropped
+                        if let Ok(stream) = stream {
+                            let _ = sender.try_send(stream);
+                        }
+                    }
+
+                    // the workers stop once the queue is empty
+                    drop(sender);
+                    for worker in workers {
+                        let _ = worker.join();
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            local_addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the address the server listens on.
+    #[inline]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for HealthServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // wake up the thread blocked in accept, an unspecified address (listening on all
+        // interfaces) can not be connected to, its loopback address can
+        let mut addr = self.local_addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        let _ = TcpStream::connect_timeout(&addr, Duration::from_secs(1));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Number of threads handling connections.
+const WORKERS: usize = 4;
+
+/// How long reading a request or writing a response may take. Probes are small and local, a
+/// client that takes longer is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Maximum number of bytes read from a request, the rest is ignored. Probes only need the request
+/// line.
+const MAX_REQUEST_LEN: u64 = 8 * 1024;
+
+/// Handles the connections received from `receiver` until its sender is dropped.
+fn serve(receiver: &Mutex<Receiver<TcpStream>>, health: &Health) {
+    loop {
+        // the lock is only held while waiting, not while responding
+        let stream = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        match stream {
+            Ok(stream) => {
+                let _ = respond(stream, health);
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+/// Reads from a [`TcpStream`] until a deadline, the read timeout of every read is the time left.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self
+            .deadline
+            .checked_duration_since(Instant::now())
+            .filter(|left| !left.is_zero())
+            .ok_or(io::ErrorKind::TimedOut)?;
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+/// Reads a request from `stream` and writes the response for it.
+fn respond(stream: TcpStream, health: &Health) -> io::Result<()> {
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut reader = BufReader::new(
+        DeadlineReader {
+            stream: &stream,
+            deadline: Instant::now() + REQUEST_TIMEOUT,
+        }
+        .take(MAX_REQUEST_LEN),
+    );
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // skip the headers
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request_line.split_ascii_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+    // ignore a query string, probes may add one
+    let path = path.map(|path| path.split_once('?').map_or(path, |(path, _)| path));
+
+    let now = Instant::now();
+    let (status, body) = match (method, path) {
+        (Some("GET" | "HEAD"), Some("/healthz")) => status_of(health.is_alive(now), "alive"),
+        (Some("GET" | "HEAD"), Some("/readyz")) => status_of(health.is_ready(now), "ready"),
+        (Some("GET" | "HEAD"), _) => ("404 Not Found", "not found"),
+        _ => ("405 Method Not Allowed", "method not allowed"),
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len() + 1
+    )?;
+    if method != Some("HEAD") {
+        writeln!(stream, "{body}")?;
+    }
+    stream.flush()
+}
+
+const fn status_of(ok: bool, what: &'static str) -> (&'static str, &'static str) {
+    if ok {
+        ("200 OK", what)
+    } else {
+        ("503 Service Unavailable", "unavailable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Health, HealthServer};
+    use crate::{
+        monitor::{event::SocketEvent, MonitorMessage},
+        Endpoint,
+    };
+    use core::time::Duration;
+    use std::{
+        io::{ErrorKind, Read, Write},
+        net::TcpStream,
+        time::Instant,
+    };
+
+    #[test]
+    fn health_state() {
+        let start = Instant::now();
+        let health = Health::new(Duration::from_secs(30));
+        assert!(health.is_alive(start));
+        assert!(!health.is_ready(start));
+
+        let endpoint = "tcp://127.0.0.1:28332".parse::<Endpoint>().unwrap();
+        health.process_event(&MonitorMessage {
+            event: SocketEvent::Connected { fd: 7 },
+            source_url: endpoint.clone(),
+        });
+        assert!(health.is_ready(start));
+        assert!(!health.is_alive(start + Duration::from_secs(31)));
+
+        health.record_activity(start + Duration::from_secs(40));
+        assert!(health.is_ready(start + Duration::from_secs(41)));
+
+        health.process_event(&MonitorMessage {
+            event: SocketEvent::Disconnected { fd: 7 },
+            source_url: endpoint,
+        });
+        assert!(!health.is_connected());
+        assert!(health.is_alive(start + Duration::from_secs(41)));
+    }
+
+    #[test]
+    fn serve_probes() {
+        let health = Health::new(Duration::from_secs(30));
+        let server = HealthServer::bind("127.0.0.1:0", health.clone()).unwrap();
+
+        let get = |request: &str| {
+            let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+            write!(stream, "{request}\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        assert!(get("GET /healthz HTTP/1.1").starts_with("HTTP/1.1 200 OK\r\n"));
+        let response = get("GET /readyz?verbose HTTP/1.1");
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.ends_with("\r\n\r\nunavailable\n"));
+
+        health.record_connected("tcp://127.0.0.1:28332".parse::<Endpoint>().unwrap());
+        assert!(get("GET /readyz HTTP/1.1").ends_with("\r\n\r\nready\n"));
+        assert!(get("HEAD /readyz HTTP/1.1").ends_with("\r\n\r\n"));
+        assert!(get("GET / HTTP/1.1").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(get("POST /healthz HTTP/1.1").starts_with("HTTP/1.1 405"));
+
+        // a client that sends nothing does not hold up other probes
+        let _idle = TcpStream::connect(server.local_addr()).unwrap();
+        let start = Instant::now();
+        assert!(get("GET /healthz HTTP/1.1").starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(start.elapsed() < super::REQUEST_TIMEOUT);
+
+        drop(server);
+    }
+
+    #[test]
+    fn request_deadline() {
+        let server = HealthServer::bind("127.0.0.1:0", Health::new(Duration::ZERO)).unwrap();
+
+        // a client trickling bytes without ever finishing the request line is dropped once the
+        // deadline of the whole request passed, not once it is silent for that long
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        let start = Instant::now();
+        let mut buf = [0; 1];
+        stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        loop {
+            assert!(start.elapsed() < super::REQUEST_TIMEOUT * 5);
+            if stream.write_all(b"G").is_err() {
+                break;
+            }
+            match stream.read(&mut buf) {
+                // closed without a response
+                Ok(0) => break,
+                Ok(_) => panic!("unexpected response"),
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => break,
+                Err(_) => {}
+            }
+        }
+        assert!(start.elapsed() >= super::REQUEST_TIMEOUT);
+    }
+
+    #[test]
+    fn drop_unspecified_address() {
+        let server = HealthServer::bind("0.0.0.0:0", Health::new(Duration::ZERO)).unwrap();
+        assert!(server.local_addr().ip().is_unspecified());
+        // returns once the listener thread stopped
+        drop(server);
+    }
+}
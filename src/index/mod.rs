@@ -0,0 +1,437 @@
+//! Electrum-style script hash index, built from `rawblock` and `rawtx` messages.
+//!
+//! [`ScriptHashIndex`] maps script hashes to the transactions that pay to or spend from the
+//! script, for both confirmed and mempool transactions. This is the basis of lightweight
+//! Electrum-like servers.
+
+mod storage;
+
+pub use storage::{IndexStorage, MemoryStorage};
+
+use crate::{message::Message, sequence_message::SequenceMessage};
+use bitcoin::{
+    hashes::{hash_newtype, sha256, Hash},
+    Block, BlockHash, OutPoint, Script, Transaction, Txid,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+hash_newtype! {
+    /// Script hash as used by the Electrum protocol: the SHA256 hash of an output script,
+    /// displayed in reverse byte order.
+    #[hash_newtype(backward)]
+    pub struct ScriptHash(sha256::Hash);
+}
+
+impl ScriptHash {
+    /// Computes the [`ScriptHash`] of `script`.
+    #[inline]
+    pub fn from_script(script: &Script) -> Self {
+        Self::hash(script.as_bytes())
+    }
+}
+
+/// A transaction in the history of a script hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HistoryEntry {
+    pub txid: Txid,
+    /// The block this transaction is confirmed in, or [`None`] if it is in the mempool.
+    pub block: Option<BlockHash>,
+}
+
+#[derive(Debug)]
+struct IndexedTx {
+    scripthashes: Vec<ScriptHash>,
+    outputs: Vec<OutPoint>,
+}
+
+#[derive(Debug)]
+struct ConnectedBlock {
+    blockhash: BlockHash,
+    txs: Vec<(Txid, IndexedTx)>,
+}
+
+/// Index from script hashes to transaction history, kept up to date with [`process`].
+///
+/// Reorgs are handled by undoing blocks, either when a `sequence` message reports a disconnected
+/// block or when a `rawblock` arrives that builds on an earlier block than the current tip. Undo
+/// data is kept for the most recent blocks only, see [`with_max_reorg_depth`]. A `rawblock` that
+/// does not build on any of these blocks (for example because blocks were missed) is connected
+/// without undoing anything, and the undo data of the blocks before it is dropped.
+///
+/// [`process`]: ScriptHashIndex::process
+/// [`with_max_reorg_depth`]: ScriptHashIndex::with_max_reorg_depth
+#[derive(Debug)]
+pub struct ScriptHashIndex<S = MemoryStorage> {
+    storage: S,
+    blocks: VecDeque<ConnectedBlock>,
+    confirmed: HashSet<Txid>,
+    mempool: HashMap<Txid, IndexedTx>,
+    max_reorg_depth: usize,
+}
+
+impl<S: IndexStorage> ScriptHashIndex<S> {
+    /// Default number of blocks that can be undone in a reorg.
+    pub const DEFAULT_MAX_REORG_DEPTH: usize = 100;
+
+    /// Creates a new [`ScriptHashIndex`] on top of `storage`.
+    #[inline]
+    pub fn new(storage: S) -> Self {
+        Self::with_max_reorg_depth(storage, Self::DEFAULT_MAX_REORG_DEPTH)
+    }
+
+    /// Creates a new [`ScriptHashIndex`] on top of `storage` that keeps undo data for
+    /// `max_reorg_depth` blocks.
+    #[inline]
+    pub fn with_max_reorg_depth(storage: S, max_reorg_depth: usize) -> Self {
+        Self {
+            storage,
+            blocks: VecDeque::new(),
+            confirmed: HashSet::new(),
+            mempool: HashMap::new(),
+            max_reorg_depth,
+        }
+    }
+
+    /// Returns a reference to the storage backend.
+    #[inline]
+    pub const fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Returns the hash of the last connected block, if any.
+    #[inline]
+    pub fn tip(&self) -> Option<BlockHash> {
+        self.blocks.back().map(|b| b.blockhash)
+    }
+
+    /// Returns the history of `scripthash`.
+    #[inline]
+    pub fn history(&self, scripthash: &ScriptHash) -> Result<Vec<HistoryEntry>, S::Error> {
+        self.storage.history(scripthash)
+    }
+
+    /// Returns the history of `script`.
+    #[inline]
+    pub fn script_history(&self, script: &Script) -> Result<Vec<HistoryEntry>, S::Error> {
+        self.history(&ScriptHash::from_script(script))
+    }
+
+    /// Updates the index with a message. Only `rawblock`, `rawtx` and the block disconnect and
+    /// mempool removal messages of the `sequence` topic change the index.
+    pub fn process(&mut self, msg: &Message) -> Result<(), S::Error> {
+        match msg {
+            Message::Block(block, _) => self.connect_block(block),
+            Message::Tx(tx, _) => self.add_mempool_tx(tx),
+            Message::Sequence(SequenceMessage::BlockDisconnect { blockhash }, _) => {
+                if self.tip() == Some(*blockhash) {
+                    self.disconnect_tip()?;
+                }
+                Ok(())
+            }
+            Message::Sequence(SequenceMessage::MempoolRemoval { txid, .. }, _) => {
+                self.remove_mempool_tx(txid)
+            }
+            Message::HashBlock(..) | Message::HashTx(..) | Message::Sequence(..) => Ok(()),
+        }
+    }
+
+    /// Adds a mempool transaction to the index. Transactions that are already indexed (in the
+    /// mempool or in a recent block) are ignored, as Bitcoin Core also sends `rawtx` messages for
+    /// transactions in new blocks.
+    pub fn add_mempool_tx(&mut self, tx: &Transaction) -> Result<(), S::Error> {
+        let txid = tx.compute_txid();
+        if self.mempool.contains_key(&txid) || self.confirmed.contains(&txid) {
+            return Ok(());
+        }
+
+        let indexed = self.index_tx(tx, txid, None)?;
+        self.mempool.insert(txid, indexed);
+
+        Ok(())
+    }
+
+    /// Removes a mempool transaction from the index.
+    pub fn remove_mempool_tx(&mut self, txid: &Txid) -> Result<(), S::Error> {
+        if let Some(indexed) = self.mempool.remove(txid) {
+            self.unindex_tx(txid, &indexed)?;
+        }
+
+        Ok(())
+    }
+
+    /// Connects a block. If the block builds on an earlier block than the current tip, blocks are
+    /// disconnected until it builds on the tip. If it does not build on any block there is undo
+    /// data for, the fork point is unknown, so nothing is disconnected and the undo data is
+    /// dropped, as the blocks before it can not be undone correctly anymore.
+    pub fn connect_block(&mut self, block: &Block) -> Result<(), S::Error> {
+        let blockhash = block.block_hash();
+        if self.blocks.iter().any(|b| b.blockhash == blockhash) {
+            return Ok(());
+        }
+
+        let prev = block.header.prev_blockhash;
+        if self.blocks.iter().any(|b| b.blockhash == prev) {
+            while self.tip() != Some(prev) {
+                self.disconnect_tip()?;
+            }
+        } else {
+            self.blocks.clear();
+            self.confirmed.clear();
+        }
+
+        let mut txs = Vec::with_capacity(block.txdata.len());
+        for tx in &block.txdata {
+            let txid = tx.compute_txid();
+            self.remove_mempool_tx(&txid)?;
+            txs.push((txid, self.index_tx(tx, txid, Some(blockhash))?));
+            self.confirmed.insert(txid);
+        }
+
+        self.blocks.push_back(ConnectedBlock { blockhash, txs });
+
+        if self.blocks.len() > self.max_reorg_depth {
+            if let Some(block) = self.blocks.pop_front() {
+                for (txid, _) in block.txs {
+                    self.confirmed.remove(&txid);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Disconnects the tip and returns its hash, or [`None`] if there is no undo data left.
+    /// Transactions of the disconnected block are removed from the index, Bitcoin Core sends them
+    /// again as `rawtx` when they are added back to its mempool.
+    pub fn disconnect_tip(&mut self) -> Result<Option<BlockHash>, S::Error> {
+        let Some(block) = self.blocks.pop_back() else {
+            return Ok(None);
+        };
+
+        for (txid, indexed) in block.txs.iter().rev() {
+            self.unindex_tx(txid, indexed)?;
+            self.confirmed.remove(txid);
+        }
+
+        Ok(Some(block.blockhash))
+    }
+
+    fn index_tx(
+        &mut self,
+        tx: &Transaction,
+        txid: Txid,
+        block: Option<BlockHash>,
+    ) -> Result<IndexedTx, S::Error> {
+        let mut scripthashes = Vec::new();
+        let mut outputs = Vec::with_capacity(tx.output.len());
+
+        if !tx.is_coinbase() {
+            for input in &tx.input {
+                if let Some(scripthash) = self.storage.output(&input.previous_output)? {
+                    if !scripthashes.contains(&scripthash) {
+                        scripthashes.push(scripthash);
+                    }
+                }
+            }
+        }
+
+        for (vout, output) in (0..).zip(&tx.output) {
+            let scripthash = ScriptHash::from_script(&output.script_pubkey);
+            let outpoint = OutPoint::new(txid, vout);
+            self.storage.insert_output(outpoint, scripthash)?;
+            outputs.push(outpoint);
+            if !scripthashes.contains(&scripthash) {
+                scripthashes.push(scripthash);
+            }
+        }
+
+        let entry = HistoryEntry { txid, block };
+        for scripthash in &scripthashes {
+            self.storage.insert_history(*scripthash, entry)?;
+        }
+
+        Ok(IndexedTx {
+            scripthashes,
+            outputs,
+        })
+    }
+
+    fn unindex_tx(&mut self, txid: &Txid, indexed: &IndexedTx) -> Result<(), S::Error> {
+        for scripthash in &indexed.scripthashes {
+            self.storage.remove_history(scripthash, txid)?;
+        }
+        for outpoint in &indexed.outputs {
+            self.storage.remove_output(outpoint)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ScriptHashIndex {
+    #[inline]
+    fn default() -> Self {
+        Self::new(MemoryStorage::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HistoryEntry, ScriptHash, ScriptHashIndex};
+    use crate::{Message, SequenceMessage};
+    use bitcoin::{
+        absolute::LockTime, block, constants::genesis_block, transaction::Version, Amount, Block,
+        Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+    };
+
+    #[test]
+    fn index() {
+        let genesis = genesis_block(Network::Bitcoin);
+        let genesis_hash = genesis.block_hash();
+        let coinbase = &genesis.txdata[0];
+        let coinbase_script = &coinbase.output[0].script_pubkey;
+
+        let script = ScriptBuf::from_bytes(vec![0x51]);
+        let spend = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(coinbase.compute_txid(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: script.clone(),
+            }],
+        };
+        let spend_txid = spend.compute_txid();
+
+        let block = Block {
+            header: block::Header {
+                prev_blockhash: genesis_hash,
+                ..genesis.header
+            },
+            txdata: vec![spend.clone()],
+        };
+        let blockhash = block.block_hash();
+
+        let mut index = ScriptHashIndex::default();
+
+        index.process(&Message::Block(genesis.clone(), 0)).unwrap();
+        assert_eq!(index.tip(), Some(genesis_hash));
+        assert_eq!(
+            index.script_history(coinbase_script).unwrap(),
+            [HistoryEntry {
+                txid: coinbase.compute_txid(),
+                block: Some(genesis_hash)
+            }]
+        );
+
+        // rawtx of a transaction in a recent block is ignored
+        index.process(&Message::Tx(coinbase.clone(), 1)).unwrap();
+        assert_eq!(index.script_history(coinbase_script).unwrap().len(), 1);
+
+        index.process(&Message::Tx(spend.clone(), 2)).unwrap();
+        let mempool_entry = HistoryEntry {
+            txid: spend_txid,
+            block: None,
+        };
+        assert_eq!(
+            index.script_history(coinbase_script).unwrap()[1],
+            mempool_entry
+        );
+        assert_eq!(index.script_history(&script).unwrap(), [mempool_entry]);
+
+        index.process(&Message::Block(block, 1)).unwrap();
+        let confirmed_entry = HistoryEntry {
+            txid: spend_txid,
+            block: Some(blockhash),
+        };
+        assert_eq!(index.tip(), Some(blockhash));
+        assert_eq!(index.script_history(&script).unwrap(), [confirmed_entry]);
+        assert_eq!(
+            index
+                .history(&ScriptHash::from_script(coinbase_script))
+                .unwrap()[1],
+            confirmed_entry
+        );
+
+        index
+            .process(&Message::Sequence(
+                SequenceMessage::BlockDisconnect {
+                    blockhash: genesis_hash,
+                },
+                0,
+            ))
+            .unwrap();
+        assert_eq!(index.tip(), Some(blockhash));
+
+        index
+            .process(&Message::Sequence(
+                SequenceMessage::BlockDisconnect { blockhash },
+                1,
+            ))
+            .unwrap();
+        assert_eq!(index.tip(), Some(genesis_hash));
+        assert!(index.script_history(&script).unwrap().is_empty());
+        assert_eq!(index.script_history(coinbase_script).unwrap().len(), 1);
+
+        index.process(&Message::Tx(spend, 3)).unwrap();
+        assert_eq!(index.script_history(&script).unwrap(), [mempool_entry]);
+        index
+            .process(&Message::Sequence(
+                SequenceMessage::MempoolRemoval {
+                    txid: spend_txid,
+                    mempool_sequence: 1,
+                },
+                2,
+            ))
+            .unwrap();
+        assert!(index.script_history(&script).unwrap().is_empty());
+    }
+
+    #[test]
+    fn missed_blocks() {
+        let genesis = genesis_block(Network::Bitcoin);
+        let script = ScriptBuf::from_bytes(vec![0x51]);
+        let block = |prev_blockhash, n| {
+            let mut coinbase = genesis.txdata[0].clone();
+            coinbase.lock_time = LockTime::from_consensus(n);
+            coinbase.output[0].script_pubkey = script.clone();
+            Block {
+                header: block::Header {
+                    prev_blockhash,
+                    nonce: n,
+                    ..genesis.header
+                },
+                txdata: vec![coinbase],
+            }
+        };
+        let entry = |block: &Block| HistoryEntry {
+            txid: block.txdata[0].compute_txid(),
+            block: Some(block.block_hash()),
+        };
+        let a = block(genesis.block_hash(), 1);
+        // the parent of c is missed
+        let b = block(a.block_hash(), 2);
+        let c = block(b.block_hash(), 3);
+
+        let mut index = ScriptHashIndex::default();
+        index.process(&Message::Block(a.clone(), 0)).unwrap();
+        index.process(&Message::Block(c.clone(), 1)).unwrap();
+
+        // the blocks before the gap stay indexed
+        assert_eq!(index.tip(), Some(c.block_hash()));
+        assert_eq!(
+            index.script_history(&script).unwrap(),
+            [entry(&a), entry(&c)]
+        );
+
+        // but they can not be undone anymore
+        assert_eq!(index.disconnect_tip().unwrap(), Some(c.block_hash()));
+        assert_eq!(index.disconnect_tip().unwrap(), None);
+        assert_eq!(index.script_history(&script).unwrap(), [entry(&a)]);
+    }
+}
@@ -0,0 +1,107 @@
+use super::{HistoryEntry, ScriptHash};
+use bitcoin::{OutPoint, Txid};
+use core::convert::Infallible;
+use std::collections::HashMap;
+
+/// Storage backend for a [`ScriptHashIndex`](super::ScriptHashIndex).
+///
+/// Implement this to persist the index, for example in a key-value store. [`MemoryStorage`] keeps
+/// everything in memory.
+pub trait IndexStorage {
+    type Error;
+
+    /// Adds `entry` to the history of `scripthash`. An entry with the same txid is replaced.
+    fn insert_history(
+        &mut self,
+        scripthash: ScriptHash,
+        entry: HistoryEntry,
+    ) -> Result<(), Self::Error>;
+
+    /// Removes the entry with `txid` from the history of `scripthash`, if present.
+    fn remove_history(&mut self, scripthash: &ScriptHash, txid: &Txid) -> Result<(), Self::Error>;
+
+    /// Returns the history of `scripthash` in insertion order.
+    fn history(&self, scripthash: &ScriptHash) -> Result<Vec<HistoryEntry>, Self::Error>;
+
+    /// Remembers the script hash of the output at `outpoint`, to be able to find the script hash
+    /// of transactions spending it.
+    fn insert_output(
+        &mut self,
+        outpoint: OutPoint,
+        scripthash: ScriptHash,
+    ) -> Result<(), Self::Error>;
+
+    /// Returns the script hash of the output at `outpoint` if it is known.
+    fn output(&self, outpoint: &OutPoint) -> Result<Option<ScriptHash>, Self::Error>;
+
+    /// Forgets the output at `outpoint`.
+    fn remove_output(&mut self, outpoint: &OutPoint) -> Result<(), Self::Error>;
+}
+
+/// [`IndexStorage`] that keeps everything in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    history: HashMap<ScriptHash, Vec<HistoryEntry>>,
+    outputs: HashMap<OutPoint, ScriptHash>,
+}
+
+impl MemoryStorage {
+    /// Creates a new, empty [`MemoryStorage`].
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IndexStorage for MemoryStorage {
+    type Error = Infallible;
+
+    fn insert_history(
+        &mut self,
+        scripthash: ScriptHash,
+        entry: HistoryEntry,
+    ) -> Result<(), Self::Error> {
+        let history = self.history.entry(scripthash).or_default();
+        match history.iter_mut().find(|e| e.txid == entry.txid) {
+            Some(existing) => *existing = entry,
+            None => history.push(entry),
+        }
+
+        Ok(())
+    }
+
+    fn remove_history(&mut self, scripthash: &ScriptHash, txid: &Txid) -> Result<(), Self::Error> {
+        if let Some(history) = self.history.get_mut(scripthash) {
+            history.retain(|e| e.txid != *txid);
+            if history.is_empty() {
+                self.history.remove(scripthash);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn history(&self, scripthash: &ScriptHash) -> Result<Vec<HistoryEntry>, Self::Error> {
+        Ok(self.history.get(scripthash).cloned().unwrap_or_default())
+    }
+
+    fn insert_output(
+        &mut self,
+        outpoint: OutPoint,
+        scripthash: ScriptHash,
+    ) -> Result<(), Self::Error> {
+        self.outputs.insert(outpoint, scripthash);
+
+        Ok(())
+    }
+
+    fn output(&self, outpoint: &OutPoint) -> Result<Option<ScriptHash>, Self::Error> {
+        Ok(self.outputs.get(outpoint).copied())
+    }
+
+    fn remove_output(&mut self, outpoint: &OutPoint) -> Result<(), Self::Error> {
+        self.outputs.remove(outpoint);
+
+        Ok(())
+    }
+}
@@ -1,27 +1,97 @@
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+mod batch;
+mod block_reader;
+mod bloom;
+mod capabilities;
+#[cfg(feature = "codec")]
+pub mod codec;
+mod context;
+mod dedup;
+mod divergence;
+mod endpoint;
 mod error;
+mod fee_histogram;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod header_chain;
+#[cfg(feature = "healthz")]
+pub mod healthz;
+#[cfg(feature = "index")]
+pub mod index;
+mod mempool;
 mod message;
 mod monitor;
+mod raw_message;
+#[cfg(feature = "rpc")]
+pub mod rpc;
 mod sequence_message;
+pub mod sink;
+#[cfg(feature = "ssh")]
+pub mod ssh;
+mod staleness;
 mod subscribe;
+#[cfg(all(feature = "systemd", unix))]
+pub mod systemd;
+#[cfg(feature = "opentelemetry")]
+mod telemetry;
+mod template;
+#[cfg(test)]
+mod test_util;
+mod topic;
+mod typed_message;
+mod utxo_delta;
 
 pub use crate::{
-    error::Error,
+    batch::{Batched, TxidBatcher, TxidBatches},
+    block_reader::BlockReader,
+    bloom::{BloomFilter, BloomFlags, BloomMatches},
+    capabilities::{capabilities, Capabilities, Capability, MissingCapability},
+    context::{global_context, terminate_global_context},
+    dedup::{DedupKey, DedupWindow},
+    divergence::{Divergence, DivergenceChecker},
+    endpoint::{Endpoint, EndpointError, Host},
+    error::{DeserializationError, Error},
+    fee_histogram::{FeeBucket, FeeHistogram, DEFAULT_FEE_BUCKETS},
+    header_chain::{HeaderChain, HeaderChainError, HeaderUpdate},
+    mempool::{MempoolEntry, MempoolGraph},
     message::{Message, DATA_MAX_LEN, SEQUENCE_LEN, TOPIC_MAX_LEN},
     monitor::{
         event::{HandshakeFailure, SocketEvent},
         MonitorMessage,
     },
+    raw_message::{FromRawMessage, IntoRawMessage, RawMessage},
     sequence_message::SequenceMessage,
-    subscribe::{blocking::subscribe_blocking, receiver::subscribe_receiver},
+    staleness::{Stale, StaleBlocks, StalenessDetector},
+    subscribe::{
+        blocking::subscribe_blocking,
+        builder::{SubscribeBuilder, DEFAULT_LINGER, DEFAULT_MAX_MSG_SIZE, DEFAULT_THREAD_NAME},
+        expiry::ExpiredMessages,
+        group::{Consumer, Distribution},
+        receiver::subscribe_receiver,
+        report::{DebugReport, EndpointReport, SocketOptions},
+        subscription::{Subscription, SubscriptionIter},
+    },
+    template::{TemplateInvalidator, TemplateSignals},
+    topic::Topic,
+    typed_message::{
+        BlockMessage, HashBlockMessage, HashTxMessage, SequenceNotification, TxMessage,
+        TypedMessage,
+    },
+    utxo_delta::{SpentOutput, UtxoDelta, UtxoDeltaTracker, UtxoEvent},
 };
 
+#[cfg(feature = "async")]
+pub use crate::template::TemplateSignalStream;
+
 #[cfg(feature = "async")]
 pub use crate::subscribe::stream::{
     subscribe_async, subscribe_async_monitor, subscribe_async_monitor_stream,
     subscribe_async_stream::{self, MessageStream},
-    subscribe_async_wait_handshake, subscribe_async_wait_handshake_timeout, SocketMessage,
+    subscribe_async_wait_handshake, subscribe_async_wait_handshake_timeout,
+    subscribe_async_wait_handshake_with, BlockingIter, SocketMessage, Timeout,
 };
 
 #[allow(deprecated)]
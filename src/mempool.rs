@@ -0,0 +1,277 @@
+use crate::{message::Message, sequence_message::SequenceMessage};
+use bitcoin::{Amount, FeeRate, Transaction, Txid, Weight};
+use std::collections::{HashMap, HashSet};
+
+/// An unconfirmed transaction in a [`MempoolGraph`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolEntry {
+    pub weight: Weight,
+    /// The fee of this transaction, if known. `rawtx` messages do not contain fees, set them with
+    /// [`MempoolGraph::set_fee`] (for example from the `getmempoolentry` RPC).
+    pub fee: Option<Amount>,
+    /// Unconfirmed transactions this transaction spends outputs of.
+    pub parents: HashSet<Txid>,
+    /// Unconfirmed transactions spending outputs of this transaction.
+    pub children: HashSet<Txid>,
+}
+
+/// Parent/child dependency graph of unconfirmed transactions, built from `rawtx`, `rawblock` and
+/// `sequence` messages.
+///
+/// Transactions are added from `rawtx` messages. They are removed by [`MempoolRemoval`] messages
+/// and when they are included in a block (`rawblock` messages), as Bitcoin Core does not send a
+/// removal message in that case. `sequence` acceptance messages only contain a txid, so they can
+/// not add transactions, subscribe to `rawtx` as well.
+///
+/// [`MempoolRemoval`]: SequenceMessage::MempoolRemoval
+#[derive(Debug, Clone, Default)]
+pub struct MempoolGraph {
+    entries: HashMap<Txid, MempoolEntry>,
+}
+
+impl MempoolGraph {
+    /// Creates a new, empty [`MempoolGraph`].
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the graph with a message. Returns `true` if the graph changed.
+    pub fn process(&mut self, msg: &Message) -> bool {
+        match msg {
+            Message::Tx(tx, _) => self.insert(tx, None),
+            Message::Sequence(SequenceMessage::MempoolRemoval { txid, .. }, _) => {
+                self.remove(txid).is_some()
+            }
+            Message::Block(block, _) => {
+                let mut changed = false;
+                for tx in &block.txdata {
+                    changed |= self.remove(&tx.compute_txid()).is_some();
+                }
+                changed
+            }
+            Message::HashBlock(..) | Message::HashTx(..) | Message::Sequence(..) => false,
+        }
+    }
+
+    /// Adds an unconfirmed transaction, linking it to its unconfirmed parents that are already in
+    /// the graph. Returns `false` if the transaction was already present.
+    pub fn insert(&mut self, tx: &Transaction, fee: Option<Amount>) -> bool {
+        let txid = tx.compute_txid();
+        if self.entries.contains_key(&txid) {
+            return false;
+        }
+
+        let parents: HashSet<Txid> = tx
+            .input
+            .iter()
+            .map(|input| input.previous_output.txid)
+            .filter(|parent| self.entries.contains_key(parent))
+            .collect();
+        for parent in &parents {
+            if let Some(entry) = self.entries.get_mut(parent) {
+                entry.children.insert(txid);
+            }
+        }
+
+        self.entries.insert(
+            txid,
+            MempoolEntry {
+                weight: tx.weight(),
+                fee,
+                parents,
+                children: HashSet::new(),
+            },
+        );
+
+        true
+    }
+
+    /// Removes a transaction, unlinking it from its parents and children.
+    pub fn remove(&mut self, txid: &Txid) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(txid)?;
+        for parent in &entry.parents {
+            if let Some(parent) = self.entries.get_mut(parent) {
+                parent.children.remove(txid);
+            }
+        }
+        for child in &entry.children {
+            if let Some(child) = self.entries.get_mut(child) {
+                child.parents.remove(txid);
+            }
+        }
+
+        Some(entry)
+    }
+
+    /// Sets the fee of a transaction. Returns `false` if the transaction is not in the graph.
+    #[inline]
+    pub fn set_fee(&mut self, txid: &Txid, fee: Amount) -> bool {
+        match self.entries.get_mut(txid) {
+            Some(entry) => {
+                entry.fee = Some(fee);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the entry of a transaction.
+    #[inline]
+    pub fn get(&self, txid: &Txid) -> Option<&MempoolEntry> {
+        self.entries.get(txid)
+    }
+
+    /// Returns the number of transactions in the graph.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the graph contains no transactions.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns all unconfirmed ancestors of a transaction, not including itself.
+    #[inline]
+    pub fn ancestors(&self, txid: &Txid) -> HashSet<Txid> {
+        self.walk(txid, |entry| &entry.parents)
+    }
+
+    /// Returns all unconfirmed descendants of a transaction, not including itself.
+    #[inline]
+    pub fn descendants(&self, txid: &Txid) -> HashSet<Txid> {
+        self.walk(txid, |entry| &entry.children)
+    }
+
+    /// Returns the cluster of a transaction: all transactions connected to it by spending
+    /// relations in either direction, including itself. Empty if the transaction is not in the
+    /// graph.
+    pub fn cluster(&self, txid: &Txid) -> HashSet<Txid> {
+        let mut cluster = HashSet::new();
+        if !self.entries.contains_key(txid) {
+            return cluster;
+        }
+
+        let mut todo = vec![*txid];
+        while let Some(txid) = todo.pop() {
+            if cluster.insert(txid) {
+                let entry = &self.entries[&txid];
+                todo.extend(entry.parents.iter().chain(&entry.children));
+            }
+        }
+
+        cluster
+    }
+
+    /// Returns the effective feerate of a transaction: the feerate of the package formed by the
+    /// transaction and its unconfirmed ancestors, which have to be mined together with it (CPFP).
+    /// [`None`] if the transaction is not in the graph or a fee in the package is unknown.
+    pub fn effective_feerate(&self, txid: &Txid) -> Option<FeeRate> {
+        let mut package = self.ancestors(txid);
+        package.insert(*txid);
+        self.package_feerate(&package)
+    }
+
+    /// Returns the feerate of a set of transactions, [`None`] if a transaction is not in the
+    /// graph or its fee is unknown.
+    pub fn package_feerate(&self, txids: &HashSet<Txid>) -> Option<FeeRate> {
+        let mut fee = Amount::ZERO;
+        let mut weight = Weight::ZERO;
+        for txid in txids {
+            let entry = self.entries.get(txid)?;
+            fee += entry.fee?;
+            weight += entry.weight;
+        }
+
+        (weight != Weight::ZERO).then(|| fee / weight)
+    }
+
+    fn walk<'a>(
+        &'a self,
+        txid: &Txid,
+        next: impl Fn(&'a MempoolEntry) -> &'a HashSet<Txid>,
+    ) -> HashSet<Txid> {
+        let mut found = HashSet::new();
+        let mut todo: Vec<Txid> = self
+            .entries
+            .get(txid)
+            .map_or_else(Vec::new, |entry| next(entry).iter().copied().collect());
+        while let Some(txid) = todo.pop() {
+            if found.insert(txid) {
+                if let Some(entry) = self.entries.get(&txid) {
+                    todo.extend(next(entry));
+                }
+            }
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_util::tx, MempoolGraph, Message, SequenceMessage};
+    use bitcoin::{
+        constants::genesis_block, hashes::Hash, Amount, Block, FeeRate, Network, OutPoint, Txid,
+    };
+    use std::collections::HashSet;
+
+    #[test]
+    fn cpfp() {
+        let confirmed = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let parent = tx(&[confirmed], &[1000]);
+        let parent_txid = parent.compute_txid();
+        let child = tx(&[OutPoint::new(parent_txid, 0)], &[1000]);
+        let child_txid = child.compute_txid();
+        let unrelated = tx(&[OutPoint::new(Txid::from_byte_array([2; 32]), 0)], &[1000]);
+
+        let mut graph = MempoolGraph::new();
+        assert!(graph.process(&Message::Tx(parent.clone(), 0)));
+        assert!(graph.process(&Message::Tx(child.clone(), 1)));
+        assert!(!graph.process(&Message::Tx(child.clone(), 2)));
+        assert!(graph.process(&Message::Tx(unrelated.clone(), 3)));
+
+        assert_eq!(graph.ancestors(&child_txid), HashSet::from([parent_txid]));
+        assert_eq!(graph.descendants(&parent_txid), HashSet::from([child_txid]));
+        assert_eq!(
+            graph.cluster(&parent_txid),
+            HashSet::from([parent_txid, child_txid])
+        );
+
+        assert_eq!(graph.effective_feerate(&child_txid), None);
+        let weight = parent.weight().to_wu();
+        graph.set_fee(&parent_txid, Amount::ZERO);
+        graph.set_fee(&child_txid, Amount::from_sat(weight * 2));
+        // the child pays for both transactions of equal weight
+        assert_eq!(
+            graph.effective_feerate(&child_txid),
+            Some(FeeRate::from_sat_per_kwu(1000))
+        );
+
+        // the parent confirms, the child stays
+        let block = Block {
+            header: genesis_block(Network::Regtest).header,
+            txdata: vec![parent],
+        };
+        assert!(graph.process(&Message::Block(block, 4)));
+        assert!(graph.ancestors(&child_txid).is_empty());
+        assert_eq!(
+            graph.effective_feerate(&child_txid),
+            Some(FeeRate::from_sat_per_kwu(2000))
+        );
+        assert_eq!(graph.len(), 2);
+
+        // the unrelated transaction is evicted
+        assert!(graph.process(&Message::Sequence(
+            SequenceMessage::MempoolRemoval {
+                txid: unrelated.compute_txid(),
+                mempool_sequence: 5
+            },
+            5
+        )));
+        assert_eq!(graph.len(), 1);
+    }
+}
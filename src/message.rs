@@ -1,9 +1,11 @@
 use crate::{
-    error::{Error, Result},
+    dedup::DedupKey,
+    error::{DeserializationError, Error, Result},
     sequence_message::SequenceMessage,
+    topic::Topic,
 };
 use bitcoin::{
-    consensus::{deserialize, serialize},
+    consensus::{encode, serialize, Decodable, Encodable},
     hashes::Hash,
     Block, BlockHash, Transaction, Txid, Weight,
 };
@@ -32,17 +34,13 @@ impl Message {
     /// Returns the topic of this [`Message`] as a string slice.
     #[inline]
     pub fn topic_str(&self) -> &'static str {
-        let topic = match self {
-            Self::HashBlock(..) => "hashblock",
-            Self::HashTx(..) => "hashtx",
-            Self::Block(..) => "rawblock",
-            Self::Tx(..) => "rawtx",
-            Self::Sequence(..) => "sequence",
-        };
-
-        debug_assert!(topic.len() <= TOPIC_MAX_LEN);
+        self.topic_type().as_str()
+    }
 
-        topic
+    /// Returns the topic of this [`Message`] as a [`Topic`].
+    #[inline]
+    pub fn topic_type(&self) -> Topic {
+        self.into()
     }
 
     /// Serializes the middle part of this [`Message`] (no topic and sequence).
@@ -65,6 +63,60 @@ impl Message {
         }
     }
 
+    /// Returns the length of the middle part of this [`Message`] when serialized.
+    #[inline]
+    pub fn data_len(&self) -> usize {
+        match self {
+            Self::HashBlock(_, _) | Self::HashTx(_, _) => 32,
+            Self::Block(block, _) => block.total_size(),
+            Self::Tx(tx, _) => tx.total_size(),
+            Self::Sequence(sm, _) => sm.raw_length(),
+        }
+    }
+
+    /// Serializes this [`Message`] to 3 ZMQ frames, ready to be sent with
+    /// [`zmq::Socket::send_multipart`]. Unlike [`serialize_to_vecs`], the data part is
+    /// serialized directly into its frame, without an intermediate buffer.
+    ///
+    /// [`serialize_to_vecs`]: Message::serialize_to_vecs
+    pub fn to_zmq_multipart(&self) -> [zmq::Message; 3] {
+        let mut data = zmq::Message::with_size(self.data_len());
+        let mut buf = &mut data[..];
+
+        match self {
+            Self::HashBlock(blockhash, _) => {
+                buf.copy_from_slice(blockhash.as_byte_array());
+                buf.reverse();
+            }
+            Self::HashTx(txid, _) => {
+                buf.copy_from_slice(txid.as_byte_array());
+                buf.reverse();
+            }
+            Self::Block(block, _) => {
+                block
+                    .consensus_encode(&mut buf)
+                    .expect("frame has the serialized size");
+            }
+            Self::Tx(tx, _) => {
+                tx.consensus_encode(&mut buf)
+                    .expect("frame has the serialized size");
+            }
+            Self::Sequence(sm, _) => {
+                buf[..32].copy_from_slice(&sm.inner_hash_as_bytes());
+                buf[32] = sm.label();
+                if let Some(mempool_sequence) = sm.mempool_sequence() {
+                    buf[33..].copy_from_slice(&mempool_sequence.to_le_bytes());
+                }
+            }
+        }
+
+        [
+            self.topic().into(),
+            data,
+            self.sequence().to_le_bytes()[..].into(),
+        ]
+    }
+
     /// Serializes this [`Message`] to 3 [`Vec<u8>`]s.
     #[inline]
     pub fn serialize_to_vecs(&self) -> [Vec<u8>; 3] {
@@ -88,6 +140,27 @@ impl Message {
         }
     }
 
+    /// Returns a key that identifies the logical event this [`Message`] is about, to detect
+    /// duplicates. See [`DedupKey`] for which messages have the same key.
+    ///
+    /// This is cheap for all messages except `rawblock` and `rawtx`, for which the block hash or
+    /// txid has to be computed.
+    #[inline]
+    pub fn dedup_key(&self) -> DedupKey {
+        let (label, hash) = match self {
+            Self::HashBlock(blockhash, _) => (None, blockhash.to_byte_array()),
+            Self::HashTx(txid, _) => (None, txid.to_byte_array()),
+            Self::Block(block, _) => (None, block.block_hash().to_byte_array()),
+            Self::Tx(tx, _) => (None, tx.compute_txid().to_byte_array()),
+            Self::Sequence(sm, _) => (Some(sm.label()), sm.inner_hash_as_bytes()),
+        };
+        DedupKey {
+            topic: self.topic_type(),
+            label,
+            hash,
+        }
+    }
+
     /// Attempts to deserialize a multipart (multiple byte slices) to a [`Message`].
     #[inline]
     pub fn from_multipart<T: AsRef<[u8]>>(mp: &[T]) -> Result<Self> {
@@ -128,8 +201,14 @@ impl Message {
                     _ /* b"hashtx" */ => Self::HashTx(Txid::from_byte_array(data), sequence),
                 }
             }
-            b"rawblock" => Self::Block(deserialize(data)?, sequence),
-            b"rawtx" => Self::Tx(deserialize(data)?, sequence),
+            b"rawblock" => Self::Block(
+                deserialize_with_context(Topic::RawBlock, data, sequence)?,
+                sequence,
+            ),
+            b"rawtx" => Self::Tx(
+                deserialize_with_context(Topic::RawTx, data, sequence)?,
+                sequence,
+            ),
             b"sequence" => Self::Sequence(SequenceMessage::from_byte_slice(data)?, sequence),
             _ => {
                 let mut buf = [0; TOPIC_MAX_LEN];
@@ -143,6 +222,34 @@ impl Message {
     }
 }
 
+/// Like [`bitcoin::consensus::deserialize`], but returns an error with context and the offset at
+/// which decoding failed.
+fn deserialize_with_context<T: Decodable>(
+    topic: Topic,
+    data: &[u8],
+    sequence: u32,
+) -> core::result::Result<T, DeserializationError> {
+    let mut reader = data;
+    let res = T::consensus_decode_from_finite_reader(&mut reader).and_then(|value| {
+        if reader.is_empty() {
+            Ok(value)
+        } else {
+            Err(encode::Error::ParseFailed(
+                "data not consumed entirely when explicitly deserializing",
+            ))
+        }
+    });
+
+    res.map_err(|source| DeserializationError {
+        topic,
+        payload_len: data.len(),
+        sequence,
+        offset: data.len() - reader.len(),
+        source,
+        endpoint: None,
+    })
+}
+
 impl<T: AsRef<[u8]>> TryFrom<&[T]> for Message {
     type Error = Error;
 
@@ -194,7 +301,7 @@ impl fmt::Display for Message {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Error, Message};
+    use crate::{Error, Message, SequenceMessage, Topic};
     use bitcoin::{consensus::serialize, constants::genesis_block, hashes::Hash, Network};
 
     #[test]
@@ -347,4 +454,62 @@ mod tests {
             Err(Error::InvalidSequenceMessageLength(32))
         ));
     }
+
+    #[test]
+    fn test_deserialization_error_context() {
+        let genesis_block = genesis_block(Network::Bitcoin);
+        let tx_bytes = serialize(&genesis_block.txdata[0]);
+
+        let Err(Error::Deserialization(err)) =
+            Message::from_multipart(&[b"rawtx" as &[u8], &tx_bytes[..50], &[0x0d, 0, 0, 0]])
+        else {
+            panic!("expected deserialization error");
+        };
+        assert_eq!(err.topic, Topic::RawTx);
+        assert_eq!(err.payload_len, 50);
+        assert_eq!(err.sequence, 13);
+        assert_eq!(err.offset, 50);
+        assert_eq!(err.endpoint, None);
+
+        let mut trailing = tx_bytes.clone();
+        trailing.push(0);
+        let Err(Error::Deserialization(err)) =
+            Message::from_multipart(&[b"rawtx" as &[u8], &trailing, &[0x0e, 0, 0, 0]])
+        else {
+            panic!("expected deserialization error");
+        };
+        assert_eq!(err.payload_len, tx_bytes.len() + 1);
+        assert_eq!(err.offset, tx_bytes.len());
+    }
+
+    #[test]
+    fn test_to_zmq_multipart() {
+        let genesis_block = genesis_block(Network::Bitcoin);
+        let tx = genesis_block.txdata[0].clone();
+        let txid = tx.compute_txid();
+
+        for msg in [
+            Message::HashBlock(genesis_block.block_hash(), 0),
+            Message::HashTx(txid, 1),
+            Message::Block(genesis_block.clone(), 2),
+            Message::Tx(tx, 3),
+            Message::Sequence(
+                SequenceMessage::MempoolAcceptance {
+                    txid,
+                    mempool_sequence: 4,
+                },
+                5,
+            ),
+            Message::Sequence(
+                SequenceMessage::BlockConnect {
+                    blockhash: genesis_block.block_hash(),
+                },
+                6,
+            ),
+        ] {
+            let frames = msg.to_zmq_multipart();
+            let frames: Vec<&[u8]> = frames.iter().map(|frame| &**frame).collect();
+            assert_eq!(frames, msg.serialize_to_vecs(), "{msg}");
+        }
+    }
 }
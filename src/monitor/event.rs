@@ -1,4 +1,11 @@
 use super::MonitorMessageError;
+use core::time::Duration;
+use std::io;
+#[cfg(unix)]
+use std::os::fd::RawFd;
+// libzmq reports the socket handle on Windows
+#[cfg(windows)]
+use std::os::windows::io::RawSocket as RawFd;
 
 /// Convenience trait to be able to use `from_raw` and `to_raw` on any value that either defines it
 /// or is a `u32`. It doesn't matter that others don't implement this trait, rustc is smart enough
@@ -6,7 +13,7 @@ use super::MonitorMessageError;
 trait U32Ext: Sized {
     fn from_raw(value: u32) -> Option<Self>;
 
-    fn to_raw(self) -> u32;
+    fn to_raw(&self) -> u32;
 }
 
 impl U32Ext for u32 {
@@ -14,8 +21,39 @@ impl U32Ext for u32 {
         Some(value)
     }
 
-    fn to_raw(self) -> Self {
-        self
+    fn to_raw(&self) -> Self {
+        *self
+    }
+}
+
+impl U32Ext for Duration {
+    fn from_raw(value: u32) -> Option<Self> {
+        Some(Self::from_millis(value.into()))
+    }
+
+    fn to_raw(&self) -> u32 {
+        self.as_millis() as u32
+    }
+}
+
+impl U32Ext for RawFd {
+    fn from_raw(value: u32) -> Option<Self> {
+        // libzmq passes the fd (a signed int on unix) as an unsigned 32 bit integer
+        Some(value as Self)
+    }
+
+    fn to_raw(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl U32Ext for io::Error {
+    fn from_raw(value: u32) -> Option<Self> {
+        Some(Self::from_raw_os_error(value as i32))
+    }
+
+    fn to_raw(&self) -> u32 {
+        self.raw_os_error().unwrap_or(0) as u32
     }
 }
 
@@ -65,6 +103,7 @@ define_handshake_failure_enum! {
     /// Possible values for the ZMQ_EVENT_HANDSHAKE_FAILED_PROTOCOL socket event.
     #[repr(u32)]
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
     pub enum HandshakeFailure {
         ZmtpUnspecified = ZMQ_PROTOCOL_ERROR_ZMTP_UNSPECIFIED,
         ZmtpUnexpectedCommand = ZMQ_PROTOCOL_ERROR_ZMTP_UNEXPECTED_COMMAND,
@@ -116,12 +155,22 @@ macro_rules! define_socket_event_enum {
                 })
             }
 
-            pub fn to_raw(self) -> (u16, Option<u32>) {
+            /// Returns the name of this event, for example `"Connected"`.
+            pub const fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        Self::$name { .. } => stringify!($name),
+                    )*
+                    Self::Unknown { .. } => "Unknown",
+                }
+            }
+
+            pub fn to_raw(&self) -> (u16, Option<u32>) {
                 match self {
                     $(
                         Self::$name $({ $value })? => (zmq_sys::$zmq_sys_name as u16, ($(Some($value.to_raw()), )? None::<u32>,).0),
                     )*
-                    Self::Unknown { event, data } => (event, Some(data)),
+                    Self::Unknown { event, data } => (*event, Some(*data)),
                 }
             }
         }
@@ -131,20 +180,26 @@ macro_rules! define_socket_event_enum {
 define_socket_event_enum! {
     /// An event from one of the connected sockets. See the "SUPPORTED EVENTS" section in the
     /// "zmq_socket_monitor" manual page (`man zmq_socket_monitor`) for the original documentation.
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    ///
+    /// The `fd` of an event is a [`RawFd`] on unix and a [`RawSocket`] on Windows.
+    ///
+    /// [`RawFd`]: https://doc.rust-lang.org/std/os/fd/type.RawFd.html
+    /// [`RawSocket`]: https://doc.rust-lang.org/std/os/windows/io/type.RawSocket.html
+    #[derive(Debug)]
+    #[non_exhaustive]
     pub enum SocketEvent {
-        Connected(fd) = ZMQ_EVENT_CONNECTED,
+        Connected(fd: RawFd) = ZMQ_EVENT_CONNECTED,
         ConnectDelayed = ZMQ_EVENT_CONNECT_DELAYED,
-        ConnectRetried(interval) = ZMQ_EVENT_CONNECT_RETRIED,
-        Listening(fd) = ZMQ_EVENT_LISTENING,
-        BindFailed(errno) = ZMQ_EVENT_BIND_FAILED,
-        Accepted(fd) = ZMQ_EVENT_ACCEPTED,
-        AcceptFailed(errno) = ZMQ_EVENT_ACCEPT_FAILED,
-        Closed(fd) = ZMQ_EVENT_CLOSED,
-        CloseFailed(errno) = ZMQ_EVENT_CLOSE_FAILED,
-        Disconnected(fd) = ZMQ_EVENT_DISCONNECTED,
+        ConnectRetried(interval: Duration) = ZMQ_EVENT_CONNECT_RETRIED,
+        Listening(fd: RawFd) = ZMQ_EVENT_LISTENING,
+        BindFailed(errno: io::Error) = ZMQ_EVENT_BIND_FAILED,
+        Accepted(fd: RawFd) = ZMQ_EVENT_ACCEPTED,
+        AcceptFailed(errno: io::Error) = ZMQ_EVENT_ACCEPT_FAILED,
+        Closed(fd: RawFd) = ZMQ_EVENT_CLOSED,
+        CloseFailed(errno: io::Error) = ZMQ_EVENT_CLOSE_FAILED,
+        Disconnected(fd: RawFd) = ZMQ_EVENT_DISCONNECTED,
         MonitorStopped = ZMQ_EVENT_MONITOR_STOPPED,
-        HandshakeFailedNoDetail(fd) = ZMQ_EVENT_HANDSHAKE_FAILED_NO_DETAIL,
+        HandshakeFailedNoDetail(fd: RawFd) = ZMQ_EVENT_HANDSHAKE_FAILED_NO_DETAIL,
         HandshakeSucceeded = ZMQ_EVENT_HANDSHAKE_SUCCEEDED,
         HandshakeFailedProtocol(err: HandshakeFailure) = ZMQ_EVENT_HANDSHAKE_FAILED_PROTOCOL,
         HandshakeFailedAuth(error_code) = ZMQ_EVENT_HANDSHAKE_FAILED_AUTH,
@@ -152,6 +207,17 @@ define_socket_event_enum! {
 }
 
 impl SocketEvent {
+    /// Returns the [`io::Error`] of this event if it carries an error number.
+    #[inline]
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Self::BindFailed { errno }
+            | Self::AcceptFailed { errno }
+            | Self::CloseFailed { errno } => Some(errno),
+            _ => None,
+        }
+    }
+
     pub fn parse_from(msg: &zmq::Message) -> Result<Self, MonitorMessageError> {
         let bytes = &**msg;
 
@@ -165,3 +231,77 @@ impl SocketEvent {
             .ok_or(MonitorMessageError::InvalidEventData(event_type, data))
     }
 }
+
+// io::Error is neither Clone nor PartialEq, events are compared and cloned by the raw event and
+// data libzmq sent
+impl Clone for SocketEvent {
+    fn clone(&self) -> Self {
+        let (event, data) = self.to_raw();
+        Self::from_raw(event, data.unwrap_or(0)).expect("raw data of a SocketEvent is valid")
+    }
+}
+
+impl PartialEq for SocketEvent {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.to_raw() == other.to_raw()
+    }
+}
+
+impl Eq for SocketEvent {}
+
+#[cfg(test)]
+mod tests {
+    use super::SocketEvent;
+    use core::time::Duration;
+
+    #[test]
+    fn typed_payloads() {
+        let retried =
+            SocketEvent::from_raw(zmq_sys::ZMQ_EVENT_CONNECT_RETRIED as u16, 250).unwrap();
+        assert_eq!(
+            retried,
+            SocketEvent::ConnectRetried {
+                interval: Duration::from_millis(250)
+            }
+        );
+        assert_eq!(
+            retried.to_raw(),
+            (zmq_sys::ZMQ_EVENT_CONNECT_RETRIED as u16, Some(250))
+        );
+
+        let connected = SocketEvent::from_raw(zmq_sys::ZMQ_EVENT_CONNECTED as u16, 7).unwrap();
+        assert_eq!(connected, SocketEvent::Connected { fd: 7 });
+        assert_eq!(connected.name(), "Connected");
+
+        let bind_failed = SocketEvent::from_raw(
+            zmq_sys::ZMQ_EVENT_BIND_FAILED as u16,
+            zmq_sys::errno::EADDRINUSE as u32,
+        )
+        .unwrap();
+        assert_eq!(
+            bind_failed,
+            SocketEvent::BindFailed {
+                errno: std::io::Error::from_raw_os_error(zmq_sys::errno::EADDRINUSE)
+            }
+        );
+        assert_eq!(
+            bind_failed.io_error().unwrap().kind(),
+            std::io::ErrorKind::AddrInUse
+        );
+        assert_eq!(bind_failed.clone(), bind_failed);
+        assert!(connected.io_error().is_none());
+
+        // errnos that are not in the table of the zmq crate can be formatted too
+        let accept_failed =
+            SocketEvent::from_raw(zmq_sys::ZMQ_EVENT_ACCEPT_FAILED as u16, 0x7fff).unwrap();
+        let SocketEvent::AcceptFailed { errno } = &accept_failed else {
+            panic!("expected AcceptFailed");
+        };
+        assert!(!errno.to_string().is_empty());
+        assert_eq!(
+            accept_failed.to_raw(),
+            (zmq_sys::ZMQ_EVENT_ACCEPT_FAILED as u16, Some(0x7fff))
+        );
+    }
+}
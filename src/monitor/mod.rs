@@ -1,13 +1,18 @@
 pub mod event;
 
+use crate::endpoint::Endpoint;
 use core::fmt;
 use event::SocketEvent;
 
-/// A [`SocketEvent`] combined with its source (the url used when connecting).
+/// A [`SocketEvent`] combined with its source (the endpoint used when connecting). Use
+/// [`Endpoint::matches`] or `==` with a string to compare the source with a configured endpoint.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MonitorMessage {
     pub event: SocketEvent,
-    pub source_url: String,
+    /// The endpoint the event is about. This was the endpoint as a [`String`] before 2.0.0, its
+    /// [`Display`](fmt::Display) implementation gives that string (with the hostname in
+    /// lowercase).
+    pub source_url: Endpoint,
 }
 
 impl MonitorMessage {
@@ -18,11 +23,18 @@ impl MonitorMessage {
 
         Ok(Self {
             event: SocketEvent::parse_from(event_message)?,
-            source_url: String::from_utf8_lossy(url_message).into(),
+            source_url: Endpoint::parse_lossy(url_message),
         })
     }
 }
 
+impl fmt::Display for MonitorMessage {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Event({}, {})", self.event.name(), self.source_url)
+    }
+}
+
 #[derive(Debug)]
 // currently all variants have the same prefix: `Invalid`, which is correct and intended
 #[allow(clippy::enum_variant_names)]
@@ -49,3 +61,36 @@ impl fmt::Display for MonitorMessageError {
 }
 
 impl std::error::Error for MonitorMessageError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{event::SocketEvent, MonitorMessage};
+
+    #[test]
+    fn display() {
+        let msg = MonitorMessage {
+            event: SocketEvent::Disconnected { fd: 7 },
+            source_url: "tcp://127.0.0.1:28332".parse().unwrap(),
+        };
+        assert_eq!(
+            msg.to_string(),
+            "Event(Disconnected, tcp://127.0.0.1:28332)"
+        );
+
+        #[cfg(feature = "async")]
+        {
+            use crate::{Message, SocketMessage};
+            use bitcoin::{hashes::Hash, BlockHash};
+
+            assert_eq!(
+                SocketMessage::<Message>::Event(msg).to_string(),
+                "Event(Disconnected, tcp://127.0.0.1:28332)"
+            );
+            let msg = Message::HashBlock(BlockHash::all_zeros(), 1);
+            assert_eq!(
+                SocketMessage::Message(msg.clone()).to_string(),
+                msg.to_string()
+            );
+        }
+    }
+}
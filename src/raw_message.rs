@@ -0,0 +1,132 @@
+use crate::{
+    error::Result,
+    message::{Message, SEQUENCE_LEN},
+};
+
+/// A type that can be parsed from the parts of a ZMQ message published by Bitcoin Core.
+///
+/// The subscribers created by [`SubscribeBuilder`] produce [`Message`]s by default. Use
+/// [`SubscribeBuilder::message_type`] to produce another type implementing this trait instead,
+/// for example one that only parses what is needed or that understands the messages of a fork,
+/// while reusing the socket, monitor and channel handling of this crate.
+///
+/// [`SubscribeBuilder`]: crate::SubscribeBuilder
+/// [`SubscribeBuilder::message_type`]: crate::SubscribeBuilder::message_type
+pub trait FromRawMessage: Sized {
+    /// Parses a message from its topic, data and (little endian) sequence part. The length of
+    /// the data part is already checked against the limits of the [`SubscribeBuilder`].
+    ///
+    /// [`SubscribeBuilder`]: crate::SubscribeBuilder
+    fn from_raw_message(topic: &[u8], data: &[u8], sequence: [u8; SEQUENCE_LEN]) -> Result<Self>;
+}
+
+/// A type that can be serialized to the parts of a ZMQ message, the counterpart of
+/// [`FromRawMessage`].
+pub trait IntoRawMessage {
+    /// Serializes this message to its topic, data and (little endian) sequence part.
+    fn into_raw_message(self) -> [Vec<u8>; 3];
+}
+
+impl FromRawMessage for Message {
+    #[inline]
+    fn from_raw_message(topic: &[u8], data: &[u8], sequence: [u8; SEQUENCE_LEN]) -> Result<Self> {
+        Self::from_parts(topic, data, sequence)
+    }
+}
+
+impl IntoRawMessage for Message {
+    #[inline]
+    fn into_raw_message(self) -> [Vec<u8>; 3] {
+        self.serialize_to_vecs()
+    }
+}
+
+impl IntoRawMessage for &Message {
+    #[inline]
+    fn into_raw_message(self) -> [Vec<u8>; 3] {
+        self.serialize_to_vecs()
+    }
+}
+
+/// A message that is not parsed, only its parts are copied.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RawMessage {
+    pub topic: Vec<u8>,
+    pub data: Vec<u8>,
+    pub sequence: u32,
+}
+
+impl FromRawMessage for RawMessage {
+    #[inline]
+    fn from_raw_message(topic: &[u8], data: &[u8], sequence: [u8; SEQUENCE_LEN]) -> Result<Self> {
+        Ok(Self {
+            topic: topic.to_vec(),
+            data: data.to_vec(),
+            sequence: u32::from_le_bytes(sequence),
+        })
+    }
+}
+
+impl IntoRawMessage for RawMessage {
+    #[inline]
+    fn into_raw_message(self) -> [Vec<u8>; 3] {
+        [self.topic, self.data, self.sequence.to_le_bytes().to_vec()]
+    }
+}
+
+impl TryFrom<RawMessage> for Message {
+    type Error = crate::Error;
+
+    #[inline]
+    fn try_from(raw: RawMessage) -> Result<Self> {
+        Self::from_parts(&raw.topic, &raw.data, raw.sequence.to_le_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FromRawMessage, IntoRawMessage, RawMessage};
+    use crate::{test_util::publish_until, Message, SubscribeBuilder};
+    use bitcoin::{hashes::Hash, BlockHash};
+
+    #[test]
+    fn raw_message_roundtrip() {
+        let msg = Message::HashBlock(BlockHash::all_zeros(), 7);
+        let [topic, data, sequence] = (&msg).into_raw_message();
+        let raw =
+            RawMessage::from_raw_message(&topic, &data, sequence.try_into().unwrap()).unwrap();
+        assert_eq!(raw.topic, b"hashblock");
+        assert_eq!(raw.sequence, 7);
+        assert_eq!(
+            raw.clone().into_raw_message(),
+            msg.clone().into_raw_message()
+        );
+        assert_eq!(Message::try_from(raw).unwrap(), msg);
+    }
+
+    #[test]
+    fn subscribe_raw_messages() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut subscription = SubscribeBuilder::new(&[&endpoint])
+            .message_type::<RawMessage>()
+            .subscription()
+            .unwrap();
+
+        let (raw, _) = publish_until(
+            &publisher,
+            |sequence| RawMessage {
+                topic: b"custom".to_vec(),
+                data: vec![1, 2, 3],
+                sequence,
+            },
+            || subscription.try_recv().unwrap(),
+        );
+        // unknown topics are passed through
+        assert_eq!(raw.topic, b"custom");
+        assert_eq!(raw.data, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::message::Message;
+use bitcoin::{consensus::encode::deserialize_hex, Transaction, Txid};
+use bitcoincore_rpc::{jsonrpc::serde_json, Client, Error, RpcApi};
+
+/// An RPC client that can look up several transactions at once.
+pub trait BatchRpcApi: RpcApi {
+    /// Calls `getrawtransaction` for every txid in `txids`, returning the results in the same
+    /// order. The default implementation makes a call per txid, [`Client`] sends them all in a
+    /// single JSON-RPC batch request.
+    fn get_raw_transactions(
+        &self,
+        txids: &[Txid],
+    ) -> Result<Vec<Result<Transaction, Error>>, Error> {
+        Ok(txids
+            .iter()
+            .map(|txid| self.get_raw_transaction(txid, None))
+            .collect())
+    }
+}
+
+impl BatchRpcApi for Client {
+    fn get_raw_transactions(
+        &self,
+        txids: &[Txid],
+    ) -> Result<Vec<Result<Transaction, Error>>, Error> {
+        if txids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let client = self.get_jsonrpc_client();
+        let params = txids
+            .iter()
+            .map(|txid| serde_json::value::to_raw_value(&[txid]))
+            .collect::<Result<Vec<_>, _>>()?;
+        let requests: Vec<_> = params
+            .iter()
+            .map(|params| client.build_request("getrawtransaction", Some(params)))
+            .collect();
+
+        Ok(client
+            .send_batch(&requests)?
+            .into_iter()
+            .map(|res| {
+                let res = res.ok_or_else(|| {
+                    Error::ReturnedError("no response to getrawtransaction in batch".to_owned())
+                })?;
+                let hex: String = res.result()?;
+                Ok(deserialize_hex(&hex)?)
+            })
+            .collect())
+    }
+}
+
+/// What [`TxEnricher`] does with a `hashtx` message of a transaction the node does not know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum NotFound {
+    /// Keep the `hashtx` message as it is.
+    #[default]
+    Keep,
+    /// Drop the message.
+    Drop,
+}
+
+/// Upgrades `hashtx` messages to `rawtx` messages by looking up the transactions with
+/// `getrawtransaction`, for nodes that only publish `zmqpubhashtx`.
+///
+/// A transaction may be unknown to the node by the time it is looked up, for example when it was
+/// evicted from the mempool, or confirmed and the node runs without `-txindex`. What happens to
+/// these messages is set with [`with_not_found`](TxEnricher::with_not_found).
+#[derive(Debug)]
+pub struct TxEnricher<C> {
+    client: C,
+    not_found: NotFound,
+    batch_len: usize,
+}
+
+impl<C: RpcApi> TxEnricher<C> {
+    /// Default maximum number of transactions looked up in a single batch request.
+    pub const DEFAULT_BATCH_LEN: usize = 100;
+
+    /// Creates a new [`TxEnricher`] that queries the node using `client`.
+    #[inline]
+    pub const fn new(client: C) -> Self {
+        Self {
+            client,
+            not_found: NotFound::Keep,
+            batch_len: Self::DEFAULT_BATCH_LEN,
+        }
+    }
+
+    /// Sets what to do with messages of unknown transactions. Defaults to [`NotFound::Keep`].
+    #[inline]
+    pub const fn with_not_found(mut self, not_found: NotFound) -> Self {
+        self.not_found = not_found;
+        self
+    }
+
+    /// Sets the maximum number of transactions looked up in a single batch request by
+    /// [`enrich_batch`](TxEnricher::enrich_batch).
+    #[inline]
+    pub const fn with_batch_len(mut self, batch_len: usize) -> Self {
+        self.batch_len = batch_len;
+        self
+    }
+
+    /// Returns the RPC client.
+    #[inline]
+    pub const fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Upgrades `msg` if it is a `hashtx` message, other messages are returned as they are.
+    /// Returns [`None`] if the transaction is unknown and [`NotFound::Drop`] is set.
+    pub fn enrich(&self, msg: Message) -> Result<Option<Message>, Error> {
+        let Message::HashTx(txid, sequence) = msg else {
+            return Ok(Some(msg));
+        };
+
+        self.upgrade(txid, sequence, self.client.get_raw_transaction(&txid, None))
+    }
+
+    fn upgrade(
+        &self,
+        txid: Txid,
+        sequence: u32,
+        res: Result<Transaction, Error>,
+    ) -> Result<Option<Message>, Error> {
+        match res {
+            Ok(tx) => Ok(Some(Message::Tx(tx, sequence))),
+            Err(err) if is_not_found(&err) => Ok(match self.not_found {
+                NotFound::Keep => Some(Message::HashTx(txid, sequence)),
+                NotFound::Drop => None,
+            }),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<C: BatchRpcApi> TxEnricher<C> {
+    /// Like [`enrich`](TxEnricher::enrich) for every message in `msgs`, looking up the
+    /// transactions in batches. The order of the messages is preserved.
+    pub fn enrich_batch(&self, msgs: Vec<Message>) -> Result<Vec<Message>, Error> {
+        let mut enriched = Vec::with_capacity(msgs.len());
+        let mut msgs = msgs.into_iter().peekable();
+
+        while msgs.peek().is_some() {
+            // take a run of messages with at most batch_len hashtx messages
+            let mut run = Vec::new();
+            let mut txids = Vec::new();
+            while let Some(msg) = msgs.next_if(|_| txids.len() < self.batch_len.max(1)) {
+                if let Message::HashTx(txid, _) = msg {
+                    txids.push(txid);
+                }
+                run.push(msg);
+            }
+
+            let mut txs = self.client.get_raw_transactions(&txids)?.into_iter();
+            for msg in run {
+                let Message::HashTx(txid, sequence) = msg else {
+                    enriched.push(msg);
+                    continue;
+                };
+                let res = txs.next().ok_or_else(|| {
+                    Error::ReturnedError("missing getrawtransaction result".to_owned())
+                })?;
+                enriched.extend(self.upgrade(txid, sequence, res)?);
+            }
+        }
+
+        Ok(enriched)
+    }
+}
+
+/// Returns `true` if `err` is Bitcoin Core's error for an unknown transaction.
+fn is_not_found(err: &Error) -> bool {
+    super::has_error_code(err, super::RPC_INVALID_ADDRESS_OR_KEY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchRpcApi, NotFound, TxEnricher};
+    use crate::rpc::RPC_INVALID_ADDRESS_OR_KEY;
+    use crate::Message;
+    use bitcoin::{
+        consensus::encode::serialize_hex, constants::genesis_block, hashes::Hash, Network, Txid,
+    };
+    use bitcoincore_rpc::{
+        jsonrpc::{self, error::RpcError, serde_json},
+        Error, RpcApi,
+    };
+    use core::cell::Cell;
+
+    /// A node that only knows the genesis coinbase transaction.
+    #[derive(Default)]
+    struct MockNode {
+        calls: Cell<usize>,
+    }
+
+    impl RpcApi for MockNode {
+        fn call<T: for<'a> jsonrpc::serde::Deserialize<'a>>(
+            &self,
+            cmd: &str,
+            args: &[serde_json::Value],
+        ) -> Result<T, Error> {
+            assert_eq!(cmd, "getrawtransaction");
+            self.calls.set(self.calls.get() + 1);
+
+            let tx = &genesis_block(Network::Bitcoin).txdata[0];
+            let txid: Txid = serde_json::from_value(args[0].clone()).unwrap();
+            if txid != tx.compute_txid() {
+                return Err(Error::JsonRpc(jsonrpc::Error::Rpc(RpcError {
+                    code: RPC_INVALID_ADDRESS_OR_KEY,
+                    message: "No such mempool or blockchain transaction".to_owned(),
+                    data: None,
+                })));
+            }
+
+            Ok(serde_json::from_value(serialize_hex(tx).into())?)
+        }
+    }
+
+    impl BatchRpcApi for MockNode {}
+
+    #[test]
+    fn enrich_hashtx() {
+        let block = genesis_block(Network::Bitcoin);
+        let tx = block.txdata[0].clone();
+        let unknown = Txid::all_zeros();
+
+        let enricher = TxEnricher::new(MockNode::default());
+        assert_eq!(
+            enricher
+                .enrich(Message::HashTx(tx.compute_txid(), 1))
+                .unwrap(),
+            Some(Message::Tx(tx.clone(), 1))
+        );
+        assert_eq!(
+            enricher.enrich(Message::HashTx(unknown, 2)).unwrap(),
+            Some(Message::HashTx(unknown, 2))
+        );
+        let hashblock = Message::HashBlock(block.block_hash(), 3);
+        assert_eq!(
+            enricher.enrich(hashblock.clone()).unwrap(),
+            Some(hashblock.clone())
+        );
+
+        let enricher = TxEnricher::new(MockNode::default())
+            .with_not_found(NotFound::Drop)
+            .with_batch_len(2);
+        let enriched = enricher
+            .enrich_batch(vec![
+                Message::HashTx(tx.compute_txid(), 4),
+                hashblock.clone(),
+                Message::HashTx(unknown, 5),
+                Message::HashTx(tx.compute_txid(), 6),
+            ])
+            .unwrap();
+        assert_eq!(
+            enriched,
+            [Message::Tx(tx.clone(), 4), hashblock, Message::Tx(tx, 6)]
+        );
+        assert_eq!(enricher.client().calls.get(), 3);
+    }
+}
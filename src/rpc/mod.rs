@@ -0,0 +1,28 @@
+//! Helpers that combine ZMQ notifications with queries to the node's JSON-RPC interface, using
+//! [`bitcoincore_rpc`].
+
+mod enrich;
+mod prevout;
+mod removal;
+mod tip;
+mod undo;
+
+pub use self::{
+    enrich::{BatchRpcApi, NotFound, TxEnricher},
+    prevout::{PrevoutResolver, TxFee},
+    removal::{RemovalClassifier, RemovalReason},
+    tip::{initial_tip, INITIAL_TIP_SEQUENCE},
+    undo::block_undo,
+};
+
+use bitcoincore_rpc::{jsonrpc, Error};
+
+/// Bitcoin Core's error code for unknown transactions (`RPC_INVALID_ADDRESS_OR_KEY`).
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+/// The JSON-RPC error code for unknown methods (`RPC_METHOD_NOT_FOUND`).
+const RPC_METHOD_NOT_FOUND: i32 = -32601;
+
+/// Returns `true` if `err` is an error returned by the node with error code `code`.
+fn has_error_code(err: &Error, code: i32) -> bool {
+    matches!(err, Error::JsonRpc(jsonrpc::Error::Rpc(err)) if err.code == code)
+}
@@ -0,0 +1,317 @@
+use crate::message::Message;
+use bitcoin::{Amount, FeeRate, OutPoint, Transaction, TxOut, Weight};
+use bitcoincore_rpc::{Error, RpcApi};
+use std::collections::{HashMap, VecDeque};
+
+/// The fee of a transaction, computed from the values of the outputs it spends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxFee {
+    /// The values of the spent outputs, in the order of the inputs.
+    pub input_values: Vec<Amount>,
+    /// The fee, the total input value minus the total output value.
+    pub fee: Amount,
+    /// The weight of the transaction.
+    pub weight: Weight,
+    /// The fee divided by the weight.
+    pub feerate: FeeRate,
+}
+
+/// Resolves the outputs spent by transactions (prevouts) to compute their fees, which `rawtx`
+/// messages lack.
+///
+/// Outputs of transactions and blocks passed to [`process`] are cached, so transactions spending
+/// recent outputs are resolved without querying the node. Other prevouts are looked up with
+/// `gettxout`, which finds unspent outputs in the UTXO set (even if a mempool transaction spends
+/// them), and then with `getrawtransaction`, which finds outputs of mempool transactions (and of
+/// confirmed ones when the node runs with `-txindex`). Looked up outputs are cached too.
+///
+/// The cache holds at most [`cache_len`] outputs, the oldest are evicted first.
+///
+/// [`process`]: PrevoutResolver::process
+/// [`cache_len`]: PrevoutResolver::with_cache_len
+#[derive(Debug)]
+pub struct PrevoutResolver<C> {
+    client: C,
+    cache: HashMap<OutPoint, TxOut>,
+    order: VecDeque<OutPoint>,
+    cache_len: usize,
+}
+
+impl<C: RpcApi> PrevoutResolver<C> {
+    /// Default maximum number of cached outputs.
+    pub const DEFAULT_CACHE_LEN: usize = 100_000;
+
+    /// Creates a new [`PrevoutResolver`] that queries the node using `client`.
+    #[inline]
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            cache: HashMap::new(),
+            order: VecDeque::new(),
+            cache_len: Self::DEFAULT_CACHE_LEN,
+        }
+    }
+
+    /// Sets the maximum number of cached outputs.
+    #[inline]
+    pub fn with_cache_len(mut self, cache_len: usize) -> Self {
+        self.cache_len = cache_len;
+        self.evict();
+        self
+    }
+
+    /// Returns the RPC client.
+    #[inline]
+    pub const fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Returns the number of cached outputs.
+    #[inline]
+    pub fn cached(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Caches the outputs of the transactions in `rawtx` and `rawblock` messages, other messages
+    /// are ignored.
+    pub fn process(&mut self, msg: &Message) {
+        match msg {
+            Message::Tx(tx, _) => self.insert_outputs(tx),
+            Message::Block(block, _) => {
+                for tx in &block.txdata {
+                    self.insert_outputs(tx);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the output `outpoint` refers to.
+    pub fn resolve(&mut self, outpoint: OutPoint) -> Result<TxOut, Error> {
+        if let Some(txout) = self.cache.get(&outpoint) {
+            return Ok(txout.clone());
+        }
+
+        let txout = match self
+            .client
+            .get_tx_out(&outpoint.txid, outpoint.vout, Some(false))?
+        {
+            Some(res) => TxOut {
+                value: res.value,
+                script_pubkey: res
+                    .script_pub_key
+                    .script()
+                    .map_err(|_| Error::UnexpectedStructure)?,
+            },
+            None => {
+                let tx = self.client.get_raw_transaction(&outpoint.txid, None)?;
+                let txout = tx
+                    .output
+                    .get(outpoint.vout as usize)
+                    .ok_or_else(|| {
+                        Error::ReturnedError(format!("output {outpoint} does not exist"))
+                    })?
+                    .clone();
+                // the other outputs are likely spent by other transactions seen soon
+                self.insert_outputs(&tx);
+                txout
+            }
+        };
+        self.insert(outpoint, txout.clone());
+
+        Ok(txout)
+    }
+
+    /// Computes the fee of `tx`. Returns [`None`] for coinbase transactions, which have no fee.
+    pub fn tx_fee(&mut self, tx: &Transaction) -> Result<Option<TxFee>, Error> {
+        if tx.is_coinbase() {
+            return Ok(None);
+        }
+
+        let input_values = tx
+            .input
+            .iter()
+            .map(|input| Ok(self.resolve(input.previous_output)?.value))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let input_value = input_values
+            .iter()
+            .try_fold(Amount::ZERO, |sum, value| sum.checked_add(*value));
+        let output_value = tx
+            .output
+            .iter()
+            .try_fold(Amount::ZERO, |sum, output| sum.checked_add(output.value));
+        let fee = input_value
+            .zip(output_value)
+            .and_then(|(input, output)| input.checked_sub(output))
+            .ok_or_else(|| {
+                Error::ReturnedError(format!(
+                    "outputs of {} exceed its inputs",
+                    tx.compute_txid()
+                ))
+            })?;
+
+        let weight = tx.weight();
+        // a transaction always has a non-zero weight
+        let feerate = fee / weight;
+
+        Ok(Some(TxFee {
+            input_values,
+            fee,
+            weight,
+            feerate,
+        }))
+    }
+
+    /// Caches the outputs of the transaction in `msg` and computes its fee. Returns [`None`] for
+    /// messages other than `rawtx` and for coinbase transactions.
+    pub fn annotate(&mut self, msg: &Message) -> Result<Option<TxFee>, Error> {
+        let Message::Tx(tx, _) = msg else {
+            return Ok(None);
+        };
+
+        self.insert_outputs(tx);
+        self.tx_fee(tx)
+    }
+
+    /// Returns the fee of `tx`, or [`None`] if it could not be computed. Useful as fee lookup
+    /// function for [`FeeHistogram`] and [`MempoolGraph`].
+    ///
+    /// [`FeeHistogram`]: crate::FeeHistogram
+    /// [`MempoolGraph`]: crate::MempoolGraph
+    #[inline]
+    pub fn fee(&mut self, tx: &Transaction) -> Option<Amount> {
+        Some(self.tx_fee(tx).ok()??.fee)
+    }
+
+    fn insert_outputs(&mut self, tx: &Transaction) {
+        let txid = tx.compute_txid();
+        for (vout, txout) in tx.output.iter().enumerate() {
+            self.insert(OutPoint::new(txid, vout as u32), txout.clone());
+        }
+    }
+
+    fn insert(&mut self, outpoint: OutPoint, txout: TxOut) {
+        if self.cache.insert(outpoint, txout).is_none() {
+            self.order.push_back(outpoint);
+            self.evict();
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.cache.len() > self.cache_len {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.cache.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrevoutResolver;
+    use crate::{test_util::tx, Message};
+    use bitcoin::{
+        consensus::encode::serialize_hex, hashes::Hash, Amount, OutPoint, Transaction, Txid,
+    };
+    use bitcoincore_rpc::{
+        jsonrpc::{self, serde_json},
+        Error, RpcApi,
+    };
+    use std::cell::Cell;
+
+    /// A node with one confirmed unspent output of 1 BTC and one mempool transaction.
+    struct MockNode {
+        utxo: OutPoint,
+        mempool_tx: Transaction,
+        calls: Cell<usize>,
+    }
+
+    impl RpcApi for MockNode {
+        fn call<T: for<'a> jsonrpc::serde::Deserialize<'a>>(
+            &self,
+            cmd: &str,
+            args: &[serde_json::Value],
+        ) -> Result<T, Error> {
+            self.calls.set(self.calls.get() + 1);
+            let txid: Txid = serde_json::from_value(args[0].clone()).unwrap();
+            let value = match cmd {
+                "gettxout" => {
+                    let vout: u32 = serde_json::from_value(args[1].clone()).unwrap();
+                    if OutPoint::new(txid, vout) == self.utxo {
+                        serde_json::json!({
+                            "bestblock": Txid::all_zeros(),
+                            "confirmations": 1,
+                            "value": 1.0,
+                            "scriptPubKey": {
+                                "asm": "",
+                                "hex": "",
+                                "type": "nonstandard",
+                            },
+                            "coinbase": false,
+                        })
+                    } else {
+                        serde_json::Value::Null
+                    }
+                }
+                "getrawtransaction" if txid == self.mempool_tx.compute_txid() => {
+                    serialize_hex(&self.mempool_tx).into()
+                }
+                "getrawtransaction" => {
+                    return Err(Error::ReturnedError("no such transaction".to_owned()))
+                }
+                _ => unreachable!("unexpected call to {cmd}"),
+            };
+
+            Ok(serde_json::from_value(value)?)
+        }
+    }
+
+    #[test]
+    fn tx_fee() {
+        let utxo = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let mempool_tx = tx(
+            &[OutPoint::new(Txid::from_byte_array([2; 32]), 0)],
+            &[50_000],
+        );
+        let mempool_txid = mempool_tx.compute_txid();
+        let mut resolver = PrevoutResolver::new(MockNode {
+            utxo,
+            mempool_tx,
+            calls: Cell::new(0),
+        });
+
+        let spend = tx(&[utxo, OutPoint::new(mempool_txid, 0)], &[99_990_000]);
+        let fee = resolver
+            .annotate(&Message::Tx(spend.clone(), 0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            fee.input_values,
+            [Amount::ONE_BTC, Amount::from_sat(50_000)]
+        );
+        assert_eq!(fee.fee, Amount::from_sat(60_000));
+        assert_eq!(fee.feerate, Amount::from_sat(60_000) / spend.weight());
+
+        // everything is cached now, including the outputs of the annotated transaction
+        let calls = resolver.client().calls.get();
+        let child = tx(
+            &[OutPoint::new(spend.compute_txid(), 0), utxo],
+            &[100_000_000],
+        );
+        assert_eq!(resolver.fee(&child), Some(Amount::from_sat(99_990_000)));
+        assert_eq!(resolver.client().calls.get(), calls);
+
+        // unknown prevout
+        let unknown = tx(&[OutPoint::new(Txid::from_byte_array([3; 32]), 0)], &[1]);
+        assert!(resolver.tx_fee(&unknown).is_err());
+
+        // outputs larger than inputs
+        let invalid = tx(&[utxo], &[200_000_000]);
+        assert!(resolver.tx_fee(&invalid).is_err());
+
+        let resolver = resolver.with_cache_len(2);
+        assert_eq!(resolver.cached(), 2);
+    }
+}
@@ -0,0 +1,333 @@
+use super::{has_error_code, RPC_INVALID_ADDRESS_OR_KEY, RPC_METHOD_NOT_FOUND};
+use bitcoin::{Transaction, Txid};
+use bitcoincore_rpc::{jsonrpc::serde_json, Error, RpcApi};
+use core::time::Duration;
+use std::time::SystemTime;
+
+/// Best-effort reason why a transaction was removed from the mempool, see
+/// [`RemovalClassifier`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// The transaction was confirmed. This is a heuristic: one of its outputs is in the UTXO
+    /// set, or, when all of them are spent already, `getrawtransaction` returns the block it was
+    /// confirmed in. The latter needs the node's `-txindex` option, without it a confirmed
+    /// transaction whose outputs were all spent is classified as [`Conflicted`].
+    ///
+    /// [`Conflicted`]: RemovalReason::Conflicted
+    Confirmed,
+    /// A transaction currently in the mempool spends one of the same inputs (RBF).
+    Replaced { by: Txid },
+    /// One of the inputs is spent by a confirmed transaction, or the transaction it spends from
+    /// is gone.
+    Conflicted,
+    /// All inputs are still unspent and the transaction was older than the mempool expiry.
+    Expired,
+    /// All inputs are still unspent, the transaction was likely evicted because the mempool was
+    /// full.
+    Evicted,
+}
+
+/// Classifies [`MempoolRemoval`] messages by querying the node.
+///
+/// `sequence` messages only contain the txid of a removed transaction, the classifier needs the
+/// transaction itself (for example from an earlier `rawtx` message) to inspect its inputs.
+/// Detecting replacements uses the `gettxspendingprevout` RPC, which requires Bitcoin Core 24 or
+/// later.
+///
+/// The node's state may have changed since the removal, so the result is only a best guess.
+///
+/// [`MempoolRemoval`]: crate::SequenceMessage::MempoolRemoval
+#[derive(Debug)]
+pub struct RemovalClassifier<C> {
+    client: C,
+    mempool_expiry: Duration,
+}
+
+impl<C: RpcApi> RemovalClassifier<C> {
+    /// Bitcoin Core's default value of the `-mempoolexpiry` option, 336 hours.
+    pub const DEFAULT_MEMPOOL_EXPIRY: Duration = Duration::from_secs(336 * 60 * 60);
+
+    /// Creates a new [`RemovalClassifier`] that queries the node using `client`.
+    #[inline]
+    pub const fn new(client: C) -> Self {
+        Self {
+            client,
+            mempool_expiry: Self::DEFAULT_MEMPOOL_EXPIRY,
+        }
+    }
+
+    /// Sets the mempool expiry of the node, if it is configured with `-mempoolexpiry`.
+    #[inline]
+    pub const fn with_mempool_expiry(mut self, mempool_expiry: Duration) -> Self {
+        self.mempool_expiry = mempool_expiry;
+        self
+    }
+
+    /// Returns the RPC client.
+    #[inline]
+    pub const fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Classifies the removal of `tx`. `first_seen` is the time the transaction entered the
+    /// mempool, if known, used to tell expiry and eviction apart.
+    pub fn classify(
+        &self,
+        tx: &Transaction,
+        first_seen: Option<SystemTime>,
+    ) -> Result<RemovalReason, Error> {
+        let txid = tx.compute_txid();
+
+        for vout in 0..tx.output.len() as u32 {
+            if self.client.get_tx_out(&txid, vout, Some(false))?.is_some() {
+                return Ok(RemovalReason::Confirmed);
+            }
+        }
+
+        if let Some(by) = self.mempool_spender(tx, txid)? {
+            return Ok(RemovalReason::Replaced { by });
+        }
+
+        for input in &tx.input {
+            let prevout = input.previous_output;
+            if self
+                .client
+                .get_tx_out(&prevout.txid, prevout.vout, Some(true))?
+                .is_none()
+            {
+                // a confirmed transaction spends its inputs too
+                return Ok(if self.in_block(txid)? {
+                    RemovalReason::Confirmed
+                } else {
+                    RemovalReason::Conflicted
+                });
+            }
+        }
+
+        let expired = first_seen
+            .and_then(|first_seen| first_seen.elapsed().ok())
+            .is_some_and(|age| age >= self.mempool_expiry);
+
+        Ok(if expired {
+            RemovalReason::Expired
+        } else {
+            RemovalReason::Evicted
+        })
+    }
+
+    /// Returns `true` if the node knows a block containing `txid`. Confirmed transactions are
+    /// only found with `-txindex`.
+    fn in_block(&self, txid: Txid) -> Result<bool, Error> {
+        let args = [serde_json::to_value(txid)?, true.into()];
+        match self
+            .client
+            .call::<serde_json::Value>("getrawtransaction", &args)
+        {
+            Ok(info) => Ok(info.get("blockhash").is_some_and(|hash| !hash.is_null())),
+            Err(err) if has_error_code(&err, RPC_INVALID_ADDRESS_OR_KEY) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns a mempool transaction other than `txid` spending one of the inputs of `tx`.
+    fn mempool_spender(&self, tx: &Transaction, txid: Txid) -> Result<Option<Txid>, Error> {
+        if tx.is_coinbase() {
+            return Ok(None);
+        }
+
+        let outpoints: Vec<_> = tx
+            .input
+            .iter()
+            .map(|input| {
+                serde_json::json!({
+                    "txid": input.previous_output.txid,
+                    "vout": input.previous_output.vout,
+                })
+            })
+            .collect();
+
+        let spenders: Vec<serde_json::Value> = match self
+            .client
+            .call("gettxspendingprevout", &[outpoints.into()])
+        {
+            Ok(spenders) => spenders,
+            // not supported by the node
+            Err(err) if has_error_code(&err, RPC_METHOD_NOT_FOUND) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        Ok(spenders
+            .iter()
+            .filter_map(|spender| spender.get("spendingtxid")?.as_str()?.parse().ok())
+            .find(|spender| *spender != txid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RemovalClassifier, RemovalReason};
+    use crate::{
+        rpc::{RPC_INVALID_ADDRESS_OR_KEY, RPC_METHOD_NOT_FOUND},
+        test_util::tx,
+    };
+    use bitcoin::{hashes::Hash, BlockHash, OutPoint, Txid};
+    use bitcoincore_rpc::{
+        jsonrpc::{self, error::RpcError, serde_json},
+        Error, RpcApi,
+    };
+    use std::time::{Duration, SystemTime};
+
+    /// A node that knows about one unspent output and optionally a replacement and a block
+    /// containing the classified transaction. Nodes before Bitcoin Core 24 do not know
+    /// `gettxspendingprevout`.
+    struct MockNode {
+        utxo: OutPoint,
+        replacement: Option<Txid>,
+        block: Option<BlockHash>,
+        before_v24: bool,
+    }
+
+    impl MockNode {
+        fn new(utxo: OutPoint) -> Self {
+            Self {
+                utxo,
+                replacement: None,
+                block: None,
+                before_v24: false,
+            }
+        }
+    }
+
+    fn rpc_error(code: i32) -> Error {
+        Error::JsonRpc(jsonrpc::Error::Rpc(RpcError {
+            code,
+            message: String::new(),
+            data: None,
+        }))
+    }
+
+    impl RpcApi for MockNode {
+        fn call<T: for<'a> jsonrpc::serde::Deserialize<'a>>(
+            &self,
+            cmd: &str,
+            args: &[serde_json::Value],
+        ) -> Result<T, Error> {
+            let value = match cmd {
+                "gettxout" => {
+                    let txid: Txid = serde_json::from_value(args[0].clone()).unwrap();
+                    let vout: u32 = serde_json::from_value(args[1].clone()).unwrap();
+                    if OutPoint::new(txid, vout) == self.utxo {
+                        serde_json::json!({
+                            "bestblock": Txid::all_zeros(),
+                            "confirmations": 1,
+                            "value": 1.0,
+                            "scriptPubKey": {
+                                "asm": "",
+                                "hex": "",
+                                "type": "nonstandard",
+                            },
+                            "coinbase": false,
+                        })
+                    } else {
+                        serde_json::Value::Null
+                    }
+                }
+                "gettxspendingprevout" if self.before_v24 => {
+                    return Err(rpc_error(RPC_METHOD_NOT_FOUND));
+                }
+                "gettxspendingprevout" => match self.replacement {
+                    Some(txid) => serde_json::json!([{ "spendingtxid": txid }]),
+                    None => serde_json::json!([{}]),
+                },
+                "getrawtransaction" => match self.block {
+                    Some(blockhash) => serde_json::json!({ "blockhash": blockhash }),
+                    None => return Err(rpc_error(RPC_INVALID_ADDRESS_OR_KEY)),
+                },
+                _ => unreachable!("unexpected call to {cmd}"),
+            };
+
+            Ok(serde_json::from_value(value)?)
+        }
+    }
+
+    #[test]
+    fn classify() {
+        let utxo = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let tx = tx(&[utxo], &[1000]);
+
+        let classifier = RemovalClassifier::new(MockNode::new(utxo));
+        assert_eq!(
+            classifier.classify(&tx, None).unwrap(),
+            RemovalReason::Evicted
+        );
+        let old = SystemTime::now() - Duration::from_secs(400 * 60 * 60);
+        assert_eq!(
+            classifier.classify(&tx, Some(old)).unwrap(),
+            RemovalReason::Expired
+        );
+
+        let by = Txid::from_byte_array([2; 32]);
+        let classifier = RemovalClassifier::new(MockNode {
+            replacement: Some(by),
+            ..MockNode::new(utxo)
+        });
+        assert_eq!(
+            classifier.classify(&tx, None).unwrap(),
+            RemovalReason::Replaced { by }
+        );
+        // replacements are not detected without gettxspendingprevout
+        let classifier = RemovalClassifier::new(MockNode {
+            replacement: Some(by),
+            before_v24: true,
+            ..MockNode::new(utxo)
+        });
+        assert_eq!(
+            classifier.classify(&tx, None).unwrap(),
+            RemovalReason::Evicted
+        );
+
+        let classifier = RemovalClassifier::new(MockNode::new(OutPoint::null()));
+        assert_eq!(
+            classifier.classify(&tx, None).unwrap(),
+            RemovalReason::Conflicted
+        );
+
+        let classifier = RemovalClassifier::new(MockNode::new(OutPoint::new(tx.compute_txid(), 0)));
+        assert_eq!(
+            classifier.classify(&tx, None).unwrap(),
+            RemovalReason::Confirmed
+        );
+        // the outputs of a confirmed transaction may be spent already
+        let classifier = RemovalClassifier::new(MockNode {
+            block: Some(BlockHash::all_zeros()),
+            ..MockNode::new(OutPoint::null())
+        });
+        assert_eq!(
+            classifier.classify(&tx, None).unwrap(),
+            RemovalReason::Confirmed
+        );
+    }
+
+    #[test]
+    fn rpc_errors_are_not_unsupported() {
+        struct FailingNode;
+
+        impl RpcApi for FailingNode {
+            fn call<T: for<'a> jsonrpc::serde::Deserialize<'a>>(
+                &self,
+                cmd: &str,
+                _args: &[serde_json::Value],
+            ) -> Result<T, Error> {
+                // any other error than RPC_METHOD_NOT_FOUND is not mistaken for an old node
+                assert_eq!(cmd, "gettxspendingprevout");
+                Err(rpc_error(-1))
+            }
+        }
+
+        // without outputs gettxout is not called
+        let tx = tx(&[OutPoint::new(Txid::from_byte_array([1; 32]), 0)], &[]);
+        assert!(RemovalClassifier::new(FailingNode)
+            .classify(&tx, None)
+            .is_err());
+    }
+}
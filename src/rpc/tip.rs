@@ -0,0 +1,60 @@
+use crate::message::Message;
+use bitcoincore_rpc::{Error, RpcApi};
+
+/// The sequence number of the synthetic message returned by [`initial_tip`]. Bitcoin Core would
+/// only reach it after publishing 2^32 - 1 `hashblock` messages, so consumers can tell the
+/// synthetic message apart from real ones, for example to not count the jump to the first real
+/// sequence number as missed messages.
+pub const INITIAL_TIP_SEQUENCE: u32 = u32::MAX;
+
+/// Returns a synthetic `hashblock` message for the node's current best block, with sequence
+/// number [`INITIAL_TIP_SEQUENCE`].
+///
+/// Call this after subscribing, or use [`SubscribeBuilder::initial_tip`] to receive it as the
+/// first message after the handshake, so consumers start from the current chain tip instead of
+/// waiting for the next block. A block found in between is also received as a real message.
+///
+/// [`SubscribeBuilder::initial_tip`]: crate::SubscribeBuilder::initial_tip
+pub fn initial_tip<C: RpcApi>(client: &C) -> Result<Message, Error> {
+    Ok(Message::HashBlock(
+        client.get_best_block_hash()?,
+        INITIAL_TIP_SEQUENCE,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{initial_tip, INITIAL_TIP_SEQUENCE};
+    use crate::Message;
+    use bitcoin::{constants::genesis_block, Network};
+    use bitcoincore_rpc::{
+        jsonrpc::{self, serde_json},
+        Error, RpcApi,
+    };
+
+    /// A node that is still at the genesis block.
+    struct MockNode;
+
+    impl RpcApi for MockNode {
+        fn call<T: for<'a> jsonrpc::serde::Deserialize<'a>>(
+            &self,
+            cmd: &str,
+            _args: &[serde_json::Value],
+        ) -> Result<T, Error> {
+            assert_eq!(cmd, "getbestblockhash");
+            let blockhash = genesis_block(Network::Regtest).block_hash();
+            Ok(serde_json::from_value(blockhash.to_string().into())?)
+        }
+    }
+
+    #[test]
+    fn initial_tip_is_best_block() {
+        assert_eq!(
+            initial_tip(&MockNode).unwrap(),
+            Message::HashBlock(
+                genesis_block(Network::Regtest).block_hash(),
+                INITIAL_TIP_SEQUENCE
+            )
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use bitcoin::{Amount, BlockHash, ScriptBuf, TxOut};
+use bitcoincore_rpc::{jsonrpc::serde_json, Error, RpcApi};
+
+/// Returns the outputs spent by the block with hash `blockhash`, in the order of its inputs
+/// (except the coinbase input), like Bitcoin Core's undo data. Use this as undo function of
+/// [`UtxoDeltaTracker`].
+///
+/// Uses `getblock` with verbosity 3, which requires Bitcoin Core 23 or later. The node needs the
+/// undo data of the block, so pruned nodes can only look up recent blocks.
+///
+/// [`UtxoDeltaTracker`]: crate::UtxoDeltaTracker
+pub fn block_undo<C: RpcApi>(client: &C, blockhash: &BlockHash) -> Result<Vec<TxOut>, Error> {
+    let block: serde_json::Value =
+        client.call("getblock", &[blockhash.to_string().into(), 3.into()])?;
+
+    let mut undo = Vec::new();
+    for tx in block["tx"].as_array().ok_or(Error::UnexpectedStructure)? {
+        for input in tx["vin"].as_array().ok_or(Error::UnexpectedStructure)? {
+            if input.get("coinbase").is_some() {
+                continue;
+            }
+
+            let prevout = input.get("prevout").ok_or(Error::UnexpectedStructure)?;
+            let value = prevout["value"]
+                .as_f64()
+                .and_then(|btc| Amount::from_btc(btc).ok())
+                .ok_or(Error::UnexpectedStructure)?;
+            let script_pubkey = prevout["scriptPubKey"]["hex"]
+                .as_str()
+                .and_then(|hex| ScriptBuf::from_hex(hex).ok())
+                .ok_or(Error::UnexpectedStructure)?;
+
+            undo.push(TxOut {
+                value,
+                script_pubkey,
+            });
+        }
+    }
+
+    Ok(undo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::block_undo;
+    use bitcoin::{hashes::Hash, Amount, BlockHash, ScriptBuf};
+    use bitcoincore_rpc::{
+        jsonrpc::{self, serde_json},
+        Error, RpcApi,
+    };
+
+    struct MockNode;
+
+    impl RpcApi for MockNode {
+        fn call<T: for<'a> jsonrpc::serde::Deserialize<'a>>(
+            &self,
+            cmd: &str,
+            args: &[serde_json::Value],
+        ) -> Result<T, Error> {
+            assert_eq!(cmd, "getblock");
+            assert_eq!(args[1], 3);
+
+            Ok(serde_json::from_value(serde_json::json!({
+                "tx": [
+                    { "vin": [{ "coinbase": "00" }] },
+                    { "vin": [
+                        { "prevout": { "value": 0.5, "scriptPubKey": { "hex": "51" } } },
+                        { "prevout": { "value": 0.00000001, "scriptPubKey": { "hex": "" } } },
+                    ] },
+                ],
+            }))?)
+        }
+    }
+
+    #[test]
+    fn undo() {
+        let undo = block_undo(&MockNode, &BlockHash::all_zeros()).unwrap();
+        assert_eq!(undo.len(), 2);
+        assert_eq!(undo[0].value, Amount::from_sat(50_000_000));
+        assert_eq!(undo[0].script_pubkey, ScriptBuf::from_bytes(vec![0x51]));
+        assert_eq!(undo[1].value, Amount::ONE_SAT);
+    }
+}
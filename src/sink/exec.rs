@@ -0,0 +1,339 @@
+use super::{hash, height, NotificationSink};
+use crate::{message::Message, topic::Topic};
+use core::fmt;
+use std::{
+    error, io,
+    process::{Child, Command, ExitStatus},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::SystemTime,
+};
+
+/// Runs a shell command for every message, like Bitcoin Core's `-blocknotify` and
+/// `-walletnotify` options.
+///
+/// The following placeholders in the command are replaced before it is run:
+/// - `%s`: the block hash or txid
+/// - `%t`: the topic, for example `hashblock`
+/// - `%h`: the block height, only known for `rawblock` messages of blocks with a BIP34 height in
+///   the coinbase, empty otherwise
+/// - `%n`: the sequence number of the message
+/// - `%%`: a literal `%`
+///
+/// The command is run with `sh -c` (`cmd /C` on Windows). Commands run concurrently, but at most
+/// [`max_concurrent`] at a time. Commands that cannot be started or exit unsuccessfully are
+/// counted, see [`failures`]. The error of a command that cannot be started is returned by
+/// [`run`], the last command that exited unsuccessfully is kept for [`take_last_failure`].
+/// Nothing is logged.
+///
+/// [`max_concurrent`]: ExecHook::max_concurrent
+/// [`failures`]: ExecHook::failures
+/// [`run`]: ExecHook::run
+/// [`take_last_failure`]: ExecHook::take_last_failure
+#[derive(Debug, Clone)]
+pub struct ExecHook {
+    command: String,
+    topics: Option<Vec<Topic>>,
+    max_concurrent: usize,
+    state: Arc<(Mutex<ExecState>, Condvar)>,
+}
+
+#[derive(Debug, Default)]
+struct ExecState {
+    running: usize,
+    failures: u64,
+    last_failure: Option<ExecFailure>,
+}
+
+/// A command of an [`ExecHook`] that was started but did not exit successfully, see
+/// [`ExecHook::take_last_failure`].
+#[derive(Debug)]
+pub enum ExecFailure {
+    /// The command exited with an unsuccessful status.
+    Exit { command: String, status: ExitStatus },
+    /// Waiting for the command to exit failed.
+    Wait { command: String, error: io::Error },
+}
+
+impl ExecFailure {
+    /// Returns the command that failed, with all placeholders replaced.
+    #[inline]
+    pub fn command(&self) -> &str {
+        match self {
+            Self::Exit { command, .. } | Self::Wait { command, .. } => command,
+        }
+    }
+}
+
+impl fmt::Display for ExecFailure {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exit { command, status } => write!(f, "'{command}' exited with {status}"),
+            Self::Wait { command, error } => {
+                write!(f, "failed to wait for '{command}': {error}")
+            }
+        }
+    }
+}
+
+impl error::Error for ExecFailure {
+    #[inline]
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Exit { .. } => None,
+            Self::Wait { error, .. } => Some(error),
+        }
+    }
+}
+
+impl ExecHook {
+    /// Default for [`max_concurrent`](ExecHook::max_concurrent).
+    pub const DEFAULT_MAX_CONCURRENT: usize = 4;
+
+    /// Creates a hook that runs `command` for every message.
+    #[inline]
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            topics: None,
+            max_concurrent: Self::DEFAULT_MAX_CONCURRENT,
+            state: Arc::default(),
+        }
+    }
+
+    /// Only runs the command for messages on `topics`.
+    #[inline]
+    pub fn topics(mut self, topics: &[Topic]) -> Self {
+        self.topics = Some(topics.to_vec());
+        self
+    }
+
+    /// Sets how many commands may run at the same time. [`run`](ExecHook::run) blocks until one
+    /// exits when this many are running. Defaults to
+    /// [`DEFAULT_MAX_CONCURRENT`](ExecHook::DEFAULT_MAX_CONCURRENT).
+    #[inline]
+    pub const fn max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = if max_concurrent == 0 {
+            1
+        } else {
+            max_concurrent
+        };
+        self
+    }
+
+    /// Returns `true` if the command is run for messages on `topic`.
+    #[inline]
+    pub fn is_selected(&self, topic: Topic) -> bool {
+        self.topics
+            .as_ref()
+            .is_none_or(|topics| topics.contains(&topic))
+    }
+
+    /// Returns the command that would be run for `msg`, with all placeholders replaced.
+    pub fn command_for(&self, msg: &Message) -> String {
+        let mut out = String::with_capacity(self.command.len() + 64);
+        let mut chars = self.command.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('s') => out.push_str(&hash(msg)),
+                Some('t') => out.push_str(msg.topic_str()),
+                Some('h') => {
+                    if let Some(height) = height(msg) {
+                        out.push_str(&height.to_string());
+                    }
+                }
+                Some('n') => out.push_str(&msg.sequence().to_string()),
+                Some('%') => out.push('%'),
+                Some(other) => {
+                    out.push('%');
+                    out.push(other);
+                }
+                None => out.push('%'),
+            }
+        }
+
+        out
+    }
+
+    /// Starts the command for `msg` if its topic is selected and returns whether it was started.
+    /// Does not wait for the command to exit, but blocks while
+    /// [`max_concurrent`](ExecHook::max_concurrent) commands are running.
+    pub fn run(&self, msg: &Message) -> io::Result<bool> {
+        if !self.is_selected(msg.topic_type()) {
+            return Ok(false);
+        }
+
+        let command = self.command_for(msg);
+
+        let (lock, cvar) = &*self.state;
+        let mut state = lock_state(lock);
+        while state.running >= self.max_concurrent {
+            state = cvar.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        let child = match shell(&command).spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                state.failures += 1;
+                return Err(err);
+            }
+        };
+        state.running += 1;
+        drop(state);
+
+        let state = self.state.clone();
+        thread::spawn(move || wait_child(child, command, &state));
+
+        Ok(true)
+    }
+
+    /// Blocks until all started commands have exited.
+    pub fn wait(&self) {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock_state(lock);
+        while state.running > 0 {
+            state = cvar.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Returns the number of commands that are running.
+    #[inline]
+    pub fn running(&self) -> usize {
+        lock_state(&self.state.0).running
+    }
+
+    /// Returns the number of commands that could not be started or exited unsuccessfully.
+    #[inline]
+    pub fn failures(&self) -> u64 {
+        lock_state(&self.state.0).failures
+    }
+
+    /// Returns the last command that exited unsuccessfully since the last call, if any.
+    #[inline]
+    pub fn take_last_failure(&self) -> Option<ExecFailure> {
+        lock_state(&self.state.0).last_failure.take()
+    }
+}
+
+impl NotificationSink for ExecHook {
+    type Error = io::Error;
+
+    /// Starts the command for `msg`, see [`run`](ExecHook::run). The timestamp is not used.
+    #[inline]
+    fn write_at(&mut self, msg: &Message, _timestamp: SystemTime) -> io::Result<()> {
+        self.run(msg).map(drop)
+    }
+
+    /// Waits for all started commands, see [`wait`](ExecHook::wait).
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.wait();
+        Ok(())
+    }
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+fn lock_state(lock: &Mutex<ExecState>) -> MutexGuard<'_, ExecState> {
+    // the state stays consistent even if a thread panicked while holding the lock
+    lock.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn wait_child(mut child: Child, command: String, state: &(Mutex<ExecState>, Condvar)) {
+    let failure = match child.wait() {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(ExecFailure::Exit { command, status }),
+        Err(error) => Some(ExecFailure::Wait { command, error }),
+    };
+
+    let (lock, cvar) = state;
+    let mut state = lock_state(lock);
+    state.running -= 1;
+    if failure.is_some() {
+        state.failures += 1;
+        state.last_failure = failure;
+    }
+    drop(state);
+    cvar.notify_all();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{ExecFailure, ExecHook};
+    use crate::{Message, SequenceMessage, Topic};
+    use bitcoin::{hashes::Hash, BlockHash, Txid};
+    use std::fs;
+
+    #[test]
+    fn command_for() {
+        let hook = ExecHook::new("notify %t %s '%h' %n 100%% %x%");
+        assert_eq!(
+            hook.command_for(&Message::Sequence(
+                SequenceMessage::MempoolRemoval {
+                    txid: Txid::all_zeros(),
+                    mempool_sequence: 3
+                },
+                7
+            )),
+            format!("notify sequence {} '' 7 100% %x%", "0".repeat(64))
+        );
+    }
+
+    #[test]
+    fn exec_hook() {
+        let dir = std::env::temp_dir().join(format!("bitcoincore-zmq-exec-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let hook = ExecHook::new(format!("touch '{}/%n-%t'", dir.display()))
+            .topics(&[Topic::HashBlock])
+            .max_concurrent(1);
+        for sequence in 0..3 {
+            assert!(hook
+                .run(&Message::HashBlock(BlockHash::all_zeros(), sequence))
+                .unwrap());
+        }
+        assert!(!hook.run(&Message::HashTx(Txid::all_zeros(), 3)).unwrap());
+        hook.wait();
+
+        assert_eq!(hook.running(), 0);
+        assert_eq!(hook.failures(), 0);
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["0-hashblock", "1-hashblock", "2-hashblock"]);
+
+        let failing = ExecHook::new("exit 3");
+        failing
+            .run(&Message::HashBlock(BlockHash::all_zeros(), 0))
+            .unwrap();
+        failing.wait();
+        assert_eq!(failing.failures(), 1);
+        let failure = failing.take_last_failure().unwrap();
+        assert_eq!(failure.command(), "exit 3");
+        assert!(matches!(failure, ExecFailure::Exit { status, .. } if status.code() == Some(3)));
+        assert!(failing.take_last_failure().is_none());
+        assert!(hook.take_last_failure().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+use super::{to_json, NotificationSink};
+use crate::message::Message;
+use core::time::Duration;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Appends every message as a JSON object on its own line (JSON Lines) to a file, rotating the
+/// file when it grows too large or too old.
+///
+/// See the [module documentation](crate::sink) for the format of the objects. With
+/// [`include_payload`], the data part is included as hex in `payload`.
+///
+/// On rotation, the file is renamed to its path with the unix timestamp appended (for example
+/// `notifications.jsonl.1700000000`) and a new file is created at the original path.
+///
+/// [`include_payload`]: JsonlSink::include_payload
+#[derive(Debug)]
+pub struct JsonlSink {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened_at: SystemTime,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    include_payload: bool,
+}
+
+impl JsonlSink {
+    /// Opens the file at `path` for appending, creating it if it does not exist.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            opened_at: SystemTime::now(),
+            max_size: None,
+            max_age: None,
+            include_payload: false,
+        })
+    }
+
+    /// Rotates the file before a line would make it larger than `max_size` bytes.
+    #[inline]
+    pub const fn rotate_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Rotates the file when it has been written to for longer than `max_age`.
+    #[inline]
+    pub const fn rotate_interval(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Includes the data part of every message as hex. Disabled by default, as `rawblock`
+    /// payloads are large.
+    #[inline]
+    pub const fn include_payload(mut self, include_payload: bool) -> Self {
+        self.include_payload = include_payload;
+        self
+    }
+
+    /// Returns the path of the file that is currently written to.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Renames the current file and starts a new one.
+    pub fn rotate(&mut self, timestamp: SystemTime) -> io::Result<()> {
+        let secs = timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{secs}"));
+        let mut rotated = PathBuf::from(rotated);
+        let mut n = 1;
+        while rotated.exists() {
+            let mut with_counter = self.path.clone().into_os_string();
+            with_counter.push(format!(".{secs}-{n}"));
+            rotated = with_counter.into();
+            n += 1;
+        }
+
+        self.file.flush()?;
+        fs::rename(&self.path, &rotated)?;
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        self.opened_at = timestamp;
+
+        Ok(())
+    }
+}
+
+impl NotificationSink for JsonlSink {
+    type Error = io::Error;
+
+    /// Appends `msg` as received at `timestamp`.
+    fn write_at(&mut self, msg: &Message, timestamp: SystemTime) -> io::Result<()> {
+        let mut line = to_json(msg, timestamp, self.include_payload);
+        line.push('\n');
+
+        let too_large = self
+            .max_size
+            .is_some_and(|max| self.size > 0 && self.size + line.len() as u64 > max);
+        let too_old = self.max_age.is_some_and(|max| {
+            timestamp
+                .duration_since(self.opened_at)
+                .is_ok_and(|age| age >= max)
+        });
+        if too_large || too_old {
+            self.rotate(timestamp)?;
+        }
+
+        // a single write per line, so lines are never interleaved or split by a crash mid-buffer
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonlSink;
+    use crate::sink::NotificationSink;
+    use crate::{Message, SequenceMessage};
+    use bitcoin::{hashes::Hash, BlockHash, Txid};
+    use core::time::Duration;
+    use std::{
+        fs,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    #[test]
+    fn jsonl_sink() {
+        let dir =
+            std::env::temp_dir().join(format!("bitcoincore-zmq-jsonl-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notifications.jsonl");
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let mut sink = JsonlSink::open(&path)
+            .unwrap()
+            .rotate_size(500)
+            .include_payload(true);
+
+        sink.write_at(&Message::HashBlock(BlockHash::all_zeros(), 1), time)
+            .unwrap();
+        sink.write_at(
+            &Message::Sequence(
+                SequenceMessage::MempoolAcceptance {
+                    txid: Txid::all_zeros(),
+                    mempool_sequence: 7,
+                },
+                2,
+            ),
+            time,
+        )
+        .unwrap();
+
+        let zeros = "0".repeat(64);
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!(
+                r#"{{"topic":"hashblock","sequence":1,"timestamp":1700000000123,"hash":"{zeros}","payload":"{zeros}"}}"#
+            )
+        );
+        assert_eq!(
+            lines[1],
+            format!(
+                r#"{{"topic":"sequence","sequence":2,"timestamp":1700000000123,"hash":"{zeros}","label":"A","mempool_sequence":7,"payload":"{zeros}41{}"}}"#,
+                "0700000000000000"
+            )
+        );
+
+        // the next line does not fit anymore
+        sink.write_at(&Message::HashTx(Txid::all_zeros(), 3), time)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert_eq!(
+            fs::read_to_string(dir.join("notifications.jsonl.1700000000")).unwrap(),
+            content
+        );
+
+        let mut sink = JsonlSink::open(&path)
+            .unwrap()
+            .rotate_interval(Duration::from_secs(60));
+        sink.write(&Message::HashTx(Txid::all_zeros(), 4)).unwrap();
+        sink.write_at(
+            &Message::HashTx(Txid::all_zeros(), 5),
+            SystemTime::now() + Duration::from_secs(60),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! Sinks that persist or forward notifications.
+//!
+//! Sinks that serialize messages as JSON write one object per message, containing `topic`,
+//! `sequence`, `timestamp` (milliseconds since the unix epoch) and `hash` (the block hash or
+//! txid). `sequence` messages also contain `label` and, for mempool events, `mempool_sequence`.
+//! If enabled, the data part is included as hex in `payload`.
+//!
+//! All sinks implement [`NotificationSink`], custom sinks can implement it too or, when writing
+//! blocks on I/O, [`AsyncNotificationSink`].
+
+mod exec;
+mod jsonl;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "webhook")]
+mod webhook;
+
+#[cfg(feature = "parquet")]
+pub use self::parquet::ParquetSink;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::{SqliteSink, StoredNotification};
+#[cfg(feature = "webhook")]
+pub use self::webhook::{WebhookError, WebhookSink};
+pub use self::{
+    exec::{ExecFailure, ExecHook},
+    jsonl::JsonlSink,
+};
+
+use crate::{message::Message, sequence_message::SequenceMessage};
+use bitcoin::hex::DisplayHex;
+use core::fmt::Write as _;
+#[cfg(feature = "async")]
+use core::future::Future;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A destination for received messages, like a file, database or external service.
+pub trait NotificationSink {
+    /// The error returned when a message could not be written.
+    type Error;
+
+    /// Writes `msg`, received at `timestamp`.
+    fn write_at(&mut self, msg: &Message, timestamp: SystemTime) -> Result<(), Self::Error>;
+
+    /// Writes `msg`, received just now.
+    #[inline]
+    fn write(&mut self, msg: &Message) -> Result<(), Self::Error> {
+        self.write_at(msg, SystemTime::now())
+    }
+
+    /// Writes out messages buffered by the sink, if any.
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The async flavor of [`NotificationSink`], for sinks that write to async clients (databases,
+/// message brokers) without blocking the runtime.
+#[cfg(feature = "async")]
+pub trait AsyncNotificationSink {
+    /// The error returned when a message could not be written.
+    type Error;
+
+    /// Writes `msg`, received at `timestamp`.
+    fn write_at(
+        &mut self,
+        msg: &Message,
+        timestamp: SystemTime,
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// Writes `msg`, received just now.
+    #[inline]
+    fn write(&mut self, msg: &Message) -> impl Future<Output = Result<(), Self::Error>> {
+        self.write_at(msg, SystemTime::now())
+    }
+
+    /// Writes out messages buffered by the sink, if any.
+    #[inline]
+    fn flush(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        async { Ok(()) }
+    }
+}
+
+/// Serializes `msg` as a JSON object in the format described in the [module documentation](self).
+fn to_json(msg: &Message, timestamp: SystemTime, include_payload: bool) -> String {
+    let millis = timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    // writing to a String never fails
+    let mut line = String::new();
+    let _ = write!(
+        line,
+        r#"{{"topic":"{}","sequence":{},"timestamp":{millis}"#,
+        msg.topic_str(),
+        msg.sequence()
+    );
+    let _ = write!(line, r#","hash":"{}""#, hash(msg));
+    if let Message::Sequence(sm, _) = msg {
+        let _ = write!(line, r#","label":"{}""#, sm.label_char());
+        if let Some(mempool_sequence) = sm.mempool_sequence() {
+            let _ = write!(line, r#","mempool_sequence":{mempool_sequence}"#);
+        }
+    }
+    if include_payload {
+        let _ = write!(
+            line,
+            r#","payload":"{}""#,
+            msg.serialize_data_to_vec().as_hex()
+        );
+    }
+    line.push('}');
+
+    line
+}
+
+/// Returns the block hash or txid of `msg` as hex.
+fn hash(msg: &Message) -> String {
+    match msg {
+        Message::HashBlock(blockhash, _) => blockhash.to_string(),
+        Message::HashTx(txid, _) => txid.to_string(),
+        Message::Block(block, _) => block.block_hash().to_string(),
+        Message::Tx(tx, _) => tx.compute_txid().to_string(),
+        Message::Sequence(
+            SequenceMessage::BlockConnect { blockhash }
+            | SequenceMessage::BlockDisconnect { blockhash },
+            _,
+        ) => blockhash.to_string(),
+        Message::Sequence(
+            SequenceMessage::MempoolAcceptance { txid, .. }
+            | SequenceMessage::MempoolRemoval { txid, .. },
+            _,
+        ) => txid.to_string(),
+    }
+}
+
+/// Returns the height of the block in `msg`, only known for `rawblock` messages of blocks with a
+/// BIP34 height in the coinbase.
+fn height(msg: &Message) -> Option<u64> {
+    match msg {
+        Message::Block(block, _) => block.bip34_block_height().ok(),
+        _ => None,
+    }
+}
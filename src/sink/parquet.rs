@@ -0,0 +1,393 @@
+use super::{hash, NotificationSink};
+use crate::message::Message;
+use arrow_array::{
+    builder::{
+        BinaryBuilder, StringBuilder, TimestampMillisecondBuilder, UInt32Builder, UInt64Builder,
+    },
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use core::fmt;
+use parquet::{
+    arrow::ArrowWriter,
+    errors::{ParquetError, Result},
+    file::properties::WriterProperties,
+};
+use std::{
+    fs::File,
+    io,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Batches messages into Arrow record batches and writes them to Parquet files in a directory,
+/// for ingestion into analytics tools like DuckDB or Spark.
+///
+/// Every row has the columns `topic`, `hash` (the block hash or txid as hex), `sequence`,
+/// `timestamp` (UTC, millisecond precision) and `size` (the length of the data part). With
+/// [`include_raw`], the data part is also stored in the `raw` column.
+///
+/// Files are named `<prefix>-<unix milliseconds>.parquet`, or `<prefix>-<unix
+/// milliseconds>-<n>.parquet` if a file with that name already exists. Existing files are never
+/// overwritten. A new file is started when the
+/// current one holds [`rows_per_file`] rows. Files are only readable after they have been
+/// finished, which happens when a new file is started, on [`close`] and when the sink is
+/// dropped.
+///
+/// [`include_raw`]: ParquetSink::include_raw
+/// [`rows_per_file`]: ParquetSink::rows_per_file
+/// [`close`]: ParquetSink::close
+pub struct ParquetSink {
+    dir: PathBuf,
+    prefix: String,
+    batch_size: usize,
+    rows_per_file: usize,
+    include_raw: bool,
+    properties: Option<WriterProperties>,
+    schema: Option<SchemaRef>,
+    rows: Rows,
+    writer: Option<ArrowWriter<File>>,
+    rows_in_file: usize,
+}
+
+#[derive(Default)]
+struct Rows {
+    len: usize,
+    topic: StringBuilder,
+    hash: StringBuilder,
+    sequence: UInt32Builder,
+    timestamp: TimestampMillisecondBuilder,
+    size: UInt64Builder,
+    raw: BinaryBuilder,
+}
+
+impl ParquetSink {
+    /// Default for [`batch_size`](ParquetSink::batch_size).
+    pub const DEFAULT_BATCH_SIZE: usize = 1024;
+
+    /// Default for [`rows_per_file`](ParquetSink::rows_per_file).
+    pub const DEFAULT_ROWS_PER_FILE: usize = 1_000_000;
+
+    /// Creates a sink that writes files named `<prefix>-<unix milliseconds>.parquet` to `dir`.
+    /// The directory must exist.
+    #[inline]
+    pub fn new(dir: impl Into<PathBuf>, prefix: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into(),
+            prefix: prefix.into(),
+            batch_size: Self::DEFAULT_BATCH_SIZE,
+            rows_per_file: Self::DEFAULT_ROWS_PER_FILE,
+            include_raw: false,
+            properties: None,
+            schema: None,
+            rows: Rows::default(),
+            writer: None,
+            rows_in_file: 0,
+        }
+    }
+
+    /// Sets how many rows are buffered before they are written as a record batch. Defaults to
+    /// [`DEFAULT_BATCH_SIZE`](ParquetSink::DEFAULT_BATCH_SIZE).
+    #[inline]
+    pub const fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets how many rows are written to a file before a new one is started. Defaults to
+    /// [`DEFAULT_ROWS_PER_FILE`](ParquetSink::DEFAULT_ROWS_PER_FILE).
+    #[inline]
+    pub const fn rows_per_file(mut self, rows_per_file: usize) -> Self {
+        self.rows_per_file = rows_per_file;
+        self
+    }
+
+    /// Adds the `raw` column with the data part of every message. Disabled by default.
+    #[inline]
+    pub const fn include_raw(mut self, include_raw: bool) -> Self {
+        self.include_raw = include_raw;
+        self
+    }
+
+    /// Sets the properties of the Parquet writer, for example the compression. Compression codecs
+    /// are enabled with the features of the `parquet` crate.
+    #[inline]
+    pub fn writer_properties(mut self, properties: WriterProperties) -> Self {
+        self.properties = Some(properties);
+        self
+    }
+
+    /// Returns the schema of the record batches.
+    pub fn schema(&mut self) -> SchemaRef {
+        self.schema
+            .get_or_insert_with(|| {
+                let mut fields = vec![
+                    Field::new("topic", DataType::Utf8, false),
+                    Field::new("hash", DataType::Utf8, false),
+                    Field::new("sequence", DataType::UInt32, false),
+                    Field::new(
+                        "timestamp",
+                        DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+                        false,
+                    ),
+                    Field::new("size", DataType::UInt64, false),
+                ];
+                if self.include_raw {
+                    fields.push(Field::new("raw", DataType::Binary, false));
+                }
+                Arc::new(Schema::new(fields))
+            })
+            .clone()
+    }
+
+    /// Writes the buffered rows and finishes the current file, making it readable.
+    pub fn close(&mut self) -> Result<()> {
+        self.flush()?;
+        self.finish_file()
+    }
+
+    /// Writes the buffered rows as a record batch, and finishes the file if it is full.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.rows.len == 0 {
+            return Ok(());
+        }
+
+        let batch = self.take_batch()?;
+
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                let file = self.create_file()?;
+                self.rows_in_file = 0;
+                self.writer.insert(ArrowWriter::try_new(
+                    file,
+                    batch.schema(),
+                    self.properties.clone(),
+                )?)
+            }
+        };
+        writer.write(&batch)?;
+        self.rows_in_file += batch.num_rows();
+
+        if self.rows_in_file >= self.rows_per_file {
+            self.finish_file()?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates a new file, without overwriting the previous one if it was started in the same
+    /// millisecond.
+    fn create_file(&self) -> Result<File> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        for n in 0.. {
+            let name = if n == 0 {
+                format!("{}-{millis}.parquet", self.prefix)
+            } else {
+                format!("{}-{millis}-{n}.parquet", self.prefix)
+            };
+            match File::options()
+                .write(true)
+                .create_new(true)
+                .open(self.dir.join(name))
+            {
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                res => return Ok(res?),
+            }
+        }
+        unreachable!("ran out of file names")
+    }
+
+    fn take_batch(&mut self) -> Result<RecordBatch> {
+        let schema = self.schema();
+        let rows = &mut self.rows;
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(rows.topic.finish()),
+            Arc::new(rows.hash.finish()),
+            Arc::new(rows.sequence.finish()),
+            Arc::new(rows.timestamp.finish().with_timezone("UTC")),
+            Arc::new(rows.size.finish()),
+        ];
+        if self.include_raw {
+            columns.push(Arc::new(rows.raw.finish()));
+        }
+        rows.len = 0;
+
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+
+    fn finish_file(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl NotificationSink for ParquetSink {
+    type Error = ParquetError;
+
+    /// Adds `msg` as received at `timestamp`. The row is buffered until a batch is full.
+    fn write_at(&mut self, msg: &Message, timestamp: SystemTime) -> Result<()> {
+        let millis = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as i64);
+
+        let rows = &mut self.rows;
+        rows.topic.append_value(msg.topic_str());
+        rows.hash.append_value(hash(msg));
+        rows.sequence.append_value(msg.sequence());
+        rows.timestamp.append_value(millis);
+        rows.size.append_value(msg.data_len() as u64);
+        if self.include_raw {
+            rows.raw.append_value(msg.serialize_data_to_vec());
+        }
+        rows.len += 1;
+
+        if rows.len >= self.batch_size || self.rows_in_file + rows.len >= self.rows_per_file.max(1)
+        {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the buffered rows as a record batch, and finishes the file if it is full.
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        ParquetSink::flush(self)
+    }
+}
+
+impl fmt::Debug for ParquetSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParquetSink")
+            .field("dir", &self.dir)
+            .field("prefix", &self.prefix)
+            .field("batch_size", &self.batch_size)
+            .field("rows_per_file", &self.rows_per_file)
+            .field("include_raw", &self.include_raw)
+            .field("buffered_rows", &self.rows.len)
+            .field("rows_in_file", &self.rows_in_file)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ParquetSink {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParquetSink;
+    use crate::sink::NotificationSink;
+    use crate::Message;
+    use arrow_array::{cast::AsArray, types::UInt64Type};
+    use bitcoin::{hashes::Hash, BlockHash, Txid};
+    use core::time::Duration;
+    use parquet::{arrow::arrow_reader::ParquetRecordBatchReaderBuilder, errors::ParquetError};
+    use std::{fs, time::UNIX_EPOCH};
+
+    #[test]
+    fn parquet_sink() {
+        let dir =
+            std::env::temp_dir().join(format!("bitcoincore-zmq-parquet-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut sink = ParquetSink::new(&dir, "zmq")
+            .batch_size(2)
+            .include_raw(true);
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        sink.write_at(&Message::HashBlock(BlockHash::all_zeros(), 0), time)
+            .unwrap();
+        sink.write_at(&Message::HashTx(Txid::all_zeros(), 1), time)
+            .unwrap();
+        sink.write_at(&Message::HashTx(Txid::all_zeros(), 2), time)
+            .unwrap();
+        sink.close().unwrap();
+
+        let files: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let file = fs::File::open(files[0].as_ref().unwrap().path()).unwrap();
+        let batches = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(rows, 3);
+        let batch = &batches[0];
+        assert_eq!(batch.num_columns(), 6);
+        let topics = batch.column_by_name("topic").unwrap().as_string::<i32>();
+        assert_eq!(topics.value(0), "hashblock");
+        assert_eq!(topics.value(1), "hashtx");
+        let sizes = batch
+            .column_by_name("size")
+            .unwrap()
+            .as_primitive::<UInt64Type>();
+        assert_eq!(sizes.value(0), 32);
+        let raw = batch.column_by_name("raw").unwrap().as_binary::<i32>();
+        assert_eq!(raw.value(1), [0; 32]);
+
+        drop(sink);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_and_flush() {
+        fn write_and_flush<S: NotificationSink<Error = ParquetError>>(
+            sink: &mut S,
+            msgs: &[Message],
+        ) {
+            for msg in msgs {
+                sink.write(msg).unwrap();
+            }
+            sink.flush().unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!(
+            "bitcoincore-zmq-parquet-files-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let msgs: Vec<_> = (0..3)
+            .map(|i| Message::HashTx(Txid::all_zeros(), i))
+            .collect();
+
+        // flushing through the trait writes the buffered rows
+        let mut sink = ParquetSink::new(&dir, "flush");
+        write_and_flush(&mut sink, &msgs[..2]);
+        assert_eq!(sink.rows.len, 0);
+        assert_eq!(sink.rows_in_file, 2);
+        drop(sink);
+
+        // files started in the same millisecond do not overwrite each other
+        let mut sink = ParquetSink::new(&dir, "files").rows_per_file(1);
+        write_and_flush(&mut sink, &msgs);
+        drop(sink);
+
+        let mut rows = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let file = fs::File::open(entry.unwrap().path()).unwrap();
+            rows += ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .metadata()
+                .file_metadata()
+                .num_rows();
+        }
+        assert_eq!(rows, 5);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+use super::{hash, height, NotificationSink};
+use crate::message::Message;
+use core::time::Duration;
+use rusqlite::{params, Connection, Result};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS notifications (
+    id INTEGER PRIMARY KEY,
+    topic TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    height INTEGER,
+    sequence INTEGER NOT NULL,
+    label TEXT,
+    mempool_sequence INTEGER,
+    timestamp INTEGER NOT NULL,
+    payload BLOB
+);
+CREATE INDEX IF NOT EXISTS notifications_hash ON notifications (hash);
+CREATE INDEX IF NOT EXISTS notifications_height ON notifications (height);
+";
+
+/// Persists messages in a SQLite database, in the `notifications` table.
+///
+/// The table has the columns `topic`, `hash` (the block hash or txid as hex), `height` (only
+/// known for `rawblock` messages of blocks with a BIP34 height in the coinbase), `sequence`,
+/// `label` and `mempool_sequence` (for `sequence` messages), `timestamp` (milliseconds since the
+/// unix epoch) and `payload` (the data part, only stored with [`include_payload`]). `hash` and
+/// `height` are indexed.
+///
+/// [`include_payload`]: SqliteSink::include_payload
+#[derive(Debug)]
+pub struct SqliteSink {
+    connection: Connection,
+    include_payload: bool,
+}
+
+/// A message stored by [`SqliteSink`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredNotification {
+    pub topic: String,
+    pub hash: String,
+    pub height: Option<u64>,
+    pub sequence: u32,
+    pub timestamp: SystemTime,
+}
+
+impl SqliteSink {
+    /// Opens or creates the database at `path`.
+    #[inline]
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Uses an already opened database, creating the table if it does not exist.
+    pub fn from_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(Self {
+            connection,
+            include_payload: false,
+        })
+    }
+
+    /// Stores the data part of every message in the `payload` column. Disabled by default.
+    #[inline]
+    pub const fn include_payload(mut self, include_payload: bool) -> Self {
+        self.include_payload = include_payload;
+        self
+    }
+
+    /// Returns the underlying connection, to query the stored messages.
+    #[inline]
+    pub const fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Returns all stored messages about the block or transaction with `hash`, oldest first.
+    pub fn by_hash(&self, hash: &str) -> Result<Vec<StoredNotification>> {
+        self.query("WHERE hash = ?1", hash)
+    }
+
+    /// Returns all stored messages about the block at `height`, oldest first.
+    pub fn by_height(&self, height: u64) -> Result<Vec<StoredNotification>> {
+        self.query("WHERE height = ?1", height)
+    }
+
+    fn query(&self, filter: &str, param: impl rusqlite::ToSql) -> Result<Vec<StoredNotification>> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "SELECT topic, hash, height, sequence, timestamp FROM notifications {filter} ORDER BY id"
+        ))?;
+        let rows = statement.query_map([param], |row| {
+            Ok(StoredNotification {
+                topic: row.get(0)?,
+                hash: row.get(1)?,
+                height: row.get(2)?,
+                sequence: row.get(3)?,
+                timestamp: UNIX_EPOCH + Duration::from_millis(row.get(4)?),
+            })
+        })?;
+
+        rows.collect()
+    }
+}
+
+impl NotificationSink for SqliteSink {
+    type Error = rusqlite::Error;
+
+    fn write_at(&mut self, msg: &Message, timestamp: SystemTime) -> Result<()> {
+        let millis = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as i64);
+        let (label, mempool_sequence) = match msg {
+            Message::Sequence(sm, _) => (Some(sm.label_char().to_string()), sm.mempool_sequence()),
+            _ => (None, None),
+        };
+        let payload = self.include_payload.then(|| msg.serialize_data_to_vec());
+
+        self.connection
+            .prepare_cached(
+                "INSERT INTO notifications (topic, hash, height, sequence, label, \
+                 mempool_sequence, timestamp, payload) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?
+            .execute(params![
+                msg.topic_str(),
+                hash(msg),
+                height(msg),
+                msg.sequence(),
+                label,
+                mempool_sequence,
+                millis,
+                payload,
+            ])?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SqliteSink, StoredNotification};
+    use crate::{sink::NotificationSink, Message, SequenceMessage};
+    use bitcoin::{hashes::Hash, Txid};
+    use core::time::Duration;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn sqlite_sink() {
+        let mut sink = SqliteSink::from_connection(rusqlite::Connection::open_in_memory().unwrap())
+            .unwrap()
+            .include_payload(true);
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+
+        sink.write_at(&Message::HashTx(Txid::all_zeros(), 0), time)
+            .unwrap();
+        sink.write_at(
+            &Message::Sequence(
+                SequenceMessage::MempoolRemoval {
+                    txid: Txid::all_zeros(),
+                    mempool_sequence: 2,
+                },
+                1,
+            ),
+            time,
+        )
+        .unwrap();
+
+        let stored = sink.by_hash(&Txid::all_zeros().to_string()).unwrap();
+        assert_eq!(
+            stored,
+            [
+                StoredNotification {
+                    topic: "hashtx".to_owned(),
+                    hash: "0".repeat(64),
+                    height: None,
+                    sequence: 0,
+                    timestamp: time,
+                },
+                StoredNotification {
+                    topic: "sequence".to_owned(),
+                    hash: "0".repeat(64),
+                    height: None,
+                    sequence: 1,
+                    timestamp: time,
+                },
+            ]
+        );
+        assert!(sink.by_height(0).unwrap().is_empty());
+
+        let (label, mempool_sequence, payload_len): (String, u64, usize) = sink
+            .connection()
+            .query_row(
+                "SELECT label, mempool_sequence, length(payload) FROM notifications WHERE id = 2",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (label.as_str(), mempool_sequence, payload_len),
+            ("R", 2, 41)
+        );
+    }
+}
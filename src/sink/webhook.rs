@@ -0,0 +1,336 @@
+use super::{to_json, NotificationSink};
+use crate::{message::Message, topic::Topic};
+use bitcoin::{
+    hashes::{hmac, sha256, Hash, HashEngine},
+    hex::DisplayHex,
+};
+use core::{fmt, time::Duration};
+use std::{thread, time::SystemTime};
+
+/// POSTs messages as JSON to one or more URLs, retrying failed deliveries with exponential
+/// backoff. See the [module documentation](crate::sink) for the format of the body.
+///
+/// When a secret is set with [`hmac_secret`], every request carries an `X-Signature-256` header
+/// with the hex encoded HMAC-SHA256 of the body, prefixed with `sha256=`, so receivers can verify
+/// the request came from this sink.
+///
+/// Only `http://` URLs are supported out of the box. Enable the `https` feature of `minreq` in
+/// your own `Cargo.toml` to also post to `https://` URLs.
+///
+/// Requests are sent on the calling thread and block until they are delivered or all retries
+/// have failed, so this is meant for low volume topics, like `hashblock` or a filtered `hashtx`.
+///
+/// [`hmac_secret`]: WebhookSink::hmac_secret
+#[derive(Debug, Clone)]
+pub struct WebhookSink {
+    urls: Vec<String>,
+    topics: Option<Vec<Topic>>,
+    secret: Option<Vec<u8>>,
+    include_payload: bool,
+    max_retries: u32,
+    backoff: Duration,
+    timeout: Duration,
+}
+
+impl WebhookSink {
+    /// Default for [`max_retries`](WebhookSink::max_retries).
+    pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+    /// Default for [`backoff`](WebhookSink::backoff).
+    pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+
+    /// Default for [`timeout`](WebhookSink::timeout).
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Creates a sink that posts every message to all `urls`.
+    #[inline]
+    pub fn new<S: Into<String>>(urls: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            urls: urls.into_iter().map(Into::into).collect(),
+            topics: None,
+            secret: None,
+            include_payload: false,
+            max_retries: Self::DEFAULT_MAX_RETRIES,
+            backoff: Self::DEFAULT_BACKOFF,
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Only posts messages on `topics`, others are ignored by [`send`](WebhookSink::send).
+    #[inline]
+    pub fn topics(mut self, topics: &[Topic]) -> Self {
+        self.topics = Some(topics.to_vec());
+        self
+    }
+
+    /// Signs every request with HMAC-SHA256 using `secret`.
+    #[inline]
+    pub fn hmac_secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Includes the data part of every message as hex. Disabled by default.
+    #[inline]
+    pub const fn include_payload(mut self, include_payload: bool) -> Self {
+        self.include_payload = include_payload;
+        self
+    }
+
+    /// Sets how many times a failed request is retried. Defaults to
+    /// [`DEFAULT_MAX_RETRIES`](WebhookSink::DEFAULT_MAX_RETRIES).
+    #[inline]
+    pub const fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the delay before the first retry, which doubles for every further retry. Defaults to
+    /// [`DEFAULT_BACKOFF`](WebhookSink::DEFAULT_BACKOFF).
+    #[inline]
+    pub const fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the timeout of a single request. Defaults to
+    /// [`DEFAULT_TIMEOUT`](WebhookSink::DEFAULT_TIMEOUT).
+    #[inline]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns `true` if messages on `topic` are posted.
+    #[inline]
+    pub fn is_selected(&self, topic: Topic) -> bool {
+        self.topics
+            .as_ref()
+            .is_none_or(|topics| topics.contains(&topic))
+    }
+
+    /// Posts `msg` to all URLs if its topic is selected, see [`send_at`](WebhookSink::send_at).
+    #[inline]
+    pub fn send(&self, msg: &Message) -> Result<bool, WebhookError> {
+        self.send_at(msg, SystemTime::now())
+    }
+
+    /// Posts `msg`, received at `timestamp`, to all URLs if its topic is selected. Returns
+    /// whether it was posted.
+    ///
+    /// Every URL is tried, even if delivering to an earlier one failed. The error of the first
+    /// URL that could not be delivered to is returned.
+    pub fn send_at(&self, msg: &Message, timestamp: SystemTime) -> Result<bool, WebhookError> {
+        if !self.is_selected(msg.topic_type()) {
+            return Ok(false);
+        }
+
+        let body = to_json(msg, timestamp, self.include_payload);
+        let signature = self.secret.as_ref().map(|secret| sign(secret, &body));
+
+        let mut res = Ok(true);
+        for url in &self.urls {
+            if let Err(err) = self.post(url, &body, signature.as_deref()) {
+                if res.is_ok() {
+                    res = Err(err);
+                }
+            }
+        }
+
+        res
+    }
+
+    fn post(&self, url: &str, body: &str, signature: Option<&str>) -> Result<(), WebhookError> {
+        let mut attempt = 0;
+        loop {
+            let mut request = minreq::post(url)
+                .with_header("Content-Type", "application/json")
+                .with_timeout(self.timeout.as_secs().max(1))
+                .with_body(body);
+            if let Some(signature) = signature {
+                request = request.with_header("X-Signature-256", signature);
+            }
+
+            let err = match request.send() {
+                Ok(response) if (200..300).contains(&response.status_code) => return Ok(()),
+                Ok(response) => WebhookError::Status {
+                    url: url.to_owned(),
+                    status: response.status_code,
+                },
+                Err(source) => WebhookError::Request {
+                    url: url.to_owned(),
+                    source,
+                },
+            };
+
+            if !err.is_retryable() || attempt >= self.max_retries {
+                return Err(err);
+            }
+            thread::sleep(self.backoff.saturating_mul(1 << attempt.min(16)));
+            attempt += 1;
+        }
+    }
+}
+
+impl NotificationSink for WebhookSink {
+    type Error = WebhookError;
+
+    /// Posts `msg` if its topic is selected, see [`send_at`](WebhookSink::send_at).
+    #[inline]
+    fn write_at(&mut self, msg: &Message, timestamp: SystemTime) -> Result<(), WebhookError> {
+        self.send_at(msg, timestamp).map(drop)
+    }
+}
+
+/// Returns the value of the `X-Signature-256` header for `body`.
+fn sign(secret: &[u8], body: &str) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret);
+    engine.input(body.as_bytes());
+    let hmac = hmac::Hmac::from_engine(engine);
+
+    format!("sha256={}", hmac.as_byte_array().as_hex())
+}
+
+/// Error returned by [`WebhookSink::send`] when a message could not be delivered.
+#[derive(Debug)]
+pub enum WebhookError {
+    /// The request could not be sent or no response was received.
+    Request { url: String, source: minreq::Error },
+    /// The server responded with a status code other than 2xx.
+    Status { url: String, status: i32 },
+}
+
+impl WebhookError {
+    /// Returns the URL the message could not be delivered to.
+    #[inline]
+    pub fn url(&self) -> &str {
+        match self {
+            Self::Request { url, .. } | Self::Status { url, .. } => url,
+        }
+    }
+
+    /// Returns `true` for errors that may go away by retrying: failed requests, server errors
+    /// and rate limiting (429).
+    #[inline]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Request { .. } => true,
+            Self::Status { status, .. } => *status >= 500 || *status == 429,
+        }
+    }
+}
+
+impl fmt::Display for WebhookError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Request { url, source } => write!(f, "request to {url} failed: {source}"),
+            Self::Status { url, status } => write!(f, "{url} responded with status {status}"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Request { source, .. } => Some(source),
+            Self::Status { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, WebhookError, WebhookSink};
+    use crate::{Message, Topic};
+    use bitcoin::{hashes::Hash, BlockHash, Txid};
+    use core::time::Duration;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+        time::UNIX_EPOCH,
+    };
+
+    /// Accepts a request for every status in `statuses`, responds with it and returns the
+    /// headers (lowercased) and body of the requests.
+    fn serve(listener: TcpListener, statuses: &[u16]) -> Vec<(Vec<String>, String)> {
+        statuses
+            .iter()
+            .map(|status| {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut headers = Vec::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_ascii_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(len) = line.strip_prefix("content-length: ") {
+                        content_length = len.parse().unwrap();
+                    }
+                    headers.push(line);
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+                (headers, String::from_utf8(body).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn webhook_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/notify", listener.local_addr().unwrap());
+        let server = thread::spawn(move || serve(listener, &[503, 200, 400]));
+
+        let sink = WebhookSink::new([url])
+            .topics(&[Topic::HashBlock])
+            .hmac_secret("secret")
+            .backoff(Duration::from_millis(1));
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+
+        // not selected, nothing is sent
+        assert!(!sink
+            .send_at(&Message::HashTx(Txid::all_zeros(), 0), time)
+            .unwrap());
+
+        // the first attempt fails with 503 and is retried
+        let msg = Message::HashBlock(BlockHash::all_zeros(), 1);
+        assert!(sink.send_at(&msg, time).unwrap());
+
+        // 400 is not retried
+        let err = sink.send_at(&msg, time).unwrap_err();
+        assert!(matches!(err, WebhookError::Status { status: 400, .. }));
+        assert!(!err.is_retryable());
+
+        let requests = server.join().unwrap();
+        let body = format!(
+            r#"{{"topic":"hashblock","sequence":1,"timestamp":1700000000123,"hash":"{}"}}"#,
+            "0".repeat(64)
+        );
+        for (headers, request_body) in &requests {
+            assert_eq!(*request_body, body);
+            assert!(headers.contains(&"content-type: application/json".to_owned()));
+            assert!(headers.contains(&format!("x-signature-256: {}", sign(b"secret", &body))));
+        }
+    }
+
+    #[test]
+    fn hmac_signature() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign(b"Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Subscribing to nodes whose ZMQ port is only reachable over SSH, by running `ssh` with a local
+//! port forward (`ssh -L`).
+//!
+//! ```no_run
+//! use bitcoincore_zmq::ssh::SshTunnelBuilder;
+//!
+//! let tunnel = SshTunnelBuilder::new("user@node.example", "127.0.0.1:28332")
+//!     .open()
+//!     .unwrap();
+//! let mut subscription = tunnel.subscription().unwrap();
+//! for msg in &mut subscription {
+//!     println!("{}", msg.unwrap());
+//! }
+//! ```
+//!
+//! The `ssh` client is used as it is configured for the current user, so keys, agents, known
+//! hosts and `~/.ssh/config` apply. It must be able to log in without asking for a password.
+//! When `ssh` exits, for example because the connection dropped, it is restarted on the same
+//! local port, so ZMQ reconnects to the node through the new tunnel on its own. Nothing is logged,
+//! the last failure of the tunnel can be checked with [`SshTunnel::take_last_error`].
+
+use crate::{error::Result, message::Message, subscribe::subscription::Subscription};
+use core::time::Duration;
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+/// Builder for an [`SshTunnel`].
+#[derive(Debug, Clone)]
+pub struct SshTunnelBuilder {
+    destination: String,
+    remote: String,
+    program: String,
+    args: Vec<String>,
+    connect_timeout: Duration,
+    reconnect_delay: Duration,
+}
+
+impl SshTunnelBuilder {
+    /// Default for [`connect_timeout`](SshTunnelBuilder::connect_timeout).
+    pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+    /// Default for [`reconnect_delay`](SshTunnelBuilder::reconnect_delay).
+    pub const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+    /// Creates a builder for a tunnel through `destination` (like `user@host`, or a host from
+    /// `~/.ssh/config`) to `remote`, the address of the ZMQ endpoint as seen from the SSH server,
+    /// like `127.0.0.1:28332`.
+    #[inline]
+    pub fn new(destination: impl Into<String>, remote: impl Into<String>) -> Self {
+        Self {
+            destination: destination.into(),
+            remote: remote.into(),
+            program: "ssh".to_owned(),
+            args: Vec::new(),
+            connect_timeout: Self::DEFAULT_CONNECT_TIMEOUT,
+            reconnect_delay: Self::DEFAULT_RECONNECT_DELAY,
+        }
+    }
+
+    /// Sets the SSH client to run. Defaults to `ssh`.
+    #[inline]
+    pub fn program(mut self, program: impl Into<String>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Passes an extra argument to the SSH client, like `-i` with a key file or `-p` with a port.
+    #[inline]
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Sets how long [`open`](SshTunnelBuilder::open) waits for the tunnel to be established.
+    #[inline]
+    pub const fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Sets how long to wait before restarting the SSH client after it exited.
+    #[inline]
+    pub const fn reconnect_delay(mut self, reconnect_delay: Duration) -> Self {
+        self.reconnect_delay = reconnect_delay;
+        self
+    }
+
+    /// Starts the SSH client and waits until the local end of the tunnel accepts connections.
+    ///
+    /// Fails if the client can not be started, exits before the tunnel is established (for
+    /// example because logging in failed), or the tunnel is not established within the
+    /// [`connect_timeout`](SshTunnelBuilder::connect_timeout).
+    pub fn open(self) -> io::Result<SshTunnel> {
+        // let the OS pick a free port, ssh binds it right after
+        let local = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?.local_addr()?;
+
+        let mut child = self.spawn(local)?;
+        if let Err(err) = wait_listening(&mut child, local, self.connect_timeout) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+
+        let state = Arc::new((
+            Mutex::new(TunnelState {
+                child,
+                closed: false,
+                restarts: 0,
+                last_error: None,
+            }),
+            Condvar::new(),
+        ));
+        let supervisor = thread::Builder::new().name("zmq-ssh".to_owned()).spawn({
+            let state = state.clone();
+            let builder = self.clone();
+            move || supervise(&builder, local, &state)
+        })?;
+
+        Ok(SshTunnel {
+            endpoint: format!("tcp://{local}"),
+            state,
+            supervisor: Some(supervisor),
+        })
+    }
+
+    fn spawn(&self, local: SocketAddr) -> io::Result<Child> {
+        Command::new(&self.program)
+            .args(&self.args)
+            .args([
+                "-N",
+                "-o",
+                "ExitOnForwardFailure=yes",
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "ServerAliveInterval=15",
+                "-L",
+            ])
+            .arg(format!("{local}:{}", self.remote))
+            .arg(&self.destination)
+            .stdin(Stdio::null())
+            .spawn()
+    }
+}
+
+/// An SSH local port forward to a ZMQ endpoint, see the [module documentation](self).
+///
+/// The SSH client is stopped when the tunnel is dropped.
+#[derive(Debug)]
+pub struct SshTunnel {
+    endpoint: String,
+    state: Arc<(Mutex<TunnelState>, Condvar)>,
+    supervisor: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct TunnelState {
+    child: Child,
+    closed: bool,
+    restarts: u64,
+    last_error: Option<io::Error>,
+}
+
+impl SshTunnel {
+    /// Returns the local endpoint of the tunnel, like `tcp://127.0.0.1:40123`, to pass to
+    /// [`SubscribeBuilder::new`](crate::SubscribeBuilder::new).
+    #[inline]
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Returns how many times the SSH client was restarted after it exited.
+    #[inline]
+    pub fn restarts(&self) -> u64 {
+        lock_state(&self.state.0).restarts
+    }
+
+    /// Returns the last failure of the tunnel since the last call, if any: the SSH client exiting
+    /// or failing to restart.
+    #[inline]
+    pub fn take_last_error(&self) -> Option<io::Error> {
+        lock_state(&self.state.0).last_error.take()
+    }
+
+    /// Subscribes to the endpoint through this tunnel with default options. The subscription
+    /// only receives messages while the tunnel is alive.
+    #[inline]
+    pub fn subscription(&self) -> Result<Subscription<Message>> {
+        crate::SubscribeBuilder::new(&[self.endpoint()]).subscription()
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.state;
+        lock_state(lock).closed = true;
+        cvar.notify_all();
+        if let Some(supervisor) = self.supervisor.take() {
+            let _ = supervisor.join();
+        }
+    }
+}
+
+fn lock_state(lock: &Mutex<TunnelState>) -> MutexGuard<'_, TunnelState> {
+    // the state stays consistent if a thread panics while holding the lock
+    lock.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Waits until `child` listens on `local`.
+fn wait_listening(child: &mut Child, local: SocketAddr, timeout: Duration) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Err(io::Error::other(format!(
+                "ssh exited before the tunnel was established ({status})"
+            )));
+        }
+        if TcpStream::connect_timeout(&local, Duration::from_millis(100)).is_ok() {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Restarts the SSH client whenever it exits, until the tunnel is closed.
+fn supervise(builder: &SshTunnelBuilder, local: SocketAddr, state: &(Mutex<TunnelState>, Condvar)) {
+    let (lock, cvar) = state;
+    let mut state = lock_state(lock);
+
+    while !state.closed {
+        match state.child.try_wait() {
+            Ok(None) => {
+                state = cvar
+                    .wait_timeout(state, Duration::from_millis(200))
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+                continue;
+            }
+            Ok(Some(status)) => {
+                state.last_error = Some(io::Error::other(format!(
+                    "ssh tunnel to {} exited ({status})",
+                    builder.destination
+                )));
+            }
+            Err(err) => state.last_error = Some(err),
+        }
+
+        state = cvar
+            .wait_timeout_while(state, builder.reconnect_delay, |state| !state.closed)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+        if state.closed {
+            break;
+        }
+
+        match builder.spawn(local) {
+            Ok(child) => {
+                state.child = child;
+                state.restarts += 1;
+            }
+            Err(err) => state.last_error = Some(err),
+        }
+    }
+
+    let _ = state.child.kill();
+    let _ = state.child.wait();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::SshTunnelBuilder;
+    use core::time::Duration;
+
+    #[test]
+    fn open_fails_without_tunnel() {
+        // exits immediately, like ssh failing to log in
+        let res = SshTunnelBuilder::new("node.invalid", "127.0.0.1:28332")
+            .program("false")
+            .open();
+        assert!(res.is_err());
+
+        let res = SshTunnelBuilder::new("node.invalid", "127.0.0.1:28332")
+            .program("bitcoincore-zmq-no-such-ssh")
+            .open();
+        assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+        // runs, but never listens
+        let res = SshTunnelBuilder::new("node.invalid", "127.0.0.1:28332")
+            .program("sh")
+            .arg("-c")
+            .arg("sleep 10")
+            .connect_timeout(Duration::from_millis(300))
+            .open();
+        assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{error::Result, message::Message};
+use core::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Annotation for a block notification whose header time is implausibly old, for example when
+/// the node is still syncing or the feed is replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stale {
+    /// Time between the block's header time and the arrival of the notification.
+    pub age: Duration,
+}
+
+/// Flags block notifications that are older than a configured maximum age by comparing the
+/// header time of [`Message::Block`]s against the time the notification arrived.
+///
+/// Only `rawblock` messages contain a header, other messages are never flagged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StalenessDetector {
+    max_age: Duration,
+}
+
+impl StalenessDetector {
+    /// Default maximum age of a block. Header times may be up to 2 hours off from the real time,
+    /// so blocks older than this are unlikely to be new blocks.
+    pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(2 * 60 * 60);
+
+    /// Creates a new [`StalenessDetector`] that flags blocks older than `max_age`.
+    #[inline]
+    pub const fn new(max_age: Duration) -> Self {
+        Self { max_age }
+    }
+
+    /// Returns the maximum age of a block before it is flagged as [`Stale`].
+    #[inline]
+    pub const fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    /// Checks `msg` assuming it arrived just now. See [`check_at`].
+    ///
+    /// [`check_at`]: StalenessDetector::check_at
+    #[inline]
+    pub fn check(&self, msg: &Message) -> Option<Stale> {
+        self.check_at(msg, SystemTime::now())
+    }
+
+    /// Returns [`Some`] if `msg` is a block with a header time more than the maximum age before
+    /// `arrival`.
+    #[inline]
+    pub fn check_at(&self, msg: &Message, arrival: SystemTime) -> Option<Stale> {
+        let Message::Block(block, _) = msg else {
+            return None;
+        };
+
+        let header_time = UNIX_EPOCH + Duration::from_secs(block.header.time.into());
+        let age = arrival.duration_since(header_time).ok()?;
+
+        (age > self.max_age).then_some(Stale { age })
+    }
+
+    /// Wraps an iterator of messages (for example the [`Receiver`] returned by
+    /// [`subscribe_receiver`]) to annotate every message with the result of [`check`].
+    ///
+    /// [`Receiver`]: std::sync::mpsc::Receiver
+    /// [`subscribe_receiver`]: crate::subscribe_receiver
+    /// [`check`]: StalenessDetector::check
+    #[inline]
+    pub fn annotate<I>(self, messages: I) -> StaleBlocks<I::IntoIter>
+    where
+        I: IntoIterator<Item = Result<Message>>,
+    {
+        StaleBlocks {
+            detector: self,
+            messages: messages.into_iter(),
+        }
+    }
+}
+
+impl Default for StalenessDetector {
+    #[inline]
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_AGE)
+    }
+}
+
+/// Iterator returned by [`StalenessDetector::annotate`].
+#[derive(Debug)]
+pub struct StaleBlocks<I> {
+    detector: StalenessDetector,
+    messages: I,
+}
+
+impl<I> Iterator for StaleBlocks<I>
+where
+    I: Iterator<Item = Result<Message>>,
+{
+    type Item = Result<(Message, Option<Stale>)>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.messages.next()?.map(|msg| {
+            let stale = self.detector.check(&msg);
+            (msg, stale)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Message, Stale, StalenessDetector};
+    use bitcoin::{constants::genesis_block, Network};
+    use core::time::Duration;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn staleness() {
+        let block = genesis_block(Network::Bitcoin);
+        let header_time = UNIX_EPOCH + Duration::from_secs(block.header.time.into());
+        let block_hash = block.block_hash();
+        let msg = Message::Block(block, 0);
+
+        let detector = StalenessDetector::new(Duration::from_secs(60));
+
+        assert_eq!(detector.check_at(&msg, header_time), None);
+        assert_eq!(
+            detector.check_at(&msg, header_time + Duration::from_secs(60)),
+            None
+        );
+        assert_eq!(
+            detector.check_at(&msg, header_time + Duration::from_secs(61)),
+            Some(Stale {
+                age: Duration::from_secs(61)
+            })
+        );
+        // header time in the future
+        assert_eq!(
+            detector.check_at(&msg, header_time - Duration::from_secs(10)),
+            None
+        );
+        assert!(detector.check_at(&msg, SystemTime::now()).is_some());
+
+        let hash_msg = Message::HashBlock(block_hash, 1);
+        assert_eq!(detector.check(&hash_msg), None);
+
+        let annotated: Vec<_> = detector
+            .annotate([Ok(msg), Ok(hash_msg)])
+            .map(|res| res.unwrap().1.is_some())
+            .collect();
+        assert_eq!(annotated, [true, false]);
+    }
+}
@@ -1,5 +1,5 @@
-use super::{new_socket_internal, subscribe_internal};
-use crate::{error::Result, message::Message};
+use super::{builder::SubscribeBuilder, new_socket_internal, subscribe_internal};
+use crate::{error::Result, message::Message, raw_message::FromRawMessage};
 use core::{convert::Infallible, ops::ControlFlow};
 
 /// Subscribes to a single ZMQ endpoint and blocks the thread until [`ControlFlow::Break`] is
@@ -46,7 +46,20 @@ pub fn subscribe_blocking<F, B>(
 where
     F: Fn(Result<Message>) -> ControlFlow<B>,
 {
-    let (_context, socket) = new_socket_internal(endpoints)?;
+    SubscribeBuilder::new(endpoints).blocking(callback)
+}
+
+impl<M: FromRawMessage> SubscribeBuilder<'_, M> {
+    /// Subscribes and blocks the thread until [`ControlFlow::Break`] is returned by the callback.
+    /// See [`subscribe_blocking`].
+    #[inline]
+    pub fn blocking<F, B>(self, callback: F) -> Result<ControlFlow<B, Infallible>>
+    where
+        F: Fn(Result<M>) -> ControlFlow<B>,
+    {
+        self.reject_topic_ttl()?;
+        let (_context, socket) = new_socket_internal(&self, true)?;
 
-    Ok(subscribe_internal(socket, callback))
+        Ok(subscribe_internal(socket, self.recv_config(), callback))
+    }
 }
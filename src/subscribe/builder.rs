@@ -0,0 +1,425 @@
+use super::expiry::{may_expire, ExpiredMessages, Expiry};
+use crate::{
+    endpoint::Endpoint,
+    error::{Error, Result},
+    message::{Message, DATA_MAX_LEN},
+    topic::Topic,
+};
+use core::{fmt, marker::PhantomData, time::Duration};
+#[cfg(feature = "rpc")]
+use std::sync::Arc;
+
+/// Default value for [`SubscribeBuilder::max_msg_size`]. This is twice Bitcoin's maximum block
+/// weight, generously above the size of any valid `rawblock` message.
+pub const DEFAULT_MAX_MSG_SIZE: usize = 2 * DATA_MAX_LEN;
+
+/// Default value for [`SubscribeBuilder::linger`]. Subscribers only send subscription frames, so
+/// there is nothing worth waiting for when closing.
+pub const DEFAULT_LINGER: Duration = Duration::ZERO;
+
+/// Default value for [`SubscribeBuilder::thread_name`].
+pub const DEFAULT_THREAD_NAME: &str = "zmq-sub";
+
+/// Builder for subscriptions that need more configuration than the `subscribe_*` functions offer.
+///
+/// The `subscribe_*` functions are shorthands for this builder with default options, for example
+/// `subscribe_receiver(endpoints)` is the same as `SubscribeBuilder::new(endpoints).receiver()`.
+///
+/// The subscribers produce `M`s, [`Message`]s by default. See [`message_type`].
+///
+/// [`message_type`]: SubscribeBuilder::message_type
+pub struct SubscribeBuilder<'a, M = Message> {
+    pub(super) endpoints: &'a [&'a str],
+    pub(super) max_msg_size: Option<usize>,
+    pub(super) linger: Duration,
+    pub(super) debug_hexdump: bool,
+    pub(super) global_context: bool,
+    pub(super) decode_threads: usize,
+    pub(super) thread_name: &'a str,
+    #[cfg(all(feature = "affinity", target_os = "linux"))]
+    pub(super) receive_thread_core: Option<usize>,
+    pub(super) max_data_len: usize,
+    pub(super) topic_max_data_len: [Option<usize>; Topic::ALL.len()],
+    topic_ttl: [Option<Duration>; Topic::ALL.len()],
+    expired_messages: Option<&'a ExpiredMessages>,
+    #[cfg(all(feature = "systemd", unix))]
+    pub(super) systemd_notify_ready: bool,
+    #[cfg(feature = "rpc")]
+    pub(super) initial_tip: Option<&'a Arc<bitcoincore_rpc::Client>>,
+    message_type: PhantomData<fn() -> M>,
+}
+
+impl<'a> SubscribeBuilder<'a> {
+    /// Creates a new [`SubscribeBuilder`] that will subscribe to `endpoints`.
+    #[inline]
+    pub const fn new(endpoints: &'a [&'a str]) -> Self {
+        Self {
+            endpoints,
+            max_msg_size: Some(DEFAULT_MAX_MSG_SIZE),
+            linger: DEFAULT_LINGER,
+            debug_hexdump: false,
+            global_context: false,
+            decode_threads: 0,
+            thread_name: DEFAULT_THREAD_NAME,
+            #[cfg(all(feature = "affinity", target_os = "linux"))]
+            receive_thread_core: None,
+            max_data_len: DATA_MAX_LEN,
+            topic_max_data_len: [None; Topic::ALL.len()],
+            topic_ttl: [None; Topic::ALL.len()],
+            expired_messages: None,
+            #[cfg(all(feature = "systemd", unix))]
+            systemd_notify_ready: false,
+            #[cfg(feature = "rpc")]
+            initial_tip: None,
+            message_type: PhantomData,
+        }
+    }
+}
+
+impl<'a, M> SubscribeBuilder<'a, M> {
+    /// Makes the subscribers produce `N`s instead of `M`s. `N` is parsed from the parts of every
+    /// received message with [`FromRawMessage::from_raw_message`].
+    ///
+    /// [`FromRawMessage::from_raw_message`]: crate::FromRawMessage::from_raw_message
+    #[inline]
+    pub const fn message_type<N>(self) -> SubscribeBuilder<'a, N> {
+        SubscribeBuilder {
+            endpoints: self.endpoints,
+            max_msg_size: self.max_msg_size,
+            linger: self.linger,
+            debug_hexdump: self.debug_hexdump,
+            global_context: self.global_context,
+            decode_threads: self.decode_threads,
+            thread_name: self.thread_name,
+            #[cfg(all(feature = "affinity", target_os = "linux"))]
+            receive_thread_core: self.receive_thread_core,
+            max_data_len: self.max_data_len,
+            topic_max_data_len: self.topic_max_data_len,
+            topic_ttl: self.topic_ttl,
+            expired_messages: self.expired_messages,
+            #[cfg(all(feature = "systemd", unix))]
+            systemd_notify_ready: self.systemd_notify_ready,
+            #[cfg(feature = "rpc")]
+            initial_tip: self.initial_tip,
+            message_type: PhantomData,
+        }
+    }
+
+    /// Sets the maximum size of a single part of a received multipart (ZMQ_MAXMSGSIZE), or
+    /// removes the limit if [`None`]. Defaults to [`DEFAULT_MAX_MSG_SIZE`].
+    ///
+    /// When a publisher sends a part larger than this, libzmq drops the connection to it and
+    /// reconnects, without the message ever reaching the subscriber. libzmq does not say why a
+    /// connection was dropped, so this can not be translated into an error. On monitored streams
+    /// it shows up as [`SocketEvent::Disconnected`], [`Subscription::debug_report`] shows the
+    /// endpoint as not connected until the reconnect, and the blocking and [`receiver`]
+    /// subscribers do not see it at all. Data parts that pass this limit but exceed
+    /// [`max_data_len`] are reported as [`Error::MessageTooLarge`].
+    ///
+    /// [`SocketEvent::Disconnected`]: crate::SocketEvent::Disconnected
+    /// [`Subscription::debug_report`]: crate::Subscription::debug_report
+    /// [`receiver`]: SubscribeBuilder::receiver
+    /// [`max_data_len`]: SubscribeBuilder::max_data_len
+    #[inline]
+    pub const fn max_msg_size(mut self, max_msg_size: Option<usize>) -> Self {
+        self.max_msg_size = max_msg_size;
+        self
+    }
+
+    /// Sets how long unsent frames (subscription changes) are kept when a socket is closed
+    /// (ZMQ_LINGER), on all sockets the subscriber creates. Defaults to [`DEFAULT_LINGER`].
+    ///
+    /// Terminating a ZMQ context, which happens when the last socket of a context that is not
+    /// the [`global_context`] is dropped, blocks until all unsent frames are sent or the linger
+    /// period ends. Keeping this short bounds how long dropping (or [`close`]) a subscription
+    /// can take, for example when a publisher is unreachable.
+    ///
+    /// [`global_context`]: SubscribeBuilder::global_context
+    /// [`close`]: crate::Subscription::close
+    #[inline]
+    pub const fn linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    /// Enables or disables printing every received multipart to stderr before it is checked and
+    /// parsed, so malformed multiparts are printed too. The topic is printed as a string and
+    /// every part as a hexdump, truncated for large parts. Useful to diagnose interoperability
+    /// issues with patched nodes and proxies. Disabled by default.
+    #[inline]
+    pub const fn debug_hexdump(mut self, debug_hexdump: bool) -> Self {
+        self.debug_hexdump = debug_hexdump;
+        self
+    }
+
+    /// Makes the subscription use the process-wide ZMQ context (see [`global_context`]) instead
+    /// of creating its own. This lets subscriptions in different parts of an application share
+    /// I/O threads. Disabled by default.
+    ///
+    /// [`global_context`]: crate::global_context
+    #[inline]
+    pub const fn global_context(mut self, global_context: bool) -> Self {
+        self.global_context = global_context;
+        self
+    }
+
+    /// Sets the number of worker threads that deserialize messages for [`receiver`]. With 0
+    /// (the default), messages are deserialized on the receiving thread. Otherwise the receiving
+    /// thread only copies the parts of every message and hands them to the workers, so parsing
+    /// large `rawblock` messages does not delay receiving. Messages are delivered in the order
+    /// they were received either way.
+    ///
+    /// At most a few messages per worker wait to be decoded and delivered. When they are not
+    /// taken fast enough, the receiving thread stops receiving, so messages queue up in the
+    /// socket, where they are dropped once its high water mark is reached.
+    ///
+    /// [`receiver`]: SubscribeBuilder::receiver
+    #[inline]
+    pub const fn decode_threads(mut self, decode_threads: usize) -> Self {
+        self.decode_threads = decode_threads;
+        self
+    }
+
+    /// Sets the name of the threads spawned by [`receiver`] and [`consumer_group`], so they can
+    /// be told apart in profilers and debuggers. The receiving thread is named `name`, the
+    /// workers of [`decode_threads`] `{name}-decode` and the thread that forwards their results
+    /// in order `{name}-forward`. Defaults to [`DEFAULT_THREAD_NAME`].
+    ///
+    /// On Linux, thread names are truncated to 15 bytes.
+    ///
+    /// [`receiver`]: SubscribeBuilder::receiver
+    /// [`consumer_group`]: SubscribeBuilder::consumer_group
+    /// [`decode_threads`]: SubscribeBuilder::decode_threads
+    #[inline]
+    pub const fn thread_name(mut self, name: &'a str) -> Self {
+        self.thread_name = name;
+        self
+    }
+
+    /// Pins the receiving thread spawned by [`receiver`] and [`consumer_group`] to CPU core
+    /// `core`, or lets the scheduler move it if [`None`] (the default). Pinning avoids
+    /// migrations and keeps the cache warm in latency sensitive deployments, preferably on a
+    /// core that is isolated from other work.
+    ///
+    /// Subscribing fails with [`Error::Io`] if the thread could not be pinned, for example
+    /// because `core` does not exist or is not in the allowed set of this process.
+    ///
+    /// [`receiver`]: SubscribeBuilder::receiver
+    /// [`consumer_group`]: SubscribeBuilder::consumer_group
+    #[cfg(all(feature = "affinity", target_os = "linux"))]
+    #[inline]
+    pub const fn receive_thread_core(mut self, core: Option<usize>) -> Self {
+        self.receive_thread_core = core;
+        self
+    }
+
+    /// Sets the maximum length of the data part of received messages. Defaults to
+    /// [`DATA_MAX_LEN`], the largest valid `rawblock` message on Bitcoin. Use a larger value for
+    /// forks and test networks with larger blocks, and raise [`max_msg_size`] along with it.
+    ///
+    /// Messages with larger data parts are reported as [`Error::MessageTooLarge`], as long as they
+    /// are within [`max_msg_size`], see there for what happens to larger ones. The blocking,
+    /// receiver and [`Subscription`] subscribers allocate a receive buffer of this length.
+    ///
+    /// [`max_msg_size`]: SubscribeBuilder::max_msg_size
+    /// [`Subscription`]: crate::Subscription
+    #[inline]
+    pub const fn max_data_len(mut self, max_data_len: usize) -> Self {
+        self.max_data_len = max_data_len;
+        self
+    }
+
+    /// Sets the maximum length of the data part of messages on `topic`, overriding
+    /// [`max_data_len`](SubscribeBuilder::max_data_len) for that topic.
+    #[inline]
+    pub const fn topic_max_data_len(mut self, topic: Topic, max_data_len: usize) -> Self {
+        self.topic_max_data_len[topic as usize] = Some(max_data_len);
+        self
+    }
+
+    /// Drops messages on `topic` that waited longer than `ttl` in the internal queues of the
+    /// subscriber, so a consumer that stalled and recovers does not spend time on notifications
+    /// that are minutes old. Only `hashtx` and `rawtx` messages expire, TTLs for block and
+    /// `sequence` messages are ignored so these are never lost.
+    ///
+    /// Messages can expire while they wait for the workers of [`decode_threads`], and while they
+    /// wait in the queue of a [`Consumer`]. The [`Receiver`] returned by [`receiver`] is an
+    /// ordinary channel that is handed out as is, messages in it do not expire. Subscribers that
+    /// have no internal queue (a [`receiver`] without [`decode_threads`], [`blocking`],
+    /// [`subscription`] and the streams) fail to subscribe with [`Error::UnsupportedOption`]
+    /// when a TTL is set, instead of ignoring it.
+    ///
+    /// [`blocking`]: SubscribeBuilder::blocking
+    /// [`subscription`]: SubscribeBuilder::subscription
+    /// [`decode_threads`]: SubscribeBuilder::decode_threads
+    /// [`Consumer`]: crate::Consumer
+    /// [`Receiver`]: std::sync::mpsc::Receiver
+    /// [`receiver`]: SubscribeBuilder::receiver
+    #[inline]
+    pub const fn topic_ttl(mut self, topic: Topic, ttl: Duration) -> Self {
+        if may_expire(topic) {
+            self.topic_ttl[topic as usize] = Some(ttl);
+        }
+        self
+    }
+
+    /// Counts the messages that expired (see [`topic_ttl`]) in `counter`.
+    ///
+    /// [`topic_ttl`]: SubscribeBuilder::topic_ttl
+    #[inline]
+    pub const fn count_expired(mut self, counter: &'a ExpiredMessages) -> Self {
+        self.expired_messages = Some(counter);
+        self
+    }
+
+    /// Makes [`wait_handshake`] tell systemd the service is ready (`READY=1`) once a connection
+    /// to all endpoints has been established. Disabled by default. See [`systemd`].
+    ///
+    /// [`wait_handshake`]: SubscribeBuilder::wait_handshake
+    /// [`systemd`]: crate::systemd
+    #[cfg(all(feature = "systemd", unix))]
+    #[inline]
+    pub const fn systemd_notify_ready(mut self, systemd_notify_ready: bool) -> Self {
+        self.systemd_notify_ready = systemd_notify_ready;
+        self
+    }
+
+    /// Makes [`wait_handshake`] look up the node's best block with `client` once a connection to
+    /// all endpoints has been established, and yield it as a synthetic `hashblock` message
+    /// before anything else. See [`rpc::initial_tip`]. The RPC call is made on a separate
+    /// thread with a clone of `client`, so it does not block the executor.
+    ///
+    /// Only [`wait_handshake`] and [`wait_handshake_with`] use this, the other subscribers do not
+    /// wait for a connection and ignore it. Call [`rpc::initial_tip`] after subscribing with
+    /// those instead.
+    ///
+    /// [`wait_handshake`]: SubscribeBuilder::wait_handshake
+    /// [`wait_handshake_with`]: SubscribeBuilder::wait_handshake_with
+    /// [`rpc::initial_tip`]: crate::rpc::initial_tip
+    #[cfg(feature = "rpc")]
+    #[inline]
+    pub const fn initial_tip(mut self, client: &'a Arc<bitcoincore_rpc::Client>) -> Self {
+        self.initial_tip = Some(client);
+        self
+    }
+
+    /// Returns [`Error::UnsupportedOption`] if a TTL is set, for subscribers where messages do
+    /// not wait in an internal queue, see [`topic_ttl`](SubscribeBuilder::topic_ttl).
+    pub(super) fn reject_topic_ttl(&self) -> Result<()> {
+        if self.topic_ttl.iter().any(Option::is_some) {
+            return Err(Error::UnsupportedOption("topic_ttl"));
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn expiry(&self) -> Expiry {
+        Expiry {
+            ttl: self.topic_ttl,
+            counter: self.expired_messages.cloned(),
+        }
+    }
+
+    pub(crate) fn recv_config(&self) -> RecvConfig {
+        RecvConfig {
+            debug_hexdump: self.debug_hexdump,
+            max_data_len: self.max_data_len,
+            topic_max_data_len: self.topic_max_data_len,
+            source: match self.endpoints {
+                [endpoint] => endpoint.parse().ok(),
+                _ => None,
+            },
+        }
+    }
+}
+
+// not derived, so M does not need to implement Clone and Debug
+
+impl<M> Clone for SubscribeBuilder<'_, M> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            endpoints: self.endpoints,
+            max_msg_size: self.max_msg_size,
+            linger: self.linger,
+            debug_hexdump: self.debug_hexdump,
+            global_context: self.global_context,
+            decode_threads: self.decode_threads,
+            thread_name: self.thread_name,
+            #[cfg(all(feature = "affinity", target_os = "linux"))]
+            receive_thread_core: self.receive_thread_core,
+            max_data_len: self.max_data_len,
+            topic_max_data_len: self.topic_max_data_len,
+            topic_ttl: self.topic_ttl,
+            expired_messages: self.expired_messages,
+            #[cfg(all(feature = "systemd", unix))]
+            systemd_notify_ready: self.systemd_notify_ready,
+            #[cfg(feature = "rpc")]
+            initial_tip: self.initial_tip,
+            message_type: PhantomData,
+        }
+    }
+}
+
+impl<M> fmt::Debug for SubscribeBuilder<'_, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("SubscribeBuilder");
+        f.field("endpoints", &self.endpoints)
+            .field("max_msg_size", &self.max_msg_size)
+            .field("linger", &self.linger)
+            .field("debug_hexdump", &self.debug_hexdump)
+            .field("global_context", &self.global_context)
+            .field("decode_threads", &self.decode_threads)
+            .field("thread_name", &self.thread_name)
+            .field("max_data_len", &self.max_data_len)
+            .field("topic_max_data_len", &self.topic_max_data_len)
+            .field("topic_ttl", &self.topic_ttl)
+            .field("expired_messages", &self.expired_messages);
+        #[cfg(all(feature = "affinity", target_os = "linux"))]
+        f.field("receive_thread_core", &self.receive_thread_core);
+        #[cfg(all(feature = "systemd", unix))]
+        f.field("systemd_notify_ready", &self.systemd_notify_ready);
+        #[cfg(feature = "rpc")]
+        f.field("initial_tip", &self.initial_tip);
+        f.finish()
+    }
+}
+
+/// The options of a [`SubscribeBuilder`] that are used while receiving messages.
+#[derive(Debug, Clone)]
+pub(crate) struct RecvConfig {
+    pub(crate) debug_hexdump: bool,
+    max_data_len: usize,
+    topic_max_data_len: [Option<usize>; Topic::ALL.len()],
+    /// The endpoint messages are received from, if there is only one. libzmq does not tell which
+    /// endpoint sent a message.
+    pub(crate) source: Option<Endpoint>,
+}
+
+impl RecvConfig {
+    /// Returns the maximum data length of messages on `topic`.
+    fn max_data_len(&self, topic: &[u8]) -> usize {
+        Topic::from_bytes(topic)
+            .and_then(|topic| self.topic_max_data_len[topic as usize])
+            .unwrap_or(self.max_data_len)
+    }
+
+    /// Returns the length of a buffer that fits the data of any allowed message.
+    pub(crate) fn buffer_len(&self) -> usize {
+        self.topic_max_data_len
+            .iter()
+            .flatten()
+            .fold(self.max_data_len, |max, len| max.max(*len))
+    }
+
+    /// Returns [`Error::MessageTooLarge`] if `len` exceeds the maximum data length of messages on
+    /// `topic`.
+    pub(crate) fn check_data_len(&self, topic: &[u8], len: usize) -> Result<()> {
+        let max = self.max_data_len(topic);
+        if len > max {
+            return Err(Error::MessageTooLarge(len, max));
+        }
+
+        Ok(())
+    }
+}
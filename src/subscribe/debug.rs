@@ -0,0 +1,114 @@
+use core::fmt::{self, Write};
+
+/// Maximum number of bytes of a single part that are dumped, the rest is truncated.
+const HEXDUMP_MAX_LEN: usize = 256;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Prints a received multipart to stderr, see [`SubscribeBuilder::debug_hexdump`].
+///
+/// [`SubscribeBuilder::debug_hexdump`]: super::builder::SubscribeBuilder::debug_hexdump
+pub(super) fn log_multipart(parts: &[&[u8]]) {
+    let mut out = String::new();
+    // writing to a String never fails
+    let _ = write_multipart(&mut out, parts);
+    #[cfg(test)]
+    LOGGED.with(|logged| logged.borrow_mut().push(out.clone()));
+    eprint!("{out}");
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The multiparts logged on this thread.
+    pub(super) static LOGGED: core::cell::RefCell<Vec<String>> = const {
+        core::cell::RefCell::new(Vec::new())
+    };
+}
+
+fn write_multipart(out: &mut impl Write, parts: &[&[u8]]) -> fmt::Result {
+    write!(
+        out,
+        "[bitcoincore-zmq] received multipart with {} part",
+        parts.len()
+    )?;
+    if parts.len() != 1 {
+        out.write_char('s')?;
+    }
+    match parts.first() {
+        Some(topic) => writeln!(out, ", topic '{}'", String::from_utf8_lossy(topic))?,
+        None => writeln!(out)?,
+    }
+
+    for (i, part) in parts.iter().enumerate() {
+        writeln!(out, "  part {i} ({} bytes):", part.len())?;
+        write_hexdump(out, part)?;
+    }
+
+    Ok(())
+}
+
+fn write_hexdump(out: &mut impl Write, bytes: &[u8]) -> fmt::Result {
+    let shown = &bytes[..bytes.len().min(HEXDUMP_MAX_LEN)];
+
+    for (line, chunk) in shown.chunks(BYTES_PER_LINE).enumerate() {
+        write!(out, "    {:08x} ", line * BYTES_PER_LINE)?;
+        for i in 0..BYTES_PER_LINE {
+            match chunk.get(i) {
+                Some(byte) => write!(out, " {byte:02x}")?,
+                None => out.write_str("   ")?,
+            }
+        }
+        out.write_str("  |")?;
+        for byte in chunk {
+            out.write_char(if byte.is_ascii_graphic() || *byte == b' ' {
+                *byte as char
+            } else {
+                '.'
+            })?;
+        }
+        writeln!(out, "|")?;
+    }
+
+    if bytes.len() > shown.len() {
+        writeln!(out, "    ... ({} more bytes)", bytes.len() - shown.len())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_multipart;
+
+    #[test]
+    fn hexdump() {
+        let mut out = String::new();
+        write_multipart(
+            &mut out,
+            &[
+                b"hashtx",
+                b"0123456789abcdef\x00\xff",
+                &[0x03, 0x00, 0x00, 0x00],
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            out,
+            "[bitcoincore-zmq] received multipart with 3 parts, topic 'hashtx'
+  part 0 (6 bytes):
+    00000000  68 61 73 68 74 78                                |hashtx|
+  part 1 (18 bytes):
+    00000000  30 31 32 33 34 35 36 37 38 39 61 62 63 64 65 66  |0123456789abcdef|
+    00000010  00 ff                                            |..|
+  part 2 (4 bytes):
+    00000000  03 00 00 00                                      |....|
+"
+        );
+
+        let mut out = String::new();
+        write_multipart(&mut out, &[&[0; 300]]).unwrap();
+        assert_eq!(out.lines().count(), 1 + 1 + 16 + 1);
+        assert!(out.ends_with("    ... (44 more bytes)\n"));
+    }
+}
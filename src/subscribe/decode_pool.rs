@@ -0,0 +1,175 @@
+use super::{
+    builder::SubscribeBuilder, decode_received, expiry::Queued, new_recv_buffer,
+    recv_parts_internal_socket, spawn_helper_thread, spawn_receive_thread,
+};
+use crate::{error::Result, message::SEQUENCE_LEN, raw_message::FromRawMessage, topic::Topic};
+use std::{
+    sync::{
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+use zmq::Socket;
+
+/// The number of messages per worker thread that may wait to be decoded or forwarded. Once this
+/// many are waiting, the receive thread blocks, so messages queue up in the socket (and are
+/// dropped when its high water mark is reached) instead of in memory without a limit.
+const QUEUED_PER_THREAD: usize = 4;
+
+/// The copied parts of a received message and where to send the decoded result.
+struct Job<M> {
+    topic: Vec<u8>,
+    data: Vec<u8>,
+    sequence: [u8; SEQUENCE_LEN],
+    received: Instant,
+    /// Receives [`None`] if the message expired before it was decoded.
+    result: SyncSender<Option<Queued<M>>>,
+}
+
+/// Receives messages from `socket` and decodes them on `threads` worker threads, passing the
+/// results to `sink` in the order they were received.
+///
+/// The receive thread only copies the parts of every message and queues them for the workers.
+/// For every message, it also queues a slot for the result to a forwarding thread, which waits
+/// for the slots in order and passes their results to `sink`. Both queues are bounded, see
+/// [`QUEUED_PER_THREAD`]. Messages that expired while queued are not decoded. All threads stop
+/// once `sink` returns `false`.
+pub(super) fn spawn_decode_pool<M: FromRawMessage + Send + 'static>(
+    builder: &SubscribeBuilder<M>,
+    socket: Socket,
+    threads: usize,
+    mut sink: impl FnMut(Queued<M>) -> bool + Send + 'static,
+) -> Result<()> {
+    let config = builder.recv_config();
+    let bound = threads.saturating_mul(QUEUED_PER_THREAD);
+    let (job_tx, job_rx) = sync_channel::<Job<M>>(bound);
+    let job_rx = Arc::new(Mutex::new(job_rx));
+    let (slot_tx, slot_rx) = sync_channel::<Receiver<Option<Queued<M>>>>(bound);
+
+    for _ in 0..threads {
+        let job_rx = job_rx.clone();
+        let expiry = builder.expiry();
+        let config = config.clone();
+        spawn_helper_thread(builder.thread_name, "decode", move || loop {
+            // release the lock before decoding, so other workers can take jobs
+            let job = job_rx.lock().unwrap().recv();
+            let Ok(job) = job else {
+                break;
+            };
+            let topic = Topic::from_bytes(&job.topic);
+            let queued = (!expiry.expire(topic, job.received)).then(|| Queued {
+                msg: decode_received(&job.topic, &job.data, job.sequence, &config),
+                topic,
+                received: job.received,
+            });
+            // the forwarding thread is gone when the subscriber stops
+            let _ = job.result.send(queued);
+        })?;
+    }
+
+    spawn_helper_thread(builder.thread_name, "forward", move || {
+        for slot in slot_rx {
+            match slot.recv() {
+                Ok(Some(queued)) => {
+                    if !sink(queued) {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(_) => break,
+            }
+        }
+    })?;
+
+    spawn_receive_thread(builder, move || {
+        let mut buf = new_recv_buffer(&config);
+
+        loop {
+            let (result_tx, result_rx) = sync_channel(1);
+            if slot_tx.send(result_rx).is_err() {
+                break;
+            }
+
+            let res = recv_parts_internal_socket(
+                &socket,
+                &mut buf,
+                &config,
+                0,
+                |topic, data, sequence| {
+                    Ok(Job {
+                        topic: topic.to_vec(),
+                        data: data.to_vec(),
+                        sequence,
+                        received: Instant::now(),
+                        result: result_tx.clone(),
+                    })
+                },
+            );
+
+            match res {
+                Ok(job) => {
+                    if job_tx.send(job).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    let _ = result_tx.send(Some(Queued {
+                        msg: Err(err),
+                        topic: None,
+                        received: Instant::now(),
+                    }));
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::spawn_decode_pool;
+    use crate::{Message, SubscribeBuilder};
+    use bitcoin::{hashes::Hash, Txid};
+    use core::time::Duration;
+    use std::sync::mpsc::sync_channel;
+
+    #[test]
+    fn backpressure() {
+        // inproc has no kernel buffers, at most the high water marks of both sockets are queued
+        // between them
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.set_sndhwm(10).unwrap();
+        publisher.bind("inproc://decode-pool-backpressure").unwrap();
+        let socket = context.socket(zmq::SUB).unwrap();
+        socket.set_rcvhwm(10).unwrap();
+        socket.connect("inproc://decode-pool-backpressure").unwrap();
+        socket.set_subscribe(b"").unwrap();
+
+        // the sink blocks until the test takes the message
+        let (tx, rx) = sync_channel(0);
+        spawn_decode_pool(&SubscribeBuilder::new(&[]), socket, 2, move |queued| {
+            tx.send(queued.msg.unwrap()).is_ok()
+        })
+        .unwrap();
+
+        let msg = Message::HashTx(Txid::all_zeros(), 0).serialize_to_vecs();
+        publisher.send_multipart(&msg, 0).unwrap();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // in bursts that fit in the sockets, so only a slow subscriber drops messages
+        for _ in 0..100 {
+            for _ in 0..10 {
+                publisher.send_multipart(&msg, zmq::DONTWAIT).unwrap();
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        // messages that did not fit in the bounded queues and the sockets were dropped
+        let mut received = 0;
+        while rx.recv_timeout(Duration::from_millis(100)).is_ok() {
+            received += 1;
+        }
+        assert!(received < 100, "{received} messages were queued");
+    }
+}
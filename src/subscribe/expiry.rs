@@ -0,0 +1,97 @@
+use crate::{error::Result, topic::Topic};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::{sync::Arc, time::Instant};
+
+/// Counts the messages that were dropped because they expired, see
+/// [`SubscribeBuilder::topic_ttl`]. Clones share the same count.
+///
+/// [`SubscribeBuilder::topic_ttl`]: crate::SubscribeBuilder::topic_ttl
+#[derive(Debug, Clone, Default)]
+pub struct ExpiredMessages(Arc<AtomicU64>);
+
+impl ExpiredMessages {
+    /// Creates a new counter, starting at 0.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of messages that expired.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Returns `true` if messages on `topic` may expire. Block and sequence messages never do, as
+/// consumers need every one of them to keep track of the chain and mempool.
+pub(super) const fn may_expire(topic: Topic) -> bool {
+    matches!(topic, Topic::HashTx | Topic::RawTx)
+}
+
+/// The TTLs of a subscriber and the counter of messages that exceeded them.
+#[derive(Debug, Clone)]
+pub(super) struct Expiry {
+    pub(super) ttl: [Option<Duration>; Topic::ALL.len()],
+    pub(super) counter: Option<ExpiredMessages>,
+}
+
+impl Expiry {
+    /// Returns `true` if a message on `topic`, received at `received`, is older than the TTL of
+    /// its topic, and counts it if so.
+    pub(super) fn expire(&self, topic: Option<Topic>, received: Instant) -> bool {
+        let expired = topic
+            .and_then(|topic| self.ttl[topic as usize])
+            .is_some_and(|ttl| received.elapsed() > ttl);
+        if expired {
+            if let Some(counter) = &self.counter {
+                counter.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        expired
+    }
+}
+
+/// A received message on its way through the internal queues of a subscriber.
+#[derive(Debug)]
+pub(crate) struct Queued<M> {
+    pub(crate) msg: Result<M>,
+    pub(crate) topic: Option<Topic>,
+    pub(crate) received: Instant,
+}
+
+impl<M> Queued<M> {
+    /// Returns `true` if this message expired, see [`Expiry::expire`].
+    pub(super) fn expire(&self, expiry: &Expiry) -> bool {
+        expiry.expire(self.topic, self.received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExpiredMessages, Expiry};
+    use crate::topic::Topic;
+    use core::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn expire_counts() {
+        let counter = ExpiredMessages::new();
+        let mut ttl = [None; Topic::ALL.len()];
+        ttl[Topic::HashTx as usize] = Some(Duration::from_secs(1));
+        let expiry = Expiry {
+            ttl,
+            counter: Some(counter.clone()),
+        };
+
+        let old = Instant::now() - Duration::from_secs(2);
+        assert!(expiry.expire(Some(Topic::HashTx), old));
+        assert!(!expiry.expire(Some(Topic::HashTx), Instant::now()));
+        assert!(!expiry.expire(Some(Topic::RawTx), old));
+        assert!(!expiry.expire(None, old));
+        assert_eq!(counter.count(), 1);
+    }
+}
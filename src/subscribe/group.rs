@@ -0,0 +1,302 @@
+use super::{
+    builder::SubscribeBuilder,
+    expiry::{Expiry, Queued},
+};
+use crate::{error::Result, message::Message, raw_message::FromRawMessage};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    time::Instant,
+};
+
+/// How a consumer group distributes messages over its consumers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Distribution {
+    /// Every consumer gets the next message in turn.
+    #[default]
+    RoundRobin,
+    /// The consumer with the fewest messages waiting gets the next message, so slow consumers
+    /// are not handed more work while others are idle. Ties are broken in round robin order.
+    LeastLoaded,
+}
+
+/// A member of a consumer group created with [`SubscribeBuilder::consumer_group`].
+///
+/// Every message is delivered to exactly one consumer of the group. Consumers can be moved to
+/// other threads, a dropped consumer no longer gets messages. The subscriber stops once all
+/// consumers are dropped.
+///
+/// Messages that expire while they wait in the queue of a consumer (see
+/// [`SubscribeBuilder::topic_ttl`]) are skipped when receiving.
+#[derive(Debug)]
+pub struct Consumer<M = Message> {
+    id: usize,
+    rx: Receiver<Queued<M>>,
+    pending: Arc<AtomicUsize>,
+    expiry: Expiry,
+}
+
+impl<M> Consumer<M> {
+    /// Returns the index of this consumer in its group.
+    #[inline]
+    pub const fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns the number of messages that were delivered to this consumer but not received yet.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Waits for the next message for this consumer. Fails when the subscriber stopped.
+    pub fn recv(&self) -> core::result::Result<Result<M>, RecvError> {
+        loop {
+            if let Some(msg) = self.received(self.rx.recv()?) {
+                return Ok(msg);
+            }
+        }
+    }
+
+    /// Returns the next message for this consumer if there is one, without blocking.
+    pub fn try_recv(&self) -> core::result::Result<Result<M>, TryRecvError> {
+        loop {
+            if let Some(msg) = self.received(self.rx.try_recv()?) {
+                return Ok(msg);
+            }
+        }
+    }
+
+    /// Waits at most `timeout` for the next message for this consumer.
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> core::result::Result<Result<M>, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if let Some(msg) = self.received(self.rx.recv_timeout(timeout)?) {
+                return Ok(msg);
+            }
+        }
+    }
+
+    /// Returns an iterator that waits for messages for this consumer, until the subscriber
+    /// stops.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = Result<M>> + '_ {
+        core::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// Takes `queued` off the pending count, returns its message if it did not expire.
+    fn received(&self, queued: Queued<M>) -> Option<Result<M>> {
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+        (!queued.expire(&self.expiry)).then_some(queued.msg)
+    }
+}
+
+impl<M: FromRawMessage + Send + 'static> SubscribeBuilder<'_, M> {
+    /// Subscribes and returns `consumers` consumers that together form a group: every message
+    /// (or error) is delivered to exactly one of them, chosen by `distribution`.
+    ///
+    /// This is the work queue counterpart of [`receiver`](SubscribeBuilder::receiver), for
+    /// handling messages on a pool of threads without sharing a [`Receiver`] behind a lock.
+    /// Messages handled by different consumers may complete out of order.
+    pub fn consumer_group(
+        self,
+        consumers: usize,
+        distribution: Distribution,
+    ) -> Result<Vec<Consumer<M>>> {
+        let (members, consumers) = new_group(consumers, &self.expiry());
+        let mut dispatcher = Dispatcher {
+            members,
+            distribution,
+            next: 0,
+        };
+
+        self.spawn_receiver(move |queued| dispatcher.dispatch(queued))?;
+
+        Ok(consumers)
+    }
+}
+
+/// The sending side of a [`Consumer`].
+struct Member<M> {
+    tx: Sender<Queued<M>>,
+    pending: Arc<AtomicUsize>,
+}
+
+/// Creates the sending and receiving sides of a group of `consumers` consumers.
+fn new_group<M>(consumers: usize, expiry: &Expiry) -> (Vec<Member<M>>, Vec<Consumer<M>>) {
+    (0..consumers)
+        .map(|id| {
+            let (tx, rx) = channel();
+            let pending = Arc::new(AtomicUsize::new(0));
+            let member = Member {
+                tx,
+                pending: pending.clone(),
+            };
+            let consumer = Consumer {
+                id,
+                rx,
+                pending,
+                expiry: expiry.clone(),
+            };
+            (member, consumer)
+        })
+        .unzip()
+}
+
+/// Distributes messages over the members of a consumer group.
+struct Dispatcher<M> {
+    members: Vec<Member<M>>,
+    distribution: Distribution,
+    next: usize,
+}
+
+impl<M> Dispatcher<M> {
+    /// Delivers `msg` to one of the members, returns `false` if all members are dropped.
+    fn dispatch(&mut self, mut msg: Queued<M>) -> bool {
+        let members = &mut self.members;
+
+        while !members.is_empty() {
+            let index = match self.distribution {
+                Distribution::RoundRobin => self.next % members.len(),
+                Distribution::LeastLoaded => (0..members.len())
+                    .map(|i| (self.next + i) % members.len())
+                    .min_by_key(|i| members[*i].pending.load(Ordering::Relaxed))
+                    .unwrap(),
+            };
+
+            let member = &members[index];
+            // counted before sending, so the consumer never sees a count below zero
+            member.pending.fetch_add(1, Ordering::Relaxed);
+            match member.tx.send(msg) {
+                Ok(()) => {
+                    self.next = index + 1;
+                    return true;
+                }
+                Err(err) => {
+                    // the consumer was dropped, hand the message to another one
+                    msg = err.0;
+                    members.swap_remove(index);
+                    self.next = index;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{new_group, Consumer, Dispatcher, Distribution};
+    use crate::{
+        subscribe::expiry::{ExpiredMessages, Expiry, Queued},
+        topic::Topic,
+        RawMessage,
+    };
+    use core::time::Duration;
+    use std::time::Instant;
+
+    fn new_dispatcher(
+        consumers: usize,
+        distribution: Distribution,
+        expiry: &Expiry,
+    ) -> (Dispatcher<RawMessage>, Vec<Consumer<RawMessage>>) {
+        let (members, consumers) = new_group(consumers, expiry);
+        let dispatcher = Dispatcher {
+            members,
+            distribution,
+            next: 0,
+        };
+        (dispatcher, consumers)
+    }
+
+    fn queued(topic: Topic, sequence: u32, received: Instant) -> Queued<RawMessage> {
+        Queued {
+            msg: Ok(RawMessage {
+                topic: topic.as_bytes().to_vec(),
+                data: Vec::new(),
+                sequence,
+            }),
+            topic: Some(topic),
+            received,
+        }
+    }
+
+    fn sequences(consumer: &Consumer<RawMessage>) -> Vec<u32> {
+        consumer.iter().map(|msg| msg.unwrap().sequence).collect()
+    }
+
+    const NO_EXPIRY: Expiry = Expiry {
+        ttl: [None; Topic::ALL.len()],
+        counter: None,
+    };
+
+    #[test]
+    fn round_robin() {
+        let (mut dispatcher, mut consumers) =
+            new_dispatcher(3, Distribution::RoundRobin, &NO_EXPIRY);
+
+        // a dropped consumer gets no messages
+        consumers.remove(1);
+        for sequence in 0..7 {
+            assert!(dispatcher.dispatch(queued(Topic::RawTx, sequence, Instant::now())));
+        }
+        drop(dispatcher);
+
+        assert_eq!(consumers[0].pending(), 4);
+        assert_eq!(sequences(&consumers[0]), [0, 2, 4, 6]);
+        assert_eq!(sequences(&consumers[1]), [1, 3, 5]);
+        assert_eq!(consumers[1].pending(), 0);
+
+        // all consumers dropped
+        let (mut dispatcher, consumers) = new_dispatcher(1, Distribution::RoundRobin, &NO_EXPIRY);
+        drop(consumers);
+        assert!(!dispatcher.dispatch(queued(Topic::RawTx, 0, Instant::now())));
+    }
+
+    #[test]
+    fn least_loaded() {
+        let (mut dispatcher, consumers) = new_dispatcher(3, Distribution::LeastLoaded, &NO_EXPIRY);
+        // consumer 0 is busy
+        dispatcher.members[0]
+            .pending
+            .store(2, core::sync::atomic::Ordering::Relaxed);
+        for sequence in 0..4 {
+            dispatcher.dispatch(queued(Topic::RawTx, sequence, Instant::now()));
+        }
+        drop(dispatcher);
+
+        assert!(consumers[0].try_recv().is_err());
+        assert_eq!(sequences(&consumers[1]), [0, 2]);
+        assert_eq!(sequences(&consumers[2]), [1, 3]);
+    }
+
+    #[test]
+    fn expired_messages_are_skipped() {
+        let counter = ExpiredMessages::new();
+        let mut expiry = NO_EXPIRY;
+        expiry.ttl[Topic::HashTx as usize] = Some(Duration::from_secs(60));
+        expiry.counter = Some(counter.clone());
+        let (mut dispatcher, consumers) = new_dispatcher(1, Distribution::RoundRobin, &expiry);
+
+        let old = Instant::now() - Duration::from_secs(120);
+        dispatcher.dispatch(queued(Topic::HashTx, 0, old));
+        dispatcher.dispatch(queued(Topic::HashBlock, 1, old));
+        dispatcher.dispatch(queued(Topic::HashTx, 2, Instant::now()));
+        drop(dispatcher);
+
+        assert_eq!(sequences(&consumers[0]), [1, 2]);
+        assert_eq!(consumers[0].pending(), 0);
+        assert_eq!(counter.count(), 1);
+    }
+}
@@ -1,61 +1,288 @@
 pub mod blocking;
+pub mod builder;
+mod debug;
+mod decode_pool;
+pub mod expiry;
+pub mod group;
 pub mod receiver;
+pub mod report;
 #[cfg(feature = "async")]
 pub mod stream;
+pub mod subscription;
 
 use crate::{
-    error::Result,
-    message::{Message, SEQUENCE_LEN, TOPIC_MAX_LEN},
-    Error, DATA_MAX_LEN,
+    capabilities::capabilities,
+    context::global_context,
+    endpoint::{Endpoint, Host},
+    error::{DeserializationError, Result},
+    message::{SEQUENCE_LEN, TOPIC_MAX_LEN},
+    raw_message::FromRawMessage,
+    topic::Topic,
+    Error,
 };
-use core::{convert::Infallible, ops::ControlFlow};
+use builder::{RecvConfig, SubscribeBuilder};
+use core::{
+    convert::Infallible,
+    ops::ControlFlow,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use expiry::Queued;
+#[cfg(feature = "async")]
+use report::Received;
+use std::{thread, time::Instant};
 use zmq::{Context, Socket};
 
-pub(super) fn new_socket_internal(endpoints: &[&str]) -> Result<(Context, Socket)> {
-    let context = Context::new();
+/// Creates a socket for `builder` and connects it. Hostnames in endpoints are resolved first if
+/// `resolve_hosts`, which blocks, so async subscribers leave that to libzmq.
+pub(super) fn new_socket_internal<M>(
+    builder: &SubscribeBuilder<M>,
+    resolve_hosts: bool,
+) -> Result<(Context, Socket)> {
+    let (context, socket, ()) =
+        new_monitored_socket_internal(builder, resolve_hosts, |_, _| Ok(()))?;
+
+    Ok((context, socket))
+}
+
+/// Like [`new_socket_internal`], calling `monitor` before connecting, so a monitor set up by it
+/// sees every connection event.
+pub(super) fn new_monitored_socket_internal<M, T>(
+    builder: &SubscribeBuilder<M>,
+    resolve_hosts: bool,
+    monitor: impl FnOnce(&Context, &Socket) -> Result<T>,
+) -> Result<(Context, Socket, T)> {
+    // fail early with a clear error instead of an obscure one from connect, or none at all
+    let capabilities = capabilities();
+    let mut ipv6 = false;
+    for endpoint in builder.endpoints {
+        capabilities.check_endpoint(endpoint)?;
+        let invalid = |err| Error::InvalidEndpoint((*endpoint).to_owned(), err);
+        let parsed = endpoint.parse::<Endpoint>().map_err(invalid)?;
+        match parsed {
+            Endpoint::Tcp {
+                host: Host::Name(_),
+                ..
+            } if !resolve_hosts => {
+                // the addresses are not known, allow connecting to both IPv4 and IPv6 ones
+                ipv6 = true;
+            }
+            // a scoped IPv6 address, kept as it is by the parser
+            Endpoint::Other(ref other) if other.starts_with("tcp://[") => ipv6 = true,
+            _ => {
+                let addrs = parsed.resolve().map_err(invalid)?;
+                ipv6 |= addrs.iter().any(|addr| addr.is_ipv6());
+            }
+        }
+    }
+
+    let context = if builder.global_context {
+        global_context()
+    } else {
+        Context::new()
+    };
 
     let socket = context.socket(zmq::SUB)?;
+    socket.set_maxmsgsize(builder.max_msg_size.map_or(-1, |max| max as i64))?;
+    socket.set_linger(linger_millis(builder.linger))?;
     socket.set_subscribe(b"")?;
+    // libzmq only connects to IPv6 addresses with this enabled
+    if ipv6 {
+        socket.set_ipv6(true)?;
+    }
+
+    let monitor = monitor(&context, &socket)?;
 
-    for endpoint in endpoints {
+    for endpoint in builder.endpoints {
         socket.connect(endpoint)?;
     }
 
-    Ok((context, socket))
+    Ok((context, socket, monitor))
 }
 
-pub(super) fn recv_internal_socket(
+/// Creates a PAIR socket connected to a monitor of `socket` that receives `events`. The
+/// receive high-water mark is `rcvhwm` if set, the default of libzmq otherwise.
+pub(super) fn new_monitor_socket(
+    context: &Context,
     socket: &Socket,
-    tmp_buffer: &mut [u8; DATA_MAX_LEN],
-) -> Result<Message> {
+    events: i32,
+    rcvhwm: Option<i32>,
+    linger: Duration,
+) -> Result<Socket> {
+    static MONITOR_ID: AtomicUsize = AtomicUsize::new(0);
+
+    // inproc endpoints are shared by all sockets of a context, which may be the global one
+    let endpoint = format!(
+        "inproc://monitor-{}",
+        MONITOR_ID.fetch_add(1, Ordering::Relaxed)
+    );
+    socket.monitor(&endpoint, events)?;
+
+    let monitor = context.socket(zmq::PAIR)?;
+    monitor.set_linger(linger_millis(linger))?;
+    if let Some(rcvhwm) = rcvhwm {
+        monitor.set_rcvhwm(rcvhwm)?;
+    }
+    monitor.connect(&endpoint)?;
+
+    Ok(monitor)
+}
+
+/// Spawns the thread that receives messages for `builder`, named and pinned as configured.
+pub(super) fn spawn_receive_thread<M>(
+    builder: &SubscribeBuilder<M>,
+    f: impl FnOnce() + Send + 'static,
+) -> Result<()> {
+    let thread = thread::Builder::new().name(builder.thread_name.to_owned());
+
+    #[cfg(all(feature = "affinity", target_os = "linux"))]
+    if let Some(core) = builder.receive_thread_core {
+        // pin from the thread itself and wait for the result, so failing to pin fails subscribing
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        thread.spawn(move || {
+            let res = pin_current_thread(core);
+            let pinned = res.is_ok();
+            let _ = tx.send(res);
+            if pinned {
+                f();
+            }
+        })?;
+        return Ok(rx.recv().expect("thread sends the result before exiting")?);
+    }
+
+    thread.spawn(f)?;
+
+    Ok(())
+}
+
+/// Spawns a helper thread of a subscriber with threads named `thread_name`, named
+/// `{thread_name}-{role}`.
+pub(super) fn spawn_helper_thread(
+    thread_name: &str,
+    role: &str,
+    f: impl FnOnce() + Send + 'static,
+) -> Result<()> {
+    thread::Builder::new()
+        .name(format!("{thread_name}-{role}"))
+        .spawn(f)?;
+
+    Ok(())
+}
+
+/// Restricts the calling thread to CPU core `core`.
+#[cfg(all(feature = "affinity", target_os = "linux"))]
+fn pin_current_thread(core: usize) -> std::io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(std::io::ErrorKind::InvalidInput.into());
+    }
+
+    // SAFETY: cpu_set_t is a plain bit set, for which all zeros is the empty set, `core` is
+    // checked to be in range and the set outlives the call
+    let res = unsafe {
+        let mut set: libc::cpu_set_t = core::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, core::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if res != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Converts a linger period to the milliseconds ZMQ_LINGER takes.
+pub(super) fn linger_millis(linger: Duration) -> i32 {
+    linger.as_millis().try_into().unwrap_or(i32::MAX)
+}
+
+/// Allocates a receive buffer large enough for the largest data part allowed by `config`.
+pub(super) fn new_recv_buffer(config: &RecvConfig) -> Box<[u8]> {
+    vec![0; config.buffer_len()].into_boxed_slice()
+}
+
+/// Receives a message from `socket`. `flags` are only used to receive the first part, the other
+/// parts of a multipart are always available once the first one has arrived.
+pub(super) fn recv_internal_socket<M: FromRawMessage>(
+    socket: &Socket,
+    tmp_buffer: &mut [u8],
+    config: &RecvConfig,
+    flags: i32,
+) -> Result<M> {
+    recv_parts_internal_socket(
+        socket,
+        tmp_buffer,
+        config,
+        flags,
+        |topic, data, sequence| decode_received(topic, data, sequence, config),
+    )
+}
+
+/// Receives a message like [`recv_internal_socket`], along with its topic and the time it was
+/// received, for the internal queues of a subscriber.
+pub(super) fn recv_queued_internal_socket<M: FromRawMessage>(
+    socket: &Socket,
+    tmp_buffer: &mut [u8],
+    config: &RecvConfig,
+) -> Queued<M> {
+    let mut topic = None;
+    let msg = recv_parts_internal_socket(socket, tmp_buffer, config, 0, |t, data, sequence| {
+        topic = Topic::from_bytes(t);
+        decode_received(t, data, sequence, config)
+    });
+
+    Queued {
+        msg,
+        topic,
+        received: Instant::now(),
+    }
+}
+
+/// Receives the parts (topic, data and sequence) of a message from `socket` and passes them to
+/// `f` without parsing them. See [`recv_internal_socket`].
+pub(super) fn recv_parts_internal_socket<T>(
+    socket: &Socket,
+    tmp_buffer: &mut [u8],
+    config: &RecvConfig,
+    flags: i32,
+    f: impl FnOnce(&[u8], &[u8], [u8; SEQUENCE_LEN]) -> Result<T>,
+) -> Result<T> {
+    if config.debug_hexdump {
+        return recv_parts_dumped(socket, config, flags, f);
+    }
+
     let mut topic = [0u8; TOPIC_MAX_LEN];
     let mut sequence = [0u8; SEQUENCE_LEN];
 
-    let topic_len = socket.recv_into(&mut topic, 0)?;
+    let topic_len = socket.recv_into(&mut topic, flags)?;
     let topic = topic
         .get(0..topic_len)
-        .ok_or(Error::InvalidTopic(topic_len, topic))?;
+        .ok_or_else(|| invalid_message(Error::InvalidTopic(topic_len, topic)))?;
 
     if !socket.get_rcvmore()? {
-        return Err(Error::InvalidMutlipartLength(1));
+        return Err(invalid_message(Error::InvalidMutlipartLength(1)));
     }
 
     let data_len = socket.recv_into(tmp_buffer, 0)?;
-    let data = tmp_buffer
-        .get(0..data_len)
-        .ok_or(Error::InvalidDataLength(data_len))?;
+    if let Err(err) = config.check_data_len(topic, data_len) {
+        // skip the rest of the multipart, so the next receive starts at a new message
+        while socket.get_rcvmore()? {
+            socket.recv_into(&mut [], 0)?;
+        }
+        return Err(invalid_message(err));
+    }
+    let data = &tmp_buffer[..data_len];
 
     if !socket.get_rcvmore()? {
-        return Err(Error::InvalidMutlipartLength(2));
+        return Err(invalid_message(Error::InvalidMutlipartLength(2)));
     }
 
     let sequence_len = socket.recv_into(&mut sequence, 0)?;
     if sequence_len != SEQUENCE_LEN {
-        return Err(Error::InvalidSequenceLength(sequence_len));
+        return Err(invalid_message(Error::InvalidSequenceLength(sequence_len)));
     }
 
     if !socket.get_rcvmore()? {
-        return Message::from_parts(topic, data, sequence);
+        return f(topic, data, sequence);
     }
 
     let mut len = 3;
@@ -66,31 +293,321 @@ pub(super) fn recv_internal_socket(
         len += 1;
 
         if !socket.get_rcvmore()? {
-            return Err(Error::InvalidMutlipartLength(len));
+            return Err(invalid_message(Error::InvalidMutlipartLength(len)));
         }
     }
 }
 
+/// [`recv_parts_internal_socket`] for [`RecvConfig::debug_hexdump`]: receives the whole multipart
+/// and dumps it before it is checked, so malformed multiparts are dumped too.
+#[cold]
+fn recv_parts_dumped<T>(
+    socket: &Socket,
+    config: &RecvConfig,
+    flags: i32,
+    f: impl FnOnce(&[u8], &[u8], [u8; SEQUENCE_LEN]) -> Result<T>,
+) -> Result<T> {
+    let parts = socket.recv_multipart(flags)?;
+    let parts: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+    debug::log_multipart(&parts);
+
+    let topic = parts[0];
+    if topic.len() > TOPIC_MAX_LEN {
+        let mut truncated = [0u8; TOPIC_MAX_LEN];
+        truncated.copy_from_slice(&topic[..TOPIC_MAX_LEN]);
+        return Err(invalid_message(Error::InvalidTopic(topic.len(), truncated)));
+    }
+    let [topic, data, sequence] = parts[..] else {
+        return Err(invalid_message(Error::InvalidMutlipartLength(parts.len())));
+    };
+    config
+        .check_data_len(topic, data.len())
+        .map_err(invalid_message)?;
+    let sequence = sequence
+        .try_into()
+        .map_err(|_| invalid_message(Error::InvalidSequenceLength(sequence.len())))?;
+
+    f(topic, data, sequence)
+}
+
+/// Tracks what a SUB socket is subscribed to, so subscriptions can be changed and restored while
+/// the socket stays connected.
+#[derive(Debug, Default)]
+pub(super) struct Subscriptions {
+    /// [`None`] means subscribed to everything (the empty prefix), which also includes topics
+    /// unknown to this crate.
+    topics: Option<Vec<Topic>>,
+    paused: bool,
+}
+
+impl Subscriptions {
+    fn prefixes(&self) -> Vec<&'static [u8]> {
+        match &self.topics {
+            None => vec![b""],
+            Some(topics) => topics.iter().map(|topic| topic.as_bytes()).collect(),
+        }
+    }
+
+    fn subscribe_all(&self, socket: &Socket) -> Result<()> {
+        for prefix in self.prefixes() {
+            socket.set_subscribe(prefix)?;
+        }
+
+        Ok(())
+    }
+
+    fn unsubscribe_all(&self, socket: &Socket) -> Result<()> {
+        for prefix in self.prefixes() {
+            socket.set_unsubscribe(prefix)?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn pause(&mut self, socket: &Socket) -> Result<()> {
+        if !self.paused {
+            self.unsubscribe_all(socket)?;
+            self.paused = true;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn resume(&mut self, socket: &Socket) -> Result<()> {
+        if self.paused {
+            self.subscribe_all(socket)?;
+            self.paused = false;
+        }
+
+        Ok(())
+    }
+
+    pub(super) const fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub(super) fn topics(&self) -> Option<Vec<Topic>> {
+        self.topics.clone()
+    }
+
+    pub(super) fn is_subscribed(&self, topic: Topic) -> bool {
+        self.topics
+            .as_ref()
+            .is_none_or(|topics| topics.contains(&topic))
+    }
+
+    pub(super) fn set_topics(&mut self, socket: &Socket, topics: &[Topic]) -> Result<()> {
+        let mut new_topics = Vec::with_capacity(topics.len());
+        for topic in topics {
+            if !new_topics.contains(topic) {
+                new_topics.push(*topic);
+            }
+        }
+
+        if !self.paused {
+            // subscribe first, so messages on topics in both sets are not missed
+            for topic in &new_topics {
+                socket.set_subscribe(topic.as_bytes())?;
+            }
+            self.unsubscribe_all(socket)?;
+        }
+
+        self.topics = Some(new_topics);
+
+        Ok(())
+    }
+
+    pub(super) fn add_topic(&mut self, socket: &Socket, topic: Topic) -> Result<()> {
+        if let Some(topics) = &mut self.topics {
+            if !topics.contains(&topic) {
+                if !self.paused {
+                    socket.set_subscribe(topic.as_bytes())?;
+                }
+                topics.push(topic);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn remove_topic(&mut self, socket: &Socket, topic: Topic) -> Result<()> {
+        match &mut self.topics {
+            None => {
+                let rest: Vec<_> = Topic::ALL.into_iter().filter(|t| *t != topic).collect();
+                self.set_topics(socket, &rest)?;
+            }
+            Some(topics) => {
+                if let Some(index) = topics.iter().position(|t| *t == topic) {
+                    if !self.paused {
+                        socket.set_unsubscribe(topic.as_bytes())?;
+                    }
+                    topics.remove(index);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Frames that are reused to receive messages without allocating per message, used by the async
+/// stream before falling back to [`async_zmq`], which allocates a [`Vec`] for every multipart.
 #[cfg(feature = "async")] // only used with the async feature on
-pub(super) fn message_from_multipart_zmq_message(messages: &[zmq::Message]) -> Result<Message> {
+pub(super) struct RecvFrames {
+    parts: [zmq::Message; 3],
+    /// Receives unexpected extra parts.
+    extra: zmq::Message,
+}
+
+#[cfg(feature = "async")]
+impl RecvFrames {
+    pub(super) fn new() -> Self {
+        Self {
+            parts: [
+                zmq::Message::new(),
+                zmq::Message::new(),
+                zmq::Message::new(),
+            ],
+            extra: zmq::Message::new(),
+        }
+    }
+
+    /// Receives and parses a message if one is pending, without blocking. Sets `received` once
+    /// the topic and sequence number are known.
+    pub(super) fn try_recv<M: FromRawMessage>(
+        &mut self,
+        socket: &Socket,
+        config: &RecvConfig,
+        received: &mut Received,
+    ) -> Option<Result<M>> {
+        match socket.recv(&mut self.parts[0], zmq::DONTWAIT) {
+            Ok(()) => Some(self.recv_rest(socket, config, received)),
+            Err(zmq::Error::EAGAIN) => None,
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+
+    fn recv_rest<M: FromRawMessage>(
+        &mut self,
+        socket: &Socket,
+        config: &RecvConfig,
+        received: &mut Received,
+    ) -> Result<M> {
+        // the other parts of a multipart are available once the first one has arrived
+        let mut len = 1;
+        let mut more = self.parts[0].get_more();
+        // copies of the extra parts, only kept to dump them
+        let mut extra = Vec::new();
+        while more {
+            let frame = self.parts.get_mut(len).unwrap_or(&mut self.extra);
+            socket.recv(frame, 0)?;
+            more = frame.get_more();
+            len += 1;
+            if config.debug_hexdump && len > 3 {
+                extra.push(self.extra.to_vec());
+            }
+        }
+
+        if config.debug_hexdump {
+            let mut parts: Vec<&[u8]> = self.parts[..len.min(3)].iter().map(|p| &**p).collect();
+            parts.extend(extra.iter().map(Vec::as_slice));
+            debug::log_multipart(&parts);
+        }
+
+        let [topic, data, sequence] = &self.parts;
+        if len != 3 {
+            return Err(invalid_message(Error::InvalidMutlipartLength(len)));
+        }
+        config
+            .check_data_len(topic, data.len())
+            .map_err(invalid_message)?;
+
+        let sequence = (**sequence)
+            .try_into()
+            .map_err(|_| invalid_message(Error::InvalidSequenceLength(sequence.len())))?;
+
+        *received = (Topic::from_bytes(topic), Some(u32::from_le_bytes(sequence)));
+        decode_received(topic, data, sequence, config)
+    }
+}
+
+#[cfg(feature = "async")] // only used with the async feature on
+pub(super) fn message_from_multipart_zmq_message<M: FromRawMessage>(
+    messages: &[zmq::Message],
+    config: &RecvConfig,
+    received: &mut Received,
+) -> Result<M> {
+    if config.debug_hexdump {
+        let parts: Vec<&[u8]> = messages.iter().map(|msg| &**msg).collect();
+        debug::log_multipart(&parts);
+    }
+
     // zmq::Message doesn't implement AsRef<[u8]>
 
     let [topic, data, sequence]: &[zmq::Message; 3] = messages
         .try_into()
-        .map_err(|_| Error::InvalidMutlipartLength(messages.len()))?;
+        .map_err(|_| invalid_message(Error::InvalidMutlipartLength(messages.len())))?;
+
+    config
+        .check_data_len(topic, data.len())
+        .map_err(invalid_message)?;
+
+    let sequence = (**sequence)
+        .try_into()
+        .map_err(|_| invalid_message(Error::InvalidSequenceLength(sequence.len())))?;
+
+    *received = (Topic::from_bytes(topic), Some(u32::from_le_bytes(sequence)));
+    decode_received(topic, data, sequence, config)
+}
+
+/// Passes through `err` about a malformed message, recording it in the telemetry if enabled.
+/// Errors from parsing are recorded by [`decode_received`].
+#[inline]
+pub(super) fn invalid_message(err: Error) -> Error {
+    #[cfg(feature = "opentelemetry")]
+    crate::telemetry::record_invalid_message();
+
+    err
+}
+
+/// Parses the parts of a received message, recording telemetry if enabled. Deserialization
+/// errors get the [`source`](RecvConfig::source) of `config`.
+pub(super) fn decode_received<M: FromRawMessage>(
+    topic: &[u8],
+    data: &[u8],
+    sequence: [u8; SEQUENCE_LEN],
+    config: &RecvConfig,
+) -> Result<M> {
+    #[cfg(feature = "opentelemetry")]
+    let span = crate::telemetry::MessageSpan::start(data.len());
+
+    let res = M::from_raw_message(topic, data, sequence).map_err(|err| match err {
+        Error::Deserialization(err) => Error::Deserialization(DeserializationError {
+            endpoint: err.endpoint.or_else(|| config.source.clone()),
+            ..err
+        }),
+        err => err,
+    });
+
+    #[cfg(feature = "opentelemetry")]
+    span.end(topic, sequence, res.as_ref().map(|_| ()));
 
-    Message::from_fixed_size_multipart::<&[u8]>(&[topic, data, sequence])
+    res
 }
 
-pub(super) fn subscribe_internal<F, B>(socket: Socket, callback: F) -> ControlFlow<B, Infallible>
+pub(super) fn subscribe_internal<M, F, B>(
+    socket: Socket,
+    config: RecvConfig,
+    callback: F,
+) -> ControlFlow<B, Infallible>
 where
-    F: Fn(Result<Message>) -> ControlFlow<B>,
+    M: FromRawMessage,
+    F: Fn(Result<M>) -> ControlFlow<B>,
 {
-    let mut buf: Box<[u8; DATA_MAX_LEN]> =
-        vec![0; DATA_MAX_LEN].into_boxed_slice().try_into().unwrap();
+    let mut buf = new_recv_buffer(&config);
 
     loop {
-        let msg = recv_internal_socket(&socket, &mut buf);
+        let msg = recv_internal_socket(&socket, &mut buf, &config, 0);
 
         callback(msg)?;
     }
@@ -1,10 +1,9 @@
-use super::{new_socket_internal, subscribe_internal};
-use crate::{error::Result, message::Message};
-use core::ops::ControlFlow;
-use std::{
-    sync::mpsc::{channel, Receiver},
-    thread,
+use super::{
+    builder::SubscribeBuilder, decode_pool::spawn_decode_pool, expiry::Queued, new_recv_buffer,
+    new_socket_internal, recv_queued_internal_socket, spawn_receive_thread,
 };
+use crate::{error::Result, message::Message, raw_message::FromRawMessage};
+use std::sync::mpsc::{channel, Receiver};
 
 /// Subscribes to a single ZMQ endpoint and returns a [`Receiver`].
 #[inline]
@@ -29,16 +28,151 @@ pub fn subscribe_multi(endpoints: &[&str]) -> Result<Receiver<Result<Message>>>
 /// Subscribes to multiple ZMQ endpoints and returns a [`Receiver`].
 #[inline]
 pub fn subscribe_receiver(endpoints: &[&str]) -> Result<Receiver<Result<Message>>> {
-    let (tx, rx) = channel();
+    SubscribeBuilder::new(endpoints).receiver()
+}
+
+impl<M: FromRawMessage + Send + 'static> SubscribeBuilder<'_, M> {
+    /// Subscribes and returns a [`Receiver`]. See [`subscribe_receiver`].
+    #[inline]
+    pub fn receiver(self) -> Result<Receiver<Result<M>>> {
+        if self.decode_threads == 0 {
+            self.reject_topic_ttl()?;
+        }
+
+        let (tx, rx) = channel();
+
+        self.spawn_receiver(move |queued| tx.send(queued.msg).is_ok())?;
+
+        Ok(rx)
+    }
 
-    let (_context, socket) = new_socket_internal(endpoints)?;
+    /// Subscribes and passes every received message to `sink` on another thread, until `sink`
+    /// returns `false`.
+    pub(super) fn spawn_receiver(
+        self,
+        mut sink: impl FnMut(Queued<M>) -> bool + Send + 'static,
+    ) -> Result<()> {
+        let (_context, socket) = new_socket_internal(&self, true)?;
 
-    thread::spawn(move || {
-        subscribe_internal(socket, |msg| match tx.send(msg) {
-            Err(_) => ControlFlow::Break(()),
-            Ok(()) => ControlFlow::Continue(()),
+        if self.decode_threads > 0 {
+            return spawn_decode_pool(&self, socket, self.decode_threads, sink);
+        }
+
+        let config = self.recv_config();
+        spawn_receive_thread(&self, move || {
+            let mut buf = new_recv_buffer(&config);
+            while sink(recv_queued_internal_socket(&socket, &mut buf, &config)) {}
         })
-    });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_util::publish_until, Error, Message, SubscribeBuilder, Topic};
+    use bitcoin::{constants::genesis_block, Network};
+
+    #[test]
+    fn decode_threads_preserve_order() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let rx = SubscribeBuilder::new(&[&endpoint])
+            .decode_threads(4)
+            .receiver()
+            .unwrap();
+
+        let block = genesis_block(Network::Bitcoin);
+        let tx = block.txdata[0].clone();
+
+        let (first, sequence) = publish_until(
+            &publisher,
+            |sequence| Message::Block(block.clone(), sequence),
+            || rx.try_recv().ok(),
+        );
+        let first = first.unwrap().sequence();
+
+        for i in 0..100 {
+            let msg = if i % 2 == 0 {
+                Message::Block(block.clone(), sequence + i)
+            } else {
+                Message::Tx(tx.clone(), sequence + i)
+            };
+            publisher
+                .send_multipart(msg.serialize_to_vecs(), 0)
+                .unwrap();
+        }
+
+        let mut expected = first + 1;
+        for msg in rx.iter() {
+            assert_eq!(msg.unwrap().sequence(), expected);
+            expected += 1;
+            if expected == sequence + 100 {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn topic_ttl_needs_queue() {
+        let builder = SubscribeBuilder::new(&["tcp://127.0.0.1:1"])
+            .topic_ttl(Topic::RawTx, core::time::Duration::from_secs(1));
+        let unsupported = |res: Result<(), Error>| {
+            assert!(matches!(res, Err(Error::UnsupportedOption("topic_ttl"))));
+        };
+
+        unsupported(builder.clone().receiver().map(drop));
+        unsupported(builder.clone().subscription().map(drop));
+        unsupported(
+            builder
+                .clone()
+                .blocking(|_| core::ops::ControlFlow::Break(()))
+                .map(drop),
+        );
+        assert!(builder.clone().decode_threads(1).receiver().is_ok());
+        assert!(builder
+            .consumer_group(1, crate::Distribution::RoundRobin)
+            .is_ok());
+
+        // block messages never expire, so their TTL is ignored
+        assert!(SubscribeBuilder::new(&["tcp://127.0.0.1:1"])
+            .topic_ttl(Topic::RawBlock, core::time::Duration::from_secs(1))
+            .subscription()
+            .is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn named_receive_thread() {
+        let _rx = SubscribeBuilder::new(&["tcp://127.0.0.1:1"])
+            .thread_name("zmq-test-recv")
+            .receiver()
+            .unwrap();
+
+        // the name is set by the thread itself once it runs
+        let named = (0..100).any(|_| {
+            std::thread::sleep(core::time::Duration::from_millis(10));
+            std::fs::read_dir("/proc/self/task").unwrap().any(|task| {
+                std::fs::read_to_string(task.unwrap().path().join("comm"))
+                    .is_ok_and(|name| name.trim_end() == "zmq-test-recv")
+            })
+        });
+        assert!(named);
+    }
 
-    Ok(rx)
+    #[cfg(all(feature = "affinity", target_os = "linux"))]
+    #[test]
+    fn pinned_receive_thread() {
+        let builder = SubscribeBuilder::new(&["tcp://127.0.0.1:1"]);
+        assert!(builder
+            .clone()
+            .receive_thread_core(Some(0))
+            .receiver()
+            .is_ok());
+        assert!(matches!(
+            builder.receive_thread_core(Some(1 << 20)).receiver(),
+            Err(crate::Error::Io(_))
+        ));
+    }
 }
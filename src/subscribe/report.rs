@@ -0,0 +1,259 @@
+use crate::{
+    error::Result,
+    monitor::{event::SocketEvent, MonitorMessage},
+    topic::Topic,
+};
+use core::fmt;
+use zmq::Socket;
+
+/// A snapshot of the state of a subscription, returned by
+/// [`Subscription::debug_report`](crate::Subscription::debug_report) and by `debug_report` of
+/// the async streams (with the `async` feature).
+///
+/// Its [`Display`](fmt::Display) implementation prints everything in a form that can be pasted
+/// into a bug report.
+///
+/// Only a [`Subscription`](crate::Subscription) and the async streams keep the state for a
+/// report. The other subscribers do not, so their queues are not part of it: the [`Receiver`]
+/// returned by [`SubscribeBuilder::receiver`](crate::SubscribeBuilder::receiver) does not know
+/// its length, the number of messages waiting for a consumer of a consumer group is returned by
+/// [`Consumer::pending`](crate::Consumer::pending).
+///
+/// [`Receiver`]: std::sync::mpsc::Receiver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugReport {
+    /// The endpoints the subscription connects to, and their connection states.
+    pub endpoints: Vec<EndpointReport>,
+    /// The topics subscribed to, [`None`] means all topics.
+    pub topics: Option<Vec<Topic>>,
+    /// Whether the subscription is paused.
+    pub paused: bool,
+    /// The number of messages received successfully.
+    pub received: u64,
+    /// The number of failed receives, for example of messages that failed to parse.
+    pub errors: u64,
+    /// The sequence number of the last message received per topic, for the topics a message was
+    /// received on.
+    pub last_sequence: Vec<(Topic, u32)>,
+    /// Whether a message is waiting in the queue of the socket. libzmq does not report how many
+    /// are waiting, and a subscription has no queue of its own.
+    pub pending: bool,
+    /// The options of the ZMQ socket.
+    pub socket: SocketOptions,
+}
+
+/// An endpoint in a [`DebugReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointReport {
+    /// The endpoint, as passed to [`SubscribeBuilder::new`](crate::SubscribeBuilder::new).
+    pub endpoint: String,
+    /// Whether a connection to the endpoint is established.
+    pub connected: bool,
+}
+
+/// The options of a ZMQ socket in a [`DebugReport`], see `zmq_getsockopt(3)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// `ZMQ_RCVHWM`
+    pub rcvhwm: i32,
+    /// `ZMQ_MAXMSGSIZE`, -1 means no limit.
+    pub maxmsgsize: i64,
+    /// `ZMQ_LINGER` in milliseconds, -1 means forever.
+    pub linger: i32,
+    /// `ZMQ_RECONNECT_IVL` in milliseconds.
+    pub reconnect_ivl: i32,
+    /// `ZMQ_RECONNECT_IVL_MAX` in milliseconds, 0 means `ZMQ_RECONNECT_IVL` is used.
+    pub reconnect_ivl_max: i32,
+    /// `ZMQ_RCVBUF` in bytes, -1 means the OS default.
+    pub rcvbuf: i32,
+}
+
+impl SocketOptions {
+    pub(super) fn get(socket: &Socket) -> Result<Self> {
+        Ok(Self {
+            rcvhwm: socket.get_rcvhwm()?,
+            maxmsgsize: socket.get_maxmsgsize()?,
+            linger: socket.get_linger()?,
+            reconnect_ivl: socket.get_reconnect_ivl()?,
+            reconnect_ivl_max: socket.get_reconnect_ivl_max()?,
+            rcvbuf: socket.get_rcvbuf()?,
+        })
+    }
+}
+
+impl fmt::Display for DebugReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "bitcoincore-zmq {} subscription",
+            env!("CARGO_PKG_VERSION")
+        )?;
+
+        writeln!(f, "endpoints:")?;
+        for endpoint in &self.endpoints {
+            let state = if endpoint.connected {
+                "connected"
+            } else {
+                "not connected"
+            };
+            writeln!(f, "  {}: {state}", endpoint.endpoint)?;
+        }
+
+        write!(f, "topics: ")?;
+        match &self.topics {
+            None => write!(f, "all")?,
+            Some(topics) if topics.is_empty() => write!(f, "none")?,
+            Some(topics) => {
+                for (i, topic) in topics.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{sep}{topic}")?;
+                }
+            }
+        }
+        writeln!(f, "{}", if self.paused { " (paused)" } else { "" })?;
+
+        writeln!(
+            f,
+            "received: {} messages, {} errors",
+            self.received, self.errors
+        )?;
+        write!(f, "last sequence:")?;
+        if self.last_sequence.is_empty() {
+            write!(f, " none")?;
+        }
+        for (topic, sequence) in &self.last_sequence {
+            write!(f, " {topic}={sequence}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "pending: {}", if self.pending { "yes" } else { "no" })?;
+
+        let socket = &self.socket;
+        write!(
+            f,
+            "socket: rcvhwm={} maxmsgsize={} linger={} reconnect_ivl={} reconnect_ivl_max={} rcvbuf={}",
+            socket.rcvhwm,
+            socket.maxmsgsize,
+            socket.linger,
+            socket.reconnect_ivl,
+            socket.reconnect_ivl_max,
+            socket.rcvbuf
+        )
+    }
+}
+
+/// The topic and sequence number of a received message, [`None`] if receiving failed before they
+/// were known.
+#[cfg(feature = "async")]
+pub(super) type Received = (Option<Topic>, Option<u32>);
+
+/// What a subscription keeps track of for its [`DebugReport`].
+pub(super) struct ReportState {
+    /// Receives the [`EVENTS`](ReportState::EVENTS) of the subscription, [`None`] if they are
+    /// passed to [`apply_event`](ReportState::apply_event) instead.
+    monitor: Option<Socket>,
+    endpoints: Vec<EndpointReport>,
+    received: u64,
+    errors: u64,
+    last_sequence: [Option<u32>; Topic::ALL.len()],
+}
+
+impl ReportState {
+    /// The events the monitor socket receives. Only these, so events of a node that can not be
+    /// reached do not pile up in the monitor socket between reports.
+    pub(super) const EVENTS: i32 =
+        zmq::SocketEvent::CONNECTED as i32 | zmq::SocketEvent::DISCONNECTED as i32;
+
+    /// The receive high-water mark of the monitor socket. The monitor is drained on every
+    /// receive, this only bounds the events of an endpoint that keeps dropping the connection
+    /// while nothing is received. Events beyond it are dropped, so the connection states may be
+    /// outdated in that case.
+    pub(super) const MONITOR_HWM: i32 = 1000;
+
+    pub(super) fn new(monitor: Option<Socket>, endpoints: &[&str]) -> Self {
+        Self {
+            monitor,
+            endpoints: endpoints
+                .iter()
+                .map(|endpoint| EndpointReport {
+                    endpoint: (*endpoint).to_owned(),
+                    connected: false,
+                })
+                .collect(),
+            received: 0,
+            errors: 0,
+            last_sequence: [None; Topic::ALL.len()],
+        }
+    }
+
+    /// Records a received message, `topic` and `sequence` are [`None`] if receiving failed before
+    /// they were known.
+    pub(super) fn record<T>(
+        &mut self,
+        topic: Option<Topic>,
+        sequence: Option<u32>,
+        res: &Result<T>,
+    ) {
+        if let (Some(topic), Some(sequence)) = (topic, sequence) {
+            self.last_sequence[topic as usize] = Some(sequence);
+        }
+        match res {
+            Ok(_) => self.received += 1,
+            Err(_) => self.errors += 1,
+        }
+    }
+
+    /// Applies the pending monitor events to the connection states of the endpoints.
+    pub(super) fn update_endpoints(&mut self) {
+        while let Some(Ok(parts)) = self
+            .monitor
+            .as_ref()
+            .map(|monitor| monitor.recv_multipart(zmq::DONTWAIT))
+        {
+            if let Ok(msg) = MonitorMessage::parse_from(&to_messages(parts)) {
+                self.apply_event(&msg);
+            }
+        }
+    }
+
+    /// Applies a monitor event to the connection states of the endpoints. Only connects and
+    /// disconnects are relevant, other events are ignored.
+    pub(super) fn apply_event(&mut self, msg: &MonitorMessage) {
+        let connected = match msg.event {
+            SocketEvent::Connected { .. } => true,
+            SocketEvent::Disconnected { .. } => false,
+            _ => return,
+        };
+        for endpoint in &mut self.endpoints {
+            if msg.source_url.matches(&endpoint.endpoint) {
+                endpoint.connected = connected;
+            }
+        }
+    }
+
+    pub(super) fn report(
+        &mut self,
+        socket: &Socket,
+        topics: Option<Vec<Topic>>,
+        paused: bool,
+    ) -> Result<DebugReport> {
+        self.update_endpoints();
+
+        Ok(DebugReport {
+            endpoints: self.endpoints.clone(),
+            topics,
+            paused,
+            received: self.received,
+            errors: self.errors,
+            last_sequence: Topic::ALL
+                .into_iter()
+                .filter_map(|topic| Some((topic, self.last_sequence[topic as usize]?)))
+                .collect(),
+            pending: socket.get_events()?.contains(zmq::POLLIN),
+            socket: SocketOptions::get(socket)?,
+        })
+    }
+}
+
+fn to_messages(parts: Vec<Vec<u8>>) -> Vec<zmq::Message> {
+    parts.into_iter().map(zmq::Message::from).collect()
+}
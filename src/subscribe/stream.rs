@@ -1,8 +1,12 @@
-use super::new_socket_internal;
+use super::{
+    builder::SubscribeBuilder, new_monitor_socket, new_monitored_socket_internal,
+    report::ReportState,
+};
 use crate::{
     error::Result,
     message::Message,
-    monitor::{event::SocketEvent, MonitorMessage, MonitorMessageError},
+    monitor::{event::SocketEvent, MonitorMessage},
+    raw_message::FromRawMessage,
 };
 use core::{
     fmt,
@@ -19,16 +23,28 @@ use futures_util::{
 };
 use std::{
     sync::{Arc, Mutex},
-    thread,
+    task::Wake,
+    thread::{self, Thread},
 };
 
-/// A [`Message`] or a [`MonitorMessage`].
+/// A [`Message`] (or another message type, see [`SubscribeBuilder::message_type`]) or a
+/// [`MonitorMessage`].
 #[derive(Debug, Clone)]
-pub enum SocketMessage {
-    Message(Message),
+pub enum SocketMessage<M = Message> {
+    Message(M),
     Event(MonitorMessage),
 }
 
+impl<M: fmt::Display> fmt::Display for SocketMessage<M> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Message(msg) => msg.fmt(f),
+            Self::Event(event) => event.fmt(f),
+        }
+    }
+}
+
 /// Stream that asynchronously produces [`Message`]s using multiple ZMQ subscribers. The ZMQ
 /// sockets are polled in a round-robin fashion.
 #[deprecated(
@@ -90,25 +106,57 @@ pub fn subscribe_single_async(endpoint: &str) -> Result<subscribe_async_stream::
 }
 
 pub mod subscribe_async_stream {
-    use crate::{error::Result, message::Message, subscribe::message_from_multipart_zmq_message};
+    use crate::{
+        error::Result,
+        message::Message,
+        raw_message::FromRawMessage,
+        subscribe::{
+            builder::RecvConfig,
+            linger_millis, message_from_multipart_zmq_message,
+            report::{DebugReport, ReportState},
+            RecvFrames, Subscriptions,
+        },
+        topic::Topic,
+    };
     use async_zmq::Subscribe;
     use core::{
+        future::poll_fn,
+        marker::PhantomData,
         pin::Pin,
         task::{Context as AsyncContext, Poll},
+        time::Duration,
     };
     use futures_util::{
         stream::{FusedStream, StreamExt},
         Stream,
     };
 
-    /// Stream returned by [`subscribe_async`][super::subscribe_async].
-    pub struct MessageStream {
+    /// Stream returned by [`subscribe_async`][super::subscribe_async]. Produces `M`s,
+    /// [`Message`]s by default.
+    ///
+    /// The stream owns its ZMQ socket. libzmq sockets may be moved to another thread, but must
+    /// not be used by multiple threads at once, so this stream is [`Send`] (it can be moved into
+    /// a task spawned on a multi-threaded runtime, like `tokio::spawn`), but not [`Sync`]. Use
+    /// a channel to share the messages with multiple tasks.
+    pub struct MessageStream<M = Message> {
         zmq_stream: Subscribe,
+        frames: RecvFrames,
+        subscriptions: Subscriptions,
+        config: RecvConfig,
+        pub(super) report: ReportState,
+        message_type: PhantomData<fn() -> M>,
     }
 
-    impl MessageStream {
-        pub(super) const fn new(zmq_stream: Subscribe) -> Self {
-            Self { zmq_stream }
+    impl<M> MessageStream<M> {
+        pub(super) fn new(zmq_stream: Subscribe, config: RecvConfig, report: ReportState) -> Self {
+            Self {
+                zmq_stream,
+                frames: RecvFrames::new(),
+                subscriptions: Subscriptions::default(),
+                config,
+                report,
+                message_type: PhantomData,
+            }
         }
 
         /// Returns a reference to the ZMQ socket used by this stream. To get the [`zmq::Socket`], use
@@ -119,25 +167,189 @@ pub mod subscribe_async_stream {
         pub const fn as_zmq_socket(&self) -> &Subscribe {
             &self.zmq_stream
         }
+
+        /// Pauses this subscription by unsubscribing from all topics. The connections to the
+        /// endpoints stay open, but Bitcoin Core stops sending messages to this subscriber until
+        /// [`resume`] is called. Messages that were already received by the socket before pausing
+        /// are still yielded by this stream.
+        ///
+        /// [`resume`]: MessageStream::resume
+        pub fn pause(&mut self) -> Result<()> {
+            self.subscriptions.pause(self.zmq_stream.as_raw_socket())
+        }
+
+        /// Resumes a subscription that was paused with [`pause`]. Messages published while the
+        /// subscription was paused are not received.
+        ///
+        /// [`pause`]: MessageStream::pause
+        pub fn resume(&mut self) -> Result<()> {
+            self.subscriptions.resume(self.zmq_stream.as_raw_socket())
+        }
+
+        /// Returns `true` if this subscription is paused.
+        pub const fn is_paused(&self) -> bool {
+            self.subscriptions.is_paused()
+        }
+
+        /// Replaces the topics this stream is subscribed to. New streams are subscribed to all
+        /// topics, use this to only receive messages on the given topics.
+        pub fn set_topics(&mut self, topics: &[Topic]) -> Result<()> {
+            self.subscriptions
+                .set_topics(self.zmq_stream.as_raw_socket(), topics)
+        }
+
+        /// Subscribes to `topic`. Does nothing if this stream is already subscribed to it.
+        pub fn add_topic(&mut self, topic: Topic) -> Result<()> {
+            self.subscriptions
+                .add_topic(self.zmq_stream.as_raw_socket(), topic)
+        }
+
+        /// Unsubscribes from `topic`. Does nothing if this stream is not subscribed to it.
+        /// Messages on `topic` that were already received by the socket are still yielded.
+        pub fn remove_topic(&mut self, topic: Topic) -> Result<()> {
+            self.subscriptions
+                .remove_topic(self.zmq_stream.as_raw_socket(), topic)
+        }
+
+        /// Returns `true` if this stream is subscribed to `topic`. This does not take
+        /// [`pause`](MessageStream::pause) into account.
+        pub fn is_subscribed(&self, topic: Topic) -> bool {
+            self.subscriptions.is_subscribed(topic)
+        }
+
+        /// Returns a snapshot of the state of this stream, see
+        /// [`Subscription::debug_report`](crate::Subscription::debug_report).
+        pub fn debug_report(&mut self) -> Result<DebugReport> {
+            self.report.report(
+                self.zmq_stream.as_raw_socket(),
+                self.subscriptions.topics(),
+                self.subscriptions.is_paused(),
+            )
+        }
+
+        /// Closes this stream, waiting at most `timeout` for unsent frames. See
+        /// [`Subscription::close`].
+        ///
+        /// This is not async: with its own context (not the [`global_context`]), it blocks the
+        /// calling thread until the context is terminated, for at most `timeout`. In async code
+        /// that is a thread of the executor, so keep `timeout` short.
+        ///
+        /// [`Subscription::close`]: crate::Subscription::close
+        /// [`global_context`]: crate::SubscribeBuilder::global_context
+        pub fn close(self, timeout: Duration) -> Result<()> {
+            let Self { zmq_stream, .. } = self;
+            zmq_stream
+                .as_raw_socket()
+                .set_linger(linger_millis(timeout))?;
+            drop(zmq_stream);
+
+            Ok(())
+        }
+    }
+
+    impl<M: FromRawMessage> MessageStream<M> {
+        /// Receives a message that is already queued by the socket, without allocating.
+        fn try_recv_queued(&mut self) -> Option<Result<M>> {
+            let mut received = (None, None);
+            let res = self.frames.try_recv(
+                self.zmq_stream.as_raw_socket(),
+                &self.config,
+                &mut received,
+            )?;
+            self.report.record(received.0, received.1, &res);
+            // keep the monitor socket from filling up between reports
+            self.report.update_endpoints();
+
+            Some(res)
+        }
+
+        /// Polls async_zmq for a message, registering for wake-up if there is none.
+        fn poll_zmq_stream(&mut self, cx: &mut AsyncContext<'_>) -> Poll<Result<M>> {
+            let mp = match self.zmq_stream.poll_next_unpin(cx) {
+                Poll::Ready(opt) => opt.unwrap(),
+                Poll::Pending => return Poll::Pending,
+            };
+            let mut received = (None, None);
+            let res = match mp {
+                Ok(mp) => message_from_multipart_zmq_message(&mp, &self.config, &mut received),
+                Err(err) => Err(err.into()),
+            };
+            self.report.record(received.0, received.1, &res);
+            self.report.update_endpoints();
+
+            Poll::Ready(res)
+        }
+
+        /// Converts this stream into an iterator that blocks the calling thread until the next
+        /// message is received, for use without an async runtime. See [`BlockingIter`].
+        ///
+        /// [`BlockingIter`]: super::BlockingIter
+        pub fn into_blocking_iter(self) -> super::BlockingIter<Self> {
+            super::BlockingIter::new(self)
+        }
+
+        /// Waits for at least one message and returns it along with all other messages that
+        /// are already queued, up to `max` in total. See [`poll_next_batch`].
+        ///
+        /// [`poll_next_batch`]: MessageStream::poll_next_batch
+        pub async fn next_batch(&mut self, max: usize) -> Vec<Result<M>> {
+            poll_fn(|cx| self.poll_next_batch(cx, max)).await
+        }
+
+        /// Polls for a batch of messages: once a message is available, all messages that are
+        /// already queued by the socket are received at once, up to `max` in total. The batch is
+        /// only empty if `max` is 0.
+        ///
+        /// Under bursts, like the `rawtx` messages following a block, this takes a single wakeup
+        /// for many messages instead of one per message.
+        pub fn poll_next_batch(
+            &mut self,
+            cx: &mut AsyncContext<'_>,
+            max: usize,
+        ) -> Poll<Vec<Result<M>>> {
+            let mut batch = Vec::new();
+            if max == 0 {
+                return Poll::Ready(batch);
+            }
+
+            loop {
+                while batch.len() < max {
+                    match self.try_recv_queued() {
+                        Some(res) => batch.push(res),
+                        None => break,
+                    }
+                }
+                if !batch.is_empty() {
+                    return Poll::Ready(batch);
+                }
+
+                // nothing queued, poll async_zmq to register for wake-up
+                match self.poll_zmq_stream(cx) {
+                    Poll::Ready(res) => batch.push(res),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        }
     }
 
-    impl Stream for MessageStream {
-        type Item = Result<Message>;
+    impl<M: FromRawMessage> Stream for MessageStream<M> {
+        type Item = Result<M>;
 
         fn poll_next(
             mut self: Pin<&mut Self>,
             cx: &mut AsyncContext<'_>,
         ) -> Poll<Option<Self::Item>> {
-            self.zmq_stream.poll_next_unpin(cx).map(|opt| {
-                Some(match opt.unwrap() {
-                    Ok(mp) => message_from_multipart_zmq_message(&mp),
-                    Err(err) => Err(err.into()),
-                })
-            })
+            // messages that are already queued are received without allocating, only when the
+            // queue is empty the async_zmq stream is polled to register for wake-up
+            if let Some(res) = self.try_recv_queued() {
+                return Poll::Ready(Some(res));
+            }
+
+            self.poll_zmq_stream(cx).map(Some)
         }
     }
 
-    impl FusedStream for MessageStream {
+    impl<M: FromRawMessage> FusedStream for MessageStream<M> {
         fn is_terminated(&self) -> bool {
             false
         }
@@ -146,18 +358,24 @@ pub mod subscribe_async_stream {
 
 /// Subscribes to multiple ZMQ endpoints and returns a stream that produces [`Message`]s.
 pub fn subscribe_async(endpoints: &[&str]) -> Result<subscribe_async_stream::MessageStream> {
-    let (_context, socket) = new_socket_internal(endpoints)?;
-
-    Ok(subscribe_async_stream::MessageStream::new(socket.into()))
+    SubscribeBuilder::new(endpoints).stream()
 }
 
 pub mod subscribe_async_monitor_stream {
     use super::{subscribe_async_stream, SocketMessage};
-    use crate::{error::Result, monitor::MonitorMessage};
+    use crate::{
+        error::Result,
+        message::Message,
+        monitor::{event::SocketEvent, MonitorMessage},
+        raw_message::FromRawMessage,
+        subscribe::{linger_millis, report::DebugReport},
+        topic::Topic,
+    };
     use async_zmq::Subscribe;
     use core::{
         pin::Pin,
         task::{Context as AsyncContext, Poll},
+        time::Duration,
     };
     use futures_util::{
         stream::{FusedStream, StreamExt},
@@ -185,18 +403,29 @@ pub mod subscribe_async_monitor_stream {
     // Better to use an empty type to not waste precious bytes
     pub(super) type RecvOnlyPair = async_zmq::Pair<Empty, Empty>;
 
-    /// Stream returned by [`subscribe_async_monitor`][super::subscribe_async_monitor].
-    pub struct MessageStream {
-        messages: subscribe_async_stream::MessageStream,
+    /// Stream returned by [`subscribe_async_monitor`][super::subscribe_async_monitor]. Like
+    /// [`subscribe_async_stream::MessageStream`], this stream is [`Send`] but not [`Sync`].
+    pub struct MessageStream<M = Message> {
+        messages: subscribe_async_stream::MessageStream<M>,
         pub(super) monitor: RecvOnlyPair,
+        /// The synthetic message yielded first, see [`SubscribeBuilder::initial_tip`].
+        ///
+        /// [`SubscribeBuilder::initial_tip`]: crate::SubscribeBuilder::initial_tip
+        #[cfg(feature = "rpc")]
+        pub(super) initial_tip: Option<Message>,
     }
 
-    impl MessageStream {
+    impl<M> MessageStream<M> {
         pub(super) const fn new(
-            messages: subscribe_async_stream::MessageStream,
+            messages: subscribe_async_stream::MessageStream<M>,
             monitor: RecvOnlyPair,
         ) -> Self {
-            Self { messages, monitor }
+            Self {
+                messages,
+                monitor,
+                #[cfg(feature = "rpc")]
+                initial_tip: None,
+            }
         }
 
         /// Returns a reference to the ZMQ socket used by this stream. To get the [`zmq::Socket`], use
@@ -213,20 +442,166 @@ pub mod subscribe_async_monitor_stream {
         pub fn as_zmq_monitor_socket(&self) -> &Socket {
             self.monitor.as_raw_socket()
         }
+
+        /// Pauses this subscription. Monitor events are still yielded while paused. See
+        /// [`subscribe_async_stream::MessageStream::pause`].
+        pub fn pause(&mut self) -> Result<()> {
+            self.messages.pause()
+        }
+
+        /// Resumes a paused subscription. See [`subscribe_async_stream::MessageStream::resume`].
+        pub fn resume(&mut self) -> Result<()> {
+            self.messages.resume()
+        }
+
+        /// Returns `true` if this subscription is paused.
+        pub const fn is_paused(&self) -> bool {
+            self.messages.is_paused()
+        }
+
+        /// Replaces the topics this stream is subscribed to. See
+        /// [`subscribe_async_stream::MessageStream::set_topics`].
+        pub fn set_topics(&mut self, topics: &[Topic]) -> Result<()> {
+            self.messages.set_topics(topics)
+        }
+
+        /// Subscribes to `topic`. See [`subscribe_async_stream::MessageStream::add_topic`].
+        pub fn add_topic(&mut self, topic: Topic) -> Result<()> {
+            self.messages.add_topic(topic)
+        }
+
+        /// Unsubscribes from `topic`. See
+        /// [`subscribe_async_stream::MessageStream::remove_topic`].
+        pub fn remove_topic(&mut self, topic: Topic) -> Result<()> {
+            self.messages.remove_topic(topic)
+        }
+
+        /// Returns `true` if this stream is subscribed to `topic`.
+        pub fn is_subscribed(&self, topic: Topic) -> bool {
+            self.messages.is_subscribed(topic)
+        }
+
+        /// Returns a snapshot of the state of this stream. See
+        /// [`subscribe_async_stream::MessageStream::debug_report`]. The connection states follow
+        /// the events received from the monitor socket, events that were not yielded yet are not
+        /// taken into account.
+        pub fn debug_report(&mut self) -> Result<DebugReport> {
+            self.messages.debug_report()
+        }
+
+        /// Receives and parses the next event, applying it to the report state.
+        pub(super) async fn next_event(&mut self) -> Result<MonitorMessage> {
+            let msg = MonitorMessage::parse_from(&self.monitor.next().await.unwrap()?)?;
+            self.record_event(&msg);
+
+            Ok(msg)
+        }
+
+        fn record_event(&mut self, msg: &MonitorMessage) {
+            #[cfg(feature = "opentelemetry")]
+            crate::telemetry::record_socket_event(msg);
+            self.messages.report.apply_event(msg);
+        }
+
+        /// Closes this stream and its monitor socket, waiting at most `timeout` for unsent
+        /// frames. See [`subscribe_async_stream::MessageStream::close`], this blocks the calling
+        /// thread in the same way.
+        pub fn close(self, timeout: Duration) -> Result<()> {
+            let Self {
+                messages, monitor, ..
+            } = self;
+            monitor.as_raw_socket().set_linger(linger_millis(timeout))?;
+            messages.close(timeout)?;
+            // the context is terminated once the monitor socket is closed too
+            drop(monitor);
+
+            Ok(())
+        }
+
+        /// Waits until `endpoint` disconnects, or any endpoint if `endpoint` is [`None`], and
+        /// returns the [`SocketEvent::Disconnected`] event.
+        ///
+        /// Only the monitor socket is read, [`Message`]s stay queued on the ZMQ socket and can be
+        /// read afterwards. Other events received while waiting are discarded.
+        ///
+        /// [`Message`]: crate::Message
+        pub async fn wait_for_disconnect(
+            &mut self,
+            endpoint: Option<&str>,
+        ) -> Result<MonitorMessage> {
+            self.wait_for_event(endpoint, |event| {
+                matches!(event, SocketEvent::Disconnected { .. })
+            })
+            .await
+        }
+
+        /// Waits until `endpoint` completes a new handshake, or any endpoint if `endpoint` is
+        /// [`None`], and returns the [`SocketEvent::HandshakeSucceeded`] event.
+        ///
+        /// Only the monitor socket is read, [`Message`]s stay queued on the ZMQ socket and can be
+        /// read afterwards. Other events received while waiting are discarded.
+        ///
+        /// [`Message`]: crate::Message
+        pub async fn wait_for_reconnect(
+            &mut self,
+            endpoint: Option<&str>,
+        ) -> Result<MonitorMessage> {
+            self.wait_for_event(endpoint, |event| {
+                matches!(event, SocketEvent::HandshakeSucceeded)
+            })
+            .await
+        }
+
+        async fn wait_for_event<F>(
+            &mut self,
+            endpoint: Option<&str>,
+            predicate: F,
+        ) -> Result<MonitorMessage>
+        where
+            F: Fn(&SocketEvent) -> bool,
+        {
+            loop {
+                let msg = self.next_event().await?;
+                if predicate(&msg.event) && endpoint.is_none_or(|e| msg.source_url.matches(e)) {
+                    return Ok(msg);
+                }
+            }
+        }
     }
 
-    impl Stream for MessageStream {
-        type Item = Result<SocketMessage>;
+    impl<M: FromRawMessage> MessageStream<M> {
+        /// Converts this stream into an iterator that blocks the calling thread until the next
+        /// message or event is received, for use without an async runtime. See
+        /// [`BlockingIter`].
+        ///
+        /// [`BlockingIter`]: super::BlockingIter
+        pub fn into_blocking_iter(self) -> super::BlockingIter<Self> {
+            super::BlockingIter::new(self)
+        }
+    }
+
+    impl<M: FromRawMessage> Stream for MessageStream<M> {
+        type Item = Result<SocketMessage<M>>;
 
         fn poll_next(
             mut self: Pin<&mut Self>,
             cx: &mut AsyncContext<'_>,
         ) -> Poll<Option<Self::Item>> {
+            #[cfg(feature = "rpc")]
+            if let Some(msg) = self.initial_tip.take() {
+                // converted like a received message, so it works with any message type
+                let [topic, data, sequence] = msg.serialize_to_vecs();
+                let sequence = sequence.try_into().expect("sequence is 4 bytes");
+                let msg = M::from_raw_message(&topic, &data, sequence);
+                return Poll::Ready(Some(msg.map(SocketMessage::Message)));
+            }
+
             match self.monitor.poll_next_unpin(cx) {
                 Poll::Ready(msg) => {
-                    return Poll::Ready(Some(Ok(SocketMessage::Event(
-                        MonitorMessage::parse_from(&msg.unwrap()?)?,
-                    ))));
+                    let msg = MonitorMessage::parse_from(&msg.unwrap()?)?;
+                    self.record_event(&msg);
+
+                    return Poll::Ready(Some(Ok(SocketMessage::Event(msg))));
                 }
                 Poll::Pending => {}
             }
@@ -237,7 +612,7 @@ pub mod subscribe_async_monitor_stream {
         }
     }
 
-    impl FusedStream for MessageStream {
+    impl<M: FromRawMessage> FusedStream for MessageStream<M> {
         fn is_terminated(&self) -> bool {
             false
         }
@@ -249,17 +624,7 @@ pub mod subscribe_async_monitor_stream {
 pub fn subscribe_async_monitor(
     endpoints: &[&str],
 ) -> Result<subscribe_async_monitor_stream::MessageStream> {
-    let (context, socket) = new_socket_internal(endpoints)?;
-
-    socket.monitor("inproc://monitor", zmq::SocketEvent::ALL as i32)?;
-
-    let monitor = context.socket(zmq::PAIR)?;
-    monitor.connect("inproc://monitor")?;
-
-    Ok(subscribe_async_monitor_stream::MessageStream::new(
-        subscribe_async_stream::MessageStream::new(socket.into()),
-        monitor.into(),
-    ))
+    SubscribeBuilder::new(endpoints).monitor_stream()
 }
 
 // TODO have some way to extract connecting to which endpoints failed, now just a (unit) error is returned (by tokio::time::timeout)
@@ -272,25 +637,127 @@ pub fn subscribe_async_monitor(
 ///
 /// **NOTE:** This method will wait indefinitely until a connection has been established, but this is
 /// often undesirable. This method should therefore be used in combination with your async
-/// runtime's timeout function. Currently, with the state of async Rust in December of 2023, it is
-/// not yet possible do this without creating an extra thread per timeout or depending on specific
-/// runtimes.
+/// runtime's timeout function, or use [`subscribe_async_wait_handshake_with`] and pass your
+/// runtime's sleep future.
 pub async fn subscribe_async_wait_handshake(
     endpoints: &[&str],
 ) -> Result<subscribe_async_monitor_stream::MessageStream> {
-    let mut stream = subscribe_async_monitor(endpoints)?;
-    let mut connecting = endpoints.len();
+    SubscribeBuilder::new(endpoints).wait_handshake().await
+}
+
+impl<M: FromRawMessage> SubscribeBuilder<'_, M> {
+    /// Subscribes and returns a stream that produces [`Message`]s. See [`subscribe_async`].
+    ///
+    /// Endpoints are validated, but unlike the other subscribers, hostnames are not resolved
+    /// first, as that would block the executor. A hostname that can not be resolved shows up as
+    /// connection retries on monitored streams, check it with [`Endpoint::resolve`] beforehand
+    /// (off the executor) to fail early instead.
+    ///
+    /// [`Endpoint::resolve`]: crate::Endpoint::resolve
+    pub fn stream(self) -> Result<subscribe_async_stream::MessageStream<M>> {
+        self.reject_topic_ttl()?;
+        let (_context, socket, monitor) =
+            new_monitored_socket_internal(&self, false, |context, socket| {
+                new_monitor_socket(
+                    context,
+                    socket,
+                    ReportState::EVENTS,
+                    Some(ReportState::MONITOR_HWM),
+                    self.linger,
+                )
+            })?;
+
+        Ok(subscribe_async_stream::MessageStream::new(
+            socket.into(),
+            self.recv_config(),
+            ReportState::new(Some(monitor), self.endpoints),
+        ))
+    }
+
+    /// Subscribes and returns a stream that yields [`Message`]s and events (see
+    /// [`MonitorMessage`]). See [`subscribe_async_monitor`]. Hostnames are not resolved first,
+    /// see [`stream`](SubscribeBuilder::stream).
+    pub fn monitor_stream(self) -> Result<subscribe_async_monitor_stream::MessageStream<M>> {
+        self.reject_topic_ttl()?;
+        let (_context, socket, monitor) =
+            new_monitored_socket_internal(&self, false, |context, socket| {
+                new_monitor_socket(
+                    context,
+                    socket,
+                    zmq::SocketEvent::ALL as i32,
+                    None,
+                    self.linger,
+                )
+            })?;
+
+        // a socket can only have one monitor, the report follows the events yielded by the stream
+        Ok(subscribe_async_monitor_stream::MessageStream::new(
+            subscribe_async_stream::MessageStream::new(
+                socket.into(),
+                self.recv_config(),
+                ReportState::new(None, self.endpoints),
+            ),
+            monitor.into(),
+        ))
+    }
 
+    /// Subscribes and returns a stream that yields [`Message`]s and events (see
+    /// [`MonitorMessage`]) once a connection has been established to all endpoints. See
+    /// [`subscribe_async_wait_handshake`].
+    pub async fn wait_handshake(self) -> Result<subscribe_async_monitor_stream::MessageStream<M>> {
+        let endpoints = self.endpoints;
+        #[cfg(all(feature = "systemd", unix))]
+        let notify_ready = self.systemd_notify_ready;
+        #[cfg(feature = "rpc")]
+        let initial_tip = self.initial_tip.cloned();
+        let stream = self.monitor_stream()?;
+
+        #[cfg_attr(not(feature = "rpc"), allow(unused_mut))]
+        let mut stream = wait_handshake_internal(stream, endpoints.len()).await?;
+
+        #[cfg(feature = "rpc")]
+        if let Some(client) = initial_tip {
+            let tip = blocking(move || crate::rpc::initial_tip(&*client)).await?;
+            stream.initial_tip = Some(tip);
+        }
+
+        #[cfg(all(feature = "systemd", unix))]
+        if notify_ready {
+            crate::systemd::notify_ready()?;
+        }
+
+        Ok(stream)
+    }
+
+    /// Like [`wait_handshake`], but gives up when `timeout` completes. See
+    /// [`subscribe_async_wait_handshake_with`].
+    ///
+    /// [`wait_handshake`]: SubscribeBuilder::wait_handshake
+    pub async fn wait_handshake_with<F>(
+        self,
+        timeout: F,
+    ) -> core::result::Result<Result<subscribe_async_monitor_stream::MessageStream<M>>, Timeout>
+    where
+        F: Future<Output = ()>,
+    {
+        match select(pin!(self.wait_handshake()), pin!(timeout)).await {
+            Either::Left((res, _)) => Ok(res),
+            Either::Right(_) => Err(Timeout(())),
+        }
+    }
+}
+
+async fn wait_handshake_internal<M>(
+    mut stream: subscribe_async_monitor_stream::MessageStream<M>,
+    mut connecting: usize,
+) -> Result<subscribe_async_monitor_stream::MessageStream<M>> {
     if connecting == 0 {
         return Ok(stream);
     }
 
     loop {
-        let msg: &[zmq::Message] = &stream.monitor.next().await.unwrap()?;
-        let [event_message, _] = msg else {
-            return Err(MonitorMessageError::InvalidMutlipartLength(msg.len()).into());
-        };
-        match SocketEvent::parse_from(event_message)? {
+        let msg = stream.next_event().await?;
+        match msg.event {
             SocketEvent::HandshakeSucceeded => {
                 connecting -= 1;
             }
@@ -308,21 +775,31 @@ pub async fn subscribe_async_wait_handshake(
 }
 
 /// See [`subscribe_async_wait_handshake`]. This method implements the inefficient, but runtime
-/// independent approach.
+/// independent approach of spawning a thread that sleeps for the duration of the timeout. Prefer
+/// [`subscribe_async_wait_handshake_with`] with your runtime's sleep future.
 pub async fn subscribe_async_wait_handshake_timeout(
     endpoints: &[&str],
     timeout: Duration,
 ) -> core::result::Result<Result<subscribe_async_monitor_stream::MessageStream>, Timeout> {
-    let subscribe = subscribe_async_wait_handshake(endpoints);
-    let timeout = sleep(timeout);
+    subscribe_async_wait_handshake_with(endpoints, sleep(timeout)).await
+}
 
-    match select(pin!(subscribe), timeout).await {
-        Either::Left((res, _)) => Ok(res),
-        Either::Right(_) => Err(Timeout(())),
-    }
+/// See [`subscribe_async_wait_handshake`]. Gives up when `timeout` completes, for example
+/// `tokio::time::sleep(duration)` or a similar future from your runtime.
+pub async fn subscribe_async_wait_handshake_with<F>(
+    endpoints: &[&str],
+    timeout: F,
+) -> core::result::Result<Result<subscribe_async_monitor_stream::MessageStream>, Timeout>
+where
+    F: Future<Output = ()>,
+{
+    SubscribeBuilder::new(endpoints)
+        .wait_handshake_with(timeout)
+        .await
 }
 
-/// Error returned by [`subscribe_async_wait_handshake_timeout`] when the connection times out.
+/// Error returned by [`subscribe_async_wait_handshake_timeout`] and
+/// [`subscribe_async_wait_handshake_with`] when the connection times out.
 /// Contains no information, but does have a Error, Debug and Display impl.
 #[derive(Debug)]
 pub struct Timeout(());
@@ -335,43 +812,378 @@ impl fmt::Display for Timeout {
 
 impl std::error::Error for Timeout {}
 
-fn sleep(dur: Duration) -> Sleep {
-    let state = Arc::new(Mutex::new(SleepReadyState::Pending));
+fn sleep(dur: Duration) -> Blocking<()> {
+    blocking(move || thread::sleep(dur))
+}
+
+/// Runs `f` on a new thread, returns a future that completes with its result. This keeps
+/// blocking calls off the executor without depending on a runtime.
+fn blocking<T, F>(f: F) -> Blocking<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let state = Arc::new(Mutex::new(BlockingState::Pending));
     {
         let state = state.clone();
         thread::spawn(move || {
-            thread::sleep(dur);
+            let res = f();
             let state = {
                 let mut g = state.lock().unwrap();
-                mem::replace(&mut *g, SleepReadyState::Done)
+                mem::replace(&mut *g, BlockingState::Done(res))
             };
-            if let SleepReadyState::PendingPolled(waker) = state {
+            if let BlockingState::PendingPolled(waker) = state {
                 waker.wake();
             }
         });
     }
 
-    Sleep(state)
+    Blocking(state)
 }
 
-enum SleepReadyState {
+enum BlockingState<T> {
     Pending,
     PendingPolled(Waker),
-    Done,
+    Done(T),
 }
 
-struct Sleep(Arc<Mutex<SleepReadyState>>);
+struct Blocking<T>(Arc<Mutex<BlockingState<T>>>);
 
-impl Future for Sleep {
-    type Output = ();
+impl<T> Future for Blocking<T> {
+    type Output = T;
 
     fn poll(self: Pin<&mut Self>, cx: &mut AsyncContext<'_>) -> Poll<Self::Output> {
         let mut g = self.0.lock().unwrap();
-        if matches!(*g, SleepReadyState::Done) {
-            Poll::Ready(())
-        } else {
-            *g = SleepReadyState::PendingPolled(cx.waker().clone());
-            Poll::Pending
+        match mem::replace(&mut *g, BlockingState::Pending) {
+            BlockingState::Done(res) => Poll::Ready(res),
+            _ => {
+                *g = BlockingState::PendingPolled(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Iterator that polls a stream on the calling thread, parking the thread until the stream wakes
+/// it. Returned by `into_blocking_iter` on [`subscribe_async_stream::MessageStream`] and
+/// [`subscribe_async_monitor_stream::MessageStream`].
+///
+/// This is a minimal executor for a single stream: the streams of this crate are woken by the
+/// I/O thread of [`async_zmq`], so no runtime is needed to drive them. This lets streams created
+/// with async-only features, like [`SubscribeBuilder::monitor_stream`], be consumed from plain
+/// threads. The stream can be taken back with [`into_inner`](BlockingIter::into_inner).
+#[derive(Debug)]
+pub struct BlockingIter<S> {
+    stream: S,
+}
+
+impl<S: Stream + Unpin> BlockingIter<S> {
+    /// Wraps `stream`.
+    #[inline]
+    pub const fn new(stream: S) -> Self {
+        Self { stream }
+    }
+
+    /// Returns the wrapped stream.
+    #[inline]
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Stream + Unpin> Iterator for BlockingIter<S> {
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a new waker every call, the iterator may have moved to another thread
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = AsyncContext::from_waker(&waker);
+
+        loop {
+            match self.stream.poll_next_unpin(&mut cx) {
+                Poll::Ready(item) => return item,
+                // spurious wake-ups only cause an extra poll
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+}
+
+/// Wakes a thread parked by [`BlockingIter`].
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// the streams and the futures returned by the builder can be used in tasks spawned on
+// multi-threaded runtimes
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<subscribe_async_stream::MessageStream>();
+    assert_send::<subscribe_async_monitor_stream::MessageStream>();
+    assert_send::<SocketMessage>();
+    assert_send::<BlockingIter<subscribe_async_stream::MessageStream>>();
+    assert_send::<Timeout>();
+    assert_send::<Blocking<()>>();
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        subscribe_async, subscribe_async_wait_handshake_with, test_util::publish_until, Message,
+        SubscribeBuilder, Topic,
+    };
+    use bitcoin::{hashes::Hash, BlockHash};
+    use core::time::Duration;
+    use futures_util::{FutureExt, StreamExt};
+
+    #[tokio::test]
+    async fn stream_receives_queued_messages() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut stream = subscribe_async(&[&endpoint]).unwrap();
+
+        let (first, sequence) = publish_until(
+            &publisher,
+            |sequence| Message::HashBlock(BlockHash::all_zeros(), sequence),
+            || stream.next().now_or_never().flatten(),
+        );
+        let first = first.unwrap().sequence();
+
+        // queue many messages at once, most of them are received without polling async_zmq
+        for i in 0..100 {
+            let msg = Message::HashBlock(BlockHash::all_zeros(), sequence + i);
+            publisher
+                .send_multipart(msg.serialize_to_vecs(), 0)
+                .unwrap();
+        }
+        // an invalid multipart is reported, and does not affect the following message
+        publisher
+            .send_multipart([b"hashblock" as &[u8]], 0)
+            .unwrap();
+        let last = Message::HashBlock(BlockHash::all_zeros(), sequence + 100);
+        publisher
+            .send_multipart(last.serialize_to_vecs(), 0)
+            .unwrap();
+
+        for expected in first + 1..sequence + 100 {
+            assert_eq!(stream.next().await.unwrap().unwrap().sequence(), expected);
+        }
+        assert!(stream.next().await.unwrap().is_err());
+        assert_eq!(stream.next().await.unwrap().unwrap(), last);
+    }
+
+    #[tokio::test]
+    async fn stream_next_batch() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut stream = subscribe_async(&[&endpoint]).unwrap();
+
+        let (batch, sequence) = publish_until(
+            &publisher,
+            |sequence| Message::HashBlock(BlockHash::all_zeros(), sequence),
+            || stream.next_batch(1).now_or_never(),
+        );
+        assert_eq!(batch.len(), 1);
+        let first = batch[0].as_ref().unwrap().sequence();
+
+        for i in 0..100 {
+            let msg = Message::HashBlock(BlockHash::all_zeros(), sequence + i);
+            publisher
+                .send_multipart(msg.serialize_to_vecs(), 0)
+                .unwrap();
+        }
+
+        let mut expected = first + 1;
+        while expected < sequence + 100 {
+            let batch = stream.next_batch(16).await;
+            assert!(!batch.is_empty() && batch.len() <= 16);
+            for msg in batch {
+                assert_eq!(msg.unwrap().sequence(), expected);
+                expected += 1;
+            }
+        }
+        assert!(stream.next_batch(0).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn debug_report() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut stream = subscribe_async(&[&endpoint]).unwrap();
+        let report = stream.debug_report().unwrap();
+        assert_eq!(report.received, 0);
+        assert!(report.last_sequence.is_empty());
+
+        let (first, _) = publish_until(
+            &publisher,
+            |sequence| Message::HashBlock(BlockHash::all_zeros(), sequence),
+            || stream.next().now_or_never().flatten(),
+        );
+        let first = first.unwrap().sequence();
+        while stream.next().now_or_never().is_some() {}
+        publisher
+            .send_multipart([b"hashblock" as &[u8]], 0)
+            .unwrap();
+        assert!(stream.next().await.unwrap().is_err());
+
+        let report = stream.debug_report().unwrap();
+        assert!(report.endpoints[0].connected);
+        assert!(report.received >= 1);
+        assert_eq!(report.errors, 1);
+        assert!(matches!(
+            report.last_sequence[..],
+            [(Topic::HashBlock, sequence)] if sequence >= first
+        ));
+
+        // the monitored stream follows the events it yields
+        let mut stream = SubscribeBuilder::new(&[&endpoint])
+            .wait_handshake()
+            .await
+            .unwrap();
+        let report = stream.debug_report().unwrap();
+        assert!(report.endpoints[0].connected);
+        assert_eq!(report.received, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stream_in_spawned_task() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        // the handshake future and the stream are both moved into tasks
+        let stream = tokio::spawn(async move {
+            SubscribeBuilder::new(&[&endpoint])
+                .wait_handshake()
+                .await
+                .unwrap()
+        })
+        .await
+        .unwrap();
+        let task = tokio::spawn(async move {
+            let mut stream = stream;
+            loop {
+                if let Some(Ok(crate::SocketMessage::Message(msg))) = stream.next().await {
+                    break msg;
+                }
+            }
+        });
+
+        let msg = Message::HashBlock(BlockHash::all_zeros(), 0);
+        while !task.is_finished() {
+            publisher
+                .send_multipart(msg.serialize_to_vecs(), 0)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
+        assert_eq!(task.await.unwrap(), msg);
+    }
+
+    #[test]
+    fn blocking_iter() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut iter = SubscribeBuilder::new(&[&endpoint])
+            .monitor_stream()
+            .unwrap()
+            .into_blocking_iter();
+
+        // the first item is a monitor event, messages are only received after connecting
+        assert!(matches!(
+            iter.next(),
+            Some(Ok(crate::SocketMessage::Event(_)))
+        ));
+
+        let publisher = std::thread::spawn(move || {
+            for sequence in 0..100 {
+                let msg = Message::HashBlock(BlockHash::all_zeros(), sequence);
+                publisher
+                    .send_multipart(msg.serialize_to_vecs(), 0)
+                    .unwrap();
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+        let msg = iter
+            .find_map(|item| match item.unwrap() {
+                crate::SocketMessage::Message(msg) => Some(msg),
+                crate::SocketMessage::Event(_) => None,
+            })
+            .unwrap();
+        assert!(matches!(msg, Message::HashBlock(_, _)));
+        drop(iter);
+        publisher.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_handshake_with() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let res = subscribe_async_wait_handshake_with(
+            &[&endpoint],
+            tokio::time::sleep(Duration::from_secs(10)),
+        )
+        .await;
+        assert!(matches!(res, Ok(Ok(_))));
+
+        // nothing listens on the port of a dropped socket
+        let closed = {
+            let socket = context.socket(zmq::PUB).unwrap();
+            socket.bind("tcp://127.0.0.1:*").unwrap();
+            socket.get_last_endpoint().unwrap().unwrap()
+        };
+        let res = subscribe_async_wait_handshake_with(
+            &[&closed],
+            tokio::time::sleep(Duration::from_millis(100)),
+        )
+        .await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn hostnames_are_not_resolved() {
+        let stream = SubscribeBuilder::new(&["tcp://node.invalid:28332"])
+            .stream()
+            .unwrap();
+        assert!(stream.as_zmq_socket().as_raw_socket().is_ipv6().unwrap());
+
+        // other errors are still reported
+        assert!(SubscribeBuilder::new(&["tcp://node.invalid"])
+            .monitor_stream()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn blocking_runs_on_another_thread() {
+        let caller = std::thread::current().id();
+        let res = super::blocking(|| {
+            std::thread::sleep(Duration::from_millis(50));
+            std::thread::current().id()
+        })
+        .await;
+        assert_ne!(res, caller);
     }
 }
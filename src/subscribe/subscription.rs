@@ -0,0 +1,529 @@
+use super::{
+    builder::{RecvConfig, SubscribeBuilder},
+    decode_received, linger_millis, new_monitor_socket, new_monitored_socket_internal,
+    new_recv_buffer, recv_parts_internal_socket,
+    report::{DebugReport, ReportState},
+    Subscriptions,
+};
+use crate::{
+    error::{Error, Result},
+    message::Message,
+    raw_message::FromRawMessage,
+    topic::Topic,
+    typed_message::TypedMessage,
+};
+use core::{fmt, marker::PhantomData, time::Duration};
+use std::time::SystemTime;
+use zmq::Socket;
+
+/// A subscription that receives messages in the caller's thread, without spawning a thread or
+/// using a channel like [`subscribe_receiver`] does. Produces `M`s, [`Message`]s by default (see
+/// [`SubscribeBuilder::message_type`]). It can be moved to another thread ([`Send`]), but not
+/// shared between threads, as libzmq sockets must not be used by multiple threads at once.
+///
+/// [`subscribe_receiver`]: crate::subscribe_receiver
+pub struct Subscription<M = Message> {
+    socket: Socket,
+    buf: Box<[u8]>,
+    subscriptions: Subscriptions,
+    config: RecvConfig,
+    report: ReportState,
+    /// When the parts of the last message were received.
+    received_at: SystemTime,
+    message_type: PhantomData<fn() -> M>,
+}
+
+impl Subscription {
+    /// Subscribes to multiple ZMQ endpoints. This is the same as
+    /// `SubscribeBuilder::new(endpoints).subscription()`.
+    #[inline]
+    pub fn new(endpoints: &[&str]) -> Result<Self> {
+        SubscribeBuilder::new(endpoints).subscription()
+    }
+}
+
+impl Subscription {
+    /// Blocks until the next message is received, and returns it as a [`TypedMessage`] with the
+    /// time its parts were received, before parsing.
+    ///
+    /// libzmq does not tell which endpoint a message came from, so its
+    /// [`source`](TypedMessage::source) is only known when subscribed to a single endpoint.
+    pub fn recv_typed(&mut self) -> Result<TypedMessage> {
+        let msg = self.recv()?;
+        Ok(TypedMessage::new(
+            msg,
+            self.received_at,
+            self.config.source.clone(),
+        ))
+    }
+}
+
+impl<M: FromRawMessage> Subscription<M> {
+    /// Blocks until the next message is received.
+    #[inline]
+    pub fn recv(&mut self) -> Result<M> {
+        self.recv_flags(0)
+    }
+
+    /// Returns the next message if one has been received already, or [`None`] if nothing is
+    /// pending. Never blocks.
+    #[inline]
+    pub fn try_recv(&mut self) -> Result<Option<M>> {
+        match self.recv_flags(zmq::DONTWAIT) {
+            Ok(msg) => Ok(Some(msg)),
+            Err(Error::Zmq(zmq::Error::EAGAIN)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Waits at most `timeout` for the next message, returns [`None`] if nothing was received
+    /// in time.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<M>> {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            if let Some(msg) = self.try_recv()? {
+                return Ok(Some(msg));
+            }
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            if left.is_zero() {
+                return Ok(None);
+            }
+            // rounded up, so the deadline has passed when poll times out
+            let millis = left.as_nanos().div_ceil(1_000_000);
+            self.socket
+                .poll(zmq::POLLIN, millis.try_into().unwrap_or(i64::MAX))?;
+        }
+    }
+
+    fn recv_flags(&mut self, flags: i32) -> Result<M> {
+        let mut received = (None, None);
+        let config = &self.config;
+        let res = recv_parts_internal_socket(
+            &self.socket,
+            &mut self.buf,
+            config,
+            flags,
+            |topic, data, sequence| {
+                self.received_at = SystemTime::now();
+                received = (Topic::from_bytes(topic), Some(u32::from_le_bytes(sequence)));
+                decode_received(topic, data, sequence, config)
+            },
+        );
+
+        // nothing was received when there is nothing pending
+        if !matches!(res, Err(Error::Zmq(zmq::Error::EAGAIN))) {
+            self.report.record(received.0, received.1, &res);
+        }
+        // keep the monitor socket from filling up between reports
+        self.report.update_endpoints();
+
+        res
+    }
+
+    /// Returns a snapshot of the state of this subscription: the connection states of its
+    /// endpoints, the last sequence number per topic, counters and socket options. Include its
+    /// output when reporting a subscription that stopped receiving messages.
+    pub fn debug_report(&mut self) -> Result<DebugReport> {
+        self.report.report(
+            &self.socket,
+            self.subscriptions.topics(),
+            self.subscriptions.is_paused(),
+        )
+    }
+
+    /// Returns an iterator that blocks on every call to `next` until a message is received. The
+    /// iterator never ends.
+    #[inline]
+    pub fn iter(&mut self) -> SubscriptionIter<'_, M> {
+        SubscriptionIter { subscription: self }
+    }
+
+    /// Stops receiving messages from the publishers until [`resume`](Self::resume) is called.
+    /// The connections stay open. Messages published while paused are dropped by the publishers,
+    /// messages already received may still be returned.
+    #[inline]
+    pub fn pause(&mut self) -> Result<()> {
+        self.subscriptions.pause(&self.socket)
+    }
+
+    /// Resumes receiving messages after [`pause`](Self::pause).
+    #[inline]
+    pub fn resume(&mut self) -> Result<()> {
+        self.subscriptions.resume(&self.socket)
+    }
+
+    /// Returns `true` if this subscription is paused.
+    #[inline]
+    pub const fn is_paused(&self) -> bool {
+        self.subscriptions.is_paused()
+    }
+
+    /// Only receive messages on `topics` from now on.
+    #[inline]
+    pub fn set_topics(&mut self, topics: &[Topic]) -> Result<()> {
+        self.subscriptions.set_topics(&self.socket, topics)
+    }
+
+    /// Also receive messages on `topic`.
+    #[inline]
+    pub fn add_topic(&mut self, topic: Topic) -> Result<()> {
+        self.subscriptions.add_topic(&self.socket, topic)
+    }
+
+    /// Stop receiving messages on `topic`.
+    #[inline]
+    pub fn remove_topic(&mut self, topic: Topic) -> Result<()> {
+        self.subscriptions.remove_topic(&self.socket, topic)
+    }
+
+    /// Returns `true` if messages on `topic` are received (when not paused).
+    #[inline]
+    pub fn is_subscribed(&self, topic: Topic) -> bool {
+        self.subscriptions.is_subscribed(topic)
+    }
+
+    /// Closes the subscription, waiting at most `timeout` for unsent frames. Unlike dropping,
+    /// which uses the [`linger`] period of the builder, this bounds the time spent closing
+    /// explicitly.
+    ///
+    /// The sockets are closed before this returns. With its own context (not the
+    /// [`global_context`]), closing them terminates the context, which blocks until the unsent
+    /// frames are sent or `timeout` has passed. The global context is never terminated, it keeps
+    /// sending the unsent frames in the background for at most `timeout`.
+    ///
+    /// [`linger`]: SubscribeBuilder::linger
+    /// [`global_context`]: SubscribeBuilder::global_context
+    pub fn close(self, timeout: Duration) -> Result<()> {
+        let Self { socket, report, .. } = self;
+        socket.set_linger(linger_millis(timeout))?;
+        // the monitor socket holds on to the context too, it is terminated once both are closed
+        drop(socket);
+        drop(report);
+
+        Ok(())
+    }
+}
+
+impl<M> fmt::Debug for Subscription<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("subscriptions", &self.subscriptions)
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, M: FromRawMessage> IntoIterator for &'a mut Subscription<M> {
+    type Item = Result<M>;
+    type IntoIter = SubscriptionIter<'a, M>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator returned by [`Subscription::iter`].
+#[derive(Debug)]
+pub struct SubscriptionIter<'a, M = Message> {
+    subscription: &'a mut Subscription<M>,
+}
+
+impl<M: FromRawMessage> Iterator for SubscriptionIter<'_, M> {
+    type Item = Result<M>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.subscription.recv())
+    }
+}
+
+impl<M: FromRawMessage> SubscribeBuilder<'_, M> {
+    /// Subscribes and returns a [`Subscription`] that receives messages in the caller's thread.
+    #[inline]
+    pub fn subscription(self) -> Result<Subscription<M>> {
+        self.reject_topic_ttl()?;
+
+        let (_context, socket, monitor) =
+            new_monitored_socket_internal(&self, true, |context, socket| {
+                new_monitor_socket(
+                    context,
+                    socket,
+                    ReportState::EVENTS,
+                    Some(ReportState::MONITOR_HWM),
+                    self.linger,
+                )
+            })?;
+
+        let config = self.recv_config();
+
+        Ok(Subscription {
+            socket,
+            buf: new_recv_buffer(&config),
+            subscriptions: Subscriptions::default(),
+            config,
+            report: ReportState::new(Some(monitor), self.endpoints),
+            received_at: SystemTime::UNIX_EPOCH,
+            message_type: PhantomData,
+        })
+    }
+}
+
+// like the streams, a subscription can be moved to another thread, but not shared
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Subscription>();
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_util::publish_until, EndpointError, Error, Message, SubscribeBuilder, Subscription,
+        Topic,
+    };
+    use bitcoin::{hashes::Hash, BlockHash, Txid};
+    use std::thread;
+
+    #[test]
+    fn subscription_iter() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut subscription = Subscription::new(&[&endpoint]).unwrap();
+        subscription.set_topics(&[Topic::HashBlock]).unwrap();
+
+        // nothing has been published yet
+        assert!(subscription.try_recv().unwrap().is_none());
+        assert!(subscription
+            .recv_timeout(core::time::Duration::from_millis(20))
+            .unwrap()
+            .is_none());
+
+        let msg = |sequence| Message::HashBlock(BlockHash::all_zeros(), sequence);
+        let (first, sequence) = publish_until(&publisher, msg, || subscription.try_recv().unwrap());
+        publisher
+            .send_multipart(msg(sequence).serialize_to_vecs(), 0)
+            .unwrap();
+        let second = subscription.iter().next().unwrap().unwrap();
+
+        assert_eq!(first.topic_type(), Topic::HashBlock);
+        assert_eq!(second.sequence(), first.sequence() + 1);
+    }
+
+    #[test]
+    fn max_data_len() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut subscription = SubscribeBuilder::new(&[&endpoint])
+            .max_data_len(64)
+            .topic_max_data_len(Topic::HashTx, 16)
+            .subscription()
+            .unwrap();
+
+        let (_, sequence) = publish_until(
+            &publisher,
+            |sequence| Message::HashBlock(BlockHash::all_zeros(), sequence),
+            || subscription.try_recv().unwrap(),
+        );
+        while subscription.try_recv().unwrap().is_some() {}
+
+        let too_large = Message::HashTx(Txid::all_zeros(), sequence);
+        publisher
+            .send_multipart(too_large.serialize_to_vecs(), 0)
+            .unwrap();
+        let fits = Message::HashBlock(BlockHash::all_zeros(), sequence + 1);
+        publisher
+            .send_multipart(fits.serialize_to_vecs(), 0)
+            .unwrap();
+
+        assert!(matches!(
+            subscription.recv(),
+            Err(Error::MessageTooLarge(32, 16))
+        ));
+        // the rest of the multipart was skipped
+        assert_eq!(subscription.recv().unwrap(), fits);
+    }
+
+    #[test]
+    fn debug_hexdump_before_checks() {
+        use crate::subscribe::debug::LOGGED;
+
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut subscription = SubscribeBuilder::new(&[&endpoint])
+            .debug_hexdump(true)
+            .subscription()
+            .unwrap();
+
+        let msg = Message::HashTx(Txid::all_zeros(), 0);
+        publish_until(&publisher, |_| &msg, || subscription.try_recv().unwrap());
+        while subscription.try_recv().unwrap().is_some() {}
+        LOGGED.with(|logged| logged.borrow_mut().clear());
+
+        let [topic, data, sequence] = &msg.serialize_to_vecs();
+        publisher
+            .send_multipart([topic, data, sequence, sequence], 0)
+            .unwrap();
+        assert!(matches!(
+            subscription.recv(),
+            Err(Error::InvalidMutlipartLength(4))
+        ));
+        publisher
+            .send_multipart([topic, data, &sequence[..2]], 0)
+            .unwrap();
+        assert!(matches!(
+            subscription.recv(),
+            Err(Error::InvalidSequenceLength(2))
+        ));
+
+        let logged = LOGGED.with(|logged| logged.take());
+        assert_eq!(logged.len(), 2);
+        assert!(logged[0].contains("with 4 parts"));
+        assert!(logged[1].contains("part 2 (2 bytes)"));
+    }
+
+    #[test]
+    fn debug_report() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut subscription = Subscription::new(&[&endpoint]).unwrap();
+        subscription.set_topics(&[Topic::HashBlock]).unwrap();
+
+        let report = subscription.debug_report().unwrap();
+        assert_eq!(report.received, 0);
+        assert!(report.last_sequence.is_empty());
+        assert!(!report.pending);
+
+        publish_until(
+            &publisher,
+            |sequence| Message::HashBlock(BlockHash::all_zeros(), sequence),
+            || subscription.try_recv().unwrap(),
+        );
+        publisher
+            .send_multipart([&b"hashblock"[..], b"short", &0u32.to_le_bytes()], 0)
+            .unwrap();
+        assert!(subscription.recv().is_err());
+        thread::sleep(core::time::Duration::from_millis(10));
+
+        let report = subscription.debug_report().unwrap();
+        assert_eq!(report.endpoints.len(), 1);
+        assert!(report.endpoints[0].connected);
+        assert_eq!(report.topics, Some(vec![Topic::HashBlock]));
+        assert_eq!(report.received, 1);
+        assert_eq!(report.errors, 1);
+        assert_eq!(report.last_sequence, [(Topic::HashBlock, 0)]);
+        assert!(report.socket.maxmsgsize > 0);
+
+        let report = report.to_string();
+        assert!(report.contains(&format!("{endpoint}: connected")));
+        assert!(report.contains("received: 1 messages, 1 errors"));
+    }
+
+    #[test]
+    fn recv_typed() {
+        let context = zmq::Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+
+        let mut subscription = Subscription::new(&[&endpoint]).unwrap();
+        publish_until(
+            &publisher,
+            |_| Message::HashBlock(BlockHash::all_zeros(), 0),
+            || subscription.try_recv().unwrap(),
+        );
+
+        let before = std::time::SystemTime::now();
+        let msg = Message::HashBlock(BlockHash::all_zeros(), 1);
+        publisher
+            .send_multipart(msg.serialize_to_vecs(), 0)
+            .unwrap();
+        let typed = loop {
+            let typed = subscription.recv_typed().unwrap();
+            if typed.sequence() == 1 {
+                break typed;
+            }
+        };
+        assert!(typed.received_at() >= before);
+        assert!(typed.received_at() <= std::time::SystemTime::now());
+        assert_eq!(typed.source(), Some(&endpoint.parse().unwrap()));
+
+        // deserialization errors name the endpoint too
+        publisher
+            .send_multipart([&b"rawtx"[..], b"short", &2u32.to_le_bytes()], 0)
+            .unwrap();
+        let Err(Error::Deserialization(err)) = subscription.recv() else {
+            panic!("expected deserialization error");
+        };
+        assert_eq!(err.endpoint, Some(endpoint.parse().unwrap()));
+        assert!(err.to_string().contains(&format!("from {endpoint}")));
+
+        // the source of a message is not known with multiple endpoints
+        let subscription = Subscription::new(&[&endpoint, "tcp://127.0.0.1:28332"]).unwrap();
+        assert_eq!(subscription.config.source, None);
+    }
+
+    #[test]
+    fn endpoint_validation() {
+        let subscription = Subscription::new(&["tcp://[::1]:28332"]).unwrap();
+        assert!(subscription.socket.is_ipv6().unwrap());
+        let subscription = Subscription::new(&["tcp://127.0.0.1:28332"]).unwrap();
+        assert!(!subscription.socket.is_ipv6().unwrap());
+        // scoped IPv6 addresses are passed to libzmq as they are
+        #[cfg(target_os = "linux")]
+        {
+            let subscription = Subscription::new(&["tcp://[fe80::1%lo]:28332"]).unwrap();
+            assert!(subscription.socket.is_ipv6().unwrap());
+        }
+
+        for (endpoint, err) in [
+            ("tcp://127.0.0.1", EndpointError::MissingPort),
+            ("tcp://[::1:28332", EndpointError::InvalidBrackets),
+            (
+                "tcp://node.invalid:28332",
+                EndpointError::UnresolvableHost("node.invalid".to_owned()),
+            ),
+        ] {
+            match Subscription::new(&["tcp://127.0.0.1:28332", endpoint]) {
+                Err(Error::InvalidEndpoint(e, kind)) => {
+                    assert_eq!((e.as_str(), kind), (endpoint, err));
+                }
+                res => panic!("{endpoint}: unexpected {res:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn close_is_bounded() {
+        // nothing listens on the port of a dropped socket, so frames stay unsent
+        let endpoint = {
+            let context = zmq::Context::new();
+            let socket = context.socket(zmq::PUB).unwrap();
+            socket.bind("tcp://127.0.0.1:*").unwrap();
+            socket.get_last_endpoint().unwrap().unwrap()
+        };
+
+        let mut subscription = SubscribeBuilder::new(&[&endpoint])
+            .linger(core::time::Duration::MAX)
+            .subscription()
+            .unwrap();
+        subscription.set_topics(&[Topic::RawBlock]).unwrap();
+
+        let start = std::time::Instant::now();
+        subscription
+            .close(core::time::Duration::from_millis(100))
+            .unwrap();
+        assert!(start.elapsed() < core::time::Duration::from_secs(5));
+    }
+}
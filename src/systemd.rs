@@ -0,0 +1,237 @@
+//! Integration with systemd's service notification protocol (see `sd_notify(3)`), for daemons
+//! that run as a `Type=notify` service, optionally with `WatchdogSec=`.
+//!
+//! Use [`SubscribeBuilder::systemd_notify_ready`] to send `READY=1` once [`wait_handshake`]
+//! completes, and a [`Watchdog`] to keep the service alive only while messages are flowing.
+//!
+//! [`SubscribeBuilder::systemd_notify_ready`]: crate::SubscribeBuilder::systemd_notify_ready
+//! [`wait_handshake`]: crate::SubscribeBuilder::wait_handshake
+
+use crate::message::Message;
+use core::time::Duration;
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    io,
+    os::unix::{ffi::OsStrExt, net::UnixDatagram},
+    process,
+    time::Instant,
+};
+
+/// Sends `state` (for example `READY=1` or `STATUS=...`) to the service manager. Returns
+/// `Ok(false)` if the process was not started by a service manager that listens for
+/// notifications (`NOTIFY_SOCKET` is not set).
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(path) = notify_socket() else {
+        return Ok(false);
+    };
+    notify_to(&path, state)?;
+
+    Ok(true)
+}
+
+/// Returns the path of the socket of the service manager, if there is one.
+fn notify_socket() -> Option<OsString> {
+    env::var_os("NOTIFY_SOCKET")
+}
+
+/// Sends `state` to the socket at `path`, an abstract socket if it starts with `@`.
+fn notify_to(path: &OsStr, state: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+        send_abstract(&socket, name, state)?;
+    } else {
+        socket.send_to(state.as_bytes(), path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_abstract(socket: &UnixDatagram, name: &[u8], state: &str) -> io::Result<()> {
+    use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+    let addr = SocketAddr::from_abstract_name(name)?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn send_abstract(_socket: &UnixDatagram, _name: &[u8], _state: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "abstract unix sockets are only supported on linux",
+    ))
+}
+
+/// Sends `READY=1` to the service manager. See [`notify`].
+#[inline]
+pub fn notify_ready() -> io::Result<bool> {
+    notify("READY=1")
+}
+
+/// Returns the watchdog timeout configured by the service manager (`WatchdogSec=`), or [`None`]
+/// if the watchdog is disabled or not meant for this process.
+pub fn watchdog_timeout() -> Option<Duration> {
+    if let Some(pid) = env::var_os("WATCHDOG_PID") {
+        if pid.to_str()?.parse::<u32>().ok()? != process::id() {
+            return None;
+        }
+    }
+
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if usec == 0 {
+        return None;
+    }
+
+    Some(Duration::from_micros(usec))
+}
+
+/// Pets the systemd watchdog, but only while messages (or other signs of life, like heartbeats)
+/// are flowing. When nothing has been received for `max_silence`, the watchdog is not pet
+/// anymore and the service manager restarts the service once the watchdog timeout passes.
+///
+/// Record activity with [`process`] or [`record_activity`] and call [`poll`] regularly, at least
+/// every [`ping_interval`].
+///
+/// [`process`]: Watchdog::process
+/// [`record_activity`]: Watchdog::record_activity
+/// [`poll`]: Watchdog::poll
+/// [`ping_interval`]: Watchdog::ping_interval
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    timeout: Duration,
+    max_silence: Duration,
+    last_activity: Instant,
+    last_ping: Option<Instant>,
+    notify_socket: Option<OsString>,
+}
+
+impl Watchdog {
+    /// Creates a watchdog for a watchdog timeout of `timeout`. `max_silence` defaults to
+    /// `timeout`. The watchdog is pet through the service manager's socket at the time of
+    /// creation (`NOTIFY_SOCKET`), if there is one.
+    #[inline]
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            max_silence: timeout,
+            last_activity: Instant::now(),
+            last_ping: None,
+            notify_socket: notify_socket(),
+        }
+    }
+
+    /// Creates a watchdog for the timeout configured by the service manager, see
+    /// [`watchdog_timeout`].
+    #[inline]
+    pub fn from_env() -> Option<Self> {
+        watchdog_timeout().map(Self::new)
+    }
+
+    /// Sets how long nothing may be received before the watchdog is not pet anymore.
+    #[inline]
+    pub const fn with_max_silence(mut self, max_silence: Duration) -> Self {
+        self.max_silence = max_silence;
+        self
+    }
+
+    /// Returns how often [`poll`](Watchdog::poll) pets the watchdog, half the watchdog timeout as
+    /// recommended by `sd_watchdog_enabled(3)`.
+    #[inline]
+    pub fn ping_interval(&self) -> Duration {
+        self.timeout / 2
+    }
+
+    /// Records that a message was received.
+    #[inline]
+    pub fn process(&mut self, _msg: &Message) {
+        self.record_activity(Instant::now());
+    }
+
+    /// Records a sign of life at `now`.
+    #[inline]
+    pub fn record_activity(&mut self, now: Instant) {
+        self.last_activity = self.last_activity.max(now);
+    }
+
+    /// Returns `true` if something was received within `max_silence` before `now`.
+    #[inline]
+    pub fn is_alive(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_activity) <= self.max_silence
+    }
+
+    /// Pets the watchdog (sends `WATCHDOG=1`) if the subscription is alive and the last ping was
+    /// at least [`ping_interval`](Watchdog::ping_interval) ago. Returns whether a ping was sent.
+    pub fn poll(&mut self, now: Instant) -> io::Result<bool> {
+        if !self.is_alive(now) {
+            return Ok(false);
+        }
+        if self
+            .last_ping
+            .is_some_and(|last| now.saturating_duration_since(last) < self.ping_interval())
+        {
+            return Ok(false);
+        }
+
+        if let Some(path) = &self.notify_socket {
+            notify_to(path, "WATCHDOG=1")?;
+        }
+        self.last_ping = Some(now);
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{notify_to, Watchdog};
+    use core::time::Duration;
+    use std::{fs, os::unix::net::UnixDatagram, time::Instant};
+
+    #[test]
+    fn watchdog() {
+        let dir =
+            std::env::temp_dir().join(format!("bitcoincore-zmq-systemd-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let manager = UnixDatagram::bind(&path).unwrap();
+        manager.set_nonblocking(true).unwrap();
+
+        let mut buf = [0; 64];
+        let mut recv = || {
+            manager
+                .recv(&mut buf)
+                .ok()
+                .map(|len| String::from_utf8(buf[..len].to_vec()).unwrap())
+        };
+
+        notify_to(path.as_os_str(), "READY=1").unwrap();
+        assert_eq!(recv().as_deref(), Some("READY=1"));
+
+        let start = Instant::now();
+        let mut watchdog =
+            Watchdog::new(Duration::from_secs(10)).with_max_silence(Duration::from_secs(30));
+        watchdog.notify_socket = Some(path.clone().into());
+        watchdog.record_activity(start);
+
+        assert!(watchdog.poll(start).unwrap());
+        assert_eq!(recv().as_deref(), Some("WATCHDOG=1"));
+        // too soon
+        assert!(!watchdog.poll(start + Duration::from_secs(4)).unwrap());
+        assert!(watchdog.poll(start + Duration::from_secs(5)).unwrap());
+        assert_eq!(recv().as_deref(), Some("WATCHDOG=1"));
+
+        // nothing received for too long
+        assert!(!watchdog.poll(start + Duration::from_secs(31)).unwrap());
+        assert_eq!(recv(), None);
+
+        watchdog.record_activity(start + Duration::from_secs(32));
+        assert!(watchdog.poll(start + Duration::from_secs(33)).unwrap());
+        assert_eq!(recv().as_deref(), Some("WATCHDOG=1"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
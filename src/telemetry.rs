@@ -0,0 +1,198 @@
+//! OpenTelemetry instrumentation, enabled with the `opentelemetry` feature.
+//!
+//! Metrics and spans are reported to the global meter and tracer providers of the
+//! [`opentelemetry`] crate, install an exporter (for example OTLP) there to collect them.
+//!
+//! Metrics:
+//! - `bitcoincore_zmq.messages`: received messages, by `topic`
+//! - `bitcoincore_zmq.bytes`: bytes of received data parts, by `topic`
+//! - `bitcoincore_zmq.errors`: received messages that were malformed or could not be parsed
+//! - `bitcoincore_zmq.socket_events`: monitor events, by `event` and `endpoint` (only with the
+//!   `async` feature)
+//!
+//! Spans:
+//! - `bitcoincore_zmq.receive`: parsing of a received message
+//! - `bitcoincore_zmq.socket_event`: a monitor event (connects, disconnects, handshakes, ...), only
+//!   with the `async` feature
+
+use crate::{error::Error, message::SEQUENCE_LEN};
+use opentelemetry::{
+    global::{self, BoxedSpan},
+    metrics::{Counter, Meter},
+    trace::{Span, Status, Tracer},
+    KeyValue,
+};
+use std::sync::OnceLock;
+
+const INSTRUMENTATION_NAME: &str = "bitcoincore-zmq";
+
+struct Instruments {
+    messages: Counter<u64>,
+    bytes: Counter<u64>,
+    errors: Counter<u64>,
+    #[cfg(feature = "async")]
+    socket_events: Counter<u64>,
+}
+
+impl Instruments {
+    fn new(meter: &Meter) -> Self {
+        Self {
+            messages: meter
+                .u64_counter("bitcoincore_zmq.messages")
+                .with_description("Received messages")
+                .build(),
+            bytes: meter
+                .u64_counter("bitcoincore_zmq.bytes")
+                .with_description("Bytes of received data parts")
+                .with_unit("By")
+                .build(),
+            errors: meter
+                .u64_counter("bitcoincore_zmq.errors")
+                .with_description("Received messages that were malformed or could not be parsed")
+                .build(),
+            #[cfg(feature = "async")]
+            socket_events: meter
+                .u64_counter("bitcoincore_zmq.socket_events")
+                .with_description("Socket monitor events")
+                .build(),
+        }
+    }
+
+    fn record_message(&self, topic: &KeyValue, len: usize) {
+        let topic = core::slice::from_ref(topic);
+        self.messages.add(1, topic);
+        self.bytes.add(len as u64, topic);
+    }
+
+    fn record_error(&self) {
+        self.errors.add(1, &[]);
+    }
+}
+
+fn instruments() -> &'static Instruments {
+    static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+
+    INSTRUMENTS.get_or_init(|| Instruments::new(&global::meter(INSTRUMENTATION_NAME)))
+}
+
+/// Span covering the parsing of a received message.
+pub(crate) struct MessageSpan {
+    span: BoxedSpan,
+    len: usize,
+}
+
+impl MessageSpan {
+    pub(crate) fn start(len: usize) -> Self {
+        Self {
+            span: global::tracer(INSTRUMENTATION_NAME).start("bitcoincore_zmq.receive"),
+            len,
+        }
+    }
+
+    pub(crate) fn end(
+        mut self,
+        topic: &[u8],
+        sequence: [u8; SEQUENCE_LEN],
+        res: Result<(), &Error>,
+    ) {
+        match res {
+            Ok(()) => {
+                let topic = KeyValue::new("topic", String::from_utf8_lossy(topic).into_owned());
+                instruments().record_message(&topic, self.len);
+
+                self.span.set_attribute(topic);
+                self.span.set_attribute(KeyValue::new(
+                    "sequence",
+                    i64::from(u32::from_le_bytes(sequence)),
+                ));
+            }
+            Err(err) => {
+                instruments().record_error();
+
+                self.span.set_status(Status::error(err.to_string()));
+            }
+        }
+        self.span
+            .set_attribute(KeyValue::new("size", self.len as i64));
+        self.span.end();
+    }
+}
+
+/// Records a received message that is malformed (wrong number of parts, too long, ...) and was
+/// not parsed. Errors from parsing are recorded when the [`MessageSpan`] ends.
+pub(crate) fn record_invalid_message() {
+    instruments().record_error();
+}
+
+/// Records a monitor event.
+#[cfg(feature = "async")]
+pub(crate) fn record_socket_event(msg: &crate::monitor::MonitorMessage) {
+    let attributes = [
+        KeyValue::new("event", msg.event.name()),
+        KeyValue::new("endpoint", msg.source_url.to_string()),
+    ];
+
+    instruments().socket_events.add(1, &attributes);
+
+    let mut span = global::tracer(INSTRUMENTATION_NAME).start("bitcoincore_zmq.socket_event");
+    span.set_attributes(attributes);
+    span.end();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Instruments;
+    use opentelemetry::{metrics::MeterProvider, KeyValue};
+    use opentelemetry_sdk::metrics::{
+        data::{AggregatedMetrics, MetricData},
+        InMemoryMetricExporter, PeriodicReader, SdkMeterProvider,
+    };
+
+    #[test]
+    fn counters() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let instruments = Instruments::new(&provider.meter("test"));
+
+        let rawtx = KeyValue::new("topic", "rawtx");
+        instruments.record_message(&rawtx, 100);
+        instruments.record_message(&rawtx, 50);
+        instruments.record_message(&KeyValue::new("topic", "hashblock"), 32);
+        instruments.record_error();
+        instruments.record_error();
+        provider.force_flush().unwrap();
+
+        let metrics = exporter.get_finished_metrics().unwrap();
+        // sums of a counter by its attributes
+        let sums = |name: &str| {
+            let metric = metrics
+                .iter()
+                .flat_map(|m| m.scope_metrics())
+                .flat_map(|s| s.metrics())
+                .find(|m| m.name() == name)
+                .unwrap();
+            let AggregatedMetrics::U64(MetricData::Sum(sum)) = metric.data() else {
+                panic!("{name} is not a u64 sum");
+            };
+            let mut sums: Vec<_> = sum
+                .data_points()
+                .map(|p| (p.attributes().cloned().collect::<Vec<_>>(), p.value()))
+                .collect();
+            sums.sort_by_key(|(_, value)| *value);
+            sums
+        };
+
+        let hashblock = KeyValue::new("topic", "hashblock");
+        assert_eq!(
+            sums("bitcoincore_zmq.messages"),
+            [(vec![hashblock.clone()], 1), (vec![rawtx.clone()], 2)]
+        );
+        assert_eq!(
+            sums("bitcoincore_zmq.bytes"),
+            [(vec![hashblock], 32), (vec![rawtx], 150)]
+        );
+        assert_eq!(sums("bitcoincore_zmq.errors"), [(vec![], 2)]);
+    }
+}
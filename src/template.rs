@@ -0,0 +1,308 @@
+use crate::{error::Result, message::Message, sequence_message::SequenceMessage};
+use bitcoin::{BlockHash, Transaction, Txid, Weight};
+use std::collections::BTreeMap;
+
+/// Decides when a block template (from `getblocktemplate`) is outdated, based on incoming
+/// messages. A template is stale when a block connects or disconnects, or when enough new
+/// mempool transactions arrived since the last signal.
+///
+/// Block changes are taken from the `hashblock`, `rawblock` and `sequence` topics, the same block
+/// arriving on multiple topics only signals once. New mempool transactions need both the `rawtx`
+/// and `sequence` topics: Bitcoin Core also publishes `rawtx` for every transaction of a connected
+/// block, so a transaction is only counted once its mempool acceptance arrives on `sequence`.
+/// Transactions that are not accepted before the next block changes are forgotten. As `rawtx`
+/// messages do not contain fees, a filter can be supplied to only count transactions considered
+/// high-fee by the caller.
+#[derive(Debug, Clone)]
+pub struct TemplateInvalidator<F = fn(&Transaction) -> bool> {
+    weight_threshold: Weight,
+    accumulated: Weight,
+    /// Weight of received transactions that passed the filter and were not accepted yet.
+    pending: BTreeMap<Txid, Weight>,
+    last_block: Option<BlockHash>,
+    filter: F,
+}
+
+impl TemplateInvalidator {
+    /// Creates a new [`TemplateInvalidator`] that counts every new mempool transaction.
+    #[inline]
+    pub fn new(weight_threshold: Weight) -> Self {
+        Self::with_filter(weight_threshold, |_| true)
+    }
+}
+
+impl<F> TemplateInvalidator<F>
+where
+    F: FnMut(&Transaction) -> bool,
+{
+    /// Creates a new [`TemplateInvalidator`] that only counts mempool transactions for which
+    /// `filter` returns `true`.
+    #[inline]
+    pub const fn with_filter(weight_threshold: Weight, filter: F) -> Self {
+        Self {
+            weight_threshold,
+            accumulated: Weight::ZERO,
+            pending: BTreeMap::new(),
+            last_block: None,
+            filter,
+        }
+    }
+
+    /// Returns the weight of counted mempool transactions since the last signal.
+    #[inline]
+    pub const fn accumulated_weight(&self) -> Weight {
+        self.accumulated
+    }
+
+    /// Processes a message and returns `true` if the block template should be refreshed.
+    pub fn update(&mut self, msg: &Message) -> bool {
+        match msg {
+            Message::HashBlock(blockhash, _)
+            | Message::Sequence(SequenceMessage::BlockConnect { blockhash }, _) => {
+                self.block_changed(*blockhash)
+            }
+            Message::Block(block, _) => self.block_changed(block.block_hash()),
+            Message::Sequence(SequenceMessage::BlockDisconnect { .. }, _) => {
+                // the tip moved back, the next connect may be a block we already saw
+                self.last_block = None;
+                self.pending.clear();
+                self.reset()
+            }
+            Message::Tx(tx, _) => {
+                if (self.filter)(tx) {
+                    self.pending.insert(tx.compute_txid(), tx.weight());
+                }
+                false
+            }
+            Message::Sequence(SequenceMessage::MempoolAcceptance { txid, .. }, _) => {
+                let Some(weight) = self.pending.remove(txid) else {
+                    return false;
+                };
+                self.accumulated += weight;
+                if self.accumulated >= self.weight_threshold {
+                    self.reset()
+                } else {
+                    false
+                }
+            }
+            Message::HashTx(..) | Message::Sequence(SequenceMessage::MempoolRemoval { .. }, _) => {
+                false
+            }
+        }
+    }
+
+    fn block_changed(&mut self, blockhash: BlockHash) -> bool {
+        if self.last_block == Some(blockhash) {
+            return false;
+        }
+        self.last_block = Some(blockhash);
+        // transactions that were not accepted to the mempool were published for this block
+        self.pending.clear();
+        self.reset()
+    }
+
+    fn reset(&mut self) -> bool {
+        self.accumulated = Weight::ZERO;
+        true
+    }
+
+    /// Wraps an iterator of messages (for example the [`Receiver`] returned by
+    /// [`subscribe_receiver`]) into an iterator that yields `()` every time the block template
+    /// should be refreshed. Errors are passed through.
+    ///
+    /// [`Receiver`]: std::sync::mpsc::Receiver
+    /// [`subscribe_receiver`]: crate::subscribe_receiver
+    #[inline]
+    pub fn signals<I>(self, messages: I) -> TemplateSignals<I::IntoIter, F>
+    where
+        I: IntoIterator<Item = Result<Message>>,
+    {
+        TemplateSignals {
+            invalidator: self,
+            messages: messages.into_iter(),
+        }
+    }
+
+    /// Wraps a stream of messages (for example the stream returned by [`subscribe_async`]) into
+    /// a stream that yields `()` every time the block template should be refreshed. Errors are
+    /// passed through.
+    ///
+    /// [`subscribe_async`]: crate::subscribe_async
+    #[cfg(feature = "async")]
+    #[inline]
+    pub fn signal_stream<S>(self, messages: S) -> TemplateSignalStream<S, F>
+    where
+        S: futures_util::Stream<Item = Result<Message>>,
+    {
+        TemplateSignalStream {
+            invalidator: self,
+            messages,
+        }
+    }
+}
+
+/// Iterator returned by [`TemplateInvalidator::signals`].
+#[derive(Debug)]
+pub struct TemplateSignals<I, F> {
+    invalidator: TemplateInvalidator<F>,
+    messages: I,
+}
+
+impl<I, F> Iterator for TemplateSignals<I, F>
+where
+    I: Iterator<Item = Result<Message>>,
+    F: FnMut(&Transaction) -> bool,
+{
+    type Item = Result<()>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.messages.next()? {
+                Ok(msg) => {
+                    if self.invalidator.update(&msg) {
+                        return Some(Ok(()));
+                    }
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub use signal_stream::TemplateSignalStream;
+
+#[cfg(feature = "async")]
+mod signal_stream {
+    use super::TemplateInvalidator;
+    use crate::{error::Result, message::Message};
+    use bitcoin::Transaction;
+    use core::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use futures_util::{Stream, StreamExt};
+
+    /// Stream returned by [`TemplateInvalidator::signal_stream`].
+    #[derive(Debug)]
+    pub struct TemplateSignalStream<S, F> {
+        pub(super) invalidator: TemplateInvalidator<F>,
+        pub(super) messages: S,
+    }
+
+    impl<S, F> Stream for TemplateSignalStream<S, F>
+    where
+        S: Stream<Item = Result<Message>> + Unpin,
+        F: FnMut(&Transaction) -> bool + Unpin,
+    {
+        type Item = Result<()>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            loop {
+                match self.messages.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(msg))) => {
+                        if self.invalidator.update(&msg) {
+                            return Poll::Ready(Some(Ok(())));
+                        }
+                    }
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                    Poll::Ready(None) => return Poll::Ready(None),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_util::tx, Message, SequenceMessage, TemplateInvalidator};
+    use bitcoin::{constants::genesis_block, hashes::Hash, Network, OutPoint, Txid, Weight};
+
+    fn accepted(txid: Txid, mempool_sequence: u64) -> Message {
+        Message::Sequence(
+            SequenceMessage::MempoolAcceptance {
+                txid,
+                mempool_sequence,
+            },
+            0,
+        )
+    }
+
+    #[test]
+    fn template_invalidation() {
+        let block = genesis_block(Network::Bitcoin);
+        let blockhash = block.block_hash();
+        let tx = |i: u8| tx(&[OutPoint::new(Txid::from_byte_array([i; 32]), 0)], &[1000]);
+        let weight = tx(0).weight();
+
+        let mut invalidator = TemplateInvalidator::new(weight * 2);
+
+        // the same block on multiple topics signals once
+        assert!(invalidator.update(&Message::HashBlock(blockhash, 0)));
+        assert!(!invalidator.update(&Message::Block(block.clone(), 0)));
+        assert!(!invalidator.update(&Message::Sequence(
+            SequenceMessage::BlockConnect { blockhash },
+            0
+        )));
+
+        // transactions count once they are accepted to the mempool
+        assert!(!invalidator.update(&Message::Tx(tx(1), 1)));
+        assert_eq!(invalidator.accumulated_weight(), Weight::ZERO);
+        assert!(!invalidator.update(&accepted(tx(1).compute_txid(), 1)));
+        assert_eq!(invalidator.accumulated_weight(), weight);
+        // acceptance without the transaction, or accepted twice
+        assert!(!invalidator.update(&accepted(tx(1).compute_txid(), 2)));
+        assert_eq!(invalidator.accumulated_weight(), weight);
+        assert!(!invalidator.update(&Message::Tx(tx(2), 2)));
+        assert!(invalidator.update(&accepted(tx(2).compute_txid(), 3)));
+        assert_eq!(invalidator.accumulated_weight(), Weight::ZERO);
+
+        // reorg back to the same block
+        assert!(!invalidator.update(&Message::Tx(tx(3), 3)));
+        assert!(!invalidator.update(&accepted(tx(3).compute_txid(), 4)));
+        assert!(invalidator.update(&Message::Sequence(
+            SequenceMessage::BlockDisconnect { blockhash },
+            1
+        )));
+        assert_eq!(invalidator.accumulated_weight(), Weight::ZERO);
+        assert!(invalidator.update(&Message::HashBlock(blockhash, 1)));
+
+        let mut filtered = TemplateInvalidator::with_filter(Weight::from_wu(1), |_| false);
+        assert!(!filtered.update(&Message::Tx(tx(1), 0)));
+        assert!(!filtered.update(&accepted(tx(1).compute_txid(), 0)));
+
+        let signals = TemplateInvalidator::new(weight)
+            .signals([
+                Ok(Message::Tx(tx(1), 0)),
+                Ok(accepted(tx(1).compute_txid(), 0)),
+                Ok(Message::HashBlock(blockhash, 0)),
+                Ok(Message::HashBlock(blockhash, 1)),
+            ])
+            .count();
+        assert_eq!(signals, 2);
+    }
+
+    #[test]
+    fn block_transactions_are_not_counted() {
+        let block = genesis_block(Network::Bitcoin);
+        let blockhash = block.block_hash();
+        let tx = |i: u8| tx(&[OutPoint::new(Txid::from_byte_array([i; 32]), 0)], &[1000]);
+
+        // Bitcoin Core publishes the transactions of a block before the block itself
+        let signals = TemplateInvalidator::new(tx(0).weight())
+            .signals([
+                Ok(Message::Tx(tx(1), 0)),
+                Ok(Message::Tx(tx(2), 1)),
+                Ok(Message::HashBlock(blockhash, 0)),
+                Ok(Message::Sequence(
+                    SequenceMessage::BlockConnect { blockhash },
+                    0,
+                )),
+                // the block transactions are forgotten, even if announced again
+                Ok(accepted(tx(1).compute_txid(), 0)),
+            ])
+            .count();
+        assert_eq!(signals, 1);
+    }
+}
@@ -0,0 +1,59 @@
+//! Fixtures shared by the tests of multiple modules.
+
+use crate::raw_message::IntoRawMessage;
+use bitcoin::{
+    absolute::LockTime, transaction::Version, Amount, OutPoint, ScriptBuf, Sequence, Transaction,
+    TxIn, TxOut, Witness,
+};
+use core::time::Duration;
+use std::thread;
+
+/// Returns a transaction spending `inputs` and creating outputs with the values (in satoshis) of
+/// `outputs`, with empty scripts.
+pub(crate) fn tx(inputs: &[OutPoint], outputs: &[u64]) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: inputs
+            .iter()
+            .map(|previous_output| TxIn {
+                previous_output: *previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: outputs
+            .iter()
+            .map(|value| TxOut {
+                value: Amount::from_sat(*value),
+                script_pubkey: ScriptBuf::new(),
+            })
+            .collect(),
+    }
+}
+
+/// Publishes `msg(sequence)` for increasing sequence numbers on `publisher` until `received`
+/// returns something, as messages published before a subscriber is connected are dropped.
+/// `received` must not block, it is called 10 milliseconds after every message. Returns what
+/// `received` returned and the next unused sequence number.
+pub(crate) fn publish_until<M, T>(
+    publisher: &zmq::Socket,
+    mut msg: impl FnMut(u32) -> M,
+    mut received: impl FnMut() -> Option<T>,
+) -> (T, u32)
+where
+    M: IntoRawMessage,
+{
+    let mut sequence = 0;
+    loop {
+        publisher
+            .send_multipart(msg(sequence).into_raw_message(), 0)
+            .unwrap();
+        sequence += 1;
+        thread::sleep(Duration::from_millis(10));
+        if let Some(received) = received() {
+            return (received, sequence);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::message::{Message, TOPIC_MAX_LEN};
+use core::fmt;
+
+/// A topic Bitcoin Core publishes messages on. Each [`Message`] variant corresponds to exactly
+/// one topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Topic {
+    HashBlock,
+    HashTx,
+    RawBlock,
+    RawTx,
+    Sequence,
+}
+
+impl Topic {
+    /// All topics Bitcoin Core publishes messages on.
+    pub const ALL: [Self; 5] = [
+        Self::HashBlock,
+        Self::HashTx,
+        Self::RawBlock,
+        Self::RawTx,
+        Self::Sequence,
+    ];
+
+    /// Returns this [`Topic`] as a byte slice, as it appears in the first part of a multipart.
+    #[inline]
+    pub const fn as_bytes(self) -> &'static [u8] {
+        self.as_str().as_bytes()
+    }
+
+    /// Returns this [`Topic`] as a string slice.
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::HashBlock => "hashblock",
+            Self::HashTx => "hashtx",
+            Self::RawBlock => "rawblock",
+            Self::RawTx => "rawtx",
+            Self::Sequence => "sequence",
+        }
+    }
+
+    /// Parses a topic from bytes. Returns [`None`] if the topic is unknown.
+    #[inline]
+    pub fn from_bytes(topic: &[u8]) -> Option<Self> {
+        Some(match topic {
+            b"hashblock" => Self::HashBlock,
+            b"hashtx" => Self::HashTx,
+            b"rawblock" => Self::RawBlock,
+            b"rawtx" => Self::RawTx,
+            b"sequence" => Self::Sequence,
+            _ => return None,
+        })
+    }
+}
+
+impl From<&Message> for Topic {
+    #[inline]
+    fn from(msg: &Message) -> Self {
+        let topic = match msg {
+            Message::HashBlock(..) => Self::HashBlock,
+            Message::HashTx(..) => Self::HashTx,
+            Message::Block(..) => Self::RawBlock,
+            Message::Tx(..) => Self::RawTx,
+            Message::Sequence(..) => Self::Sequence,
+        };
+
+        debug_assert!(topic.as_bytes().len() <= TOPIC_MAX_LEN);
+
+        topic
+    }
+}
+
+impl fmt::Display for Topic {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Message, Topic};
+    use bitcoin::{hashes::Hash, BlockHash};
+
+    #[test]
+    fn topic_roundtrip() {
+        for topic in Topic::ALL {
+            assert_eq!(Topic::from_bytes(topic.as_bytes()), Some(topic));
+        }
+
+        assert_eq!(Topic::from_bytes(b"hashblock!"), None);
+        assert_eq!(Topic::from_bytes(b""), None);
+
+        let msg = Message::HashBlock(BlockHash::all_zeros(), 0);
+        assert_eq!(Topic::from(&msg).as_bytes(), msg.topic());
+    }
+}
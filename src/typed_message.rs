@@ -0,0 +1,225 @@
+use crate::{
+    endpoint::Endpoint, message::Message, sequence_message::SequenceMessage, topic::Topic,
+};
+use bitcoin::{Block, BlockHash, Transaction, Txid};
+use std::time::SystemTime;
+
+/// A `hashblock` message with metadata.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HashBlockMessage {
+    pub hash: BlockHash,
+    pub sequence: u32,
+    pub received_at: SystemTime,
+    /// The endpoint the message was received from, if known.
+    pub source: Option<Endpoint>,
+}
+
+/// A `hashtx` message with metadata.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HashTxMessage {
+    pub txid: Txid,
+    pub sequence: u32,
+    pub received_at: SystemTime,
+    /// The endpoint the message was received from, if known.
+    pub source: Option<Endpoint>,
+}
+
+/// A `rawblock` message with metadata.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BlockMessage {
+    pub block: Block,
+    /// The hash of `block`, computed once on conversion.
+    pub hash: BlockHash,
+    pub sequence: u32,
+    pub received_at: SystemTime,
+    /// The endpoint the message was received from, if known.
+    pub source: Option<Endpoint>,
+}
+
+/// A `rawtx` message with metadata.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TxMessage {
+    pub tx: Transaction,
+    /// The txid of `tx`, computed once on conversion.
+    pub txid: Txid,
+    pub sequence: u32,
+    pub received_at: SystemTime,
+    /// The endpoint the message was received from, if known.
+    pub source: Option<Endpoint>,
+}
+
+/// A `sequence` message with metadata.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SequenceNotification {
+    pub message: SequenceMessage,
+    pub sequence: u32,
+    pub received_at: SystemTime,
+    /// The endpoint the message was received from, if known.
+    pub source: Option<Endpoint>,
+}
+
+/// A [`Message`] with named fields and room for metadata, like the time it was received.
+///
+/// Convert a [`Message`] with [`TypedMessage::new`] or [`From`], and back with [`From`].
+/// [`Subscription::recv_typed`] receives them with the time they were received and their
+/// source.
+///
+/// [`Subscription::recv_typed`]: crate::Subscription::recv_typed
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TypedMessage {
+    HashBlock(HashBlockMessage),
+    HashTx(HashTxMessage),
+    Block(BlockMessage),
+    Tx(TxMessage),
+    Sequence(SequenceNotification),
+}
+
+impl TypedMessage {
+    /// Converts a [`Message`] to a [`TypedMessage`] with the given metadata.
+    pub fn new(msg: Message, received_at: SystemTime, source: Option<Endpoint>) -> Self {
+        match msg {
+            Message::HashBlock(hash, sequence) => Self::HashBlock(HashBlockMessage {
+                hash,
+                sequence,
+                received_at,
+                source,
+            }),
+            Message::HashTx(txid, sequence) => Self::HashTx(HashTxMessage {
+                txid,
+                sequence,
+                received_at,
+                source,
+            }),
+            Message::Block(block, sequence) => Self::Block(BlockMessage {
+                hash: block.block_hash(),
+                block,
+                sequence,
+                received_at,
+                source,
+            }),
+            Message::Tx(tx, sequence) => Self::Tx(TxMessage {
+                txid: tx.compute_txid(),
+                tx,
+                sequence,
+                received_at,
+                source,
+            }),
+            Message::Sequence(message, sequence) => Self::Sequence(SequenceNotification {
+                message,
+                sequence,
+                received_at,
+                source,
+            }),
+        }
+    }
+
+    /// Returns the topic of this [`TypedMessage`] as a [`Topic`].
+    #[inline]
+    pub const fn topic_type(&self) -> Topic {
+        match self {
+            Self::HashBlock(_) => Topic::HashBlock,
+            Self::HashTx(_) => Topic::HashTx,
+            Self::Block(_) => Topic::RawBlock,
+            Self::Tx(_) => Topic::RawTx,
+            Self::Sequence(_) => Topic::Sequence,
+        }
+    }
+
+    /// Returns the sequence of this [`TypedMessage`], see [`Message::sequence`].
+    #[inline]
+    pub const fn sequence(&self) -> u32 {
+        match self {
+            Self::HashBlock(HashBlockMessage { sequence, .. })
+            | Self::HashTx(HashTxMessage { sequence, .. })
+            | Self::Block(BlockMessage { sequence, .. })
+            | Self::Tx(TxMessage { sequence, .. })
+            | Self::Sequence(SequenceNotification { sequence, .. }) => *sequence,
+        }
+    }
+
+    /// Returns the time this [`TypedMessage`] was received.
+    #[inline]
+    pub const fn received_at(&self) -> SystemTime {
+        match self {
+            Self::HashBlock(HashBlockMessage { received_at, .. })
+            | Self::HashTx(HashTxMessage { received_at, .. })
+            | Self::Block(BlockMessage { received_at, .. })
+            | Self::Tx(TxMessage { received_at, .. })
+            | Self::Sequence(SequenceNotification { received_at, .. }) => *received_at,
+        }
+    }
+
+    /// Returns the endpoint this [`TypedMessage`] was received from, if known.
+    #[inline]
+    pub fn source(&self) -> Option<&Endpoint> {
+        match self {
+            Self::HashBlock(HashBlockMessage { source, .. })
+            | Self::HashTx(HashTxMessage { source, .. })
+            | Self::Block(BlockMessage { source, .. })
+            | Self::Tx(TxMessage { source, .. })
+            | Self::Sequence(SequenceNotification { source, .. }) => source.as_ref(),
+        }
+    }
+}
+
+impl From<Message> for TypedMessage {
+    /// Converts a [`Message`] to a [`TypedMessage`] received now from an unknown source. Now is
+    /// the time of the conversion, which may be long after the message was received. Use
+    /// [`TypedMessage::new`] with the actual time if it is known.
+    #[inline]
+    fn from(msg: Message) -> Self {
+        Self::new(msg, SystemTime::now(), None)
+    }
+}
+
+impl From<TypedMessage> for Message {
+    #[inline]
+    fn from(msg: TypedMessage) -> Self {
+        match msg {
+            TypedMessage::HashBlock(m) => Self::HashBlock(m.hash, m.sequence),
+            TypedMessage::HashTx(m) => Self::HashTx(m.txid, m.sequence),
+            TypedMessage::Block(m) => Self::Block(m.block, m.sequence),
+            TypedMessage::Tx(m) => Self::Tx(m.tx, m.sequence),
+            TypedMessage::Sequence(m) => Self::Sequence(m.message, m.sequence),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Endpoint, Message, SequenceMessage, Topic, TypedMessage};
+    use bitcoin::{constants::genesis_block, Network};
+    use std::time::SystemTime;
+
+    #[test]
+    fn conversion() {
+        let block = genesis_block(Network::Bitcoin);
+        let blockhash = block.block_hash();
+        let tx = block.txdata[0].clone();
+
+        let msgs = [
+            Message::HashBlock(blockhash, 0),
+            Message::HashTx(tx.compute_txid(), 1),
+            Message::Block(block, 2),
+            Message::Tx(tx, 3),
+            Message::Sequence(SequenceMessage::BlockConnect { blockhash }, 4),
+        ];
+
+        let endpoint = "tcp://127.0.0.1:28332".parse::<Endpoint>().unwrap();
+        for msg in msgs {
+            let typed =
+                TypedMessage::new(msg.clone(), SystemTime::UNIX_EPOCH, Some(endpoint.clone()));
+            assert_eq!(typed.topic_type(), msg.topic_type());
+            assert_eq!(typed.sequence(), msg.sequence());
+            assert_eq!(typed.received_at(), SystemTime::UNIX_EPOCH);
+            assert_eq!(typed.source(), Some(&endpoint));
+
+            if let TypedMessage::Block(m) = &typed {
+                assert_eq!(m.hash, blockhash);
+                assert_eq!(typed.topic_type(), Topic::RawBlock);
+            }
+
+            assert_eq!(Message::from(typed), msg);
+        }
+    }
+}
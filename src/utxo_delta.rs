@@ -0,0 +1,315 @@
+use crate::{message::Message, sequence_message::SequenceMessage};
+use bitcoin::{Block, BlockHash, OutPoint, TxOut};
+use std::collections::{HashSet, VecDeque};
+
+/// The changes a block makes to the UTXO set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoDelta {
+    /// The hash of the block.
+    pub blockhash: BlockHash,
+    /// The hash of the block's parent.
+    pub prev_blockhash: BlockHash,
+    /// The height of the block, if it has a BIP34 height in the coinbase.
+    pub height: Option<u64>,
+    /// Outputs created by the block, in block order. Outputs that are spent in the same block
+    /// and provably unspendable (`OP_RETURN`) outputs are left out.
+    pub created: Vec<(OutPoint, TxOut)>,
+    /// Outputs spent by the block, in block order. Outputs created in the same block are left
+    /// out.
+    pub spent: Vec<SpentOutput>,
+}
+
+/// An output spent by a block, see [`UtxoDelta::spent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpentOutput {
+    /// The spent output.
+    pub outpoint: OutPoint,
+    /// The script and value of the spent output, only known when undo data is available (see
+    /// [`UtxoDeltaTracker::new`]). Needed to restore the output when the block is disconnected.
+    pub txout: Option<TxOut>,
+}
+
+impl UtxoDelta {
+    /// Computes the delta of `block`. `undo` are the outputs spent by the block's inputs (except
+    /// the coinbase input) in block order, like Bitcoin Core's undo data, if available.
+    pub fn from_block(block: &Block, undo: Option<Vec<TxOut>>) -> Self {
+        let mut created = Vec::new();
+        let mut spent = Vec::new();
+        let mut undo = undo.map(Vec::into_iter);
+
+        for tx in &block.txdata {
+            if !tx.is_coinbase() {
+                for input in &tx.input {
+                    spent.push(SpentOutput {
+                        outpoint: input.previous_output,
+                        txout: undo.as_mut().and_then(Iterator::next),
+                    });
+                }
+            }
+
+            let txid = tx.compute_txid();
+            for (vout, txout) in tx.output.iter().enumerate() {
+                if !txout.script_pubkey.is_op_return() {
+                    created.push((OutPoint::new(txid, vout as u32), txout.clone()));
+                }
+            }
+        }
+
+        // outputs created and spent in the same block never enter the UTXO set
+        let created_set: HashSet<_> = created.iter().map(|(outpoint, _)| *outpoint).collect();
+        let spent_in_block: HashSet<_> = spent
+            .iter()
+            .map(|spent| spent.outpoint)
+            .filter(|outpoint| created_set.contains(outpoint))
+            .collect();
+        created.retain(|(outpoint, _)| !spent_in_block.contains(outpoint));
+        spent.retain(|spent| !spent_in_block.contains(&spent.outpoint));
+
+        Self {
+            blockhash: block.block_hash(),
+            prev_blockhash: block.header.prev_blockhash,
+            height: block.bip34_block_height().ok(),
+            created,
+            spent,
+        }
+    }
+}
+
+/// An event produced by [`UtxoDeltaTracker`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UtxoEvent {
+    /// A block was connected, apply its delta: add the created outputs and remove the spent ones.
+    Connected(UtxoDelta),
+    /// A block was disconnected (reorg), reverse its delta: remove the created outputs and
+    /// restore the spent ones.
+    Disconnected(UtxoDelta),
+}
+
+/// Turns `rawblock` messages into a stream of [`UtxoEvent`]s, reversing blocks that are
+/// disconnected in a reorg.
+///
+/// Reorgs are detected from `rawblock` messages of blocks that do not build on the last one,
+/// and from `sequence` messages of disconnected blocks. Only the deltas of the last
+/// [`max_depth`] blocks are kept, deeper reorgs can not be reversed.
+///
+/// The spent outputs are only known with undo data, which is looked up with the function
+/// passed to [`new`], for example using [`rpc::block_undo`] with the `rpc` feature.
+///
+/// [`max_depth`]: UtxoDeltaTracker::with_max_depth
+/// [`new`]: UtxoDeltaTracker::new
+/// [`rpc::block_undo`]: crate::rpc::block_undo
+#[derive(Debug, Clone)]
+pub struct UtxoDeltaTracker<F = fn(&Block) -> Option<Vec<TxOut>>> {
+    undo_fn: F,
+    chain: VecDeque<UtxoDelta>,
+    max_depth: usize,
+}
+
+impl<F> UtxoDeltaTracker<F>
+where
+    F: FnMut(&Block) -> Option<Vec<TxOut>>,
+{
+    /// Default value of [`with_max_depth`](UtxoDeltaTracker::with_max_depth).
+    pub const DEFAULT_MAX_DEPTH: usize = 100;
+
+    /// Creates a new [`UtxoDeltaTracker`] that looks up the undo data of blocks with `undo_fn`.
+    #[inline]
+    pub fn new(undo_fn: F) -> Self {
+        Self {
+            undo_fn,
+            chain: VecDeque::new(),
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Sets how many of the last connected blocks are kept to reverse them in a reorg.
+    #[inline]
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self.trim();
+        self
+    }
+
+    /// Returns the hash of the last connected block.
+    #[inline]
+    pub fn tip(&self) -> Option<BlockHash> {
+        self.chain.back().map(|delta| delta.blockhash)
+    }
+
+    /// Processes a message and returns the resulting events. Only `rawblock` and `sequence`
+    /// messages are used.
+    pub fn process(&mut self, msg: &Message) -> Vec<UtxoEvent> {
+        let mut events = Vec::new();
+
+        match msg {
+            Message::Block(block, _) => {
+                let blockhash = block.block_hash();
+                if self.chain.iter().any(|delta| delta.blockhash == blockhash) {
+                    return events;
+                }
+
+                let prev = block.header.prev_blockhash;
+                if self.chain.iter().any(|delta| delta.blockhash == prev) {
+                    while self.tip() != Some(prev) {
+                        // the chain contains prev, so it is never empty here
+                        let delta = self.chain.pop_back().unwrap();
+                        events.push(UtxoEvent::Disconnected(delta));
+                    }
+                } else if !self.chain.is_empty() {
+                    // the fork point is unknown, older blocks can not be reversed anymore
+                    self.chain.clear();
+                }
+
+                let delta = UtxoDelta::from_block(block, (self.undo_fn)(block));
+                self.chain.push_back(delta.clone());
+                self.trim();
+                events.push(UtxoEvent::Connected(delta));
+            }
+            Message::Sequence(SequenceMessage::BlockDisconnect { blockhash }, _)
+                if self.tip() == Some(*blockhash) =>
+            {
+                // the guard checked that there is a tip
+                let delta = self.chain.pop_back().unwrap();
+                events.push(UtxoEvent::Disconnected(delta));
+            }
+            _ => {}
+        }
+
+        events
+    }
+
+    fn trim(&mut self) {
+        while self.chain.len() > self.max_depth.max(1) {
+            self.chain.pop_front();
+        }
+    }
+}
+
+impl Default for UtxoDeltaTracker {
+    #[inline]
+    fn default() -> Self {
+        Self::new(|_| None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpentOutput, UtxoDeltaTracker, UtxoEvent};
+    use crate::{test_util::tx, Message, SequenceMessage};
+    use bitcoin::{
+        block::{Header, Version as BlockVersion},
+        hashes::Hash,
+        Amount, Block, BlockHash, CompactTarget, OutPoint, ScriptBuf, Transaction, TxMerkleNode,
+        TxOut, Txid,
+    };
+
+    fn block(prev_blockhash: BlockHash, nonce: u32, txdata: Vec<Transaction>) -> Block {
+        Block {
+            header: Header {
+                version: BlockVersion::TWO,
+                prev_blockhash,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 0,
+                bits: CompactTarget::from_consensus(0),
+                nonce,
+            },
+            txdata,
+        }
+    }
+
+    #[test]
+    fn utxo_delta() {
+        let coinbase = tx(&[OutPoint::null()], &[50]);
+        let utxo = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let parent = tx(&[utxo], &[10, 20]);
+        let parent_out = OutPoint::new(parent.compute_txid(), 0);
+        let child = tx(&[parent_out], &[5]);
+        let mut op_return = tx(&[OutPoint::new(Txid::from_byte_array([2; 32]), 0)], &[0]);
+        op_return.output[0].script_pubkey = ScriptBuf::new_op_return([]);
+
+        let undo = vec![
+            TxOut {
+                value: Amount::from_sat(40),
+                script_pubkey: ScriptBuf::new(),
+            },
+            // parent_out, spent in the same block
+            parent.output[0].clone(),
+            TxOut {
+                value: Amount::from_sat(1),
+                script_pubkey: ScriptBuf::new(),
+            },
+        ];
+
+        let a = block(
+            BlockHash::all_zeros(),
+            0,
+            vec![coinbase.clone(), parent.clone(), child.clone(), op_return],
+        );
+        let mut tracker = UtxoDeltaTracker::new(|_: &Block| Some(undo.clone()));
+        let events = tracker.process(&Message::Block(a.clone(), 0));
+        let [UtxoEvent::Connected(delta)] = &events[..] else {
+            panic!("unexpected events {events:?}");
+        };
+        assert_eq!(delta.blockhash, a.block_hash());
+        let created: Vec<_> = delta
+            .created
+            .iter()
+            .map(|(outpoint, _)| *outpoint)
+            .collect();
+        assert_eq!(
+            created,
+            [
+                OutPoint::new(coinbase.compute_txid(), 0),
+                OutPoint::new(parent.compute_txid(), 1),
+                OutPoint::new(child.compute_txid(), 0),
+            ]
+        );
+        assert_eq!(
+            delta.spent,
+            [
+                SpentOutput {
+                    outpoint: utxo,
+                    txout: Some(undo[0].clone()),
+                },
+                SpentOutput {
+                    outpoint: OutPoint::new(Txid::from_byte_array([2; 32]), 0),
+                    txout: Some(undo[2].clone()),
+                },
+            ]
+        );
+
+        // b1 builds on a, b2 replaces b1
+        let b1 = block(a.block_hash(), 1, vec![coinbase.clone()]);
+        let b2 = block(a.block_hash(), 2, vec![coinbase.clone()]);
+        assert!(matches!(
+            &tracker.process(&Message::Block(b1.clone(), 1))[..],
+            [UtxoEvent::Connected(_)]
+        ));
+        let events = tracker.process(&Message::Block(b2.clone(), 2));
+        assert!(matches!(
+            &events[..],
+            [UtxoEvent::Disconnected(d), UtxoEvent::Connected(c)]
+                if d.blockhash == b1.block_hash() && c.blockhash == b2.block_hash()
+        ));
+
+        // disconnect through a sequence message
+        let events = tracker.process(&Message::Sequence(
+            SequenceMessage::BlockDisconnect {
+                blockhash: b2.block_hash(),
+            },
+            0,
+        ));
+        assert!(
+            matches!(&events[..], [UtxoEvent::Disconnected(d)] if d.blockhash == b2.block_hash())
+        );
+        assert_eq!(tracker.tip(), Some(a.block_hash()));
+
+        // without undo data
+        let mut tracker = UtxoDeltaTracker::default();
+        let events = tracker.process(&Message::Block(a, 0));
+        let [UtxoEvent::Connected(delta)] = &events[..] else {
+            panic!("unexpected events {events:?}");
+        };
+        assert!(delta.spent.iter().all(|spent| spent.txout.is_none()));
+    }
+}